@@ -0,0 +1,460 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::thread::JoinHandle;
+
+use fractal_renderer_shared as shared;
+use shared::math::{Complex64, ComplexNumber, DDVec2};
+use glam::{dvec2, DVec2};
+use serde::{Deserialize, Serialize};
+
+use crate::compute::CpuPrecision;
+use crate::headless::{self, View};
+use crate::scene::Scene;
+
+
+/// Frames zoomed further than this are computed with the double-double precision
+pub const DOUBLE_DOUBLE_ZOOM: f64 = 1e-13;
+
+/// The values of the view that change along an [`Animation`]
+#[derive(Copy, Clone, Serialize, Deserialize)]
+pub struct Keyframe
+{
+	/// Time of the keyframe in seconds
+	pub time: f64,
+	pub center: DDVec2,
+	pub zoom: f64,
+	pub julia_constant: Complex64,
+	pub iteration_limit: u32,
+	pub color_frequency: f32,
+}
+
+impl Keyframe
+{
+	/// Captures the animated values of `scene`
+	pub fn from_scene(scene: &Scene, time: f64) -> Self
+	{
+		Self
+		{
+			time,
+			center: scene.center.into(),
+			zoom: scene.zoom,
+			julia_constant: scene.fractal.secondary_pos,
+			iteration_limit: scene.fractal.iteration_limit,
+			color_frequency: scene.fractal.color_frequency,
+		}
+	}
+
+	pub fn apply_to_scene(&self, scene: &mut Scene)
+	{
+		scene.center = self.center.into();
+		scene.zoom = self.zoom;
+		scene.fractal.secondary_pos = self.julia_constant;
+		scene.fractal.iteration_limit = self.iteration_limit;
+		scene.fractal.color_frequency = self.color_frequency;
+	}
+
+	pub fn apply_to_view(&self, view: &mut View)
+	{
+		view.center = self.center;
+		view.zoom = self.zoom;
+		view.fractal.secondary_pos = self.julia_constant;
+		view.fractal.iteration_limit = self.iteration_limit;
+		view.fractal.color_frequency = self.color_frequency;
+		if self.zoom < DOUBLE_DOUBLE_ZOOM
+		{
+			view.precision = CpuPrecision::DoubleDouble;
+		}
+	}
+}
+
+/// A timeline of keyframes, the zoom is interpolated in log space and the position and julia constant along Catmull-Rom splines
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Animation
+{
+	/// Sorted by time
+	keyframes: Vec<Keyframe>,
+	pub frame_rate: u32,
+}
+
+impl Default for Animation
+{
+	fn default() -> Self
+	{
+		Self
+		{
+			keyframes: Vec::new(),
+			frame_rate: 30,
+		}
+	}
+}
+
+impl Animation
+{
+	pub fn keyframes(&self) -> &[Keyframe]
+	{
+		&self.keyframes
+	}
+
+	/// Adds a keyframe after the ones with the same or an earlier time
+	pub fn insert(&mut self, keyframe: Keyframe)
+	{
+		let index = self.keyframes.partition_point(|k| k.time <= keyframe.time);
+		self.keyframes.insert(index, keyframe);
+	}
+
+	pub fn remove(&mut self, index: usize) -> Keyframe
+	{
+		self.keyframes.remove(index)
+	}
+
+	/// Changes the time of a keyframe, which may move it in the timeline
+	pub fn set_time(&mut self, index: usize, time: f64)
+	{
+		let mut keyframe = self.keyframes.remove(index);
+		keyframe.time = time;
+		self.insert(keyframe);
+	}
+
+	pub fn start_time(&self) -> f64
+	{
+		self.keyframes.first().map_or(0.0, |k| k.time)
+	}
+
+	pub fn duration(&self) -> f64
+	{
+		self.keyframes.last().map_or(0.0, |k| k.time) - self.start_time()
+	}
+
+	/// Number of frames to render, the first and last keyframes are both included
+	pub fn frame_count(&self) -> u32
+	{
+		if self.keyframes.is_empty()
+		{
+			return 0;
+		}
+
+		(self.duration() * self.frame_rate.max(1) as f64).round() as u32 + 1
+	}
+
+	pub fn frame_time(&self, frame: u32) -> f64
+	{
+		self.start_time() + frame as f64 / self.frame_rate.max(1) as f64
+	}
+
+	/// The interpolated values at `time`, clamped to the timeline
+	pub fn sample(&self, time: f64) -> Option<Keyframe>
+	{
+		let last = self.keyframes.len().checked_sub(1)?;
+		let segment = self.keyframes.partition_point(|k| k.time <= time).clamp(1, last.max(1)) - 1;
+		let k1 = &self.keyframes[segment];
+		let Some(k2) = self.keyframes.get(segment + 1)
+		else
+		{
+			return Some(Keyframe { time, ..*k1 });
+		};
+		let k0 = &self.keyframes[segment.saturating_sub(1)];
+		let k3 = &self.keyframes[(segment + 2).min(last)];
+
+		let segment_duration = k2.time - k1.time;
+		let t = if segment_duration > 0.0
+		{
+			((time - k1.time) / segment_duration).clamp(0.0, 1.0)
+		}
+		else
+		{
+			1.0
+		};
+
+		// Relative to the first point of the segment, so that the spline is computed in double precision even in deep zooms
+		let offset = |k: &Keyframe|
+		{
+			let offset = k.center - k1.center;
+			dvec2(offset.x.hi(), offset.y.hi())
+		};
+		let center_offset = catmull_rom([offset(k0), DVec2::ZERO, offset(k2), offset(k3)], t);
+
+		let constant = |k: &Keyframe| dvec2(k.julia_constant.re(), k.julia_constant.im());
+		let julia_constant = catmull_rom([constant(k0), constant(k1), constant(k2), constant(k3)], t);
+
+		Some(Keyframe
+		{
+			time,
+			center: k1.center + center_offset.into(),
+			zoom: (k1.zoom.ln() + (k2.zoom.ln() - k1.zoom.ln()) * t).exp(),
+			julia_constant: Complex64::new(julia_constant.x, julia_constant.y),
+			iteration_limit: (k1.iteration_limit as f64 + (k2.iteration_limit as f64 - k1.iteration_limit as f64) * t).round() as u32,
+			color_frequency: k1.color_frequency + (k2.color_frequency - k1.color_frequency) * t as f32,
+		})
+	}
+
+	/// Renders every frame on the CPU to `frame_00000.png`, `frame_00001.png`... in `directory`.
+	///
+	/// The values that are not animated are taken from `base`, and `progress` is called with the number of frames written after each frame,
+	/// the rendering stops when it returns `false`.
+	pub fn render_frames(&self, base: &View, directory: impl AsRef<Path>, mut progress: impl FnMut(u32) -> bool) -> Result<(), png::EncodingError>
+	{
+		let directory = directory.as_ref();
+		std::fs::create_dir_all(directory)?;
+
+		for frame in 0..self.frame_count()
+		{
+			let mut view = *base;
+			if let Some(keyframe) = self.sample(self.frame_time(frame))
+			{
+				keyframe.apply_to_view(&mut view);
+			}
+
+			let data = headless::render(&view);
+			headless::save_png(directory.join(format!("frame_{frame:05}.png")), view.size, &data)?;
+
+			if !progress(frame + 1)
+			{
+				break;
+			}
+		}
+
+		Ok(())
+	}
+}
+
+// Uniform Catmull-Rom spline between points[1] and points[2]
+fn catmull_rom(points: [DVec2; 4], t: f64) -> DVec2
+{
+	let [p0, p1, p2, p3] = points;
+	let t2 = t * t;
+	let t3 = t2 * t;
+
+	0.5 * (2.0 * p1
+		+ (p2 - p0) * t
+		+ (2.0 * p0 - 5.0 * p1 + 4.0 * p2 - p3) * t2
+		+ (3.0 * p1 - p0 - 3.0 * p2 + p3) * t3)
+}
+
+/// Renders an animation in a background thread
+pub struct AnimationRender
+{
+	frame_count: u32,
+	rendered_frames: Arc<AtomicU32>,
+	cancelled: Arc<AtomicBool>,
+	thread: JoinHandle<Result<(), png::EncodingError>>,
+}
+
+impl AnimationRender
+{
+	pub fn start(animation: Animation, base: View, directory: PathBuf) -> Self
+	{
+		let frame_count = animation.frame_count();
+		let rendered_frames = Arc::new(AtomicU32::new(0));
+		let cancelled = Arc::new(AtomicBool::new(false));
+
+		let thread = std::thread::spawn(
+		{
+			let rendered_frames = rendered_frames.clone();
+			let cancelled = cancelled.clone();
+			move || animation.render_frames(&base, directory, |written|
+			{
+				rendered_frames.store(written, Ordering::Relaxed);
+				!cancelled.load(Ordering::Relaxed)
+			})
+		});
+
+		Self
+		{
+			frame_count,
+			rendered_frames,
+			cancelled,
+			thread,
+		}
+	}
+
+	pub fn frame_count(&self) -> u32
+	{
+		self.frame_count
+	}
+
+	/// Number of frames already written
+	pub fn rendered_frames(&self) -> u32
+	{
+		self.rendered_frames.load(Ordering::Relaxed)
+	}
+
+	/// Stops the rendering after the current frame
+	pub fn cancel(&self)
+	{
+		self.cancelled.store(true, Ordering::Relaxed);
+	}
+
+	pub fn is_finished(&self) -> bool
+	{
+		self.thread.is_finished()
+	}
+
+	/// Waits for the end of the rendering
+	pub fn join(self) -> Result<(), png::EncodingError>
+	{
+		self.thread.join().expect("The animation rendering thread panicked")
+	}
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+	use glam::uvec2;
+	use shared::formula::Formula64;
+	use shared::fractal::FractalParams64;
+	use shared::gradient::Gradient;
+	use shared::math::DoubleDouble;
+
+	fn keyframe(time: f64, x: f64, y: f64, zoom: f64, iteration_limit: u32) -> Keyframe
+	{
+		Keyframe
+		{
+			time,
+			center: DDVec2::new(DoubleDouble::from(x), DoubleDouble::from(y)),
+			zoom,
+			julia_constant: Complex64::new(x * 0.5, y * 0.5),
+			iteration_limit,
+			color_frequency: time as f32 + 1.0,
+		}
+	}
+
+	fn animation(keyframes: &[Keyframe]) -> Animation
+	{
+		let mut animation = Animation::default();
+		for &keyframe in keyframes
+		{
+			animation.insert(keyframe);
+		}
+		animation
+	}
+
+	fn assert_matches(sample: &Keyframe, keyframe: &Keyframe)
+	{
+		assert!((sample.center.x - keyframe.center.x).hi().abs() < 1e-15);
+		assert!((sample.center.y - keyframe.center.y).hi().abs() < 1e-15);
+		assert!((sample.zoom / keyframe.zoom - 1.0).abs() < 1e-12);
+		assert!(sample.julia_constant.fuzzy_eq(keyframe.julia_constant, 1e-15));
+		assert_eq!(sample.iteration_limit, keyframe.iteration_limit);
+		assert!((sample.color_frequency - keyframe.color_frequency).abs() < 1e-6);
+	}
+
+	#[test]
+	fn sample_returns_the_keyframes_at_their_times()
+	{
+		let keyframes = [
+			keyframe(0.0, 0.0, 0.0, 1.0, 100),
+			keyframe(1.0, -0.5, 0.25, 1e-3, 500),
+			keyframe(3.0, -0.75, 0.1, 1e-10, 2000),
+			keyframe(4.5, -0.7499, 0.1001, 1e-20, 5000),
+		];
+		let animation = animation(&keyframes);
+
+		for keyframe in &keyframes
+		{
+			let sample = animation.sample(keyframe.time).unwrap();
+			assert_eq!(sample.time, keyframe.time);
+			assert_matches(&sample, keyframe);
+		}
+
+		// Clamped to the timeline
+		assert_matches(&animation.sample(-1.0).unwrap(), &keyframes[0]);
+		assert_matches(&animation.sample(10.0).unwrap(), &keyframes[3]);
+	}
+
+	#[test]
+	fn sample_zooms_monotonically_in_log_space()
+	{
+		let animation = animation(&[
+			keyframe(0.0, 0.0, 0.0, 1.0, 100),
+			keyframe(2.0, -0.5, 0.25, 1e-6, 500),
+			keyframe(3.0, -0.75, 0.1, 1e-8, 2000),
+			keyframe(5.0, -0.75, 0.1, 1e-2, 200),
+		]);
+
+		let log_zoom = |time: f64| animation.sample(time).unwrap().zoom.ln();
+		let steps = 100;
+		for (start, end) in [(0.0, 2.0), (2.0, 3.0), (3.0, 5.0)]
+		{
+			let (start_zoom, end_zoom) = (log_zoom(start), log_zoom(end));
+			let mut previous = start_zoom;
+			for step in 1..=steps
+			{
+				let zoom = log_zoom(start + (end - start) * step as f64 / steps as f64);
+				assert!((zoom - previous) * (end_zoom - start_zoom) >= 0.0, "the zoom changes direction between {start} and {end}");
+				// Linear in log space
+				let expected = start_zoom + (end_zoom - start_zoom) * step as f64 / steps as f64;
+				assert!((zoom - expected).abs() < 1e-9);
+				previous = zoom;
+			}
+		}
+	}
+
+	#[test]
+	fn sample_without_keyframes()
+	{
+		let animation = Animation::default();
+		assert!(animation.sample(0.0).is_none());
+		assert_eq!(animation.frame_count(), 0);
+	}
+
+	#[test]
+	fn sample_with_one_keyframe()
+	{
+		let only = keyframe(2.0, -0.5, 0.25, 1e-3, 500);
+		let animation = animation(&[only]);
+
+		for time in [0.0, 2.0, 7.5]
+		{
+			let sample = animation.sample(time).unwrap();
+			assert_eq!(sample.time, time);
+			assert_matches(&sample, &only);
+		}
+		assert_eq!(animation.frame_count(), 1);
+	}
+
+	fn test_view() -> View
+	{
+		View
+		{
+			center: DDVec2::default(),
+			zoom: 1.0,
+			size: uvec2(4, 3),
+			fractal: FractalParams64::default(),
+			formula: Formula64::default(),
+			gradient: Gradient::default(),
+			precision: CpuPrecision::Double,
+		}
+	}
+
+	#[test]
+	fn render_counts_the_written_frames()
+	{
+		let mut animation = animation(&[keyframe(0.0, 0.0, 0.0, 1.0, 50), keyframe(1.0, -0.5, 0.0, 0.5, 50)]);
+		animation.frame_rate = 2;
+		let directory = std::env::temp_dir().join(format!("fractal_animation_test_{}", std::process::id()));
+
+		let mut progress = Vec::new();
+		animation.render_frames(&test_view(), &directory, |written|
+		{
+			assert!(directory.join(format!("frame_{:05}.png", written - 1)).exists());
+			progress.push(written);
+			written < 2
+		}).unwrap();
+		assert_eq!(progress, [1, 2]);
+		assert!(!directory.join("frame_00002.png").exists());
+
+		let render = AnimationRender::start(animation, test_view(), directory.clone());
+		while !render.is_finished()
+		{
+			std::thread::sleep(std::time::Duration::from_millis(1));
+		}
+		assert_eq!(render.rendered_frames(), 3);
+		assert_eq!(render.rendered_frames(), render.frame_count());
+		render.join().unwrap();
+
+		std::fs::remove_dir_all(directory).unwrap();
+	}
+}
@@ -1,11 +1,15 @@
-use std::collections::BTreeMap;
 use std::fmt::Debug;
 
 use egui::InnerResponse;
-use fractal_renderer_shared::fractal::ColorPalette;
+use fractal_renderer_shared::fractal::{ColorMapping, ColorPalette, InteriorColoring, OrbitTrap, OrbitTrapColoring, OrbitTrapShape};
 use fractal_renderer_shared as shared;
 use shared::math::*;
 use shared::fractal::{FractalKind, FractalVariation, RenderTechnique};
+use shared::fractal::escape_time_method::SeriesApproximation;
+use shared::fractal::newton::{self, Polynomial};
+use shared::formula::Formula64;
+use shared::gradient::{Gradient, GradientInterpolation, GradientStop, MAX_GRADIENT_STOPS};
+use shared::histogram::{CellHistogram, Histogram};
 use glam::{dvec2, DVec2, i64vec2};
 use winit::application::ApplicationHandler;
 use winit::dpi::{PhysicalPosition, PhysicalSize};
@@ -13,19 +17,27 @@ use winit::event::{ElementState, KeyEvent, MouseButton, MouseScrollDelta, TouchP
 use winit::keyboard::{KeyCode, PhysicalKey};
 use winit::window::WindowAttributes;
 
+use crate::deep_zoom::{DeepZoom, FixedVec2, ReferenceOrbit};
+use crate::scene::{Scene, SCENE_VERSION};
+use crate::animation::Animation;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::animation::{AnimationRender, Keyframe};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::cell_cache::{CellCache, CellHasher, CellReadbacks};
 use crate::gui::EguiRenderer;
 use crate::{Target, render};
 use crate::quad_cell::QuadPos;
-use crate::compute::Compute;
-use crate::render::Render;
+use crate::cell_store::CellStore;
+use crate::compute::{AnyCompute, CellResolution, Compute, ComputeBackend, CpuPrecision};
+use crate::render::{CellFormat, Render};
 
 const VERTEX32_SHADER_CODE: &[u8] = include_bytes!(env!("fractal_renderer_shader_vertex32.spv"));
 const VERTEX64_SHADER_CODE: &[u8] = include_bytes!(env!("fractal_renderer_shader_vertex64.spv"));
 const FRAGMENT_SHADER_CODE: &[u8] = include_bytes!(env!("fractal_renderer_shader_fragment.spv"));
-const COMPUTE32_SHADER_CODE: &[u8] = include_bytes!(env!("fractal_renderer_shader_computation32.spv")); // fractal_renderer_shader_compute32.spv
-const COMPUTE64_SHADER_CODE: &[u8] = include_bytes!(env!("fractal_renderer_shader_computation64.spv")); // fractal_renderer_shader_compute64.spv
 
-type ShaderCompute = crate::compute::ShaderRenderCompute;
+const MEGABYTE: u64 = 1 << 20;
+/// GPU memory the cell textures can use before the least useful cells are evicted
+const DEFAULT_CELL_MEMORY_BUDGET: u64 = 512 * MEGABYTE;
 
 pub struct AppWrapper<Init, C>
 {
@@ -147,9 +159,12 @@ pub struct App<C>
 	app_data: AppData,
 	mouse_left_down: bool,
 	mouse_right_down: bool,
+	/// Last view written in the page URL, so that it is only replaced when the view changes
+	#[cfg(target_arch = "wasm32")]
+	url_fragment: String,
 }
 
-impl App<ShaderCompute>
+impl App<AnyCompute>
 {
 	pub async fn build(device_limits: wgpu::Limits, window: winit::window::Window) -> Self
 	{
@@ -157,15 +172,14 @@ impl App<ShaderCompute>
 		
 		let use_double_precision = target.device.features().contains(wgpu::Features::SHADER_F64);
 
-		let (vertex_shader_code, fragment_shader_code, compute_shader_code) =
-			if use_double_precision
-			{
-				(VERTEX64_SHADER_CODE, FRAGMENT_SHADER_CODE, COMPUTE64_SHADER_CODE)
-			}
-			else
-			{
-				(VERTEX32_SHADER_CODE, FRAGMENT_SHADER_CODE, COMPUTE32_SHADER_CODE)
-			};
+		let vertex_shader_code = if use_double_precision
+		{
+			VERTEX64_SHADER_CODE
+		}
+		else
+		{
+			VERTEX32_SHADER_CODE
+		};
 		
 		let vertex_shader_module = target.device.create_shader_module(
 			wgpu::ShaderModuleDescriptor
@@ -178,36 +192,11 @@ impl App<ShaderCompute>
 			wgpu::ShaderModuleDescriptor
 			{
 				label: Some("fragment_shader"),
-				source: wgpu::util::make_spirv(fragment_shader_code),
+				source: wgpu::util::make_spirv(FRAGMENT_SHADER_CODE),
 			});
-		
-		let compute_shader_module = target.device.create_shader_module(
-			wgpu::ShaderModuleDescriptor
-			{
-				label: Some("compute_shader"),
-				source: wgpu::util::make_spirv(compute_shader_code),
-			});
-
-		/*let (cell_size, compute) = if target.supports_compute_shader
-		{
-			let cell_size = PhysicalSize::new(256, 256);
-
-			let workgroup_size = glam::uvec2(16, 16);
-			let compute = crate::compute::ShaderCompute::new(&target, &compute_shader_module, workgroup_size, cell_size, use_double_precision);
-
-			(cell_size, AnyCompute::Shader(compute))
-		}
-		else
-		{
-			let cell_size = PhysicalSize::new(32, 32);
-			
-			let compute = crate::compute::ThreadedCompute::new(cell_size);
-			
-			(cell_size, AnyCompute::Threaded(compute))
-		};*/
 
 		let cell_size = PhysicalSize::new(256, 256);
-		let compute = ShaderCompute::new(&target, &compute_shader_module, &compute_shader_module, cell_size, use_double_precision);
+		let compute = AnyCompute::new(&target, ComputeBackend::default_for(&target), cell_size, CellFormat::Color);
 
 		let render = Render::new(&target, &vertex_shader_module, &fragment_shader_module, cell_size, use_double_precision);
 		
@@ -222,18 +211,31 @@ impl<C: Compute> App<C>
 	{
 		let gui = EguiRenderer::new(&target);
 		let screen_size = target.window.inner_size();
+		let mut app_data = AppData::new(cell_size, screen_size);
+		app_data.supports_deep_zoom = compute.supports_perturbation();
+		app_data.compute_backend = compute.backend();
+		app_data.supported_compute_backends = ComputeBackend::ALL.into_iter().filter(|backend| backend.is_supported(&target)).collect();
+		app_data.supports_escape_data = target.supports_escape_data;
 		Self
 		{
 			target,
 			gui,
 			render,
 			compute,
-			app_data: AppData::new(cell_size, screen_size),
+			app_data,
 			mouse_left_down: false,
 			mouse_right_down: false,
+			#[cfg(target_arch = "wasm32")]
+			url_fragment: String::new(),
 		}
 	}
 
+	/// Restores the view saved in a scene file
+	pub fn load_scene(&mut self, scene: &Scene)
+	{
+		self.app_data.load_scene(scene);
+	}
+
     fn resize(&mut self, new_size: PhysicalSize<u32>)
 	{
 		self.target.resize(new_size);
@@ -251,7 +253,19 @@ impl<C: Compute> App<C>
 				world_to_view_scale: scale,
 			});
 
-		self.render.make_render_pass(self.app_data.cells.values(), &view, commands);
+		if self.app_data.cell_format() == CellFormat::EscapeData && self.app_data.color_mapping == ColorMapping::Histogram
+		{
+			self.app_data.update_histogram();
+			self.render.set_histogram(&self.target.queue, &self.app_data.histogram);
+		}
+		let cell_texture_size = self.app_data.cell_texture_size();
+		self.render.set_coloring(&self.target.queue, &shared::render::ColoringUniforms::new(
+			self.app_data.color_params(),
+			glam::uvec2(cell_texture_size.width, cell_texture_size.height),
+			self.app_data.histogram.key_range()),
+			&self.app_data.gradient);
+
+		self.render.make_render_pass(self.app_data.cells.instances(), &view, commands);
 
 		self.gui.draw(&self.target, commands, &view, |ui| {self.app_data.gui(ui);});
 	}
@@ -260,12 +274,19 @@ impl<C: Compute> App<C>
 	{
 		self.app_data.require_redraw = false;
 
-		// Free cells that are far away
-		self.app_data.cleanup_cells();
+		// Free the least useful cells above the memory budget
+		self.app_data.evict_cells();
+
+		self.app_data.update_deep_zoom();
+
+		#[cfg(not(target_arch = "wasm32"))]
+		self.app_data.load_cached_cells(&self.target, &self.render);
 
 		let mut commands = self.target.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
 
 		self.compute.update_before_render(&self.target, &self.render, &mut self.app_data, &mut commands);
+		#[cfg(not(target_arch = "wasm32"))]
+		self.app_data.update_render_duration();
 		
 		let output = self.target.surface.get_current_texture()?;
 
@@ -275,9 +296,27 @@ impl<C: Compute> App<C>
 		self.target.queue.submit(std::iter::once(commands.finish()));
 		output.present();
 
+		#[cfg(not(target_arch = "wasm32"))]
+		self.app_data.save_computed_cells(&self.target);
+
+		#[cfg(target_arch = "wasm32")]
+		self.update_url_fragment();
+
 		Ok(())
 	}
 
+	/// Keeps the page URL in sync with the view, so that it can be shared
+	#[cfg(target_arch = "wasm32")]
+	fn update_url_fragment(&mut self)
+	{
+		let fragment = crate::url_fragment::encode(&self.app_data.scene());
+		if fragment != self.url_fragment
+		{
+			crate::wasm::replace_url_fragment(&fragment);
+			self.url_fragment = fragment;
+		}
+	}
+
 	fn window_event(
 		&mut self,
 		event_loop: &winit::event_loop::ActiveEventLoop,
@@ -337,7 +376,7 @@ impl<C: Compute> App<C>
 				{
 					KeyCode::Escape => event_loop.exit(),
 					KeyCode::KeyM => self.app_data.set_fractal_kind(FractalKind::MandelbrotSet),
-					KeyCode::Comma | KeyCode::Digit3 | KeyCode::Numpad3 => self.app_data.set_fractal_kind(FractalKind::Multibrot3),
+					KeyCode::Comma | KeyCode::Digit3 | KeyCode::Numpad3 => self.app_data.set_fractal_kind(FractalKind::Multibrot),
 					KeyCode::KeyT => self.app_data.set_fractal_kind(FractalKind::Tricorn),
 					KeyCode::KeyS => self.app_data.set_fractal_kind(FractalKind::BurningShip),
 					KeyCode::KeyC => self.app_data.set_fractal_kind(FractalKind::CosLeaf),
@@ -350,17 +389,19 @@ impl<C: Compute> App<C>
 							FractalVariation::Normal => FractalVariation::JuliaSet,
 							FractalVariation::JuliaSet => FractalVariation::Normal,
 						});
-						(self.app_data.pos, self.app_data.fractal_params.secondary_pos) = (self.app_data.fractal_params.secondary_pos.to_vector(), Complex64::from_vector(self.app_data.pos));
-						(self.app_data.zoom, self.app_data.secondary_zoom) = (self.app_data.secondary_zoom, self.app_data.zoom);
+						self.app_data.swap_primary_and_secondary();
 					},
 					KeyCode::KeyO =>
 					{
 						self.app_data.set_fractal_rendering(match self.app_data.fractal_params.render_technique
 						{
-							RenderTechnique::Normal => RenderTechnique::OrbitTrapPoint,
-							RenderTechnique::OrbitTrapPoint => RenderTechnique::OrbitTrapCross,
-							RenderTechnique::OrbitTrapCross => RenderTechnique::NormalMap,
-							RenderTechnique::NormalMap => RenderTechnique::Normal,
+							RenderTechnique::Normal => RenderTechnique::NormalMap,
+							RenderTechnique::NormalMap => RenderTechnique::DistanceEstimate,
+							RenderTechnique::DistanceEstimate => RenderTechnique::StripeAverage,
+							RenderTechnique::StripeAverage => RenderTechnique::CurvatureAverage,
+							RenderTechnique::CurvatureAverage => RenderTechnique::TriangleInequalityAverage,
+							RenderTechnique::TriangleInequalityAverage => RenderTechnique::OrbitTrap,
+							RenderTechnique::OrbitTrap => RenderTechnique::Normal,
 						});
 					},
 					KeyCode::KeyR =>
@@ -553,18 +594,159 @@ impl<C: Compute> App<C>
 	}
 }
 
+/// Rendering options of the animation window
+#[cfg(not(target_arch = "wasm32"))]
+struct AnimationSettings
+{
+	directory: String,
+	size: [u32; 2],
+	preview_time: f64,
+	render: Option<AnimationRender>,
+	/// Result of the last rendering
+	status: Option<String>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Default for AnimationSettings
+{
+	fn default() -> Self
+	{
+		Self
+		{
+			directory: String::from("frames"),
+			size: [1920, 1080],
+			preview_time: 0.0,
+			render: None,
+			status: None,
+		}
+	}
+}
+
+/// Cache of the computed cells on the disk
+#[cfg(not(target_arch = "wasm32"))]
+struct DiskCacheSettings
+{
+	enabled: bool,
+	directory: String,
+	/// Size limit in megabytes
+	max_size: u64,
+	/// Opened when the cache is first used
+	cache: Option<CellCache>,
+	readbacks: CellReadbacks,
+	/// Cells computed during the current frame, they are saved once the frame is submitted
+	computed_cells: Vec<QuadPos>,
+	/// Error of the last cache operation
+	status: Option<String>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl DiskCacheSettings
+{
+	/// The cache, opened if needed, or `None` when it is disabled or couldn't be opened
+	fn open(&mut self) -> Option<&mut CellCache>
+	{
+		if !self.enabled
+		{
+			return None;
+		}
+
+		if self.cache.is_none()
+		{
+			match CellCache::open(&self.directory, self.max_size * MEGABYTE)
+			{
+				Ok(cache) => self.cache = Some(cache),
+				Err(error) =>
+				{
+					self.status = Some(format!("{}: {error}", self.directory));
+					self.enabled = false;
+					return None;
+				},
+			}
+		}
+
+		self.cache.as_mut()
+	}
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Default for DiskCacheSettings
+{
+	fn default() -> Self
+	{
+		Self
+		{
+			enabled: true,
+			directory: String::from("cell_cache"),
+			max_size: 1024,
+			cache: None,
+			readbacks: CellReadbacks::default(),
+			computed_cells: Vec::new(),
+			status: None,
+		}
+	}
+}
+
 pub struct AppData
 {
 	cell_size: u32,
 	screen_size: PhysicalSize<u32>,
-	cells: BTreeMap<QuadPos, crate::render::Instance>,
+	cells: CellStore,
     pos: DVec2,
     zoom: f64,
 	secondary_zoom: f64,
 	pub(crate) fractal_params: shared::fractal::FractalParams64,
+	/// Iteration formula of [`FractalKind::Custom`]
+	pub(crate) formula: Formula64,
+	formula_text: String,
+	formula_error: Option<String>,
+	/// Gradient of [`ColorPalette::Custom`]
+	pub(crate) gradient: Gradient,
+	/// Sequence of [`FractalKind::Lyapunov`] as written by the user
+	lyapunov_sequence_text: String,
+	lyapunov_sequence_error: Option<String>,
+	/// Path of the scene file to save or open from the GUI
+	#[cfg(not(target_arch = "wasm32"))]
+	scene_path: String,
+	/// Result of the last scene file operation
+	#[cfg(not(target_arch = "wasm32"))]
+	scene_status: Option<String>,
+	/// Keyframes of the zoom animation
+	pub(crate) animation: Animation,
+	#[cfg(not(target_arch = "wasm32"))]
+	animation_settings: AnimationSettings,
 	prev_mouse_pos: Option<PhysicalPosition<f64>>,
 	prev_touch_pos: [Option<(u64, PhysicalPosition<f64>)>; 2],
 	require_redraw: bool,
+	/// When enabled, `pos` is relative to the high precision origin of the deep zoom
+	deep_zoom: Option<DeepZoom>,
+	supports_deep_zoom: bool,
+	/// Skip the iterations shared by every pixel of a cell when the deep zoom is enabled
+	use_series_approximation: bool,
+	/// Number of iterations skipped by the series approximation for the last computed cell
+	skipped_iterations: u32,
+	/// Incremented every time the loaded cells are discarded
+	cells_generation: u64,
+	compute_backend: ComputeBackend,
+	supported_compute_backends: Vec<ComputeBackend>,
+	/// Backend selected in the GUI, replaces the current one before computing the next cells
+	requested_compute_backend: Option<ComputeBackend>,
+	/// Scalar of the CPU backend, the double-double reaches deeper zooms than the double without perturbation
+	cpu_precision: CpuPrecision,
+	/// Store the escape data of the cells and color them when they are drawn, so that changing the colors doesn't compute them again
+	display_coloring: bool,
+	supports_escape_data: bool,
+	/// Only used when the cells are colored when drawn
+	color_mapping: ColorMapping,
+	/// Distribution of the escape values of the visible cells, for [`ColorMapping::Histogram`]
+	histogram: Histogram,
+	/// Time at which the cells were discarded, until every visible cell is loaded
+	#[cfg(not(target_arch = "wasm32"))]
+	render_start: Option<std::time::Instant>,
+	/// Time taken to load every visible cell the last time they were discarded
+	#[cfg(not(target_arch = "wasm32"))]
+	render_duration: Option<std::time::Duration>,
+	#[cfg(not(target_arch = "wasm32"))]
+	disk_cache: DiskCacheSettings,
 }
 
 impl AppData
@@ -575,14 +757,46 @@ impl AppData
 		{
 			cell_size: cell_size.width.min(cell_size.height),
 			screen_size: PhysicalSize::default(),
-			cells: BTreeMap::new(),
+			cells: CellStore::new(DEFAULT_CELL_MEMORY_BUDGET),
 			pos: DVec2::ZERO,
 			zoom: 1.0,
 			secondary_zoom: 1.0,
 			fractal_params: Default::default(),
+			formula: Formula64::default(),
+			formula_text: String::from("z^2 + c"),
+			formula_error: None,
+			gradient: Gradient::default(),
+			lyapunov_sequence_text: String::from("AB"),
+			lyapunov_sequence_error: None,
+			#[cfg(not(target_arch = "wasm32"))]
+			scene_path: String::from("scene.json"),
+			#[cfg(not(target_arch = "wasm32"))]
+			scene_status: None,
+			animation: Animation::default(),
+			#[cfg(not(target_arch = "wasm32"))]
+			animation_settings: AnimationSettings::default(),
 			prev_mouse_pos: None,
 			prev_touch_pos: [None, None],
 			require_redraw: false,
+			deep_zoom: None,
+			supports_deep_zoom: false,
+			use_series_approximation: true,
+			skipped_iterations: 0,
+			cells_generation: 0,
+			compute_backend: ComputeBackend::RenderPass,
+			supported_compute_backends: Vec::new(),
+			requested_compute_backend: None,
+			cpu_precision: CpuPrecision::Double,
+			display_coloring: false,
+			supports_escape_data: false,
+			color_mapping: ColorMapping::Logarithmic,
+			histogram: Histogram::default(),
+			#[cfg(not(target_arch = "wasm32"))]
+			render_start: None,
+			#[cfg(not(target_arch = "wasm32"))]
+			render_duration: None,
+			#[cfg(not(target_arch = "wasm32"))]
+			disk_cache: DiskCacheSettings::default(),
 		};
 
 		this.resize(screen_size);
@@ -601,8 +815,12 @@ impl AppData
 
 	fn reset(&mut self)
 	{
-		self.cells = BTreeMap::new();
+		self.clear_cells();
 		self.pos = DVec2::ZERO;
+		if let Some(deep_zoom) = &mut self.deep_zoom
+		{
+			*deep_zoom = DeepZoom::default();
+		}
 		self.zoom = 1.0;
 		self.fractal_params.secondary_pos = Complex64::ZERO;
 		self.fractal_params.iteration_limit = self.fractal_params.fractal_kind.default_iteration_limit();
@@ -621,12 +839,167 @@ impl AppData
 		self.require_redraw = true;
 	}
 
-	fn reset_fractal_rendering(&mut self)
+	/// Discards every cell, the cells being computed become outdated
+	fn clear_cells(&mut self)
 	{
 		self.cells.clear();
+		self.cells_generation += 1;
+		#[cfg(not(target_arch = "wasm32"))]
+		{
+			self.render_start = Some(std::time::Instant::now());
+		}
+	}
+
+	/// Stops the render timer once every visible cell is loaded at full resolution
+	#[cfg(not(target_arch = "wasm32"))]
+	fn update_render_duration(&mut self)
+	{
+		if let Some(start) = self.render_start
+		{
+			if self.visible_cells().all(|pos| self.is_cell_loaded(pos) && !self.is_cell_preview(pos))
+			{
+				self.render_duration = Some(start.elapsed());
+				self.render_start = None;
+			}
+		}
+	}
+
+	pub fn cell_texture_size(&self) -> PhysicalSize<u32>
+	{
+		PhysicalSize::new(self.cell_size, self.cell_size)
+	}
+
+	/// The backend selected in the GUI, if it is different from the current one
+	pub fn take_requested_compute_backend(&mut self) -> Option<ComputeBackend>
+	{
+		self.requested_compute_backend.take().filter(|backend| *backend != self.compute_backend)
+	}
+
+	pub fn cpu_precision(&self) -> CpuPrecision
+	{
+		self.cpu_precision
+	}
+
+	/// Called after the backend was replaced, the cells are computed again
+	pub fn set_compute_backend(&mut self, backend: ComputeBackend, supports_perturbation: bool)
+	{
+		self.compute_backend = backend;
+		self.supports_deep_zoom = supports_perturbation;
+		if !self.can_deep_zoom()
+		{
+			self.set_deep_zoom(false);
+		}
+		self.reset_fractal_rendering();
+	}
+
+	/// Incremented every time the loaded cells are discarded, cells requested before are outdated
+	pub fn cells_generation(&self) -> u64
+	{
+		self.cells_generation
+	}
+
+	pub fn request_redraw(&mut self)
+	{
+		self.require_redraw = true;
+	}
+
+	fn reset_fractal_rendering(&mut self)
+	{
+		self.clear_cells();
+		if let Some(deep_zoom) = &mut self.deep_zoom
+		{
+			deep_zoom.invalidate_reference();
+		}
 		self.require_redraw = true;
 	}
 
+	/// The position of the center of the view, including the deep zoom origin
+	fn absolute_pos(&self) -> DVec2
+	{
+		match &self.deep_zoom
+		{
+			Some(deep_zoom) => deep_zoom.origin() + self.pos,
+			None => self.pos,
+		}
+	}
+
+	/// Swaps the primary and secondary position and zoom, used when switching between the normal and julia variations
+	fn swap_primary_and_secondary(&mut self)
+	{
+		let pos = self.absolute_pos();
+		if self.deep_zoom.is_some()
+		{
+			self.deep_zoom = Some(DeepZoom::default());
+		}
+
+		(self.pos, self.fractal_params.secondary_pos) = (self.fractal_params.secondary_pos.to_vector(), Complex64::from_vector(pos));
+		(self.zoom, self.secondary_zoom) = (self.secondary_zoom, self.zoom);
+	}
+
+	fn can_deep_zoom(&self) -> bool
+	{
+		self.supports_deep_zoom && shared::fractal::perturbation::supports_perturbation(&self.fractal_params)
+	}
+
+	fn set_deep_zoom(&mut self, enabled: bool)
+	{
+		if enabled == self.deep_zoom.is_some() || (enabled && !self.can_deep_zoom())
+		{
+			return;
+		}
+
+		self.pos = self.absolute_pos();
+		self.deep_zoom = enabled.then(DeepZoom::default);
+		self.reset_fractal_rendering();
+	}
+
+	fn update_deep_zoom(&mut self)
+	{
+		let pixel_world_size = self.pixel_world_size();
+		let viewport_world_size = self.viewport_world_size();
+		let Some(deep_zoom) = &mut self.deep_zoom
+		else
+		{
+			return;
+		};
+
+		if deep_zoom.needs_recenter(self.pos, pixel_world_size)
+		{
+			deep_zoom.recenter(self.pos);
+			self.pos = DVec2::ZERO;
+			self.cells.clear();
+			self.cells_generation += 1;
+		}
+
+		deep_zoom.update_reference(self.pos, pixel_world_size, viewport_world_size, &self.fractal_params);
+	}
+
+	/// The reference orbit to use to compute cells when the deep zoom is enabled
+	pub fn reference_orbit(&self) -> Option<&ReferenceOrbit>
+	{
+		self.deep_zoom.as_ref().and_then(DeepZoom::reference)
+	}
+
+	/// The series approximation to compute the cell between `min_pos` and `max_pos`, which are relative to the reference point
+	pub fn series_approximation(&mut self, min_pos: DVec2, max_pos: DVec2) -> SeriesApproximation<f64>
+	{
+		let pixel_world_size = (max_pos.x - min_pos.x).abs() / self.cell_size as f64;
+		let series = match self.reference_orbit()
+		{
+			Some(reference) if self.use_series_approximation => reference.series_approximation(min_pos, max_pos, pixel_world_size, &self.fractal_params),
+			_ => SeriesApproximation::none(),
+		};
+
+		self.skipped_iterations = series.skipped_iterations;
+		series
+	}
+
+	/// The origin that cell positions are relative to when the deep zoom is enabled
+	pub fn deep_zoom_origin(&self) -> Option<DDVec2>
+	{
+		self.deep_zoom.as_ref().map(DeepZoom::high_precision_origin)
+	}
+
 	fn set_fractal_kind(&mut self, fractal_kind: FractalKind)
 	{
 		if self.fractal_params.fractal_kind == fractal_kind
@@ -637,6 +1010,10 @@ impl AppData
 		self.fractal_params.fractal_kind = fractal_kind;
 		
 		self.fractal_params.iteration_limit = self.fractal_params.fractal_kind.default_iteration_limit();
+		if !self.can_deep_zoom()
+		{
+			self.set_deep_zoom(false);
+		}
 		self.reset_fractal_rendering();
 	}
 
@@ -661,6 +1038,10 @@ impl AppData
 
 		self.fractal_params.render_technique = rendering_technique;
 		
+		if !self.can_deep_zoom()
+		{
+			self.set_deep_zoom(false);
+		}
 		self.reset_fractal_rendering();
 	}
 
@@ -673,7 +1054,68 @@ impl AppData
 
 		self.fractal_params.color_palette = color_palette;
 		
-		self.reset_fractal_rendering();
+		self.reset_colors();
+	}
+
+	/// The cells only need to be computed again when they store their colors
+	fn reset_colors(&mut self)
+	{
+		match self.cell_format()
+		{
+			CellFormat::Color => self.reset_fractal_rendering(),
+			CellFormat::EscapeData => self.require_redraw = true,
+		}
+	}
+
+	/// The parameters used to color the cells that store escape data
+	fn color_params(&self) -> shared::fractal::ColorParams
+	{
+		let mut params = self.fractal_params.color_params();
+		params.color_mapping = self.color_mapping;
+		params
+	}
+
+	/// Merges the histograms of the visible cells, the previous histogram is kept until a visible cell has one
+	fn update_histogram(&mut self)
+	{
+		let cell_histograms: Vec<&CellHistogram> = self.visible_cells()
+			.filter_map(|pos| self.cells.histogram(pos))
+			.filter(|histogram| !histogram.is_empty())
+			.collect();
+		if !cell_histograms.is_empty()
+		{
+			self.histogram = Histogram::merge(&cell_histograms);
+		}
+	}
+
+	/// Sets the histogram collected by the backend for the cell computed at `pos` with `resolution`, ignored if the cell was replaced since
+	pub fn set_cell_histogram(&mut self, pos: QuadPos, generation: u64, resolution: CellResolution, histogram: CellHistogram)
+	{
+		if generation != self.cells_generation || !self.cells.contains(pos) || self.cells.is_preview(pos) != (resolution == CellResolution::Preview)
+		{
+			return;
+		}
+
+		self.cells.set_histogram(pos, histogram);
+		if self.color_mapping == ColorMapping::Histogram
+		{
+			self.require_redraw = true;
+		}
+	}
+
+	/// What the cells store, the escape data can only be colored when drawn for the escape time fractals
+	pub fn cell_format(&self) -> CellFormat
+	{
+		// The escape data has no room for the normals of the lit techniques
+		let lit = self.fractal_params.lighting.lights(self.fractal_params.render_technique);
+		if self.display_coloring && self.supports_escape_data && self.fractal_params.fractal_kind.is_escape_time() && !lit
+		{
+			CellFormat::EscapeData
+		}
+		else
+		{
+			CellFormat::Color
+		}
 	}
 	
 	fn base_pixel_world_size(&self) -> f64
@@ -699,16 +1141,10 @@ impl AppData
 		(min_exponent, max_exponent)
 	}
 
-	fn cleanup_cells(&mut self)
+	fn evict_cells(&mut self)
 	{
-		let viewport_size = self.viewport_world_size();
-		let exponent_range = self.exponent_range();
-
-		let valid_exponents = (exponent_range.0 - 4) ..= (exponent_range.1 + 4);
-		let valid_pos_min = self.pos - viewport_size * 2.0;
-		let valid_pos_max = self.pos + viewport_size * 2.0;
-
-		self.cells.retain(|pos, _cell| valid_exponents.contains(&pos.exponent) && pos.cell_bottom_left().cmplt(valid_pos_max).all() && pos.cell_top_right().cmpgt(valid_pos_min).all());
+		self.cells.mark_visible(self.visible_cells());
+		self.cells.evict(self.pos, self.viewport_world_size(), self.exponent_range().0);
 	}
 
 	pub fn visible_cells(&self) -> impl Iterator<Item = QuadPos>
@@ -738,29 +1174,160 @@ impl AppData
 		})
 	}
 
+	/// Whether the cell is shown, at full resolution or as a preview
 	pub fn is_cell_loaded(&self, pos: QuadPos) -> bool
 	{
-		self.cells.contains_key(&pos)
+		self.cells.contains(pos)
+	}
+
+	/// Whether the cell is shown as a preview, until it is computed at full resolution
+	pub fn is_cell_preview(&self, pos: QuadPos) -> bool
+	{
+		self.cells.is_preview(pos)
+	}
+
+	/// Compiles the formula text, returns true if it changed the formula
+	fn update_formula(&mut self) -> bool
+	{
+		match self.formula_text.parse()
+		{
+			Ok(formula) =>
+			{
+				self.formula = formula;
+				self.formula_error = None;
+				true
+			},
+			Err(error) =>
+			{
+				self.formula_error = Some(format!("{error}"));
+				false
+			},
+		}
+	}
+
+	/// Parses the lyapunov sequence text, returns true if it changed the sequence
+	fn update_lyapunov_sequence(&mut self) -> bool
+	{
+		match self.lyapunov_sequence_text.parse()
+		{
+			Ok(sequence) =>
+			{
+				self.fractal_params.lyapunov_sequence = sequence;
+				self.lyapunov_sequence_error = None;
+				true
+			},
+			Err(error) =>
+			{
+				self.lyapunov_sequence_error = Some(format!("{error}"));
+				false
+			},
+		}
+	}
+
+	/// The current view, to save it in a scene file
+	pub fn scene(&self) -> Scene
+	{
+		let center = match &self.deep_zoom
+		{
+			Some(deep_zoom) => deep_zoom.exact_origin() + self.pos,
+			None => FixedVec2::from(self.pos),
+		};
+
+		Scene
+		{
+			version: SCENE_VERSION,
+			center,
+			zoom: self.zoom,
+			secondary_zoom: self.secondary_zoom,
+			deep_zoom: self.deep_zoom.is_some(),
+			fractal: self.fractal_params,
+			formula: self.formula_text.clone(),
+			gradient: self.gradient,
+			animation: self.animation.clone(),
+		}
+	}
+
+	/// Restores the view saved in a scene file
+	pub fn load_scene(&mut self, scene: &Scene)
+	{
+		self.fractal_params = scene.fractal;
+		self.formula_text = scene.formula.clone();
+		self.update_formula();
+		self.lyapunov_sequence_text = scene.fractal.lyapunov_sequence.to_string();
+		self.lyapunov_sequence_error = None;
+		self.gradient = scene.gradient;
+		self.animation = scene.animation.clone();
+
+		self.zoom = scene.zoom;
+		self.secondary_zoom = scene.secondary_zoom;
+		self.deep_zoom = None;
+		self.pos = scene.center.to_dvec2();
+		if scene.deep_zoom && self.can_deep_zoom()
+		{
+			self.deep_zoom = Some(DeepZoom::with_origin(scene.center));
+			self.pos = DVec2::ZERO;
+		}
+
+		self.reset_fractal_rendering();
+	}
+
+	/// Moves the view to show the whole region of the lyapunov fractal
+	fn view_lyapunov_region(&mut self)
+	{
+		let (min, max) = (self.fractal_params.lyapunov_region_min, self.fractal_params.lyapunov_region_max);
+		let window_size = dvec2(self.screen_size.width as f64, self.screen_size.height as f64);
+		let zoom = (max - min) / (window_size * self.base_pixel_world_size());
+
+		self.set_deep_zoom(false);
+		self.pos = (min + max) * 0.5;
+		self.zoom = zoom.max_element();
+		self.require_redraw = true;
+	}
+
+	/// The fractal parameters to compute the cell at `pos`
+	pub fn cell_fractal_params(&self, pos: QuadPos) -> shared::fractal::FractalParams64
+	{
+		let mut params = self.fractal_params;
+		params.pixel_size = pos.cell_size() / self.cell_size as f64;
+		params
 	}
 
-	pub fn make_cell(&mut self, target: &Target, render: &Render, pos: QuadPos) -> &render::Instance
+	/// Creates the cell at `pos`, replacing its preview, the computed part of its texture is given by `resolution`
+	pub fn make_cell(&mut self, target: &Target, render: &Render, pos: QuadPos, resolution: CellResolution) -> &render::Instance
 	{
 		let cell_size = pos.cell_size();
 		let cell_pos = pos.cell_bottom_left();
 
-		let cell = render.make_instance(target);
+		let cell = render.make_instance(target, self.cell_format());
+
+		// The preview is in the corner of the texture, the texels on its border are sampled at their center so that the rest of the texture isn't blended in
+		let texture_size = self.cell_texture_size();
+		let computed_size = resolution.texture_size(texture_size);
+		let texture_size = glam::vec2(texture_size.width as f32, texture_size.height as f32);
+		let computed_size = glam::vec2(computed_size.width as f32, computed_size.height as f32);
+		let (uv_min, uv_size) = match resolution
+		{
+			CellResolution::Preview => (0.5 / texture_size, (computed_size - 1.0) / texture_size),
+			CellResolution::Full => (glam::Vec2::ZERO, glam::Vec2::ONE),
+		};
 
 		cell.set_data(&target.queue, &shared::render::Instance64
 		{
 			pos: cell_pos,
 			size: DVec2::splat(cell_size),
+			uv_min,
+			uv_size,
 		});
 
-		self.cells.insert(pos, cell);
+		#[cfg(not(target_arch = "wasm32"))]
+		if resolution == CellResolution::Full
+		{
+			self.disk_cache.computed_cells.push(pos);
+		}
 
 		self.require_redraw = true;
 
-		&self.cells[&pos]
+		self.cells.insert(pos, cell, resolution == CellResolution::Preview)
 	}
 
 	pub fn gui(&mut self, ctx: &egui::Context) -> bool
@@ -800,11 +1367,13 @@ impl AppData
 						ui.label("Fractal Kind");
 						if let Some(fractal_kind) = select_in_list(ui, &self.fractal_params.fractal_kind, [
 							(FractalKind::MandelbrotSet, "Mandelbrot Set"),
-							(FractalKind::Multibrot3, "Multibrot 3"),
+							(FractalKind::Multibrot, "Multibrot"),
 							(FractalKind::Tricorn, "Tricorn"),
 							(FractalKind::BurningShip, "Burning Ship"),
 							(FractalKind::CosLeaf, "Cos Leaf"),
+							(FractalKind::Custom, "Custom"),
 							(FractalKind::Newton3, "Newton 3"),
+							(FractalKind::NewtonPolynomial, "Newton Polynomial"),
 							(FractalKind::Lyapunov, "Lyapunov"),
 						])
 						{
@@ -812,33 +1381,193 @@ impl AppData
 							changed = true;
 						}
 						ui.end_row();
-		
-						ui.label("Variation");
-						if let Some(fractal_variation) = select_in_list(ui, &self.fractal_params.variation, [
-							(FractalVariation::Normal, "Classic"),
-							(FractalVariation::JuliaSet, "Julia Set"),
-						])
+
+						if self.fractal_params.fractal_kind == FractalKind::Multibrot
 						{
-							self.set_fractal_variation(fractal_variation);
-							// Swap primary and secondary pos/zoom
-							(self.pos, self.fractal_params.secondary_pos) = (self.fractal_params.secondary_pos.to_vector(), Complex64::from_vector(self.pos));
-							(self.zoom, self.secondary_zoom) = (self.secondary_zoom, self.zoom);
-							changed = true;
+							ui.label("Exponent");
+							ui.horizontal(|ui|
+							{
+								let exponent = &mut self.fractal_params.exponent;
+								changed |= ui.add(egui::DragValue::new(exponent.re_mut()).speed(0.01).prefix("re: ")).changed();
+								changed |= ui.add(egui::DragValue::new(exponent.im_mut()).speed(0.01).prefix("im: ")).changed();
+							});
+							ui.end_row();
+
+							// Only the integer exponents can be rendered with the perturbation
+							if !self.can_deep_zoom()
+							{
+								self.set_deep_zoom(false);
+							}
+						}
+
+						if self.fractal_params.fractal_kind == FractalKind::NewtonPolynomial
+						{
+							let mut polynomial: Polynomial<f64> = self.fractal_params.polynomial.into();
+							let mut polynomial_changed = false;
+
+							ui.label("Degree");
+							let mut degree = polynomial.degree as usize;
+							if ui.add(egui::DragValue::new(&mut degree).range(1..=newton::MAX_DEGREE)).changed()
+							{
+								// The new leading coefficient must not be 0
+								for coefficient in &mut polynomial.coefficients[degree + 1..]
+								{
+									*coefficient = Complex64::ZERO;
+								}
+								if polynomial.coefficients[degree] == Complex64::ZERO
+								{
+									polynomial.coefficients[degree] = Complex64::ONE;
+								}
+								polynomial_changed = true;
+							}
+							ui.end_row();
+
+							ui.label("Coefficients");
+							ui.vertical(|ui|
+							{
+								for i in (0..=degree).rev()
+								{
+									ui.horizontal(|ui|
+									{
+										let coefficient = &mut polynomial.coefficients[i];
+										ui.label(format!("z^{i}"));
+										polynomial_changed |= ui.add(egui::DragValue::new(coefficient.re_mut()).speed(0.01).prefix("re: ")).changed();
+										polynomial_changed |= ui.add(egui::DragValue::new(coefficient.im_mut()).speed(0.01).prefix("im: ")).changed();
+									});
+								}
+							});
+							ui.end_row();
+
+							if polynomial_changed
+							{
+								polynomial = Polynomial::from_coefficients(&polynomial.coefficients);
+								self.fractal_params.polynomial = polynomial.into();
+								changed = true;
+							}
+
+							ui.label("Roots");
+							ui.vertical(|ui|
+							{
+								for root in &polynomial.roots[..polynomial.degree as usize]
+								{
+									ui.label(format!("{:.6} {:+.6}i", root.re(), root.im()));
+								}
+							});
+							ui.end_row();
+						}
+
+						if self.fractal_params.fractal_kind == FractalKind::Lyapunov
+						{
+							ui.label("Sequence");
+							ui.vertical(|ui|
+							{
+								if ui.text_edit_singleline(&mut self.lyapunov_sequence_text).changed()
+								{
+									changed |= self.update_lyapunov_sequence();
+								}
+								if let Some(error) = &self.lyapunov_sequence_error
+								{
+									ui.colored_label(ui.visuals().error_fg_color, error);
+								}
+							});
+							ui.end_row();
+
+							ui.label("Region");
+							let mut view_region = false;
+							ui.vertical(|ui|
+							{
+								let params = &mut self.fractal_params;
+								ui.horizontal(|ui|
+								{
+									ui.label("a");
+									changed |= ui.add(egui::DragValue::new(&mut params.lyapunov_region_min.x).speed(0.01).range(f64::NEG_INFINITY..=params.lyapunov_region_max.x)).changed();
+									changed |= ui.add(egui::DragValue::new(&mut params.lyapunov_region_max.x).speed(0.01).range(params.lyapunov_region_min.x..=f64::INFINITY)).changed();
+								});
+								ui.horizontal(|ui|
+								{
+									ui.label("b");
+									changed |= ui.add(egui::DragValue::new(&mut params.lyapunov_region_min.y).speed(0.01).range(f64::NEG_INFINITY..=params.lyapunov_region_max.y)).changed();
+									changed |= ui.add(egui::DragValue::new(&mut params.lyapunov_region_max.y).speed(0.01).range(params.lyapunov_region_min.y..=f64::INFINITY)).changed();
+								});
+								view_region = ui.button("View Region").clicked();
+							});
+							ui.end_row();
+
+							if view_region
+							{
+								self.view_lyapunov_region();
+							}
+						}
+
+						if self.fractal_params.fractal_kind == FractalKind::Custom
+						{
+							ui.label("Formula");
+							ui.vertical(|ui|
+							{
+								if ui.text_edit_singleline(&mut self.formula_text).changed()
+								{
+									changed |= self.update_formula();
+								}
+								if let Some(error) = &self.formula_error
+								{
+									ui.colored_label(ui.visuals().error_fg_color, error);
+								}
+							});
+							ui.end_row();
+						}
+		
+						ui.label("Variation");
+						if let Some(fractal_variation) = select_in_list(ui, &self.fractal_params.variation, [
+							(FractalVariation::Normal, "Classic"),
+							(FractalVariation::JuliaSet, "Julia Set"),
+						])
+						{
+							self.set_fractal_variation(fractal_variation);
+							self.swap_primary_and_secondary();
+							changed = true;
 						}
 						ui.end_row();
 		
 						ui.label("Render Technique");
 						if let Some(rendering_technique) = select_in_list(ui, &self.fractal_params.render_technique, [
 							(RenderTechnique::Normal, "Normal"),
-							(RenderTechnique::OrbitTrapPoint, "Orbit Trap Point"),
-							(RenderTechnique::OrbitTrapCross, "Orbit Trap Cross"),
 							(RenderTechnique::NormalMap, "Normal Map"),
+							(RenderTechnique::DistanceEstimate, "Distance Estimate"),
+							(RenderTechnique::StripeAverage, "Stripe Average"),
+							(RenderTechnique::CurvatureAverage, "Curvature Average"),
+							(RenderTechnique::TriangleInequalityAverage, "Triangle Inequality Average"),
+							(RenderTechnique::OrbitTrap, "Orbit Trap"),
 						])
 						{
 							self.set_fractal_rendering(rendering_technique);
 							changed = true;
 						}
 						ui.end_row();
+
+						if matches!(self.fractal_params.render_technique, RenderTechnique::NormalMap | RenderTechnique::DistanceEstimate)
+						{
+							changed |= self.lighting_gui(ui);
+						}
+
+						if self.fractal_params.render_technique == RenderTechnique::OrbitTrap
+						{
+							changed |= self.orbit_trap_gui(ui);
+						}
+
+						ui.label("Interior");
+						if let Some(interior_coloring) = select_in_list(ui, &self.fractal_params.interior_coloring, [
+							(InteriorColoring::Black, "Black"),
+							(InteriorColoring::Period, "Period"),
+							(InteriorColoring::FinalModulus, "Final Modulus"),
+							(InteriorColoring::FinalAngle, "Final Angle"),
+							(InteriorColoring::Multiplier, "Multiplier"),
+							(InteriorColoring::DistanceEstimate, "Distance Estimate"),
+						])
+						{
+							self.fractal_params.interior_coloring = interior_coloring;
+							changed = true;
+						}
+						ui.end_row();
 						
 						ui.label("Iteration Limit");
 						ui.horizontal(|ui|
@@ -850,9 +1579,17 @@ impl AppData
 						ui.label("Position");
 						ui.horizontal(|ui|
 						{
-							let speed = self.zoom * 0.05;
-							ui.add(egui::DragValue::new(&mut self.pos.x).speed(speed).prefix("x: "));
-							ui.add(egui::DragValue::new(&mut self.pos.y).speed(speed).prefix("y: "));
+							if self.deep_zoom.is_some()
+							{
+								let pos = self.absolute_pos();
+								ui.label(format!("x: {:.17} y: {:.17}", pos.x, pos.y));
+							}
+							else
+							{
+								let speed = self.zoom * 0.05;
+								ui.add(egui::DragValue::new(&mut self.pos.x).speed(speed).prefix("x: "));
+								ui.add(egui::DragValue::new(&mut self.pos.y).speed(speed).prefix("y: "));
+							}
 						});
 						ui.end_row();
 						
@@ -869,30 +1606,255 @@ impl AppData
 						ui.horizontal(|ui|
 						{
 							let speed = self.zoom * 0.02;
-							ui.add(egui::DragValue::new(&mut self.zoom).speed(speed).suffix("x").range(0.000000000000001..=f64::MAX));
+							let min_zoom = if self.deep_zoom.is_some() { f64::MIN_POSITIVE } else { 0.000000000000001 };
+							ui.add(egui::DragValue::new(&mut self.zoom).speed(speed).suffix("x").range(min_zoom..=f64::MAX));
+						});
+						ui.end_row();
+
+						ui.label("Deep Zoom");
+						ui.add_enabled_ui(self.can_deep_zoom(), |ui|
+						{
+							let mut deep_zoom = self.deep_zoom.is_some();
+							if ui.checkbox(&mut deep_zoom, "Perturbation").on_disabled_hover_text("Requires 64 bit float support, an escape time fractal and a render technique that doesn't use the derivative").changed()
+							{
+								self.set_deep_zoom(deep_zoom);
+							}
+
+							if self.deep_zoom.is_some()
+							{
+								if ui.checkbox(&mut self.use_series_approximation, "Series Approximation").changed()
+								{
+									self.reset_fractal_rendering();
+								}
+								if self.use_series_approximation
+								{
+									ui.label(format!("Skipped: {}", self.skipped_iterations));
+								}
+							}
+						});
+						ui.end_row();
+
+						ui.label("Backend");
+						ui.horizontal(|ui|
+						{
+							let backends = self.supported_compute_backends.iter().map(|backend| (*backend, backend.name()));
+							if let Some(backend) = select_in_list(ui, &self.compute_backend, backends)
+							{
+								self.requested_compute_backend = Some(backend);
+								self.require_redraw = true;
+							}
+
+							#[cfg(not(target_arch = "wasm32"))]
+							if let Some(duration) = self.render_duration
+							{
+								ui.label(format!("{:.2} s", duration.as_secs_f64()));
+							}
+						});
+						ui.end_row();
+
+						if self.compute_backend == ComputeBackend::Cpu
+						{
+							ui.label("Precision");
+							let precisions = [(CpuPrecision::Double, "Double"), (CpuPrecision::DoubleDouble, "Double-Double")];
+							if let Some(precision) = select_in_list(ui, &self.cpu_precision, precisions)
+							{
+								self.cpu_precision = precision;
+								self.reset_fractal_rendering();
+							}
+							ui.end_row();
+						}
+
+						ui.label("Cell Memory");
+						ui.horizontal(|ui|
+						{
+							let mut budget = self.cells.budget() / MEGABYTE;
+							if ui.add(egui::DragValue::new(&mut budget).range(16..=u64::MAX).suffix(" MB")).changed()
+							{
+								self.cells.set_budget(budget * MEGABYTE);
+								self.require_redraw = true;
+							}
+							ui.label(format!("{:.1} MB used by {} cells", self.cells.memory_usage() as f64 / MEGABYTE as f64, self.cells.len()));
 						});
 						ui.end_row();
+
+						#[cfg(not(target_arch = "wasm32"))]
+						{
+							ui.label("Disk Cache");
+							ui.vertical(|ui| self.disk_cache_gui(ui));
+							ui.end_row();
+						}
 						
 						ui.label("Color Palette");
 						if let Some(color_palette) = select_in_list(ui, &self.fractal_params.color_palette, [
 							(ColorPalette::Default, "Default"),
 							(ColorPalette::Flames, "Flames"),
 							(ColorPalette::Temperature, "Temperature"),
+							(ColorPalette::Custom, "Custom"),
 						])
 						{
 							self.set_color_palette(color_palette);
-							changed = true;
 						}
 						ui.end_row();
+
+						if self.fractal_params.color_palette == ColorPalette::Custom
+						{
+							let mut stops: Vec<GradientStop> = self.gradient.stops().collect();
+							let mut interpolation = self.gradient.interpolation;
+							let mut gradient_changed = false;
+
+							ui.label("Interpolation");
+							if let Some(new_interpolation) = select_in_list(ui, &interpolation, [
+								(GradientInterpolation::Linear, "Linear"),
+								(GradientInterpolation::Smooth, "Smooth"),
+								(GradientInterpolation::Constant, "Constant"),
+							])
+							{
+								interpolation = new_interpolation;
+								gradient_changed = true;
+							}
+							ui.end_row();
+
+							ui.label("Gradient");
+							ui.vertical(|ui|
+							{
+								// Preview of one period of the gradient
+								let (rect, _) = ui.allocate_exact_size(egui::vec2(ui.available_width().min(256.0), 16.0), egui::Sense::hover());
+								let segment_count = 64;
+								for i in 0..segment_count
+								{
+									let color = self.gradient.sample((i as f32 + 0.5) / segment_count as f32);
+									let x = |i: usize| egui::lerp(rect.x_range(), i as f32 / segment_count as f32);
+									let segment = egui::Rect::from_x_y_ranges(x(i)..=x(i + 1), rect.y_range());
+									ui.painter().rect_filled(segment, 0.0, egui::Color32::from_rgb(gui_color_byte(color.x), gui_color_byte(color.y), gui_color_byte(color.z)));
+								}
+
+								let mut removed = None;
+								for (i, stop) in stops.iter_mut().enumerate()
+								{
+									ui.horizontal(|ui|
+									{
+										let mut color = [gui_color_byte(stop.color.x), gui_color_byte(stop.color.y), gui_color_byte(stop.color.z)];
+										if ui.color_edit_button_srgb(&mut color).changed()
+										{
+											stop.color = glam::Vec3::from(color.map(|c| c as f32 / 255.0));
+											gradient_changed = true;
+										}
+										gradient_changed |= ui.add(egui::DragValue::new(&mut stop.position).speed(0.005).range(0.0..=1.0)).changed();
+										if ui.add_enabled(self.gradient.stop_count > 1, egui::Button::new("Remove")).clicked()
+										{
+											removed = Some(i);
+										}
+									});
+								}
+
+								if let Some(i) = removed
+								{
+									stops.remove(i);
+									gradient_changed = true;
+								}
+
+								if ui.add_enabled(stops.len() < MAX_GRADIENT_STOPS, egui::Button::new("Add Stop")).clicked()
+								{
+									// In the middle of the largest gap, with the color the gradient already has there
+									let gap = |i: usize| (stops[(i + 1) % stops.len()].position - stops[i].position).rem_euclid(1.0);
+									let gap = |i: usize| if gap(i) == 0.0 { 1.0 } else { gap(i) };
+									let position = (0..stops.len())
+										.max_by(|&a, &b| gap(a).total_cmp(&gap(b)))
+										.map_or(0.0, |i| (stops[i].position + gap(i) * 0.5).rem_euclid(1.0));
+									stops.push(GradientStop::new(position, self.gradient.sample(position)));
+									gradient_changed = true;
+								}
+							});
+							ui.end_row();
+
+							if gradient_changed
+							{
+								self.gradient = Gradient::new(&stops, interpolation);
+								self.reset_colors();
+							}
+						}
 						
 						ui.label("Color Frequency");
 						ui.horizontal(|ui|
 						{
 							let speed = self.fractal_params.color_frequency * 0.02;
-							changed |= ui.add(egui::DragValue::new(&mut self.fractal_params.color_frequency).speed(speed).range(0.000000000000001..=f64::MAX)).changed();
+							if ui.add(egui::DragValue::new(&mut self.fractal_params.color_frequency).speed(speed).range(0.000000000000001..=f64::MAX)).changed()
+							{
+								self.reset_colors();
+							}
 						});
 						ui.end_row();
-		
+
+						ui.label("Coloring");
+						let response = ui.add_enabled(self.supports_escape_data, egui::Checkbox::new(&mut self.display_coloring, "Color when drawn"))
+							.on_hover_text("Changing the colors doesn't compute the cells again, except for the Newton and Lyapunov fractals and the lit techniques")
+							.on_disabled_hover_text("The adapter can't render to float textures");
+						changed |= response.changed();
+						ui.end_row();
+
+						ui.label("Color Mapping");
+						let color_mapping = ui.add_enabled_ui(self.cell_format() == CellFormat::EscapeData, |ui| select_in_list(ui, &self.color_mapping, [
+							(ColorMapping::Logarithmic, "Logarithmic"),
+							(ColorMapping::Histogram, "Histogram"),
+						])).inner;
+						if let Some(color_mapping) = color_mapping
+						{
+							self.color_mapping = color_mapping;
+							self.require_redraw = true;
+						}
+						ui.end_row();
+
+						ui.label("Anti-aliasing");
+						if let Some(samples_per_axis) = select_in_list(ui, &self.fractal_params.samples_per_axis, [
+							(1, "Off"),
+							(2, "2x2 samples"),
+							(3, "3x3 samples"),
+							(4, "4x4 samples"),
+						])
+						{
+							self.fractal_params.samples_per_axis = samples_per_axis;
+							changed = true;
+						}
+						ui.end_row();
+
+						#[cfg(not(target_arch = "wasm32"))]
+						{
+							ui.label("Scene");
+							ui.vertical(|ui|
+							{
+								ui.text_edit_singleline(&mut self.scene_path);
+								ui.horizontal(|ui|
+								{
+									if ui.button("Save").clicked()
+									{
+										self.scene_status = Some(match self.scene().save(&self.scene_path)
+										{
+											Ok(()) => format!("Saved to {}", self.scene_path),
+											Err(error) => format!("{error}"),
+										});
+									}
+									if ui.button("Open").clicked()
+									{
+										self.scene_status = match Scene::load(&self.scene_path)
+										{
+											Ok(scene) =>
+											{
+												self.load_scene(&scene);
+												changed = true;
+												None
+											},
+											Err(error) => Some(format!("{error}")),
+										};
+									}
+								});
+								if let Some(status) = &self.scene_status
+								{
+									ui.label(status);
+								}
+							});
+							ui.end_row();
+						}
+
 						ui.label("");
 						if ui.button("Reset").clicked()
 						{
@@ -905,16 +1867,592 @@ impl AppData
 					}).inner
 			});
 
-		let changed = match response
+		#[cfg(not(target_arch = "wasm32"))]
+		self.animation_gui(ctx);
+
+		let mut changed = match response
 		{
 			Some(InnerResponse{ inner: Some(true), .. }) => true,
 			_ => false,
 		};
 
+		if self.fractal_params.render_technique == RenderTechnique::OrbitTrap
+		{
+			changed |= self.orbit_trap_handle(ctx);
+		}
+
 		if changed
 		{
 			self.reset_fractal_rendering();
 		}
 		changed
 	}
+
+	/// Rows of the lighting parameters, returns true if one changed
+	fn lighting_gui(&mut self, ui: &mut egui::Ui) -> bool
+	{
+		let render_technique = self.fractal_params.render_technique;
+		let lighting = &mut self.fractal_params.lighting;
+		let mut changed = false;
+
+		ui.label("Lighting");
+		ui.horizontal(|ui|
+		{
+			// The normal map is always lit
+			if render_technique == RenderTechnique::DistanceEstimate
+			{
+				let mut lit = lighting.distance_estimate != 0;
+				if ui.checkbox(&mut lit, "Enabled").changed()
+				{
+					lighting.distance_estimate = lit as u32;
+					changed = true;
+				}
+			}
+			// Greyed out when they have no effect, such as when the distance estimate isn't lit
+			let lit = lighting.lights(render_technique);
+			changed |= ui.add_enabled(lit, egui::DragValue::new(&mut lighting.azimuth).speed(1.0).range(0.0..=360.0).prefix("azimuth: ").suffix("°")).changed();
+			changed |= ui.add_enabled(lit, egui::DragValue::new(&mut lighting.elevation).speed(1.0).range(0.0..=90.0).prefix("elevation: ").suffix("°")).changed();
+		});
+		ui.end_row();
+
+		let lit = lighting.lights(render_technique);
+		ui.label("Reflection");
+		ui.horizontal(|ui|
+		{
+			changed |= ui.add_enabled(lit, egui::DragValue::new(&mut lighting.ambient).speed(0.01).range(0.0..=2.0).prefix("ambient: ")).changed();
+			changed |= ui.add_enabled(lit, egui::DragValue::new(&mut lighting.diffuse).speed(0.01).range(0.0..=2.0).prefix("diffuse: ")).changed();
+			changed |= ui.add_enabled(lit, egui::DragValue::new(&mut lighting.specular).speed(0.01).range(0.0..=2.0).prefix("specular: ")).changed();
+			changed |= ui.add_enabled(lit, egui::DragValue::new(&mut lighting.shininess).speed(0.5).range(1.0..=500.0).prefix("shininess: ")).changed();
+		});
+		ui.end_row();
+
+		changed
+	}
+
+	/// Rows of the orbit trap parameters, returns true if one changed
+	fn orbit_trap_gui(&mut self, ui: &mut egui::Ui) -> bool
+	{
+		fn select_value<T: Eq>(ui: &mut egui::Ui, selected_value: &mut T, list: impl IntoIterator<Item = (T, &'static str)>) -> bool
+		{
+			ui.horizontal(|ui|
+			{
+				let mut changed = false;
+				for (value, name) in list
+				{
+					changed |= ui.selectable_value(selected_value, value, name).changed();
+				}
+				changed
+			}).inner
+		}
+
+		let speed = self.zoom * 0.05;
+		let trap = &mut self.fractal_params.orbit_trap;
+		let mut changed = false;
+
+		ui.label("Trap Preset");
+		ui.horizontal(|ui|
+		{
+			for (preset, name) in [(OrbitTrap::POINT, "Point at Origin"), (OrbitTrap::CROSS, "Cross on Axes")]
+			{
+				if ui.button(name).clicked()
+				{
+					let coloring = trap.coloring;
+					*trap = preset;
+					trap.coloring = coloring;
+					changed = true;
+				}
+			}
+		});
+		ui.end_row();
+
+		ui.label("Trap Shape");
+		changed |= select_value(ui, &mut trap.shape, [
+			(OrbitTrapShape::Point, "Point"),
+			(OrbitTrapShape::Line, "Line"),
+			(OrbitTrapShape::Circle, "Circle"),
+			(OrbitTrapShape::Cross, "Cross"),
+			(OrbitTrapShape::Square, "Square"),
+		]);
+		ui.end_row();
+
+		ui.label("Trap");
+		ui.horizontal(|ui|
+		{
+			changed |= ui.add(egui::DragValue::new(&mut trap.center.x).speed(speed).prefix("x: ")).changed();
+			changed |= ui.add(egui::DragValue::new(&mut trap.center.y).speed(speed).prefix("y: ")).changed();
+			changed |= ui.add(egui::DragValue::new(&mut trap.rotation).speed(1.0).range(-180.0..=180.0).prefix("rotation: ").suffix("°")).changed();
+			if matches!(trap.shape, OrbitTrapShape::Circle | OrbitTrapShape::Square)
+			{
+				changed |= ui.add(egui::DragValue::new(&mut trap.size).speed(speed).range(0.0..=f32::MAX).prefix("size: ")).changed();
+			}
+		}).response.on_hover_text("The center of the trap can also be dragged in the view");
+		ui.end_row();
+
+		ui.label("Trap Coloring");
+		changed |= select_value(ui, &mut trap.coloring, [
+			(OrbitTrapColoring::Distance, "Distance"),
+			(OrbitTrapColoring::Iteration, "Iteration"),
+			(OrbitTrapColoring::Angle, "Angle"),
+		]);
+		ui.end_row();
+
+		changed
+	}
+
+	/// Draws the orbit trap over the view with a handle to drag its center, returns true if it was moved
+	fn orbit_trap_handle(&mut self, ctx: &egui::Context) -> bool
+	{
+		const HANDLE_RADIUS: f32 = 6.0;
+
+		let pixels_per_point = ctx.pixels_per_point() as f64;
+		let pixel_world_size = self.pixel_world_size();
+		let view_pos = self.absolute_pos();
+		let viewport_world_size = self.viewport_world_size();
+		let screen_center = dvec2(self.screen_size.width as f64, self.screen_size.height as f64) * 0.5;
+		let to_screen = |pos: DVec2|
+		{
+			let pos = (screen_center + (pos - view_pos) * dvec2(1.0, -1.0) / pixel_world_size) / pixels_per_point;
+			egui::pos2(pos.x as f32, pos.y as f32)
+		};
+
+		let trap = &mut self.fractal_params.orbit_trap;
+		let center = trap.center.as_dvec2();
+		let handle_pos = to_screen(center);
+		// Far outside of the view when zoomed in deep enough
+		if !handle_pos.x.is_finite() || !handle_pos.y.is_finite() || handle_pos.to_vec2().length() > 1e6
+		{
+			return false;
+		}
+
+		let rotation = trap.rotation.to_radians() as f64;
+		let axis = dvec2(rotation.cos(), rotation.sin());
+		let normal = axis.perp();
+		let size = trap.size as f64;
+		// Long enough for the lines to cross the view
+		let line_length = viewport_world_size.length() + (center - view_pos).length();
+
+		let painter = ctx.layer_painter(egui::LayerId::background());
+		let stroke = egui::Stroke::new(1.5, egui::Color32::WHITE);
+		let line = |direction: DVec2|
+		{
+			painter.line_segment([to_screen(center - direction * line_length), to_screen(center + direction * line_length)], stroke);
+		};
+		match trap.shape
+		{
+			OrbitTrapShape::Point => {},
+			OrbitTrapShape::Line => line(axis),
+			OrbitTrapShape::Circle =>
+			{
+				painter.circle_stroke(handle_pos, (size / pixel_world_size / pixels_per_point) as f32, stroke);
+			},
+			OrbitTrapShape::Cross =>
+			{
+				line(axis);
+				line(normal);
+			},
+			OrbitTrapShape::Square =>
+			{
+				let corners = [axis + normal, normal - axis, -axis - normal, axis - normal].map(|corner| to_screen(center + corner * size));
+				painter.add(egui::Shape::closed_line(corners.to_vec(), stroke));
+			},
+		}
+
+		let response = egui::Area::new(egui::Id::new("orbit_trap_handle"))
+			.fixed_pos(handle_pos - egui::Vec2::splat(HANDLE_RADIUS))
+			.show(ctx, |ui|
+			{
+				let (rect, response) = ui.allocate_exact_size(egui::Vec2::splat(HANDLE_RADIUS * 2.0), egui::Sense::drag());
+				let fill = if response.hovered() || response.dragged() { egui::Color32::WHITE } else { egui::Color32::from_gray(200) };
+				ui.painter().circle(rect.center(), HANDLE_RADIUS, fill, egui::Stroke::new(1.5, egui::Color32::BLACK));
+				response.on_hover_cursor(egui::CursorIcon::Grab)
+			}).inner;
+
+		let delta = response.drag_delta();
+		if delta == egui::Vec2::ZERO
+		{
+			return false;
+		}
+
+		let delta = dvec2(delta.x as f64, -delta.y as f64) * pixels_per_point * pixel_world_size;
+		trap.center = (center + delta).as_vec2();
+		true
+	}
+}
+
+
+#[cfg(not(target_arch = "wasm32"))]
+impl AppData
+{
+	/// Hasher of every parameter that changes the pixels of the cells, see [`CellHasher::cell_key`]
+	fn cell_hasher(&self, target: &Target) -> CellHasher
+	{
+		let mut hasher = CellHasher::default();
+
+		// The pixel size only depends on the position of the cell
+		let mut fractal_params = self.fractal_params;
+		fractal_params.pixel_size = 0.0;
+		let cell_format = self.cell_format();
+		if cell_format == CellFormat::EscapeData
+		{
+			// The colors aren't in the cells
+			fractal_params.color_palette = ColorPalette::Default;
+			fractal_params.color_frequency = 1.0;
+		}
+		hasher.write(bytemuck::bytes_of(&fractal_params));
+		hasher.write(bytemuck::bytes_of(&self.formula));
+		if cell_format == CellFormat::Color
+		{
+			hasher.write(bytemuck::bytes_of(&self.gradient));
+		}
+
+		// The cells are relative to the origin of the deep zoom
+		if let Some(origin) = self.deep_zoom_origin()
+		{
+			for value in [origin.x, origin.y]
+			{
+				hasher.write(&value.hi().to_le_bytes());
+				hasher.write(&value.lo().to_le_bytes());
+			}
+			hasher.write(&[self.use_series_approximation as u8]);
+		}
+
+		// The backends and precisions round differently
+		hasher.write(self.compute_backend.name().as_bytes());
+		if self.compute_backend == ComputeBackend::Cpu
+		{
+			hasher.write(format!("{:?}", self.cpu_precision).as_bytes());
+		}
+		hasher.write(&[target.device.features().contains(wgpu::Features::SHADER_F64) as u8]);
+		hasher.write(format!("{:?}", cell_format.texture_format(target)).as_bytes());
+		hasher.write(&self.cell_size.to_le_bytes());
+
+		hasher
+	}
+
+	/// Loads the visible cells that are in the disk cache, before the missing ones are computed
+	fn load_cached_cells(&mut self, target: &Target, render: &Render)
+	{
+		// Limits the time spent reading files in a frame, the next cells are loaded in the following frames
+		const MAX_LOADED_CELLS: usize = 32;
+
+		self.disk_cache.computed_cells.clear();
+		if self.disk_cache.open().is_none()
+		{
+			return;
+		}
+
+		let hasher = self.cell_hasher(target);
+		let texture_size = self.cell_texture_size();
+		let texel_size = self.cell_format().texel_size(target);
+		let data_size = (texture_size.width * texture_size.height) as u64 * texel_size as u64;
+		let missing_cells: Vec<QuadPos> = self.visible_cells().filter(|pos| !self.is_cell_loaded(*pos) || self.is_cell_preview(*pos)).collect();
+
+		let Some(cache) = self.disk_cache.cache.as_mut()
+		else
+		{
+			return;
+		};
+		let cached_cells: Vec<(QuadPos, u64)> = missing_cells.into_iter()
+			.map(|pos| (pos, hasher.cell_key(pos)))
+			.filter(|(_pos, key)| cache.contains(*key))
+			.take(MAX_LOADED_CELLS)
+			.collect();
+		let loaded_cells: Vec<(QuadPos, Vec<u8>)> = cached_cells.into_iter()
+			.filter_map(|(pos, key)| Some((pos, cache.get(key, data_size)?)))
+			.collect();
+
+		for (pos, data) in loaded_cells
+		{
+			let destination = self.make_cell(target, render, pos, CellResolution::Full).fractal_texture();
+			target.queue.write_texture(
+				wgpu::ImageCopyTexture
+				{
+					texture: destination,
+					mip_level: 0,
+					origin: wgpu::Origin3d::ZERO,
+					aspect: wgpu::TextureAspect::All,
+				},
+				&data,
+				wgpu::ImageDataLayout
+				{
+					offset: 0,
+					bytes_per_row: Some(texture_size.width * texel_size),
+					rows_per_image: None,
+				},
+				destination.size()
+			);
+
+			if self.cell_format() == CellFormat::EscapeData
+			{
+				let histogram = crate::compute::read_cell_histogram(&data, (texture_size.width * texel_size) as usize, texture_size);
+				self.set_cell_histogram(pos, self.cells_generation, CellResolution::Full, histogram);
+			}
+		}
+
+		// Only the cells computed by the backend are saved
+		self.disk_cache.computed_cells.clear();
+	}
+
+	/// Reads back the cells computed in the frame that was just submitted, they are saved once the GPU copied them
+	fn save_computed_cells(&mut self, target: &Target)
+	{
+		let computed_cells = std::mem::take(&mut self.disk_cache.computed_cells);
+		if self.disk_cache.open().is_none()
+		{
+			self.disk_cache.readbacks.clear();
+			return;
+		}
+
+		if !computed_cells.is_empty()
+		{
+			let hasher = self.cell_hasher(target);
+			let mut commands = target.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("cell_readback") });
+			for pos in computed_cells
+			{
+				if let Some(cell) = self.cells.get(pos)
+				{
+					self.disk_cache.readbacks.queue(target, &mut commands, cell.fractal_texture(), hasher.cell_key(pos));
+				}
+			}
+			target.queue.submit(std::iter::once(commands.finish()));
+		}
+
+		let settings = &mut self.disk_cache;
+		if let Some(cache) = &mut settings.cache
+		{
+			settings.readbacks.save_finished(target, cache);
+		}
+
+		// Keep polling until every cell is saved
+		self.require_redraw |= !self.disk_cache.readbacks.is_empty();
+	}
+
+	fn disk_cache_gui(&mut self, ui: &mut egui::Ui)
+	{
+		let settings = &mut self.disk_cache;
+		ui.horizontal(|ui|
+		{
+			if ui.checkbox(&mut settings.enabled, "Enabled").changed()
+			{
+				settings.status = None;
+			}
+			if ui.add_enabled(settings.cache.is_some(), egui::Button::new("Clear")).clicked()
+			{
+				if let Some(cache) = &mut settings.cache
+				{
+					cache.clear();
+				}
+			}
+		});
+
+		// Opened again with the new directory when it is used
+		if ui.text_edit_singleline(&mut settings.directory).lost_focus()
+			&& settings.cache.as_ref().is_some_and(|cache| cache.directory() != std::path::Path::new(&settings.directory))
+		{
+			settings.cache = None;
+		}
+
+		ui.horizontal(|ui|
+		{
+			if ui.add(egui::DragValue::new(&mut settings.max_size).range(1..=u64::MAX).suffix(" MB")).changed()
+			{
+				if let Some(cache) = &mut settings.cache
+				{
+					cache.set_max_size(settings.max_size * MEGABYTE);
+				}
+			}
+			if let Some(cache) = &settings.cache
+			{
+				ui.label(format!("{:.1} MB used", cache.size() as f64 / MEGABYTE as f64));
+			}
+		});
+
+		if let Some(status) = &settings.status
+		{
+			ui.label(status);
+		}
+	}
+
+	/// Moves the view to the values of a keyframe
+	fn go_to_keyframe(&mut self, keyframe: &Keyframe)
+	{
+		let mut scene = self.scene();
+		keyframe.apply_to_scene(&mut scene);
+		scene.deep_zoom |= keyframe.zoom < crate::animation::DOUBLE_DOUBLE_ZOOM;
+		self.load_scene(&scene);
+	}
+
+	/// The view rendered for the values that are not animated
+	fn animation_base_view(&self) -> crate::headless::View
+	{
+		let scene = self.scene();
+		let [width, height] = self.animation_settings.size;
+
+		crate::headless::View
+		{
+			center: scene.center.into(),
+			zoom: scene.zoom,
+			size: glam::uvec2(width, height),
+			fractal: self.fractal_params,
+			formula: self.formula,
+			gradient: self.gradient,
+			precision: crate::compute::CpuPrecision::Double,
+		}
+	}
+
+	fn update_animation_render(&mut self)
+	{
+		let settings = &mut self.animation_settings;
+		let Some(render) = settings.render.take_if(|render| render.is_finished())
+		else
+		{
+			// Keep drawing the progress
+			self.require_redraw |= settings.render.is_some();
+			return;
+		};
+
+		settings.status = Some(match render.join()
+		{
+			Ok(()) => format!("Rendered to {}", settings.directory),
+			Err(error) => format!("{error}"),
+		});
+	}
+
+	fn animation_gui(&mut self, ctx: &egui::Context)
+	{
+		self.update_animation_render();
+
+		egui::Window::new("Animation")
+			.default_open(false)
+			.resizable(false)
+			.show(ctx, |ui|
+			{
+				let mut go_to = None;
+				let mut removed = None;
+				let mut moved = None;
+
+				egui::Grid::new("keyframe_grid")
+					.num_columns(4)
+					.striped(true)
+					.show(ui, |ui|
+					{
+						ui.label("Time (s)");
+						ui.label("Zoom");
+						ui.end_row();
+
+						for (i, keyframe) in self.animation.keyframes().iter().enumerate()
+						{
+							let mut time = keyframe.time;
+							if ui.add(egui::DragValue::new(&mut time).speed(0.05).range(0.0..=f64::MAX)).changed()
+							{
+								moved = Some((i, time));
+							}
+							ui.label(format!("{:.3e}", keyframe.zoom));
+							if ui.button("Go To").clicked()
+							{
+								go_to = Some(*keyframe);
+							}
+							if ui.button("Remove").clicked()
+							{
+								removed = Some(i);
+							}
+							ui.end_row();
+						}
+					});
+
+				if let Some((i, time)) = moved
+				{
+					self.animation.set_time(i, time);
+				}
+				if let Some(i) = removed
+				{
+					self.animation.remove(i);
+				}
+
+				if ui.button("Add Keyframe").clicked()
+				{
+					// Two seconds after the last keyframe
+					let time = self.animation.keyframes().last().map_or(0.0, |k| k.time + 2.0);
+					self.animation.insert(Keyframe::from_scene(&self.scene(), time));
+				}
+
+				if self.animation.keyframes().len() >= 2
+				{
+					let start = self.animation.start_time();
+					let end = start + self.animation.duration();
+					let preview = ui.add(egui::Slider::new(&mut self.animation_settings.preview_time, start..=end).text("Preview"));
+					if preview.changed()
+					{
+						go_to = self.animation.sample(self.animation_settings.preview_time);
+					}
+				}
+
+				if let Some(keyframe) = go_to
+				{
+					self.go_to_keyframe(&keyframe);
+				}
+
+				ui.separator();
+
+				egui::Grid::new("animation_render_grid")
+					.num_columns(2)
+					.show(ui, |ui|
+					{
+						let settings = &mut self.animation_settings;
+
+						ui.label("Frame Rate");
+						ui.add(egui::DragValue::new(&mut self.animation.frame_rate).range(1..=240));
+						ui.end_row();
+
+						ui.label("Size");
+						ui.horizontal(|ui|
+						{
+							ui.add(egui::DragValue::new(&mut settings.size[0]).range(1..=16384));
+							ui.label("x");
+							ui.add(egui::DragValue::new(&mut settings.size[1]).range(1..=16384));
+						});
+						ui.end_row();
+
+						ui.label("Directory");
+						ui.text_edit_singleline(&mut settings.directory);
+						ui.end_row();
+					});
+
+				let frame_count = self.animation.frame_count();
+				match &self.animation_settings.render
+				{
+					Some(render) =>
+					{
+						ui.horizontal(|ui|
+						{
+							ui.label(format!("Rendering frame {} of {}", (render.rendered_frames() + 1).min(render.frame_count()), render.frame_count()));
+							if ui.button("Cancel").clicked()
+							{
+								render.cancel();
+							}
+						});
+					},
+					None =>
+					{
+						if ui.add_enabled(frame_count > 0, egui::Button::new(format!("Render {frame_count} Frames"))).clicked()
+						{
+							let base = self.animation_base_view();
+							let directory = std::path::PathBuf::from(&self.animation_settings.directory);
+							self.animation_settings.render = Some(AnimationRender::start(self.animation.clone(), base, directory));
+							self.animation_settings.status = None;
+						}
+						if let Some(status) = &self.animation_settings.status
+						{
+							ui.label(status);
+						}
+					},
+				}
+			});
+	}
+}
+
+/// Converts a color component between 0 and 1 to the bytes used by egui
+fn gui_color_byte(component: f32) -> u8
+{
+	shared::compute::color_to_byte(component.clamp(0.0, 1.0)) as u8
 }
@@ -0,0 +1,503 @@
+use std::fmt::Display;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use fractal_renderer_lib::animation::Keyframe;
+use fractal_renderer_lib::compute::CpuPrecision;
+use fractal_renderer_lib::headless::{self, View};
+use fractal_renderer_lib::scene::{Scene, SceneError};
+use fractal_renderer_lib::url_fragment;
+use fractal_renderer_shared as shared;
+use shared::fractal::{ColorPalette, FractalKind, FractalVariation, InteriorColoring, OrbitTrapColoring, OrbitTrapShape, RenderTechnique};
+use shared::fractal::newton::{self, Polynomial};
+use shared::fractal::lyapunov::ParseLyapunovSequenceError;
+use shared::formula::{Formula64, ParseFormulaError};
+use shared::gradient::{Gradient, GradientInterpolation, GradientStop};
+use shared::math::{Complex64, DDVec2, DoubleDouble};
+use glam::{dvec2, uvec2, DVec2};
+
+const USAGE: &str = "\
+Render a fractal view to a PNG image without opening a window
+
+Usage: fractal_renderer_headless [OPTIONS] --output <FILE>
+       fractal_renderer_headless [OPTIONS] --save-scene <FILE>
+       fractal_renderer_headless [OPTIONS] --frames <DIRECTORY>
+
+Options:
+  -o, --output <FILE>            Path of the PNG image to write
+      --scene <FILE>             Scene file to start from, the options after it override its values
+      --save-scene <FILE>        Path of a scene file to write with the view described by the options
+      --frames <DIRECTORY>       Renders the keyframe animation to frame_00000.png, frame_00001.png... in DIRECTORY
+      --keyframe <TIME>          Adds a keyframe at TIME seconds with the center, zoom, julia constant, iteration limit
+                                 and color frequency given before it
+      --frame-rate <FPS>         Frame rate of the animation [default: 30]
+      --kind <KIND>              mandelbrot, multibrot, tricorn, burning-ship, cos-leaf, custom, newton3, newton-polynomial, lyapunov [default: mandelbrot]
+      --exponent <RE[,IM]>       Exponent of the multibrot kind, real or complex [default: 3]
+      --polynomial <COEFFICIENTS>
+                                 Coefficients of the newton-polynomial kind from the highest degree, separated by ';',
+                                 complex ones are written re,im [default: 1;0;-2;2]
+      --lyapunov-sequence <SEQUENCE>
+                                 Sequence of the parameters a and b of the lyapunov kind, such as \"AABAB\" [default: AB]
+      --lyapunov-region <A_MIN,B_MIN,A_MAX,B_MAX>
+                                 Region of the parameters a and b of the lyapunov kind [default: 0,0,4,4]
+      --formula <FORMULA>        Iteration formula of the custom kind, such as \"z^3 - z + c\" [default: z^2 + c]
+      --variation <VARIATION>    normal, julia [default: normal]
+      --technique <TECHNIQUE>    normal, normal-map, distance-estimate, stripe-average, curvature-average,
+                                 triangle-inequality-average, orbit-trap [default: normal]
+      --trap-shape <SHAPE>       Shape of the orbit-trap technique: point, line, circle, cross, square [default: point]
+      --trap <X,Y[,ROTATION[,SIZE]]>
+                                 Center of the trap, its rotation in degrees and the size of the circle and square [default: 0,0,0,1]
+      --trap-coloring <COLORING> distance, iteration, angle [default: distance]
+      --interior <COLORING>      black, period, final-modulus, final-angle, multiplier, distance-estimate [default: black]
+      --light <AZIMUTH,ELEVATION>
+                                 Direction of the light in degrees, also lights the distance-estimate technique [default: 45,45]
+      --reflection <AMBIENT,DIFFUSE,SPECULAR,SHININESS>
+                                 Blinn-Phong reflection of the lit techniques [default: 0.2,0.8,0.3,20]
+      --center <X,Y>             Center of the view [default: 0,0]
+      --zoom <ZOOM>              Zoom factor, smaller values zoom in [default: 1]
+      --julia-constant <X,Y>     Constant used by the julia variation [default: 0,0]
+      --iterations <N>           Iteration limit [default: depends on the fractal kind]
+      --palette <PALETTE>        default, flames, temperature, custom [default: default]
+      --gradient <STOPS>         Gradient of the custom palette, stops written POSITION:RRGGBB separated by ';',
+                                 such as \"0:000764;0.16:206bcb;0.42:edffff;0.64:ffaa00;0.86:000200\"
+                                 [default: the colors of the default palette]
+      --gradient-interpolation <INTERPOLATION>
+                                 linear, smooth, constant [default: smooth]
+      --color-frequency <F>      Color frequency [default: 1]
+      --supersampling <N>        Anti-aliasing with NxN samples per pixel, up to 16 [default: 1]
+      --size <WIDTHxHEIGHT>      Size of the image in pixels [default: 1920x1080]
+      --precision <PRECISION>    double, double-double [default: double]
+  -h, --help                     Print this help
+";
+
+#[derive(Debug)]
+enum Error
+{
+	MissingValue(String),
+	InvalidValue(String, String),
+	UnknownArgument(String),
+	InvalidFormula(ParseFormulaError),
+	InvalidLyapunovSequence(ParseLyapunovSequenceError),
+	Scene(SceneError),
+	NoKeyframes,
+	MissingOutput,
+}
+
+impl Display for Error
+{
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+	{
+		match self
+		{
+			Self::MissingValue(flag) => write!(f, "missing value for {flag}"),
+			Self::InvalidValue(flag, value) => write!(f, "invalid value '{value}' for {flag}"),
+			Self::UnknownArgument(arg) => write!(f, "unknown argument '{arg}'"),
+			Self::InvalidFormula(error) => write!(f, "invalid formula: {error}"),
+			Self::InvalidLyapunovSequence(error) => write!(f, "invalid lyapunov sequence: {error}"),
+			Self::Scene(error) => write!(f, "{error}"),
+			Self::NoKeyframes => write!(f, "the animation has no keyframes, add them with --keyframe or a scene file"),
+			Self::MissingOutput => write!(f, "the --output, --save-scene or --frames argument is required"),
+		}
+	}
+}
+
+impl std::error::Error for Error
+{
+
+}
+
+fn parse_kind(value: &str) -> Option<FractalKind>
+{
+	Some(match value
+	{
+		"mandelbrot" => FractalKind::MandelbrotSet,
+		"multibrot" => FractalKind::Multibrot,
+		"tricorn" => FractalKind::Tricorn,
+		"burning-ship" => FractalKind::BurningShip,
+		"cos-leaf" => FractalKind::CosLeaf,
+		"custom" => FractalKind::Custom,
+		"newton3" => FractalKind::Newton3,
+		"newton-polynomial" => FractalKind::NewtonPolynomial,
+		"lyapunov" => FractalKind::Lyapunov,
+		_ => return None,
+	})
+}
+
+fn parse_variation(value: &str) -> Option<FractalVariation>
+{
+	Some(match value
+	{
+		"normal" => FractalVariation::Normal,
+		"julia" => FractalVariation::JuliaSet,
+		_ => return None,
+	})
+}
+
+fn parse_technique(value: &str) -> Option<RenderTechnique>
+{
+	Some(match value
+	{
+		"normal" => RenderTechnique::Normal,
+		"normal-map" => RenderTechnique::NormalMap,
+		"distance-estimate" => RenderTechnique::DistanceEstimate,
+		"stripe-average" => RenderTechnique::StripeAverage,
+		"curvature-average" => RenderTechnique::CurvatureAverage,
+		"triangle-inequality-average" => RenderTechnique::TriangleInequalityAverage,
+		"orbit-trap" => RenderTechnique::OrbitTrap,
+		_ => return None,
+	})
+}
+
+fn parse_interior(value: &str) -> Option<InteriorColoring>
+{
+	Some(match value
+	{
+		"black" => InteriorColoring::Black,
+		"period" => InteriorColoring::Period,
+		"final-modulus" => InteriorColoring::FinalModulus,
+		"final-angle" => InteriorColoring::FinalAngle,
+		"multiplier" => InteriorColoring::Multiplier,
+		"distance-estimate" => InteriorColoring::DistanceEstimate,
+		_ => return None,
+	})
+}
+
+fn parse_trap_shape(value: &str) -> Option<OrbitTrapShape>
+{
+	Some(match value
+	{
+		"point" => OrbitTrapShape::Point,
+		"line" => OrbitTrapShape::Line,
+		"circle" => OrbitTrapShape::Circle,
+		"cross" => OrbitTrapShape::Cross,
+		"square" => OrbitTrapShape::Square,
+		_ => return None,
+	})
+}
+
+fn parse_trap_coloring(value: &str) -> Option<OrbitTrapColoring>
+{
+	Some(match value
+	{
+		"distance" => OrbitTrapColoring::Distance,
+		"iteration" => OrbitTrapColoring::Iteration,
+		"angle" => OrbitTrapColoring::Angle,
+		_ => return None,
+	})
+}
+
+fn parse_palette(value: &str) -> Option<ColorPalette>
+{
+	Some(match value
+	{
+		"default" => ColorPalette::Default,
+		"flames" => ColorPalette::Flames,
+		"temperature" => ColorPalette::Temperature,
+		"custom" => ColorPalette::Custom,
+		_ => return None,
+	})
+}
+
+fn parse_interpolation(value: &str) -> Option<GradientInterpolation>
+{
+	Some(match value
+	{
+		"linear" => GradientInterpolation::Linear,
+		"smooth" => GradientInterpolation::Smooth,
+		"constant" => GradientInterpolation::Constant,
+		_ => return None,
+	})
+}
+
+fn parse_precision(value: &str) -> Option<CpuPrecision>
+{
+	Some(match value
+	{
+		"double" => CpuPrecision::Double,
+		"double-double" => CpuPrecision::DoubleDouble,
+		_ => return None,
+	})
+}
+
+// A real number, or a complex number written re,im
+fn parse_complex(value: &str) -> Option<Complex64>
+{
+	match value.split_once(',')
+	{
+		Some(_) => parse_pair(value, ',').map(|(re, im)| Complex64::new(re, im)),
+		None => value.trim().parse().ok().map(|re| Complex64::new(re, 0.0)),
+	}
+}
+
+// Coefficients from the highest degree, such as "1;0;-2;2" or "1;0,1;-1"
+fn parse_polynomial(value: &str) -> Option<Polynomial<f64>>
+{
+	let mut coefficients = value.split(';').map(parse_complex).collect::<Option<Vec<_>>>()?;
+	coefficients.reverse();
+
+	let polynomial = Polynomial::from_coefficients(&coefficients);
+	(coefficients.len() <= newton::MAX_DEGREE + 1 && polynomial.degree > 0).then_some(polynomial)
+}
+
+// Stops such as "0:ff8000;0.5:0000ff", the color is in hexadecimal sRGB
+fn parse_gradient_stops(value: &str) -> Option<Vec<GradientStop>>
+{
+	value.split(';').map(|stop|
+	{
+		let (position, color) = stop.split_once(':')?;
+		let position: f32 = position.trim().parse().ok().filter(|position| (0.0..=1.0).contains(position))?;
+		let color = color.trim().trim_start_matches('#');
+		let color = u32::from_str_radix(color, 16).ok().filter(|_| color.len() == 6)?;
+		let component = |shift: u32| ((color >> shift) & 0xff) as f32 / 255.0;
+		Some(GradientStop::new(position, glam::vec3(component(16), component(8), component(0))))
+	}).collect::<Option<Vec<_>>>().filter(|stops| stops.len() <= shared::gradient::MAX_GRADIENT_STOPS)
+}
+
+// The minimum and maximum corners, written a_min,b_min,a_max,b_max
+fn parse_region(value: &str) -> Option<(DVec2, DVec2)>
+{
+	let values = value.split(',').map(|v| v.trim().parse().ok()).collect::<Option<Vec<f64>>>()?;
+	let [a_min, b_min, a_max, b_max] = values[..]
+	else
+	{
+		return None;
+	};
+	(a_min < a_max && b_min < b_max).then_some((dvec2(a_min, b_min), dvec2(a_max, b_max)))
+}
+
+fn parse_reflection(value: &str) -> Option<(f32, f32, f32, f32)>
+{
+	let values = value.split(',').map(|v| v.trim().parse().ok()).collect::<Option<Vec<f32>>>()?;
+	let [ambient, diffuse, specular, shininess] = values[..]
+	else
+	{
+		return None;
+	};
+	(ambient >= 0.0 && diffuse >= 0.0 && specular >= 0.0 && shininess >= 1.0).then_some((ambient, diffuse, specular, shininess))
+}
+
+// The center, optionally followed by the rotation and the size, such as "0.5,0" or "0,0,45,0.5"
+fn parse_trap(value: &str) -> Option<(glam::Vec2, Option<f32>, Option<f32>)>
+{
+	let values = value.split(',').map(|v| v.trim().parse().ok()).collect::<Option<Vec<f32>>>()?;
+	let (x, y, rotation, size) = match values[..]
+	{
+		[x, y] => (x, y, None, None),
+		[x, y, rotation] => (x, y, Some(rotation), None),
+		[x, y, rotation, size] => (x, y, Some(rotation), Some(size)),
+		_ => return None,
+	};
+	size.is_none_or(|size| size >= 0.0).then_some((glam::vec2(x, y), rotation, size))
+}
+
+fn parse_pair<T: std::str::FromStr>(value: &str, separator: char) -> Option<(T, T)>
+{
+	let (a, b) = value.split_once(separator)?;
+	Some((a.trim().parse().ok()?, b.trim().parse().ok()?))
+}
+
+struct Args
+{
+	output: Option<PathBuf>,
+	save_scene: Option<PathBuf>,
+	frames: Option<PathBuf>,
+	scene: Scene,
+	view: View,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Option<Args>, Error>
+{
+	let mut output = None;
+	let mut save_scene = None;
+	let mut frames = None;
+	// Everything but the size and the precision of the image
+	let mut scene = Scene::default();
+	let mut size = uvec2(1920, 1080);
+	let mut iteration_limit = None;
+	// The iteration limit of the scene file, until the fractal kind is changed
+	let mut scene_iteration_limit = None;
+	let mut precision = CpuPrecision::Double;
+
+	while let Some(arg) = args.next()
+	{
+		if arg == "-h" || arg == "--help"
+		{
+			return Ok(None);
+		}
+
+		let value = args.next().ok_or_else(|| Error::MissingValue(arg.clone()))?;
+		let invalid = || Error::InvalidValue(arg.clone(), value.clone());
+		let fractal = &mut scene.fractal;
+		let gradient = &mut scene.gradient;
+
+		match arg.as_str()
+		{
+			"-o" | "--output" => output = Some(PathBuf::from(&value)),
+			"--scene" =>
+			{
+				scene = Scene::load(&value).map_err(Error::Scene)?;
+				scene_iteration_limit = Some(scene.fractal.iteration_limit);
+			},
+			"--save-scene" => save_scene = Some(PathBuf::from(&value)),
+			"--frames" => frames = Some(PathBuf::from(&value)),
+			"--keyframe" =>
+			{
+				let time = value.parse().ok().filter(|time: &f64| *time >= 0.0).ok_or_else(invalid)?;
+				let mut keyframe = Keyframe::from_scene(&scene, time);
+				keyframe.iteration_limit = iteration_limit.or(scene_iteration_limit).unwrap_or(scene.fractal.fractal_kind.default_iteration_limit());
+				scene.animation.insert(keyframe);
+			},
+			"--frame-rate" => scene.animation.frame_rate = value.parse().ok().filter(|rate: &u32| *rate > 0).ok_or_else(invalid)?,
+			"--kind" =>
+			{
+				fractal.fractal_kind = parse_kind(&value).ok_or_else(invalid)?;
+				scene_iteration_limit = None;
+			},
+			"--exponent" => fractal.exponent = parse_complex(&value).ok_or_else(invalid)?,
+			"--polynomial" => fractal.polynomial = parse_polynomial(&value).ok_or_else(invalid)?.into(),
+			"--lyapunov-sequence" => fractal.lyapunov_sequence = value.parse().map_err(Error::InvalidLyapunovSequence)?,
+			"--lyapunov-region" => (fractal.lyapunov_region_min, fractal.lyapunov_region_max) = parse_region(&value).ok_or_else(invalid)?,
+			"--formula" =>
+			{
+				value.parse::<Formula64>().map_err(Error::InvalidFormula)?;
+				scene.formula = value;
+			},
+			"--variation" => fractal.variation = parse_variation(&value).ok_or_else(invalid)?,
+			"--technique" => match url_fragment::legacy_orbit_trap(&value)
+			{
+				Some(trap) =>
+				{
+					fractal.render_technique = RenderTechnique::OrbitTrap;
+					fractal.orbit_trap = trap;
+				},
+				None => fractal.render_technique = parse_technique(&value).ok_or_else(invalid)?,
+			},
+			"--interior" => fractal.interior_coloring = parse_interior(&value).ok_or_else(invalid)?,
+			"--light" =>
+			{
+				(fractal.lighting.azimuth, fractal.lighting.elevation) = parse_pair(&value, ',').ok_or_else(invalid)?;
+				fractal.lighting.distance_estimate = 1;
+			},
+			"--reflection" =>
+			{
+				let lighting = &mut fractal.lighting;
+				(lighting.ambient, lighting.diffuse, lighting.specular, lighting.shininess) = parse_reflection(&value).ok_or_else(invalid)?;
+			},
+			"--trap-shape" => fractal.orbit_trap.shape = parse_trap_shape(&value).ok_or_else(invalid)?,
+			"--trap" =>
+			{
+				let trap = &mut fractal.orbit_trap;
+				let (center, rotation, size) = parse_trap(&value).ok_or_else(invalid)?;
+				trap.center = center;
+				trap.rotation = rotation.unwrap_or(trap.rotation);
+				trap.size = size.unwrap_or(trap.size);
+			},
+			"--trap-coloring" => fractal.orbit_trap.coloring = parse_trap_coloring(&value).ok_or_else(invalid)?,
+			"--palette" => fractal.color_palette = parse_palette(&value).ok_or_else(invalid)?,
+			"--gradient" => *gradient = Gradient::new(&parse_gradient_stops(&value).ok_or_else(invalid)?, gradient.interpolation),
+			"--gradient-interpolation" => gradient.interpolation = parse_interpolation(&value).ok_or_else(invalid)?,
+			"--precision" => precision = parse_precision(&value).ok_or_else(invalid)?,
+			"--center" =>
+			{
+				// Parsed with the double-double precision so that deep zoom locations are not truncated
+				let (x, y): (DoubleDouble, DoubleDouble) = parse_pair(&value, ',').ok_or_else(invalid)?;
+				scene.center = DDVec2::new(x, y).into();
+			},
+			"--julia-constant" =>
+			{
+				let (x, y) = parse_pair(&value, ',').ok_or_else(invalid)?;
+				fractal.secondary_pos = Complex64::new(x, y);
+			},
+			"--zoom" => scene.zoom = value.parse().ok().filter(|zoom: &f64| *zoom > 0.0).ok_or_else(invalid)?,
+			"--iterations" => iteration_limit = Some(value.parse().map_err(|_| invalid())?),
+			"--color-frequency" => fractal.color_frequency = value.parse().map_err(|_| invalid())?,
+			"--supersampling" => fractal.samples_per_axis = value.parse().ok().filter(|samples: &u32| (1..=16).contains(samples)).ok_or_else(invalid)?,
+			"--size" =>
+			{
+				let (width, height) = parse_pair(&value, 'x').filter(|(w, h)| *w > 0 && *h > 0).ok_or_else(invalid)?;
+				size = uvec2(width, height);
+			},
+			_ => return Err(Error::UnknownArgument(arg)),
+		}
+	}
+
+	scene.fractal.iteration_limit = iteration_limit.or(scene_iteration_limit).unwrap_or(scene.fractal.fractal_kind.default_iteration_limit());
+
+	if output.is_none() && save_scene.is_none() && frames.is_none()
+	{
+		return Err(Error::MissingOutput);
+	}
+
+	if frames.is_some() && scene.animation.keyframes().is_empty()
+	{
+		return Err(Error::NoKeyframes);
+	}
+
+	let view = View
+	{
+		center: scene.center.into(),
+		zoom: scene.zoom,
+		size,
+		fractal: scene.fractal,
+		formula: scene.formula.parse().map_err(Error::InvalidFormula)?,
+		gradient: scene.gradient,
+		precision,
+	};
+
+	Ok(Some(Args
+	{
+		output,
+		save_scene,
+		frames,
+		scene,
+		view,
+	}))
+}
+
+fn run() -> Result<(), Box<dyn std::error::Error>>
+{
+	let Some(args) = parse_args(std::env::args().skip(1))?
+	else
+	{
+		print!("{USAGE}");
+		return Ok(());
+	};
+
+	if let Some(path) = &args.save_scene
+	{
+		args.scene.save(path)?;
+	}
+
+	if let Some(path) = &args.output
+	{
+		let data = headless::render(&args.view);
+		headless::save_png(path, args.view.size, &data)?;
+	}
+
+	if let Some(directory) = &args.frames
+	{
+		let animation = &args.scene.animation;
+		let frame_count = animation.frame_count();
+		animation.render_frames(&args.view, directory, |written|
+		{
+			eprint!("\rframe {written} of {frame_count}");
+			true
+		})?;
+		eprintln!();
+	}
+
+	Ok(())
+}
+
+fn main() -> ExitCode
+{
+	match run()
+	{
+		Ok(()) => ExitCode::SUCCESS,
+		Err(error) =>
+		{
+			eprintln!("error: {error}");
+			ExitCode::FAILURE
+		},
+	}
+}
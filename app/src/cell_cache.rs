@@ -0,0 +1,357 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
+use std::time::SystemTime;
+
+use winit::dpi::PhysicalSize;
+
+use crate::quad_cell::QuadPos;
+use crate::Target;
+
+
+const FILE_EXTENSION: &str = "cell";
+
+/// Stable 64 bit FNV-1a hash, so that the keys of the cells stay the same across sessions
+#[derive(Copy, Clone)]
+pub struct CellHasher(u64);
+
+impl Default for CellHasher
+{
+	fn default() -> Self
+	{
+		Self(0xcbf29ce484222325)
+	}
+}
+
+impl CellHasher
+{
+	pub fn write(&mut self, bytes: &[u8])
+	{
+		for byte in bytes
+		{
+			self.0 ^= *byte as u64;
+			self.0 = self.0.wrapping_mul(0x100000001b3);
+		}
+	}
+
+	pub fn finish(self) -> u64
+	{
+		self.0
+	}
+
+	/// The key of the cell at `pos`, from the hasher of the parameters of the view
+	pub fn cell_key(mut self, pos: QuadPos) -> u64
+	{
+		self.write(&pos.unscaled_pos.x.to_le_bytes());
+		self.write(&pos.unscaled_pos.y.to_le_bytes());
+		self.write(&pos.exponent.to_le_bytes());
+		self.finish()
+	}
+}
+
+struct Entry
+{
+	size: u64,
+	last_used: SystemTime,
+}
+
+/// Pixels of computed cells saved in a directory, the least recently used cells are removed above the size limit.
+///
+/// The last use of a cell is its file modification time, so that it is kept across sessions.
+pub struct CellCache
+{
+	directory: PathBuf,
+	max_size: u64,
+	entries: HashMap<u64, Entry>,
+	size: u64,
+}
+
+impl CellCache
+{
+	pub fn open(directory: impl Into<PathBuf>, max_size: u64) -> std::io::Result<Self>
+	{
+		let directory = directory.into();
+		std::fs::create_dir_all(&directory)?;
+
+		let mut entries = HashMap::new();
+		for file in std::fs::read_dir(&directory)?
+		{
+			let file = file?;
+			let path = file.path();
+			let Some(key) = Self::key_from_path(&path)
+			else
+			{
+				continue;
+			};
+
+			let metadata = file.metadata()?;
+			entries.insert(key, Entry
+			{
+				size: metadata.len(),
+				last_used: metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+			});
+		}
+
+		let mut cache = Self
+		{
+			directory,
+			max_size,
+			size: entries.values().map(|entry| entry.size).sum(),
+			entries,
+		};
+		cache.evict();
+
+		Ok(cache)
+	}
+
+	pub fn directory(&self) -> &Path
+	{
+		&self.directory
+	}
+
+	/// Total size of the cached cells in bytes
+	pub fn size(&self) -> u64
+	{
+		self.size
+	}
+
+	pub fn max_size(&self) -> u64
+	{
+		self.max_size
+	}
+
+	pub fn set_max_size(&mut self, max_size: u64)
+	{
+		self.max_size = max_size;
+		self.evict();
+	}
+
+	pub fn contains(&self, key: u64) -> bool
+	{
+		self.entries.contains_key(&key)
+	}
+
+	/// Reads the pixels of a cell, which becomes the most recently used
+	pub fn get(&mut self, key: u64, expected_size: u64) -> Option<Vec<u8>>
+	{
+		let path = self.directory.join(Self::file_name(key));
+		let entry = self.entries.get_mut(&key)?;
+
+		let Some(data) = std::fs::read(&path).ok().filter(|data| data.len() as u64 == expected_size)
+		else
+		{
+			// Written with another cell size, or modified outside of the app
+			self.remove(key);
+			return None;
+		};
+
+		let now = SystemTime::now();
+		entry.last_used = now;
+		if let Ok(file) = File::options().write(true).open(&path)
+		{
+			let _ = file.set_modified(now);
+		}
+
+		Some(data)
+	}
+
+	pub fn insert(&mut self, key: u64, data: &[u8]) -> std::io::Result<()>
+	{
+		if self.contains(key)
+		{
+			return Ok(());
+		}
+
+		File::create(self.directory.join(Self::file_name(key)))?.write_all(data)?;
+
+		self.entries.insert(key, Entry
+		{
+			size: data.len() as u64,
+			last_used: SystemTime::now(),
+		});
+		self.size += data.len() as u64;
+		self.evict();
+
+		Ok(())
+	}
+
+	/// Removes every cached cell
+	pub fn clear(&mut self)
+	{
+		let keys: Vec<u64> = self.entries.keys().copied().collect();
+		for key in keys
+		{
+			self.remove(key);
+		}
+	}
+
+	fn remove(&mut self, key: u64)
+	{
+		if let Some(entry) = self.entries.remove(&key)
+		{
+			self.size -= entry.size;
+			let _ = std::fs::remove_file(self.directory.join(Self::file_name(key)));
+		}
+	}
+
+	fn evict(&mut self)
+	{
+		if self.size <= self.max_size
+		{
+			return;
+		}
+
+		let mut keys: Vec<(SystemTime, u64)> = self.entries.iter().map(|(key, entry)| (entry.last_used, *key)).collect();
+		keys.sort_unstable();
+
+		for (_last_used, key) in keys
+		{
+			if self.size <= self.max_size
+			{
+				break;
+			}
+			self.remove(key);
+		}
+	}
+
+	fn file_name(key: u64) -> String
+	{
+		format!("{key:016x}.{FILE_EXTENSION}")
+	}
+
+	fn key_from_path(path: &Path) -> Option<u64>
+	{
+		if path.extension()? != FILE_EXTENSION
+		{
+			return None;
+		}
+
+		u64::from_str_radix(path.file_stem()?.to_str()?, 16).ok()
+	}
+}
+
+/// Copy of a cell texture being read back to be saved in the cache
+struct Readback
+{
+	key: u64,
+	buffer: wgpu::Buffer,
+	padded_bytes_per_row: u32,
+	bytes_per_row: u32,
+	/// Result of the mapping, set by the map callback
+	mapped: Arc<OnceLock<bool>>,
+	/// Whether the mapping was requested, only possible once the copy was submitted
+	mapping: bool,
+}
+
+/// Reads computed cells back from the GPU and saves them in a [`CellCache`]
+#[derive(Default)]
+pub struct CellReadbacks
+{
+	readbacks: Vec<Readback>,
+}
+
+impl CellReadbacks
+{
+	/// Copies `texture` to a buffer that can be read once the commands are submitted
+	pub fn queue(&mut self, target: &Target, commands: &mut wgpu::CommandEncoder, texture: &wgpu::Texture, key: u64)
+	{
+		let size = PhysicalSize::new(texture.width(), texture.height());
+		let bytes_per_row = size.width * texture.format().block_copy_size(None).unwrap_or(4);
+		let padded_bytes_per_row = wgpu::util::align_to(bytes_per_row, wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+
+		let buffer = target.device.create_buffer(
+			&wgpu::BufferDescriptor
+			{
+				label: Some("cell_readback"),
+				size: (padded_bytes_per_row * size.height) as u64,
+				usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+				mapped_at_creation: false,
+			});
+
+		commands.copy_texture_to_buffer(
+			wgpu::ImageCopyTexture
+			{
+				texture,
+				mip_level: 0,
+				origin: wgpu::Origin3d::ZERO,
+				aspect: wgpu::TextureAspect::All,
+			},
+			wgpu::ImageCopyBuffer
+			{
+				buffer: &buffer,
+				layout: wgpu::ImageDataLayout
+				{
+					offset: 0,
+					bytes_per_row: Some(padded_bytes_per_row),
+					rows_per_image: None,
+				},
+			},
+			texture.size());
+
+		self.readbacks.push(Readback
+		{
+			key,
+			buffer,
+			padded_bytes_per_row,
+			bytes_per_row,
+			mapped: Arc::new(OnceLock::new()),
+			mapping: false,
+		});
+	}
+
+	/// Saves the cells that were read back, must be called after the commands of the previous calls to [`Self::queue`] are submitted
+	pub fn save_finished(&mut self, target: &Target, cache: &mut CellCache)
+	{
+		if self.readbacks.is_empty()
+		{
+			return;
+		}
+
+		for readback in self.readbacks.iter_mut().filter(|readback| !readback.mapping)
+		{
+			let mapped = readback.mapped.clone();
+			readback.buffer.slice(..).map_async(wgpu::MapMode::Read, move |result| { let _ = mapped.set(result.is_ok()); });
+			readback.mapping = true;
+		}
+
+		target.device.poll(wgpu::Maintain::Poll);
+
+		self.readbacks.retain(|readback|
+		{
+			match readback.mapped.get()
+			{
+				None => return true,
+				Some(false) => return false,
+				Some(true) => {},
+			}
+
+			let bytes_per_row = readback.bytes_per_row as usize;
+			let data: Vec<u8> = readback.buffer.slice(..).get_mapped_range()
+				.chunks(readback.padded_bytes_per_row as usize)
+				.flat_map(|row| &row[..bytes_per_row])
+				.copied()
+				.collect();
+
+			if let Err(error) = cache.insert(readback.key, &data)
+			{
+				log::warn!("Couldn't save a cell in the cache: {error}");
+			}
+
+			false
+		});
+	}
+
+	/// Whether cells are still being read back
+	pub fn is_empty(&self) -> bool
+	{
+		self.readbacks.is_empty()
+	}
+
+	/// Forgets the cells being read back
+	pub fn clear(&mut self)
+	{
+		self.readbacks.clear();
+	}
+}
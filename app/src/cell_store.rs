@@ -0,0 +1,180 @@
+use std::collections::BTreeMap;
+
+use glam::DVec2;
+
+use fractal_renderer_shared::histogram::CellHistogram;
+
+use crate::quad_cell::QuadPos;
+use crate::render::Instance;
+
+
+/// Weights of the eviction priority, a cell unseen for a second weighs like a cell one viewport away or one level away from the view
+const FRAMES_PER_PRIORITY: f64 = 60.0;
+const VIEWPORTS_PER_PRIORITY: f64 = 1.0;
+const LEVELS_PER_PRIORITY: f64 = 1.0;
+
+struct StoredCell
+{
+	instance: Instance,
+	/// Frame at which the cell was last visible
+	last_visible: u64,
+	is_preview: bool,
+	/// Distribution of the escape values, once collected by the backend
+	histogram: Option<CellHistogram>,
+}
+
+/// The loaded cells, the least useful ones are evicted when their textures use more GPU memory than the budget
+pub struct CellStore
+{
+	/// Sorted from the largest to the smallest cells, which are drawn on top
+	cells: BTreeMap<QuadPos, StoredCell>,
+	/// GPU memory used by the textures of the cells
+	memory_usage: u64,
+	budget: u64,
+	frame: u64,
+}
+
+impl CellStore
+{
+	pub fn new(budget: u64) -> Self
+	{
+		Self
+		{
+			cells: BTreeMap::new(),
+			memory_usage: 0,
+			budget,
+			frame: 0,
+		}
+	}
+
+	pub fn len(&self) -> usize
+	{
+		self.cells.len()
+	}
+
+	/// GPU memory used by the loaded cells, in bytes
+	pub fn memory_usage(&self) -> u64
+	{
+		self.memory_usage
+	}
+
+	pub fn budget(&self) -> u64
+	{
+		self.budget
+	}
+
+	/// Changes the budget, the cells above it are evicted by the next call to [`Self::evict`]
+	pub fn set_budget(&mut self, budget: u64)
+	{
+		self.budget = budget;
+	}
+
+	pub fn contains(&self, pos: QuadPos) -> bool
+	{
+		self.cells.contains_key(&pos)
+	}
+
+	pub fn is_preview(&self, pos: QuadPos) -> bool
+	{
+		self.cells.get(&pos).is_some_and(|cell| cell.is_preview)
+	}
+
+	pub fn get(&self, pos: QuadPos) -> Option<&Instance>
+	{
+		self.cells.get(&pos).map(|cell| &cell.instance)
+	}
+
+	pub fn histogram(&self, pos: QuadPos) -> Option<&CellHistogram>
+	{
+		self.cells.get(&pos)?.histogram.as_ref()
+	}
+
+	pub fn set_histogram(&mut self, pos: QuadPos, histogram: CellHistogram)
+	{
+		if let Some(cell) = self.cells.get_mut(&pos)
+		{
+			cell.histogram = Some(histogram);
+		}
+	}
+
+	/// Adds a cell, or replaces the cell at the same position
+	pub fn insert(&mut self, pos: QuadPos, instance: Instance, is_preview: bool) -> &Instance
+	{
+		self.memory_usage += instance.memory_size();
+		let cell = StoredCell
+		{
+			instance,
+			last_visible: self.frame,
+			is_preview,
+			histogram: None,
+		};
+
+		if let Some(replaced) = self.cells.insert(pos, cell)
+		{
+			self.memory_usage -= replaced.instance.memory_size();
+		}
+		&self.cells[&pos].instance
+	}
+
+	/// The instances to draw, the smallest cells last
+	pub fn instances(&self) -> impl Iterator<Item = &Instance>
+	{
+		self.cells.values().map(|cell| &cell.instance)
+	}
+
+	pub fn clear(&mut self)
+	{
+		self.cells.clear();
+		self.memory_usage = 0;
+	}
+
+	/// Starts a new frame, in which the cells at `visible` are seen
+	pub fn mark_visible(&mut self, visible: impl IntoIterator<Item = QuadPos>)
+	{
+		self.frame += 1;
+		for pos in visible
+		{
+			if let Some(cell) = self.cells.get_mut(&pos)
+			{
+				cell.last_visible = self.frame;
+			}
+		}
+	}
+
+	/// Removes cells until the budget is met, the cells that were not visible for a long time, are far from `view_center` or far from the level
+	/// `view_exponent` go first. The cells visible in the current frame are kept even above the budget.
+	pub fn evict(&mut self, view_center: DVec2, viewport_size: DVec2, view_exponent: i32)
+	{
+		if self.memory_usage <= self.budget
+		{
+			return;
+		}
+
+		let viewport_length = viewport_size.length().max(f64::MIN_POSITIVE);
+		let mut candidates: Vec<(f64, QuadPos)> = self.cells.iter()
+			.filter(|(_pos, cell)| cell.last_visible != self.frame)
+			.map(|(pos, cell)|
+			{
+				let cell_center = (pos.cell_bottom_left() + pos.cell_top_right()) * 0.5;
+				let age = (self.frame - cell.last_visible) as f64 / FRAMES_PER_PRIORITY;
+				let distance = (cell_center - view_center).length() / viewport_length / VIEWPORTS_PER_PRIORITY;
+				let level = (pos.exponent - view_exponent).abs() as f64 / LEVELS_PER_PRIORITY;
+				(age + distance + level, *pos)
+			})
+			.collect();
+
+		candidates.sort_unstable_by(|(priority1, _pos1), (priority2, _pos2)| priority2.total_cmp(priority1));
+
+		for (_priority, pos) in candidates
+		{
+			if self.memory_usage <= self.budget
+			{
+				break;
+			}
+			if let Some(cell) = self.cells.remove(&pos)
+			{
+				self.memory_usage -= cell.instance.memory_size();
+			}
+		}
+	}
+}
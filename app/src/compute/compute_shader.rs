@@ -1,8 +1,9 @@
 use fractal_renderer_shared as shared;
 use crate::app::AppData;
-use crate::render::Render;
+use crate::render::{CellFormat, Render};
 use crate::Target;
 use crate::quad_cell::QuadPos;
+use super::{CellResolution, HistogramReadbacks};
 use wgpu::{ComputePipeline, Buffer, BindGroup, CommandEncoder};
 use winit::dpi::PhysicalSize;
 use glam::dvec2;
@@ -12,15 +13,21 @@ pub struct ShaderCompute
 {
     fixed: Fixed,
     dynamic: Dynamic,
+    histograms: HistogramReadbacks,
 }
 
 struct Fixed
 {
     workgroup_size: glam::UVec2,
     use_double_precision: bool,
+    cell_format: CellFormat,
+    /// Size of a pixel of the output buffer in bytes
+    texel_size: u32,
     compute_pipeline: ComputePipeline,
     bind_group_layout: wgpu::BindGroupLayout,
     params_buffer: Buffer,
+    formula_buffer: Buffer,
+    gradient_buffer: Buffer,
 }
 
 struct Dynamic
@@ -32,15 +39,15 @@ struct Dynamic
 
 impl Fixed
 {
-    fn new(target: &Target, shader_module: &wgpu::ShaderModule, workgroup_size: glam::UVec2, use_double_precision: bool) -> Self
+    fn new(target: &Target, shader_module: &wgpu::ShaderModule, workgroup_size: glam::UVec2, cell_format: CellFormat, use_double_precision: bool) -> Self
     {
-        let data_size = if use_double_precision
+        let (data_size, formula_size) = if use_double_precision
         {
-            std::mem::size_of::<shared::compute::Params64>()
+            (std::mem::size_of::<shared::compute::Params64>(), std::mem::size_of::<shared::formula::Formula64>())
         }
         else
         {
-            std::mem::size_of::<shared::compute::Params32>()
+            (std::mem::size_of::<shared::compute::Params32>(), std::mem::size_of::<shared::formula::Formula32>())
         };
 
         let params_buffer = target.device.create_buffer(
@@ -51,6 +58,24 @@ impl Fixed
                 usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
                 mapped_at_creation: false,
             });
+
+        let formula_buffer = target.device.create_buffer(
+            &wgpu::BufferDescriptor
+            {
+                label: Some("formula"),
+                size: formula_size as u64,
+                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            });
+
+        let gradient_buffer = target.device.create_buffer(
+            &wgpu::BufferDescriptor
+            {
+                label: Some("gradient"),
+                size: std::mem::size_of::<shared::gradient::Gradient>() as u64,
+                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            });
         
         let bind_group_layout = target.device.create_bind_group_layout(
             &wgpu::BindGroupLayoutDescriptor
@@ -80,7 +105,31 @@ impl Fixed
                             min_binding_size: None,
                             ty: wgpu::BufferBindingType::Storage { read_only: false },
                         },
-                    }
+                    },
+                    wgpu::BindGroupLayoutEntry
+                    {
+                        binding: 2,
+                        count: None,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer
+                        {
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                            ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        },
+                    },
+                    wgpu::BindGroupLayoutEntry
+                    {
+                        binding: 3,
+                        count: None,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer
+                        {
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                            ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        },
+                    },
                 ],
             });
 
@@ -98,7 +147,11 @@ impl Fixed
                 label: Some("compute_pipeline"),
                 layout: Some(&pipeline_layout),
                 module: shader_module,
-                entry_point: "compute_mandelbrot",
+                entry_point: match cell_format
+                {
+                    CellFormat::Color => "compute_mandelbrot",
+                    CellFormat::EscapeData => "compute_escape_data",
+                },
                 compilation_options: Default::default(),
                 cache: None,
             });
@@ -109,7 +162,11 @@ impl Fixed
             compute_pipeline,
             bind_group_layout,
             params_buffer,
+            formula_buffer,
+            gradient_buffer,
             use_double_precision,
+            cell_format,
+            texel_size: cell_format.texel_size(target),
         }
     }
 }
@@ -120,11 +177,11 @@ impl Dynamic
     {
         let size = PhysicalSize
         {
-            width: wgpu::util::align_to(texture_size.width, fixed.workgroup_size.x.max(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT / std::mem::size_of::<u32>() as u32)),
+            width: wgpu::util::align_to(texture_size.width, fixed.workgroup_size.x.max(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT / fixed.texel_size)),
             height: wgpu::util::align_to(texture_size.height, fixed.workgroup_size.y)
         };
         
-        let mem_size = (size.width * size.height * std::mem::size_of::<u32>() as u32) as wgpu::BufferAddress;
+        let mem_size = (size.width * size.height * fixed.texel_size) as wgpu::BufferAddress;
         
         let output_buffer = target.device.create_buffer(
             &wgpu::BufferDescriptor
@@ -150,7 +207,17 @@ impl Dynamic
                     {
                         binding: 1,
                         resource: output_buffer.as_entire_binding(),
-                    }
+                    },
+                    wgpu::BindGroupEntry
+                    {
+                        binding: 2,
+                        resource: fixed.formula_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry
+                    {
+                        binding: 3,
+                        resource: fixed.gradient_buffer.as_entire_binding(),
+                    },
                 ],
             });
 
@@ -165,15 +232,16 @@ impl Dynamic
 
 impl ShaderCompute
 {
-    pub fn new(target: &Target, shader_module: &wgpu::ShaderModule, workgroup_size: glam::UVec2, texture_size: PhysicalSize<u32>, use_double_precision: bool) -> Self
+    pub fn new(target: &Target, shader_module: &wgpu::ShaderModule, workgroup_size: glam::UVec2, texture_size: PhysicalSize<u32>, cell_format: CellFormat, use_double_precision: bool) -> Self
     {
-        let fixed = Fixed::new(target, shader_module, workgroup_size, use_double_precision);
+        let fixed = Fixed::new(target, shader_module, workgroup_size, cell_format, use_double_precision);
         let dynamic = Dynamic::new(target, &fixed, texture_size);
 
         Self
         {
             fixed,
             dynamic,
+            histograms: HistogramReadbacks::default(),
         }
     }
 
@@ -182,12 +250,14 @@ impl ShaderCompute
         &self.dynamic.output_buffer
     }
 
-    pub fn make_compute_pass(&self, commands: &mut CommandEncoder)
+    /// Computes the cell at the size given by `resolution`, the rows of the output buffer are as wide as that size
+    pub fn make_compute_pass(&self, commands: &mut CommandEncoder, resolution: CellResolution)
     {
+        let size = resolution.texture_size(self.dynamic.size);
         let mut compute_pass = commands.begin_compute_pass(&wgpu::ComputePassDescriptor { label: None, timestamp_writes: None });
         compute_pass.set_bind_group(0, &self.dynamic.bind_group, &[]);
         compute_pass.set_pipeline(&self.fixed.compute_pipeline);
-        compute_pass.dispatch_workgroups(self.dynamic.size.width / self.fixed.workgroup_size.x, self.dynamic.size.height / self.fixed.workgroup_size.y, 1);
+        compute_pass.dispatch_workgroups(size.width / self.fixed.workgroup_size.x, size.height / self.fixed.workgroup_size.y, 1);
     }
 
     pub fn set_params(
@@ -207,12 +277,32 @@ impl ShaderCompute
         }
     }
 
+    fn set_formula(
+        &self,
+        queue: &wgpu::Queue,
+        formula: &shared::formula::Formula64
+    )
+    {
+        if self.fixed.use_double_precision
+        {
+            queue.write_buffer(&self.fixed.formula_buffer, 0, bytemuck::bytes_of(formula));
+        }
+        else
+        {
+            let formula: shared::formula::Formula32 = (*formula).into();
+            queue.write_buffer(&self.fixed.formula_buffer, 0, bytemuck::bytes_of(&formula));
+        }
+    }
+
     pub fn copy_buffer_to_texture(
         &self,
         commands: &mut wgpu::CommandEncoder,
         destination: &wgpu::Texture,
+        resolution: CellResolution,
     )
     {
+        let size = resolution.texture_size(self.dynamic.size);
+
         commands.copy_buffer_to_texture(
             wgpu::ImageCopyBuffer
             {
@@ -220,7 +310,7 @@ impl ShaderCompute
                 layout: wgpu::ImageDataLayout
                 {
                     offset: 0,
-                    bytes_per_row: Some(self.dynamic.size.width * std::mem::size_of::<u32>() as u32),
+                    bytes_per_row: Some(size.width * self.fixed.texel_size),
                     rows_per_image: None
                 }
             },
@@ -231,11 +321,16 @@ impl ShaderCompute
                 origin: wgpu::Origin3d::ZERO,
                 aspect: wgpu::TextureAspect::All,
             },
-            destination.size()
+            wgpu::Extent3d
+            {
+                width: size.width,
+                height: size.height,
+                depth_or_array_layers: 1,
+            }
         );
     }
 
-    fn compute_cell(&self, target: &Target, render: &Render, app: &mut AppData, commands: &mut wgpu::CommandEncoder, pos: QuadPos)
+    fn compute_cell(&mut self, target: &Target, render: &Render, app: &mut AppData, commands: &mut wgpu::CommandEncoder, pos: QuadPos, resolution: CellResolution)
     {
 		let cell_size = pos.cell_size();
 		let cell_pos = pos.cell_bottom_left();
@@ -244,13 +339,21 @@ impl ShaderCompute
 		{
 			min_pos: cell_pos + dvec2(0.0, cell_size),
 			max_pos: cell_pos + dvec2(cell_size, 0.0),
-			fractal: app.fractal_params,
+			fractal: app.cell_fractal_params(pos),
 		});
+		self.set_formula(&target.queue, &app.formula);
+		target.queue.write_buffer(&self.fixed.gradient_buffer, 0, bytemuck::bytes_of(&app.gradient));
+
+        let generation = app.cells_generation();
+        let cell = app.make_cell(target, render, pos, resolution);
 
-        let cell = app.make_cell(target, render, pos);
+		self.make_compute_pass(commands, resolution);
+		self.copy_buffer_to_texture(commands, cell.fractal_texture(), resolution);
 
-		self.make_compute_pass(commands);
-		self.copy_buffer_to_texture(commands, cell.fractal_texture());
+		if self.fixed.cell_format == CellFormat::EscapeData
+		{
+			self.histograms.queue(target, commands, cell.fractal_texture(), pos, generation, resolution);
+		}
     }
 }
 
@@ -258,14 +361,23 @@ impl super::Compute for ShaderCompute
 {
     fn update_before_render(&mut self, target: &Target, render: &Render, app: &mut AppData, commands: &mut wgpu::CommandEncoder)
     {
-        // Find new cell to load
-		for pos in app.visible_cells()
-		{
-            if !app.is_cell_loaded(pos)
-            {
-                self.compute_cell(target, render, app, commands, pos);
-                return;
-            }
-		}
+        self.histograms.receive_finished(target, app);
+
+        // Find new cell to load, or a preview to refine
+        let next_cell = super::cells_to_compute(app).next();
+        if let Some((pos, resolution)) = next_cell
+        {
+            self.compute_cell(target, render, app, commands, pos, resolution);
+        }
+    }
+
+    fn backend(&self) -> super::ComputeBackend
+    {
+        super::ComputeBackend::ComputeShader
+    }
+
+    fn cell_format(&self) -> CellFormat
+    {
+        self.fixed.cell_format
     }
 }
@@ -0,0 +1,149 @@
+use std::sync::{Arc, OnceLock};
+
+use fractal_renderer_shared as shared;
+use shared::compute::EscapeData;
+use shared::histogram::CellHistogram;
+use winit::dpi::PhysicalSize;
+
+use crate::Target;
+use crate::app::AppData;
+use crate::quad_cell::QuadPos;
+use super::CellResolution;
+
+/// The histogram of the escape data of the first `size.height` rows of `data`, each row starts with `size.width` texels
+pub fn read_cell_histogram(data: &[u8], bytes_per_row: usize, size: PhysicalSize<u32>) -> CellHistogram
+{
+    let texel_size = std::mem::size_of::<EscapeData>();
+    let row_size = size.width as usize * texel_size;
+    let texels = data.chunks(bytes_per_row)
+        .take(size.height as usize)
+        .flat_map(move |row| row[..row_size].chunks_exact(texel_size))
+        .map(|texel| EscapeData
+        {
+            value: f32::from_ne_bytes([texel[0], texel[1], texel[2], texel[3]]),
+            coverage: f32::from_ne_bytes([texel[4], texel[5], texel[6], texel[7]]),
+            interior_value: f32::from_ne_bytes([texel[8], texel[9], texel[10], texel[11]]),
+            interior_coverage: f32::from_ne_bytes([texel[12], texel[13], texel[14], texel[15]]),
+        });
+
+    CellHistogram::new(texels)
+}
+
+/// Copy of the computed part of a cell texture being read back
+struct Readback
+{
+    pos: QuadPos,
+    generation: u64,
+    resolution: CellResolution,
+    buffer: wgpu::Buffer,
+    padded_bytes_per_row: u32,
+    size: PhysicalSize<u32>,
+    /// Result of the mapping, set by the map callback
+    mapped: Arc<OnceLock<bool>>,
+    /// Whether the mapping was requested, only possible once the copy was submitted
+    mapping: bool,
+}
+
+/// Reads the escape data computed on the GPU back to collect the histograms of the cells
+#[derive(Default)]
+pub struct HistogramReadbacks
+{
+    readbacks: Vec<Readback>,
+}
+
+impl HistogramReadbacks
+{
+    /// Copies the part of `texture` computed at `resolution` to a buffer that can be read once the commands are submitted
+    pub fn queue(&mut self, target: &Target, commands: &mut wgpu::CommandEncoder, texture: &wgpu::Texture, pos: QuadPos, generation: u64, resolution: CellResolution)
+    {
+        let size = resolution.texture_size(PhysicalSize::new(texture.width(), texture.height()));
+        let bytes_per_row = size.width * std::mem::size_of::<EscapeData>() as u32;
+        let padded_bytes_per_row = wgpu::util::align_to(bytes_per_row, wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+
+        let buffer = target.device.create_buffer(
+            &wgpu::BufferDescriptor
+            {
+                label: Some("histogram_readback"),
+                size: (padded_bytes_per_row * size.height) as u64,
+                usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            });
+
+        commands.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture
+            {
+                texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::ImageCopyBuffer
+            {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout
+                {
+                    offset: 0,
+                    bytes_per_row: Some(padded_bytes_per_row),
+                    rows_per_image: None,
+                },
+            },
+            wgpu::Extent3d
+            {
+                width: size.width,
+                height: size.height,
+                depth_or_array_layers: 1,
+            });
+
+        self.readbacks.push(Readback
+        {
+            pos,
+            generation,
+            resolution,
+            buffer,
+            padded_bytes_per_row,
+            size,
+            mapped: Arc::new(OnceLock::new()),
+            mapping: false,
+        });
+    }
+
+    /// Gives the histograms of the cells that were read back to `app`, must be called after the commands of the previous calls to
+    /// [`Self::queue`] are submitted
+    pub fn receive_finished(&mut self, target: &Target, app: &mut AppData)
+    {
+        if self.readbacks.is_empty()
+        {
+            return;
+        }
+
+        for readback in self.readbacks.iter_mut().filter(|readback| !readback.mapping)
+        {
+            let mapped = readback.mapped.clone();
+            readback.buffer.slice(..).map_async(wgpu::MapMode::Read, move |result| { let _ = mapped.set(result.is_ok()); });
+            readback.mapping = true;
+        }
+
+        target.device.poll(wgpu::Maintain::Poll);
+
+        self.readbacks.retain(|readback|
+        {
+            match readback.mapped.get()
+            {
+                None => return true,
+                Some(false) => return false,
+                Some(true) => {},
+            }
+
+            let histogram = read_cell_histogram(&readback.buffer.slice(..).get_mapped_range(), readback.padded_bytes_per_row as usize, readback.size);
+            app.set_cell_histogram(readback.pos, readback.generation, readback.resolution, histogram);
+
+            false
+        });
+
+        // Keep polling until every histogram is received
+        if !self.readbacks.is_empty()
+        {
+            app.request_redraw();
+        }
+    }
+}
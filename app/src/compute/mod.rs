@@ -1,34 +1,230 @@
 mod compute_shader;
 mod render_shader;
 mod threaded;
+mod histogram;
 
 pub use compute_shader::*;
 pub use render_shader::*;
 pub use threaded::*;
+pub use histogram::*;
+
+use winit::dpi::PhysicalSize;
 
 use crate::Target;
 use crate::app::AppData;
-use crate::render::Render;
+use crate::render::{CellFormat, Render};
+use crate::quad_cell::QuadPos;
+
+const COMPUTATION32_SHADER_CODE: &[u8] = include_bytes!(env!("fractal_renderer_shader_computation32.spv"));
+const COMPUTATION64_SHADER_CODE: &[u8] = include_bytes!(env!("fractal_renderer_shader_computation64.spv"));
+const COMPUTE32_SHADER_CODE: &[u8] = include_bytes!(env!("fractal_renderer_shader_compute32.spv"));
+const COMPUTE64_SHADER_CODE: &[u8] = include_bytes!(env!("fractal_renderer_shader_compute64.spv"));
 
 pub trait Compute: Sized + 'static
 {
     fn update_before_render(&mut self, target: &Target, render: &Render, app: &mut AppData, commands: &mut wgpu::CommandEncoder);
+
+    /// Whether cells can be computed from a reference orbit, see [`AppData::reference_orbit`]
+    fn supports_perturbation(&self) -> bool
+    {
+        false
+    }
+
+    fn backend(&self) -> ComputeBackend;
+
+    /// What the computed cells contain
+    fn cell_format(&self) -> CellFormat;
+}
+
+/// Resolution at which a cell is computed, a preview is shown until the cell is computed again at full resolution
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum CellResolution
+{
+    Preview,
+    Full,
+}
+
+impl CellResolution
+{
+    /// Ratio between the full resolution and the resolution of the previews
+    pub const PREVIEW_SCALE: u32 = 4;
+
+    /// Size of the part of the cell texture that is computed
+    pub fn texture_size(self, cell_size: PhysicalSize<u32>) -> PhysicalSize<u32>
+    {
+        match self
+        {
+            Self::Preview => PhysicalSize::new(cell_size.width / Self::PREVIEW_SCALE, cell_size.height / Self::PREVIEW_SCALE),
+            Self::Full => cell_size,
+        }
+    }
+}
+
+/// The visible cells left to compute, the missing cells are previewed first and then computed at full resolution
+pub fn cells_to_compute(app: &AppData) -> impl Iterator<Item = (QuadPos, CellResolution)> + '_
+{
+    let previews = app.visible_cells().filter(|pos| !app.is_cell_loaded(*pos)).map(|pos| (pos, CellResolution::Preview));
+    let refinements = app.visible_cells().filter(|pos| app.is_cell_preview(*pos)).map(|pos| (pos, CellResolution::Full));
+    previews.chain(refinements)
+}
+
+/// The implementations of [`Compute`] that can be selected at runtime
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ComputeBackend
+{
+    /// Computes the cells in a fragment shader, see [`ShaderRenderCompute`]
+    RenderPass,
+    /// See [`ShaderCompute`]
+    ComputeShader,
+    /// See [`ThreadedCompute`]
+    Cpu,
+}
+
+impl ComputeBackend
+{
+    pub const ALL: [Self; 3] = [Self::RenderPass, Self::ComputeShader, Self::Cpu];
+
+    pub fn name(self) -> &'static str
+    {
+        match self
+        {
+            Self::RenderPass => "Render Pass",
+            Self::ComputeShader => "Compute Shader",
+            Self::Cpu => "CPU",
+        }
+    }
+
+    pub fn is_supported(self, target: &Target) -> bool
+    {
+        match self
+        {
+            Self::RenderPass | Self::Cpu => true,
+            Self::ComputeShader => target.supports_compute_shader,
+        }
+    }
+
+    /// The backend to start with on the adapter of `target`
+    pub fn default_for(target: &Target) -> Self
+    {
+        // A software adapter is slower than the worker threads of the CPU backend
+        if target.is_software_adapter
+        {
+            Self::Cpu
+        }
+        else
+        {
+            Self::RenderPass
+        }
+    }
 }
 
 pub enum AnyCompute
 {
+    Render(ShaderRenderCompute),
     Shader(ShaderCompute),
     Threaded(ThreadedCompute),
 }
 
+impl AnyCompute
+{
+    /// Creates the backend, or the render pass backend if `backend` isn't supported by the adapter of `target`
+    pub fn new(target: &Target, backend: ComputeBackend, cell_size: PhysicalSize<u32>, cell_format: CellFormat) -> Self
+    {
+        let backend = if backend.is_supported(target)
+        {
+            backend
+        }
+        else
+        {
+            log::warn!("The {} backend is not supported by the adapter, falling back to the {} backend", backend.name(), ComputeBackend::RenderPass.name());
+            ComputeBackend::RenderPass
+        };
+
+        let use_double_precision = target.device.features().contains(wgpu::Features::SHADER_F64);
+        let create_shader_module = |label, code32, code64|
+        {
+            let code = if use_double_precision
+            {
+                code64
+            }
+            else
+            {
+                code32
+            };
+
+            target.device.create_shader_module(
+                wgpu::ShaderModuleDescriptor
+                {
+                    label: Some(label),
+                    source: wgpu::util::make_spirv(code),
+                })
+        };
+
+        match backend
+        {
+            ComputeBackend::RenderPass =>
+            {
+                let shader_module = create_shader_module("computation_shader", COMPUTATION32_SHADER_CODE, COMPUTATION64_SHADER_CODE);
+                Self::Render(ShaderRenderCompute::new(target, &shader_module, &shader_module, cell_size, cell_format, use_double_precision))
+            },
+            ComputeBackend::ComputeShader =>
+            {
+                let shader_module = create_shader_module("compute_shader", COMPUTE32_SHADER_CODE, COMPUTE64_SHADER_CODE);
+                let workgroup_size = glam::uvec2(16, 16);
+                Self::Shader(ShaderCompute::new(target, &shader_module, workgroup_size, cell_size, cell_format, use_double_precision))
+            },
+            ComputeBackend::Cpu => Self::Threaded(ThreadedCompute::new(cell_size, cell_format)),
+        }
+    }
+}
+
 impl Compute for AnyCompute
 {
     fn update_before_render(&mut self, target: &Target, render: &Render, app: &mut AppData, commands: &mut wgpu::CommandEncoder)
     {
+        // The pipelines are created again for another cell format
+        let requested_backend = app.take_requested_compute_backend();
+        if requested_backend.is_some() || app.cell_format() != self.cell_format()
+        {
+            *self = Self::new(target, requested_backend.unwrap_or(self.backend()), app.cell_texture_size(), app.cell_format());
+            app.set_compute_backend(self.backend(), self.supports_perturbation());
+        }
+
         match self
         {
+            Self::Render(render_shader) => render_shader.update_before_render(target, render, app, commands),
             Self::Shader(shader) => shader.update_before_render(target, render, app, commands),
             Self::Threaded(threaded) => threaded.update_before_render(target, render, app, commands),
         }
     }
-}
\ No newline at end of file
+
+    fn supports_perturbation(&self) -> bool
+    {
+        match self
+        {
+            Self::Render(render_shader) => render_shader.supports_perturbation(),
+            Self::Shader(shader) => shader.supports_perturbation(),
+            Self::Threaded(threaded) => threaded.supports_perturbation(),
+        }
+    }
+
+    fn backend(&self) -> ComputeBackend
+    {
+        match self
+        {
+            Self::Render(render_shader) => render_shader.backend(),
+            Self::Shader(shader) => shader.backend(),
+            Self::Threaded(threaded) => threaded.backend(),
+        }
+    }
+
+    fn cell_format(&self) -> CellFormat
+    {
+        match self
+        {
+            Self::Render(render_shader) => render_shader.cell_format(),
+            Self::Shader(shader) => shader.cell_format(),
+            Self::Threaded(threaded) => threaded.cell_format(),
+        }
+    }
+}
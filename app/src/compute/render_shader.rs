@@ -1,33 +1,223 @@
 use fractal_renderer_shared as shared;
 use crate::app::AppData;
-use crate::render::Render;
+use crate::render::{CellFormat, Render};
 use crate::Target;
 use crate::quad_cell::QuadPos;
-use wgpu::{BindGroup, Buffer, CommandEncoder, RenderPipeline, Texture};
+use super::{CellResolution, HistogramReadbacks};
+use crate::deep_zoom::ReferenceOrbit;
+use shared::fractal::escape_time_method::SeriesApproximation64;
+use wgpu::util::DeviceExt;
+use wgpu::{BindGroup, BindGroupLayout, Buffer, CommandEncoder, RenderPipeline, Texture, TextureFormat};
 use winit::dpi::PhysicalSize;
-use glam::dvec2;
+use glam::{dvec2, DVec2};
 
 
 pub struct ShaderRenderCompute
 {
     use_double_precision: bool,
+    cell_format: CellFormat,
     render_pipeline: RenderPipeline,
     param_uniform_buffer: Buffer,
+    formula_buffer: Buffer,
+    gradient_buffer: Buffer,
     bind_group: BindGroup,
     output_texture: Texture,
+    texture_size: PhysicalSize<u32>,
+    perturbation: Option<Perturbation>,
+    histograms: HistogramReadbacks,
+}
+
+/// Pipeline computing cells relative to a reference orbit, only available with double precision
+struct Perturbation
+{
+    render_pipeline: RenderPipeline,
+    bind_group_layout: BindGroupLayout,
+    bind_group: Option<BindGroup>,
+    reference_id: Option<u64>,
+    series_uniform_buffer: Buffer,
+}
+
+fn create_render_pipeline(target: &Target, bind_group_layout: &BindGroupLayout, vertex_shader_module: &wgpu::ShaderModule, fragment_shader_module: &wgpu::ShaderModule, fragment_entry_point: &str, format: TextureFormat) -> RenderPipeline
+{
+    let pipeline_layout = target.device.create_pipeline_layout(
+        &wgpu::PipelineLayoutDescriptor
+        {
+            label: Some("computation_render_pipeline_layout"),
+            bind_group_layouts: &[bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+    target.device.create_render_pipeline(
+        &wgpu::RenderPipelineDescriptor
+        {
+            label: Some("computation_render_pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState
+            {
+                module: vertex_shader_module,
+                entry_point: "vertex",
+                buffers: &[],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(wgpu::FragmentState
+            {
+                module: fragment_shader_module,
+                entry_point: fragment_entry_point,
+                targets: &[Some(format.into())],
+                compilation_options: Default::default(),
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        })
+}
+
+impl Perturbation
+{
+    fn new(target: &Target, vertex_shader_module: &wgpu::ShaderModule, fragment_shader_module: &wgpu::ShaderModule, cell_format: CellFormat, format: TextureFormat) -> Self
+    {
+        let bind_group_layout = target.device.create_bind_group_layout(
+            &wgpu::BindGroupLayoutDescriptor
+            {
+                label: Some("perturbation_render_bind_group_layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry
+                    {
+                        binding: 0,
+                        count: None,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer
+                        {
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                            ty: wgpu::BufferBindingType::Uniform,
+                        },
+                    },
+                    wgpu::BindGroupLayoutEntry
+                    {
+                        binding: 1,
+                        count: None,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer
+                        {
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                            ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        },
+                    },
+                    wgpu::BindGroupLayoutEntry
+                    {
+                        binding: 2,
+                        count: None,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer
+                        {
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                            ty: wgpu::BufferBindingType::Uniform,
+                        },
+                    },
+                    wgpu::BindGroupLayoutEntry
+                    {
+                        binding: 3,
+                        count: None,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer
+                        {
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                            ty: wgpu::BufferBindingType::Uniform,
+                        },
+                    },
+                ],
+            });
+
+        let fragment_entry_point = match cell_format
+        {
+            CellFormat::Color => "fragment_perturbation",
+            CellFormat::EscapeData => "fragment_perturbation_escape_data",
+        };
+        let render_pipeline = create_render_pipeline(target, &bind_group_layout, vertex_shader_module, fragment_shader_module, fragment_entry_point, format);
+
+        let series_uniform_buffer = target.device.create_buffer(
+            &wgpu::BufferDescriptor
+            {
+                label: Some("series_uniform"),
+                size: std::mem::size_of::<SeriesApproximation64>() as u64,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            });
+
+        Self
+        {
+            render_pipeline,
+            bind_group_layout,
+            bind_group: None,
+            reference_id: None,
+            series_uniform_buffer,
+        }
+    }
+
+    fn upload_reference(&mut self, target: &Target, param_uniform_buffer: &Buffer, gradient_buffer: &Buffer, reference: &ReferenceOrbit)
+    {
+        if self.reference_id == Some(reference.id)
+        {
+            return;
+        }
+
+        let reference_orbit_buffer = target.device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor
+            {
+                label: Some("reference_orbit"),
+                contents: bytemuck::cast_slice(&reference.orbit),
+                usage: wgpu::BufferUsages::STORAGE,
+            });
+
+        self.bind_group = Some(target.device.create_bind_group(
+            &wgpu::BindGroupDescriptor
+            {
+                label: Some("perturbation_render_bind_group"),
+                layout: &self.bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry
+                    {
+                        binding: 0,
+                        resource: param_uniform_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry
+                    {
+                        binding: 1,
+                        resource: reference_orbit_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry
+                    {
+                        binding: 2,
+                        resource: self.series_uniform_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry
+                    {
+                        binding: 3,
+                        resource: gradient_buffer.as_entire_binding(),
+                    },
+                ],
+            }));
+        self.reference_id = Some(reference.id);
+    }
 }
 
 impl ShaderRenderCompute
 {
-    pub fn new(target: &Target, vertex_shader_module: &wgpu::ShaderModule, fragment_shader_module: &wgpu::ShaderModule, texture_size: PhysicalSize<u32>, use_double_precision: bool) -> Self
+    pub fn new(target: &Target, vertex_shader_module: &wgpu::ShaderModule, fragment_shader_module: &wgpu::ShaderModule, texture_size: PhysicalSize<u32>, cell_format: CellFormat, use_double_precision: bool) -> Self
     {
-        let data_size = if use_double_precision
+        let (data_size, formula_size) = if use_double_precision
         {
-            std::mem::size_of::<shared::compute::Params64>()
+            (std::mem::size_of::<shared::compute::Params64>(), std::mem::size_of::<shared::formula::Formula64>())
         }
         else
         {
-            std::mem::size_of::<shared::compute::Params32>()
+            (std::mem::size_of::<shared::compute::Params32>(), std::mem::size_of::<shared::formula::Formula32>())
         };
 
         let param_uniform_buffer = target.device.create_buffer(
@@ -38,6 +228,24 @@ impl ShaderRenderCompute
                 usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
                 mapped_at_creation: false,
             });
+
+        let formula_buffer = target.device.create_buffer(
+            &wgpu::BufferDescriptor
+            {
+                label: Some("formula"),
+                size: formula_size as u64,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            });
+
+        let gradient_buffer = target.device.create_buffer(
+            &wgpu::BufferDescriptor
+            {
+                label: Some("gradient"),
+                size: std::mem::size_of::<shared::gradient::Gradient>() as u64,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            });
         
         let bind_group_layout = target.device.create_bind_group_layout(
             &wgpu::BindGroupLayoutDescriptor
@@ -55,45 +263,44 @@ impl ShaderRenderCompute
                             min_binding_size: None,
                             ty: wgpu::BufferBindingType::Uniform,
                         },
-                    }
+                    },
+                    wgpu::BindGroupLayoutEntry
+                    {
+                        binding: 1,
+                        count: None,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer
+                        {
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                            ty: wgpu::BufferBindingType::Uniform,
+                        },
+                    },
+                    wgpu::BindGroupLayoutEntry
+                    {
+                        binding: 2,
+                        count: None,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer
+                        {
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                            ty: wgpu::BufferBindingType::Uniform,
+                        },
+                    },
                 ],
             });
 
-        let pipeline_layout = target.device.create_pipeline_layout(
-            &wgpu::PipelineLayoutDescriptor
-            {
-                label: Some("computation_render_pipeline_layout"),
-                bind_group_layouts: &[&bind_group_layout],
-                push_constant_ranges: &[],
-            });
+        let format = cell_format.texture_format(target);
 
-        let format = target.config.format.add_srgb_suffix();
+        let fragment_entry_point = match cell_format
+        {
+            CellFormat::Color => "fragment",
+            CellFormat::EscapeData => "fragment_escape_data",
+        };
+        let render_pipeline = create_render_pipeline(target, &bind_group_layout, vertex_shader_module, fragment_shader_module, fragment_entry_point, format);
 
-        let render_pipeline = target.device.create_render_pipeline(
-            &wgpu::RenderPipelineDescriptor
-            {
-                label: Some("computation_render_pipeline"),
-                layout: Some(&pipeline_layout),
-                vertex: wgpu::VertexState
-                {
-                    module: vertex_shader_module,
-                    entry_point: "vertex",
-                    buffers: &[],
-                    compilation_options: Default::default(),
-                },
-                fragment: Some(wgpu::FragmentState
-                {
-                    module: fragment_shader_module,
-                    entry_point: "fragment",
-                    targets: &[Some(format.into())],
-                    compilation_options: Default::default(),
-                }),
-                primitive: wgpu::PrimitiveState::default(),
-                depth_stencil: None,
-                multisample: wgpu::MultisampleState::default(),
-                multiview: None,
-                cache: None,
-            });
+        let perturbation = use_double_precision.then(|| Perturbation::new(target, vertex_shader_module, fragment_shader_module, cell_format, format));
     
         let bind_group = target.device.create_bind_group(
             &wgpu::BindGroupDescriptor
@@ -106,6 +313,16 @@ impl ShaderRenderCompute
                         binding: 0,
                         resource: param_uniform_buffer.as_entire_binding(),
                     },
+                    wgpu::BindGroupEntry
+                    {
+                        binding: 1,
+                        resource: formula_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry
+                    {
+                        binding: 2,
+                        resource: gradient_buffer.as_entire_binding(),
+                    },
                 ],
             });
 
@@ -132,14 +349,27 @@ impl ShaderRenderCompute
         {
             render_pipeline,
             param_uniform_buffer,
+            formula_buffer,
+            gradient_buffer,
             use_double_precision,
+            cell_format,
             bind_group,
-            output_texture
+            output_texture,
+            texture_size,
+            perturbation,
+            histograms: HistogramReadbacks::default(),
         }
     }
 
-    fn make_computation_render_pass(&self, commands: &mut CommandEncoder)
+    /// Computes the cell in the corner of the output texture, at the size given by `resolution`
+    fn make_computation_render_pass(&self, commands: &mut CommandEncoder, use_perturbation: bool, resolution: CellResolution)
     {
+        let (render_pipeline, bind_group) = match &self.perturbation
+        {
+            Some(Perturbation { render_pipeline, bind_group: Some(bind_group), .. }) if use_perturbation => (render_pipeline, bind_group),
+            _ => (&self.render_pipeline, &self.bind_group),
+        };
+
         let output_texture_view = self.output_texture.create_view(&wgpu::TextureViewDescriptor::default());
 
         let mut render_pass = commands.begin_render_pass(
@@ -162,8 +392,10 @@ impl ShaderRenderCompute
                 occlusion_query_set: None,
             });
 
-        render_pass.set_pipeline(&self.render_pipeline);
-        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        let size = resolution.texture_size(self.texture_size);
+        render_pass.set_viewport(0.0, 0.0, size.width as f32, size.height as f32, 0.0, 1.0);
+        render_pass.set_pipeline(render_pipeline);
+        render_pass.set_bind_group(0, bind_group, &[]);
         render_pass.draw(0..6, 0..1);
     }
 
@@ -184,12 +416,32 @@ impl ShaderRenderCompute
         }
     }
 
+    fn set_formula(
+        &self,
+        queue: &wgpu::Queue,
+        formula: &shared::formula::Formula64
+    )
+    {
+        if self.use_double_precision
+        {
+            queue.write_buffer(&self.formula_buffer, 0, bytemuck::bytes_of(formula));
+        }
+        else
+        {
+            let formula: shared::formula::Formula32 = (*formula).into();
+            queue.write_buffer(&self.formula_buffer, 0, bytemuck::bytes_of(&formula));
+        }
+    }
+
     fn copy_output_to_texture(
         &self,
         commands: &mut wgpu::CommandEncoder,
         destination: &wgpu::Texture,
+        resolution: CellResolution,
     )
     {
+        let size = resolution.texture_size(self.texture_size);
+
         commands.copy_texture_to_texture(
             wgpu::ImageCopyTexture
             {
@@ -205,26 +457,50 @@ impl ShaderRenderCompute
                 origin: wgpu::Origin3d::ZERO,
                 aspect: wgpu::TextureAspect::All,
             },
-            destination.size()
+            wgpu::Extent3d
+            {
+                width: size.width,
+                height: size.height,
+                depth_or_array_layers: 1,
+            }
         );
     }
 
-    fn compute_cell(&self, target: &Target, render: &Render, app: &mut AppData, commands: &mut wgpu::CommandEncoder, pos: QuadPos)
+    fn compute_cell(&mut self, target: &Target, render: &Render, app: &mut AppData, commands: &mut wgpu::CommandEncoder, pos: QuadPos, resolution: CellResolution)
     {
 		let cell_size = pos.cell_size();
 		let cell_pos = pos.cell_bottom_left();
+		// With perturbation, the positions are relative to the reference point
+		let reference_offset = app.reference_orbit().map(|reference| reference.offset);
+		let origin = reference_offset.unwrap_or(DVec2::ZERO);
+		let min_pos = cell_pos + dvec2(0.0, cell_size) - origin;
+		let max_pos = cell_pos + dvec2(cell_size, 0.0) - origin;
         
 		self.set_params(&target.queue, &shared::compute::Params64
 		{
-			min_pos: cell_pos + dvec2(0.0, cell_size),
-			max_pos: cell_pos + dvec2(cell_size, 0.0),
-			fractal: app.fractal_params,
+			min_pos,
+			max_pos,
+			fractal: app.cell_fractal_params(pos),
 		});
+		self.set_formula(&target.queue, &app.formula);
+		target.queue.write_buffer(&self.gradient_buffer, 0, bytemuck::bytes_of(&app.gradient));
 
-        let cell = app.make_cell(target, render, pos);
+		if let (Some(perturbation), Some(_)) = (&self.perturbation, reference_offset)
+		{
+			let series: SeriesApproximation64 = app.series_approximation(min_pos, max_pos).into();
+			target.queue.write_buffer(&perturbation.series_uniform_buffer, 0, bytemuck::bytes_of(&series));
+		}
+
+        let generation = app.cells_generation();
+        let cell = app.make_cell(target, render, pos, resolution);
+
+		self.make_computation_render_pass(commands, reference_offset.is_some(), resolution);
+		self.copy_output_to_texture(commands, cell.fractal_texture(), resolution);
 
-		self.make_computation_render_pass(commands);
-		self.copy_output_to_texture(commands, cell.fractal_texture());
+		if self.cell_format == CellFormat::EscapeData
+		{
+			self.histograms.queue(target, commands, cell.fractal_texture(), pos, generation, resolution);
+		}
     }
 }
 
@@ -232,14 +508,33 @@ impl super::Compute for ShaderRenderCompute
 {
     fn update_before_render(&mut self, target: &Target, render: &Render, app: &mut AppData, commands: &mut wgpu::CommandEncoder)
     {
-        // Find new cell to load
-		for pos in app.visible_cells()
-		{
-            if !app.is_cell_loaded(pos)
-            {
-                self.compute_cell(target, render, app, commands, pos);
-                return;
-            }
-		}
+        if let (Some(perturbation), Some(reference)) = (&mut self.perturbation, app.reference_orbit())
+        {
+            perturbation.upload_reference(target, &self.param_uniform_buffer, &self.gradient_buffer, reference);
+        }
+
+        self.histograms.receive_finished(target, app);
+
+        // Find new cell to load, or a preview to refine
+        let next_cell = super::cells_to_compute(app).next();
+        if let Some((pos, resolution)) = next_cell
+        {
+            self.compute_cell(target, render, app, commands, pos, resolution);
+        }
+    }
+
+    fn supports_perturbation(&self) -> bool
+    {
+        self.perturbation.is_some()
+    }
+
+    fn backend(&self) -> super::ComputeBackend
+    {
+        super::ComputeBackend::RenderPass
+    }
+
+    fn cell_format(&self) -> CellFormat
+    {
+        self.cell_format
     }
 }
@@ -1,58 +1,326 @@
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::sync::{mpsc, Arc, Condvar, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::JoinHandle;
+
 use fractal_renderer_shared as shared;
 use crate::app::AppData;
-use crate::render::Render;
+use crate::render::{CellFormat, Render};
 use crate::Target;
 use crate::quad_cell::QuadPos;
+use super::CellResolution;
+use shared::compute::EscapeData;
+use shared::formula::Formula64;
+use shared::gradient::Gradient;
+use shared::histogram::CellHistogram;
+use shared::math::{Complex64, DDVec2, DoubleDouble};
+use shared::fractal::escape_time_method::SeriesApproximation;
 use winit::dpi::PhysicalSize;
-use glam::{dvec2, uvec2};
+use glam::{dvec2, uvec2, UVec2};
+
 
+/// The scalar type used to compute the pixels on the CPU
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum CpuPrecision
+{
+    #[default]
+    Double,
+    /// Slower, but precise down to zooms of about 1e-30 without perturbation
+    DoubleDouble,
+}
 
+/// Computes the cells on a pool of worker threads, the cells are uploaded to their textures as they finish
 pub struct ThreadedCompute
 {
     texture_size: PhysicalSize<u32>,
-    aligned_width: u32
+    cell_format: CellFormat,
+    queue: Arc<JobQueue>,
+    results: mpsc::Receiver<CellResult>,
+    workers: Vec<JoinHandle<()>>,
+    /// Cells queued or being computed, with the generation of the cells they were requested for
+    pending: BTreeMap<QuadPos, u64>,
+    /// Reference orbit shared with the workers, with its id
+    reference_orbit: Option<(u64, Arc<[Complex64]>)>,
 }
 
-impl ThreadedCompute
+#[derive(Copy, Clone)]
+struct CellLayout
+{
+    texture_size: PhysicalSize<u32>,
+    /// Size of a pixel in bytes
+    texel_size: u32,
+    aligned_width: u32,
+}
+
+struct JobQueue
+{
+    jobs: Mutex<VecDeque<CellJob>>,
+    available: Condvar,
+    stop: AtomicBool,
+}
+
+struct CellJob
+{
+    pos: QuadPos,
+    generation: u64,
+    resolution: CellResolution,
+    format: CellFormat,
+    layout: CellLayout,
+    params: CellParams,
+    formula: Formula64,
+    gradient: Gradient,
+}
+
+enum CellParams
+{
+    Double(shared::compute::Params64),
+    DoubleDouble(shared::compute::Params<DoubleDouble>),
+    /// The positions are relative to the reference point
+    Perturbation
+    {
+        params: shared::compute::Params64,
+        reference_orbit: Arc<[Complex64]>,
+        series: SeriesApproximation<f64>,
+    },
+}
+
+struct CellResult
+{
+    pos: QuadPos,
+    generation: u64,
+    resolution: CellResolution,
+    layout: CellLayout,
+    data: CellData,
+    /// Collected with the escape data
+    histogram: Option<CellHistogram>,
+}
+
+/// The pixels of a cell in the [`CellFormat`] of the backend
+enum CellData
+{
+    Color(Vec<u32>),
+    EscapeData(Vec<EscapeData>),
+}
+
+impl CellData
 {
-    pub fn new(texture_size: PhysicalSize<u32>) -> Self
+    fn texel_size(format: CellFormat) -> u32
     {
-        let aligned_width = wgpu::util::align_to(texture_size.width, wgpu::COPY_BYTES_PER_ROW_ALIGNMENT / std::mem::size_of::<u32>() as u32);
+        match format
+        {
+            CellFormat::Color => std::mem::size_of::<u32>() as u32,
+            CellFormat::EscapeData => std::mem::size_of::<EscapeData>() as u32,
+        }
+    }
 
+    fn bytes(&self) -> &[u8]
+    {
+        match self
+        {
+            Self::Color(data) => bytemuck::cast_slice(data),
+            Self::EscapeData(data) => bytemuck::cast_slice(data),
+        }
+    }
+}
+
+impl CellLayout
+{
+    fn new(texture_size: PhysicalSize<u32>, texel_size: u32) -> Self
+    {
         Self
         {
             texture_size,
-            aligned_width
+            texel_size,
+            aligned_width: wgpu::util::align_to(texture_size.width, wgpu::COPY_BYTES_PER_ROW_ALIGNMENT / texel_size),
         }
     }
 
-    pub fn do_compute(&self, data: &mut [u32], params: shared::compute::Params64)
+    fn compute<T: Copy + Default>(&self, compute_pixel: impl Fn(UVec2, UVec2) -> T) -> Vec<T>
     {
+        let mut data = vec![T::default(); (self.aligned_width * self.texture_size.height) as usize];
+
+        let size = uvec2(self.texture_size.width, self.texture_size.height);
         for y in 0..self.texture_size.height
         {
             for x in 0..self.texture_size.width
             {
-                let pixel = &mut data[(x + y * self.aligned_width) as usize];
-                *pixel = shared::compute::run(uvec2(x, y), uvec2(self.texture_size.width, self.texture_size.height), params.into());
+                data[(x + y * self.aligned_width) as usize] = compute_pixel(uvec2(x, y), size);
             }
         }
+
+        data
     }
 
-    fn compute_cell(&self, target: &Target, render: &Render, app: &mut AppData, pos: QuadPos)
+    /// The pixels of `data` computed by [`Self::compute`], without the padding of the rows
+    fn pixels<'a, T: Copy>(&self, data: &'a [T]) -> impl Iterator<Item = T> + Clone + 'a
+    {
+        let width = self.texture_size.width as usize;
+        data.chunks(self.aligned_width as usize).flat_map(move |row| &row[..width]).copied()
+    }
+}
+
+impl CellJob
+{
+    fn run(&self) -> CellResult
+    {
+        let data = match (&self.params, self.format)
+        {
+            (CellParams::Double(params), CellFormat::Color) =>
+            {
+                CellData::Color(self.layout.compute(|id, size| shared::compute::run(id, size, (*params).into(), &self.formula, &self.gradient)))
+            },
+            (CellParams::DoubleDouble(params), CellFormat::Color) =>
+            {
+                CellData::Color(self.layout.compute(|id, size| shared::compute::run(id, size, *params, &self.formula, &self.gradient)))
+            },
+            (CellParams::Perturbation { params, reference_orbit, series }, CellFormat::Color) =>
+            {
+                CellData::Color(self.layout.compute(|id, size| shared::compute::run_perturbation(id, size, (*params).into(), reference_orbit, series, &self.gradient)))
+            },
+            (CellParams::Double(params), CellFormat::EscapeData) =>
+            {
+                CellData::EscapeData(self.layout.compute(|id, size| shared::compute::run_escape_data(id, size, (*params).into(), &self.formula)))
+            },
+            (CellParams::DoubleDouble(params), CellFormat::EscapeData) =>
+            {
+                CellData::EscapeData(self.layout.compute(|id, size| shared::compute::run_escape_data(id, size, *params, &self.formula)))
+            },
+            (CellParams::Perturbation { params, reference_orbit, series }, CellFormat::EscapeData) =>
+            {
+                CellData::EscapeData(self.layout.compute(|id, size| shared::compute::run_perturbation_escape_data(id, size, (*params).into(), reference_orbit, series)))
+            },
+        };
+
+        let histogram = match &data
+        {
+            CellData::Color(_) => None,
+            CellData::EscapeData(data) => Some(CellHistogram::new(self.layout.pixels(data))),
+        };
+
+        CellResult
+        {
+            pos: self.pos,
+            generation: self.generation,
+            resolution: self.resolution,
+            layout: self.layout,
+            data,
+            histogram,
+        }
+    }
+}
+
+impl ThreadedCompute
+{
+    pub fn new(texture_size: PhysicalSize<u32>, cell_format: CellFormat) -> Self
+    {
+        let queue = Arc::new(JobQueue
+        {
+            jobs: Mutex::new(VecDeque::new()),
+            available: Condvar::new(),
+            stop: AtomicBool::new(false),
+        });
+        let (sender, results) = mpsc::channel();
+
+        let workers = (0..worker_count()).map(|_|
+        {
+            let queue = queue.clone();
+            let sender = sender.clone();
+            std::thread::spawn(move || run_worker(&queue, &sender))
+        }).collect();
+
+        Self
+        {
+            texture_size,
+            cell_format,
+            queue,
+            results,
+            workers,
+            pending: BTreeMap::new(),
+            reference_orbit: None,
+        }
+    }
+
+    /// Maximum number of cells queued or being computed, so that the queue stays short while navigating
+    fn max_pending(&self) -> usize
+    {
+        self.workers.len() * 2
+    }
+
+    fn make_job(&mut self, app: &mut AppData, pos: QuadPos, resolution: CellResolution, generation: u64) -> CellJob
     {
 		let cell_size = pos.cell_size();
 		let cell_pos = pos.cell_bottom_left();
-        
-        let mut data = vec![0_u32; (self.aligned_width * self.texture_size.height) as usize];
+		let min_pos = cell_pos + dvec2(0.0, cell_size);
+		let max_pos = cell_pos + dvec2(cell_size, 0.0);
 
-        self.do_compute(&mut data[..], shared::compute::Params64
-		{
-			min_pos: cell_pos + dvec2(0.0, cell_size),
-			max_pos: cell_pos + dvec2(cell_size, 0.0),
-			fractal: app.fractal_params,
-		});
+        let params = match (app.cpu_precision(), app.reference_orbit())
+        {
+            (CpuPrecision::DoubleDouble, _) =>
+            {
+                // The double-double is precise enough to use the deep zoom origin directly, without perturbation
+                let origin = app.deep_zoom_origin().unwrap_or_default();
+                CellParams::DoubleDouble(shared::compute::Params::<DoubleDouble>
+                {
+                    min_pos: origin + DDVec2::from(min_pos),
+                    max_pos: origin + DDVec2::from(max_pos),
+                    fractal: app.cell_fractal_params(pos).into(),
+                })
+            },
+            (CpuPrecision::Double, Some(reference)) =>
+            {
+                let reference_orbit = match &self.reference_orbit
+                {
+                    Some((id, orbit)) if *id == reference.id => orbit.clone(),
+                    _ =>
+                    {
+                        let orbit: Arc<[Complex64]> = reference.orbit.as_slice().into();
+                        self.reference_orbit = Some((reference.id, orbit.clone()));
+                        orbit
+                    },
+                };
+
+                let offset = reference.offset;
+                let params = shared::compute::Params64
+                {
+                    min_pos: min_pos - offset,
+                    max_pos: max_pos - offset,
+                    fractal: app.cell_fractal_params(pos),
+                };
+
+                CellParams::Perturbation
+                {
+                    params,
+                    reference_orbit,
+                    series: app.series_approximation(min_pos - offset, max_pos - offset),
+                }
+            },
+            (CpuPrecision::Double, None) =>
+            {
+                CellParams::Double(shared::compute::Params64
+                {
+                    min_pos,
+                    max_pos,
+                    fractal: app.cell_fractal_params(pos),
+                })
+            },
+        };
+
+        CellJob
+        {
+            pos,
+            generation,
+            resolution,
+            format: self.cell_format,
+            layout: CellLayout::new(resolution.texture_size(self.texture_size), CellData::texel_size(self.cell_format)),
+            params,
+            formula: app.formula,
+            gradient: app.gradient,
+        }
+    }
 
-        let cell = app.make_cell(target, render, pos);
+    fn upload_cell(&self, target: &Target, render: &Render, app: &mut AppData, result: &CellResult)
+    {
+        let cell = app.make_cell(target, render, result.pos, result.resolution);
         let destination = cell.fractal_texture();
         target.queue.write_texture(
             wgpu::ImageCopyTexture
@@ -62,15 +330,101 @@ impl ThreadedCompute
                 origin: wgpu::Origin3d::ZERO,
                 aspect: wgpu::TextureAspect::All,
             },
-            bytemuck::cast_slice(&data[..]),
+            result.data.bytes(),
             wgpu::ImageDataLayout
             {
                 offset: 0,
-                bytes_per_row: Some(self.aligned_width * std::mem::size_of::<u32>() as u32),
+                bytes_per_row: Some(result.layout.aligned_width * result.layout.texel_size),
                 rows_per_image: None
             },
-            destination.size()
+            wgpu::Extent3d
+            {
+                width: result.layout.texture_size.width,
+                height: result.layout.texture_size.height,
+                depth_or_array_layers: 1,
+            }
         );
+
+        if let Some(histogram) = &result.histogram
+        {
+            app.set_cell_histogram(result.pos, result.generation, result.resolution, histogram.clone());
+        }
+    }
+
+    fn receive_cell(&mut self, target: &Target, render: &Render, app: &mut AppData, result: CellResult)
+    {
+        if self.pending.get(&result.pos) == Some(&result.generation)
+        {
+            self.pending.remove(&result.pos);
+        }
+
+        // Cells requested before the cells were discarded are outdated, and previews are only shown until the full resolution is loaded
+        let is_needed = match result.resolution
+        {
+            CellResolution::Preview => !app.is_cell_loaded(result.pos),
+            CellResolution::Full => !app.is_cell_loaded(result.pos) || app.is_cell_preview(result.pos),
+        };
+        if result.generation == app.cells_generation() && is_needed
+        {
+            self.upload_cell(target, render, app, &result);
+        }
+    }
+}
+
+impl Drop for ThreadedCompute
+{
+    fn drop(&mut self)
+    {
+        self.queue.stop.store(true, Ordering::Relaxed);
+        {
+            let _jobs = self.queue.jobs.lock().unwrap();
+            self.queue.available.notify_all();
+        }
+
+        for worker in self.workers.drain(..)
+        {
+            let _ = worker.join();
+        }
+    }
+}
+
+/// Without threads, on the web, the cells are computed one per frame on the main thread
+fn worker_count() -> usize
+{
+    if cfg!(target_arch = "wasm32")
+    {
+        return 0;
+    }
+
+    // Leave a core for the main thread
+    std::thread::available_parallelism().map_or(1, |n| n.get().saturating_sub(1).max(1))
+}
+
+fn run_worker(queue: &JobQueue, results: &mpsc::Sender<CellResult>)
+{
+    loop
+    {
+        let job =
+        {
+            let mut jobs = queue.jobs.lock().unwrap();
+            loop
+            {
+                if queue.stop.load(Ordering::Relaxed)
+                {
+                    return;
+                }
+                if let Some(job) = jobs.pop_front()
+                {
+                    break job;
+                }
+                jobs = queue.available.wait(jobs).unwrap();
+            }
+        };
+
+        if results.send(job.run()).is_err()
+        {
+            return;
+        }
     }
 }
 
@@ -78,14 +432,74 @@ impl super::Compute for ThreadedCompute
 {
     fn update_before_render(&mut self, target: &Target, render: &Render, app: &mut AppData, _commands: &mut wgpu::CommandEncoder)
     {
-        // Find new cell to load
-		for pos in app.visible_cells()
-		{
-            if !app.is_cell_loaded(pos)
+        while let Ok(result) = self.results.try_recv()
+        {
+            self.receive_cell(target, render, app, result);
+        }
+
+        let generation = app.cells_generation();
+
+        if self.workers.is_empty()
+        {
+            let next_cell = super::cells_to_compute(app).next();
+            if let Some((pos, resolution)) = next_cell
             {
-                self.compute_cell(target, render, app, pos);
-                return;
+                let result = self.make_job(app, pos, resolution, generation).run();
+                self.upload_cell(target, render, app, &result);
             }
-		}
+            return;
+        }
+
+        let visible: Vec<(QuadPos, CellResolution)> = super::cells_to_compute(app).collect();
+
+        {
+            // Forget the queued cells that are outdated, no longer visible or already refined, the ones being computed are kept
+            let visible_set: BTreeSet<(QuadPos, CellResolution)> = visible.iter().copied().collect();
+            let is_useful = |job: &CellJob| job.generation == generation && visible_set.contains(&(job.pos, job.resolution));
+
+            let mut jobs = self.queue.jobs.lock().unwrap();
+            for job in jobs.iter().filter(|job| !is_useful(job))
+            {
+                if self.pending.get(&job.pos) == Some(&job.generation)
+                {
+                    self.pending.remove(&job.pos);
+                }
+            }
+            jobs.retain(is_useful);
+            self.pending.retain(|_pos, pending_generation| *pending_generation == generation);
+        }
+
+        let free_slots = self.max_pending().saturating_sub(self.pending.len());
+        // A preview and its refinement are never pending together, the refinement is requested once the preview is loaded
+        let new_cells: Vec<(QuadPos, CellResolution)> = visible.into_iter().filter(|(pos, _resolution)| !self.pending.contains_key(pos)).take(free_slots).collect();
+        let new_jobs: Vec<CellJob> = new_cells.into_iter().map(|(pos, resolution)| self.make_job(app, pos, resolution, generation)).collect();
+
+        for job in &new_jobs
+        {
+            self.pending.insert(job.pos, generation);
+        }
+        self.queue.jobs.lock().unwrap().extend(new_jobs);
+        self.queue.available.notify_all();
+
+        if !self.pending.is_empty()
+        {
+            // Keep drawing to receive the cells being computed
+            app.request_redraw();
+        }
+    }
+
+    fn supports_perturbation(&self) -> bool
+    {
+        true
+    }
+
+    fn backend(&self) -> super::ComputeBackend
+    {
+        super::ComputeBackend::Cpu
+    }
+
+    fn cell_format(&self) -> CellFormat
+    {
+        self.cell_format
     }
 }
@@ -0,0 +1,215 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use fractal_renderer_shared as shared;
+use shared::math::{Complex64, ComplexNumber, DDVec2, Fixed};
+use shared::fractal::{FractalParams, FractalParams64, perturbation};
+use shared::fractal::escape_time_method::{self, SeriesApproximation};
+use glam::{dvec2, DVec2};
+use serde::{Deserialize, Serialize};
+
+/// Enough limbs to hold the origin at any zoom level an f64 pixel size can represent
+const ORIGIN_LIMBS: usize = 18;
+
+/// The offset from the origin is folded into the origin once it is this many pixels long, before it loses too much precision
+const MAX_OFFSET_PIXELS: f64 = (1_u64 << 40) as f64;
+
+/// Largest error of the series approximation, relative to the size of a pixel
+const SERIES_TOLERANCE: f64 = 0.001;
+
+static NEXT_REFERENCE_ID: AtomicU64 = AtomicU64::new(0);
+
+/// A position with the precision of the deep zoom origin
+#[derive(Copy, Clone, Default, Debug, PartialEq, Serialize, Deserialize)]
+pub struct FixedVec2
+{
+	pub x: Fixed<ORIGIN_LIMBS>,
+	pub y: Fixed<ORIGIN_LIMBS>,
+}
+
+pub struct ReferenceOrbit
+{
+	/// Unique identifier, used by the compute backends to know when to upload a new orbit
+	pub id: u64,
+	/// Position of the reference point relative to the origin
+	pub offset: DVec2,
+	pub orbit: Vec<Complex64>,
+	limbs: usize,
+}
+
+/// State of the perturbation rendering mode.
+///
+/// Positions are split into a high precision origin and a low precision offset from it.
+/// The pixels are computed relative to a reference point whose orbit is computed with the high precision.
+#[derive(Default)]
+pub struct DeepZoom
+{
+	origin: FixedVec2,
+	reference: Option<ReferenceOrbit>,
+}
+
+impl ReferenceOrbit
+{
+	/// Computes the series approximation for the cell between `min_pos` and `max_pos`, which are relative to the reference point
+	pub fn series_approximation(&self, min_pos: DVec2, max_pos: DVec2, pixel_world_size: f64, params: &FractalParams64) -> SeriesApproximation<f64>
+	{
+		// The corners, the middle of the edges and the center of the cell
+		let probes: [Complex64; 9] = core::array::from_fn(|i| Complex64::from_vector(min_pos + (max_pos - min_pos) * dvec2((i % 3) as f64, (i / 3) as f64) * 0.5));
+		let scale = probes.iter().map(|probe| probe.modulus()).fold(0.0, f64::max).max(f64::MIN_POSITIVE);
+		let params: FractalParams<f64> = (*params).into();
+
+		escape_time_method::compute_series_approximation(params, &self.orbit, scale, probes, SERIES_TOLERANCE * pixel_world_size / scale)
+	}
+}
+
+fn required_limbs(pixel_world_size: f64) -> usize
+{
+	// One limb for the integer part, and enough fractional bits to resolve a fraction of a pixel
+	let fractional_bits = (-pixel_world_size.log2()).max(0.0) + 64.0;
+	1 + (fractional_bits / 64.0).ceil() as usize
+}
+
+fn compute_orbit<const N: usize>(origin: FixedVec2, offset: DVec2, params: &FractalParams64, orbit: &mut [Complex64]) -> usize
+{
+	let re = origin.x.resize::<N>() + Fixed::from_f64(offset.x);
+	let im = origin.y.resize::<N>() + Fixed::from_f64(offset.y);
+	perturbation::compute_reference_orbit(re, im, params, orbit)
+}
+
+impl FixedVec2
+{
+	pub fn to_dvec2(self) -> DVec2
+	{
+		dvec2(self.x.to_f64(), self.y.to_f64())
+	}
+}
+
+impl From<DVec2> for FixedVec2
+{
+	fn from(value: DVec2) -> Self
+	{
+		Self
+		{
+			x: Fixed::from_f64(value.x),
+			y: Fixed::from_f64(value.y),
+		}
+	}
+}
+
+impl From<DDVec2> for FixedVec2
+{
+	fn from(value: DDVec2) -> Self
+	{
+		Self
+		{
+			x: Fixed::from_f64(value.x.hi()) + Fixed::from_f64(value.x.lo()),
+			y: Fixed::from_f64(value.y.hi()) + Fixed::from_f64(value.y.lo()),
+		}
+	}
+}
+
+impl From<FixedVec2> for DDVec2
+{
+	fn from(value: FixedVec2) -> Self
+	{
+		DDVec2::new(value.x.into(), value.y.into())
+	}
+}
+
+impl std::ops::Add<DVec2> for FixedVec2
+{
+	type Output = Self;
+
+	fn add(self, rhs: DVec2) -> Self
+	{
+		Self
+		{
+			x: self.x + Fixed::from_f64(rhs.x),
+			y: self.y + Fixed::from_f64(rhs.y),
+		}
+	}
+}
+
+impl DeepZoom
+{
+	/// Starts at a high precision origin, such as the center of a saved scene
+	pub fn with_origin(origin: FixedVec2) -> Self
+	{
+		Self
+		{
+			origin,
+			reference: None,
+		}
+	}
+
+	pub fn origin(&self) -> DVec2
+	{
+		self.origin.to_dvec2()
+	}
+
+	/// The origin with all its precision, to save it in a scene
+	pub fn exact_origin(&self) -> FixedVec2
+	{
+		self.origin
+	}
+
+	/// The origin with the precision of a [`shared::math::DoubleDouble`], for the CPU path that doesn't use perturbation
+	pub fn high_precision_origin(&self) -> DDVec2
+	{
+		self.origin.into()
+	}
+
+	pub fn reference(&self) -> Option<&ReferenceOrbit>
+	{
+		self.reference.as_ref()
+	}
+
+	pub fn invalidate_reference(&mut self)
+	{
+		self.reference = None;
+	}
+
+	/// Returns true if the offset is long enough that it should be moved into the origin
+	pub fn needs_recenter(&self, offset: DVec2, pixel_world_size: f64) -> bool
+	{
+		offset.abs().max_element() > pixel_world_size * MAX_OFFSET_PIXELS
+	}
+
+	/// Moves the origin by `offset`, positions relative to the previous origin become invalid
+	pub fn recenter(&mut self, offset: DVec2)
+	{
+		self.origin = self.origin + offset;
+		self.reference = None;
+	}
+
+	/// Computes a new reference orbit at `center` if the current one is too far away or not precise enough
+	pub fn update_reference(&mut self, center: DVec2, pixel_world_size: f64, viewport_world_size: DVec2, params: &FractalParams64)
+	{
+		let limbs = required_limbs(pixel_world_size);
+
+		if let Some(reference) = &self.reference
+		{
+			if reference.limbs >= limbs && (reference.offset - center).abs().cmple(viewport_world_size).all()
+			{
+				return;
+			}
+		}
+
+		let mut orbit = vec![Complex64::ZERO; params.iteration_limit as usize + 1];
+		let length = match limbs
+		{
+			0..=3 => compute_orbit::<3>(self.origin, center, params, &mut orbit),
+			4..=5 => compute_orbit::<5>(self.origin, center, params, &mut orbit),
+			6..=9 => compute_orbit::<9>(self.origin, center, params, &mut orbit),
+			_ => compute_orbit::<ORIGIN_LIMBS>(self.origin, center, params, &mut orbit),
+		};
+		orbit.truncate(length);
+
+		self.reference = Some(ReferenceOrbit
+		{
+			id: NEXT_REFERENCE_ID.fetch_add(1, Ordering::Relaxed),
+			offset: center,
+			orbit,
+			limbs,
+		});
+	}
+}
@@ -0,0 +1,144 @@
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+use fractal_renderer_shared as shared;
+use shared::formula::Formula64;
+use shared::gradient::Gradient;
+use shared::math::{DDVec2, DoubleDouble};
+use glam::{dvec2, uvec2, UVec2};
+
+use crate::compute::CpuPrecision;
+
+
+/// Describes a single view to render without a window
+#[derive(Copy, Clone)]
+pub struct View
+{
+	/// Kept in high precision so that the double-double path can render deep zooms
+	pub center: DDVec2,
+	pub zoom: f64,
+	pub size: UVec2,
+	pub fractal: shared::fractal::FractalParams64,
+	/// Iteration formula of [`shared::fractal::FractalKind::Custom`]
+	pub formula: Formula64,
+	/// Gradient of [`shared::fractal::ColorPalette::Custom`]
+	pub gradient: Gradient,
+	pub precision: CpuPrecision,
+}
+
+impl View
+{
+	/// The world size of a pixel, matching the interactive app at the same zoom
+	pub fn pixel_world_size(&self) -> f64
+	{
+		4.0 / self.size.x.min(self.size.y).max(1) as f64 * self.zoom
+	}
+
+	fn fractal_params(&self) -> shared::fractal::FractalParams64
+	{
+		let mut params = self.fractal;
+		params.pixel_size = self.pixel_world_size();
+		params
+	}
+
+	pub fn params(&self) -> shared::compute::Params64
+	{
+		let half_size = self.size.as_dvec2() * self.pixel_world_size() / 2.0;
+		let center = dvec2(self.center.x.hi(), self.center.y.hi());
+
+		shared::compute::Params64
+		{
+			min_pos: center + dvec2(-half_size.x, half_size.y),
+			max_pos: center + dvec2(half_size.x, -half_size.y),
+			fractal: self.fractal_params(),
+		}
+	}
+
+	pub fn params_double_double(&self) -> shared::compute::Params<DoubleDouble>
+	{
+		let half_size = self.size.as_dvec2() * self.pixel_world_size() / 2.0;
+
+		shared::compute::Params
+		{
+			min_pos: self.center + dvec2(-half_size.x, half_size.y).into(),
+			max_pos: self.center + dvec2(half_size.x, -half_size.y).into(),
+			fractal: self.fractal_params().into(),
+		}
+	}
+}
+
+/// Computes every pixel of the view on the CPU, using all the available cores
+pub fn render(view: &View) -> Vec<u32>
+{
+	match view.precision
+	{
+		CpuPrecision::Double =>
+		{
+			let params = view.params();
+			render_pixels(view.size, |id| shared::compute::run(id, view.size, params.into(), &view.formula, &view.gradient))
+		},
+		CpuPrecision::DoubleDouble =>
+		{
+			let params = view.params_double_double();
+			render_pixels(view.size, |id| shared::compute::run(id, view.size, params, &view.formula, &view.gradient))
+		},
+	}
+}
+
+fn render_pixels(size: UVec2, compute_pixel: impl Fn(UVec2) -> u32 + Sync) -> Vec<u32>
+{
+	let mut data = vec![0_u32; (size.x * size.y) as usize];
+
+	if data.is_empty()
+	{
+		return data;
+	}
+
+	let thread_count = std::thread::available_parallelism().map_or(1, |n| n.get());
+	let rows_per_thread = (size.y as usize).div_ceil(thread_count);
+
+	std::thread::scope(|scope|
+	{
+		for (chunk_index, chunk) in data.chunks_mut(rows_per_thread * size.x as usize).enumerate()
+		{
+			let compute_pixel = &compute_pixel;
+			scope.spawn(move ||
+			{
+				let first_row = (chunk_index * rows_per_thread) as u32;
+
+				for (i, pixel) in chunk.iter_mut().enumerate()
+				{
+					let x = i as u32 % size.x;
+					let y = first_row + i as u32 / size.x;
+					*pixel = compute_pixel(uvec2(x, y));
+				}
+			});
+		}
+	});
+
+	data
+}
+
+/// Writes pixels produced by [`shared::compute::run`] to a PNG file
+pub fn save_png(path: impl AsRef<Path>, size: UVec2, data: &[u32]) -> Result<(), png::EncodingError>
+{
+	let file = File::create(path)?;
+
+	let mut encoder = png::Encoder::new(BufWriter::new(file), size.x, size.y);
+	encoder.set_color(png::ColorType::Rgba);
+	encoder.set_depth(png::BitDepth::Eight);
+	encoder.set_source_srgb(png::SrgbRenderingIntent::Perceptual);
+
+	let bytes: Vec<u8> = data.iter()
+		.flat_map(|pixel|
+		{
+			let [b, g, r, a] = pixel.to_le_bytes();
+			[r, g, b, a]
+		})
+		.collect();
+
+	let mut writer = encoder.write_header()?;
+	writer.write_image_data(&bytes)?;
+	writer.finish()
+}
@@ -2,8 +2,16 @@ mod target;
 pub mod compute;
 pub mod render;
 mod quad_cell;
+mod cell_store;
+pub mod deep_zoom;
 pub mod app;
 mod gui;
+pub mod headless;
+pub mod scene;
+pub mod animation;
+pub mod url_fragment;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod cell_cache;
 
 pub use target::Target;
 
@@ -43,7 +51,15 @@ pub mod wasm
 
 				wasm_bindgen_futures::spawn_local(async move
 				{
-					let app = App::build(wgpu::Limits::downlevel_webgl2_defaults(), window).await;
+					let mut app = App::build(wgpu::Limits::downlevel_webgl2_defaults(), window).await;
+					if let Some(fragment) = read_url_fragment()
+					{
+						match crate::url_fragment::decode(&fragment)
+						{
+							Ok(scene) => app.load_scene(&scene),
+							Err(error) => log::warn!("Couldn't restore the view from the URL: {error}"),
+						}
+					}
 					event_proxy.send_event(UserEvent::Initialized(app)).expect("Initialized web");
 				});
 				None
@@ -51,4 +67,25 @@ pub mod wasm
 
 		event_loop.spawn_app(app);
 	}
-}
\ No newline at end of file
+
+	fn read_url_fragment() -> Option<String>
+	{
+		let hash = web_sys::window()?.location().hash().ok()?;
+		let fragment = hash.trim_start_matches('#');
+		(!fragment.is_empty()).then(|| fragment.to_string())
+	}
+
+	/// Replaces the fragment of the page URL without adding an entry to the browser history
+	pub(crate) fn replace_url_fragment(fragment: &str)
+	{
+		let result = web_sys::window()
+			.ok_or(JsValue::NULL)
+			.and_then(|window| window.history())
+			.and_then(|history| history.replace_state_with_url(&JsValue::NULL, "", Some(&format!("#{fragment}"))));
+
+		if let Err(error) = result
+		{
+			log::warn!("Couldn't update the URL: {error:?}");
+		}
+	}
+}
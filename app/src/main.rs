@@ -1,14 +1,32 @@
 use fractal_renderer_lib::app::{App, AppWrapper};
+use fractal_renderer_lib::scene::Scene;
 
 fn main() -> Result<(), winit::error::EventLoopError>
 {
 	env_logger::init();
+
+	// An optional scene file to open
+	let scene = match std::env::args().nth(1).map(Scene::load).transpose()
+	{
+		Ok(scene) => scene,
+		Err(error) =>
+		{
+			eprintln!("error: {error}");
+			std::process::exit(1);
+		},
+	};
+
 	let event_loop = winit::event_loop::EventLoop::with_user_event().build().expect("Failed to create event loop");
 	
 	let mut app = AppWrapper::new(|window|
 	{
 		use pollster::FutureExt;
-		Some(App::build(wgpu::Limits::default(), window).block_on())
+		let mut app = App::build(wgpu::Limits::default(), window).block_on();
+		if let Some(scene) = &scene
+		{
+			app.load_scene(scene);
+		}
+		Some(app)
 	});
 	
 	event_loop.run_app(&mut app)
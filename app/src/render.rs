@@ -2,12 +2,46 @@ use fractal_renderer_shared as shared;
 use winit::dpi::PhysicalSize;
 
 
+/// Format of the escape data cells, filled with [`shared::compute::EscapeData`]
+pub const ESCAPE_DATA_TEXTURE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba32Float;
+
+/// What the cell textures contain
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CellFormat
+{
+    /// The colors of the pixels
+    Color,
+    /// The escape data of the pixels, which are colored when drawn so that the colors can change without computing the cells again
+    EscapeData,
+}
+
+impl CellFormat
+{
+    pub fn texture_format(self, target: &crate::Target) -> wgpu::TextureFormat
+    {
+        match self
+        {
+            Self::Color => target.config.format.add_srgb_suffix(),
+            Self::EscapeData => ESCAPE_DATA_TEXTURE_FORMAT,
+        }
+    }
+
+    /// Size of a texel in bytes
+    pub fn texel_size(self, target: &crate::Target) -> u32
+    {
+        self.texture_format(target).block_copy_size(None).unwrap_or(4)
+    }
+}
+
 pub struct Render
 {
     texture_size: PhysicalSize<u32>,
     use_double_precision: bool,
     render_pipeline: wgpu::RenderPipeline,
     instance_bind_group_layout: wgpu::BindGroupLayout,
+    /// Draws the cells of [`CellFormat::EscapeData`], their textures can't be filtered
+    escape_data_render_pipeline: wgpu::RenderPipeline,
+    escape_data_instance_bind_group_layout: wgpu::BindGroupLayout,
     uniform: Uniform,
 }
 
@@ -15,12 +49,17 @@ struct Uniform
 {
     bind_group_layout: wgpu::BindGroupLayout,
     buffer: wgpu::Buffer,
+    coloring_buffer: wgpu::Buffer,
+    gradient_buffer: wgpu::Buffer,
+    /// Ranks of the bins of the histogram, a texture since WebGL2 has no storage buffers
+    histogram_texture: wgpu::Texture,
     bind_group: wgpu::BindGroup,
 }
 
 pub struct Instance
 {
     use_double_precision: bool,
+    format: CellFormat,
     buffer: wgpu::Buffer,
     fractal_texture: wgpu::Texture,
     bind_group: wgpu::BindGroup,
@@ -56,6 +95,42 @@ impl Uniform
                         ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
                         count: None,
                     },
+                    wgpu::BindGroupLayoutEntry
+                    {
+                        binding: 2,
+                        count: None,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer
+                        {
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                            ty: wgpu::BufferBindingType::Uniform,
+                        },
+                    },
+                    wgpu::BindGroupLayoutEntry
+                    {
+                        binding: 3,
+                        count: None,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer
+                        {
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                            ty: wgpu::BufferBindingType::Uniform,
+                        },
+                    },
+                    wgpu::BindGroupLayoutEntry
+                    {
+                        binding: 4,
+                        count: None,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture
+                        {
+                            multisampled: false,
+                            view_dimension: wgpu::TextureViewDimension::D1,
+                            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        },
+                    },
                 ],
             });
 
@@ -77,6 +152,43 @@ impl Uniform
                 mapped_at_creation: false,
             });
 
+        let coloring_buffer = target.device.create_buffer(
+            &wgpu::BufferDescriptor
+            {
+                label: Some("coloring_uniforms"),
+                size: std::mem::size_of::<shared::render::ColoringUniforms>() as u64,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            });
+
+        let gradient_buffer = target.device.create_buffer(
+            &wgpu::BufferDescriptor
+            {
+                label: Some("gradient"),
+                size: std::mem::size_of::<shared::gradient::Gradient>() as u64,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            });
+
+        let histogram_texture = target.device.create_texture(
+            &wgpu::TextureDescriptor
+            {
+                label: Some("histogram"),
+                size: wgpu::Extent3d
+                {
+                    width: shared::histogram::HISTOGRAM_BINS as u32,
+                    height: 1,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D1,
+                format: wgpu::TextureFormat::R32Float,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+                view_formats: &[wgpu::TextureFormat::R32Float],
+            });
+        let histogram_texture_view = histogram_texture.create_view(&wgpu::TextureViewDescriptor::default());
+
         let fractal_sampler = target.device.create_sampler(
             &wgpu::SamplerDescriptor
             {
@@ -101,6 +213,21 @@ impl Uniform
                         binding: 1,
                         resource: wgpu::BindingResource::Sampler(&fractal_sampler),
                     },
+                    wgpu::BindGroupEntry
+                    {
+                        binding: 2,
+                        resource: coloring_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry
+                    {
+                        binding: 3,
+                        resource: gradient_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry
+                    {
+                        binding: 4,
+                        resource: wgpu::BindingResource::TextureView(&histogram_texture_view),
+                    },
                 ],
             });
 
@@ -108,6 +235,9 @@ impl Uniform
         {
             bind_group_layout,
             buffer,
+            coloring_buffer,
+            gradient_buffer,
+            histogram_texture,
             bind_group
         }
     }
@@ -115,7 +245,7 @@ impl Uniform
 
 impl Instance
 {
-    fn new(target: &crate::Target, render: &Render) -> Self
+    fn new(target: &crate::Target, render: &Render, format: CellFormat) -> Self
     {
         let instance_data_size = if render.use_double_precision
         {
@@ -135,7 +265,7 @@ impl Instance
                 mapped_at_creation: false,
             });
             
-        let format = target.config.format.add_srgb_suffix();
+        let texture_format = format.texture_format(target);
         
 		let fractal_texture = target.device.create_texture(
 			&wgpu::TextureDescriptor
@@ -150,19 +280,25 @@ impl Instance
 				mip_level_count: 1,
 				sample_count: 1,
 				dimension: wgpu::TextureDimension::D2,
-				format: format,
-				usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
-				view_formats: &[format],
+				format: texture_format,
+				usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST | wgpu::TextureUsages::COPY_SRC,
+				view_formats: &[texture_format],
 			}
 		);
 
         let fractal_texture_view = fractal_texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let bind_group_layout = match format
+        {
+            CellFormat::Color => &render.instance_bind_group_layout,
+            CellFormat::EscapeData => &render.escape_data_instance_bind_group_layout,
+        };
         
         let bind_group = target.device.create_bind_group(
             &wgpu::BindGroupDescriptor
             {
                 label: None,
-                layout: &render.instance_bind_group_layout,
+                layout: bind_group_layout,
                 entries: &[
                     wgpu::BindGroupEntry
                     {
@@ -180,6 +316,7 @@ impl Instance
         Self
         {
             use_double_precision: render.use_double_precision,
+            format,
             buffer,
             fractal_texture,
             bind_group,
@@ -191,6 +328,18 @@ impl Instance
         &self.fractal_texture
     }
 
+    pub fn format(&self) -> CellFormat
+    {
+        self.format
+    }
+
+    /// GPU memory used by the texture, in bytes
+    pub fn memory_size(&self) -> u64
+    {
+        let texel_size = self.fractal_texture.format().block_copy_size(None).unwrap_or(4);
+        self.fractal_texture.width() as u64 * self.fractal_texture.height() as u64 * texel_size as u64
+    }
+
     pub fn set_data(&self, queue: &wgpu::Queue, instance: &shared::render::Instance64)
     {
         if self.use_double_precision
@@ -205,77 +354,90 @@ impl Instance
     }
 }
 
-impl Render
+/// The cells of [`CellFormat::EscapeData`] are not `filterable`
+fn create_instance_bind_group_layout(target: &crate::Target, filterable: bool) -> wgpu::BindGroupLayout
 {
-    pub fn new(target: &crate::Target, vertex_shader_module: &wgpu::ShaderModule, fragment_shader_module: &wgpu::ShaderModule, texture_size: PhysicalSize<u32>, use_double_precision: bool) -> Self
-    {
-		let uniform = Uniform::new(target, use_double_precision);
-        
-        let instance_bind_group_layout = target.device.create_bind_group_layout(
-            &wgpu::BindGroupLayoutDescriptor
-            {
-                label: None,
-                entries: &[
-                    wgpu::BindGroupLayoutEntry
+    target.device.create_bind_group_layout(
+        &wgpu::BindGroupLayoutDescriptor
+        {
+            label: None,
+            entries: &[
+                wgpu::BindGroupLayoutEntry
+                {
+                    binding: 0,
+                    count: None,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer
                     {
-                        binding: 0,
-                        count: None,
-                        visibility: wgpu::ShaderStages::VERTEX,
-                        ty: wgpu::BindingType::Buffer
-                        {
-                            has_dynamic_offset: false,
-                            min_binding_size: None,
-                            ty: wgpu::BufferBindingType::Uniform,
-                        },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                        ty: wgpu::BufferBindingType::Uniform,
                     },
-                    wgpu::BindGroupLayoutEntry
+                },
+                wgpu::BindGroupLayoutEntry
+                {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture
                     {
-                        binding: 1,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Texture
-                        {
-                            multisampled: false,
-                            view_dimension: wgpu::TextureViewDimension::D2,
-                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                        },
-                        count: None,
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable },
                     },
-                ],
-            });
+                    count: None,
+                },
+            ],
+        })
+}
 
-        let pipeline_layout = target.device.create_pipeline_layout(
-            &wgpu::PipelineLayoutDescriptor
-            {
-                label: None,
-                bind_group_layouts: &[&uniform.bind_group_layout, &instance_bind_group_layout],
-                push_constant_ranges: &[],
-            });
+fn create_render_pipeline(target: &crate::Target, uniform_bind_group_layout: &wgpu::BindGroupLayout, instance_bind_group_layout: &wgpu::BindGroupLayout, vertex_shader_module: &wgpu::ShaderModule, fragment_shader_module: &wgpu::ShaderModule, fragment_entry_point: &str) -> wgpu::RenderPipeline
+{
+    let pipeline_layout = target.device.create_pipeline_layout(
+        &wgpu::PipelineLayoutDescriptor
+        {
+            label: None,
+            bind_group_layouts: &[uniform_bind_group_layout, instance_bind_group_layout],
+            push_constant_ranges: &[],
+        });
 
-        let render_pipeline = target.device.create_render_pipeline(
-            &wgpu::RenderPipelineDescriptor
+    target.device.create_render_pipeline(
+        &wgpu::RenderPipelineDescriptor
+        {
+            label: None,
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState
             {
-                label: None,
-                layout: Some(&pipeline_layout),
-                vertex: wgpu::VertexState
-                {
-                    module: vertex_shader_module,
-                    entry_point: "vertex",
-                    buffers: &[],
-                    compilation_options: Default::default(),
-                },
-                fragment: Some(wgpu::FragmentState
-                {
-                    module: fragment_shader_module,
-                    entry_point: "fragment",
-                    targets: &[Some(target.config.format.into())],
-                    compilation_options: Default::default(),
-                }),
-                primitive: wgpu::PrimitiveState::default(),
-                depth_stencil: None,
-                multisample: wgpu::MultisampleState::default(),
-                multiview: None,
-                cache: None,
-            });
+                module: vertex_shader_module,
+                entry_point: "vertex",
+                buffers: &[],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(wgpu::FragmentState
+            {
+                module: fragment_shader_module,
+                entry_point: fragment_entry_point,
+                targets: &[Some(target.config.format.into())],
+                compilation_options: Default::default(),
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        })
+}
+
+impl Render
+{
+    pub fn new(target: &crate::Target, vertex_shader_module: &wgpu::ShaderModule, fragment_shader_module: &wgpu::ShaderModule, texture_size: PhysicalSize<u32>, use_double_precision: bool) -> Self
+    {
+		let uniform = Uniform::new(target, use_double_precision);
+        
+        let instance_bind_group_layout = create_instance_bind_group_layout(target, true);
+        let render_pipeline = create_render_pipeline(target, &uniform.bind_group_layout, &instance_bind_group_layout, vertex_shader_module, fragment_shader_module, "fragment");
+
+        let escape_data_instance_bind_group_layout = create_instance_bind_group_layout(target, false);
+        let escape_data_render_pipeline = create_render_pipeline(target, &uniform.bind_group_layout, &escape_data_instance_bind_group_layout, vertex_shader_module, fragment_shader_module, "fragment_escape_data");
 
         Self
         {
@@ -283,13 +445,15 @@ impl Render
             use_double_precision,
             render_pipeline,
             instance_bind_group_layout,
+            escape_data_render_pipeline,
+            escape_data_instance_bind_group_layout,
             uniform,
         }
     }
 
-    pub fn make_instance(&self, target: &crate::Target) -> Instance
+    pub fn make_instance(&self, target: &crate::Target, format: CellFormat) -> Instance
     {
-        Instance::new(target, self)
+        Instance::new(target, self, format)
     }
 
     pub fn make_render_pass<'i>(&self, instances: impl IntoIterator<Item = &'i Instance>, view: &wgpu::TextureView, commands: &mut wgpu::CommandEncoder)
@@ -314,11 +478,20 @@ impl Render
                 occlusion_query_set: None,
             });
 
-        render_pass.set_pipeline(&self.render_pipeline);
         render_pass.set_bind_group(0, &self.uniform.bind_group, &[]);
 
+        let mut format = None;
         for instance in instances
         {
+            if format != Some(instance.format)
+            {
+                render_pass.set_pipeline(match instance.format
+                {
+                    CellFormat::Color => &self.render_pipeline,
+                    CellFormat::EscapeData => &self.escape_data_render_pipeline,
+                });
+                format = Some(instance.format);
+            }
             render_pass.set_bind_group(1, &instance.bind_group, &[]);
             render_pass.draw(0..6, 0..1);
         }
@@ -340,4 +513,32 @@ impl Render
             queue.write_buffer(&self.uniform.buffer, 0, bytemuck::bytes_of(&uniform));
         }
     }
+
+    /// Sets how the cells of [`CellFormat::EscapeData`] are colored
+    pub fn set_coloring(&self, queue: &wgpu::Queue, coloring: &shared::render::ColoringUniforms, gradient: &shared::gradient::Gradient)
+    {
+        queue.write_buffer(&self.uniform.coloring_buffer, 0, bytemuck::bytes_of(coloring));
+        queue.write_buffer(&self.uniform.gradient_buffer, 0, bytemuck::bytes_of(gradient));
+    }
+
+    /// Only used by [`shared::fractal::ColorMapping::Histogram`]
+    pub fn set_histogram(&self, queue: &wgpu::Queue, histogram: &shared::histogram::Histogram)
+    {
+        queue.write_texture(
+            wgpu::ImageCopyTexture
+            {
+                texture: &self.uniform.histogram_texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            bytemuck::cast_slice(histogram.ranks()),
+            wgpu::ImageDataLayout
+            {
+                offset: 0,
+                bytes_per_row: None,
+                rows_per_image: None,
+            },
+            self.uniform.histogram_texture.size());
+    }
 }
@@ -0,0 +1,273 @@
+use std::fmt::Display;
+use std::path::Path;
+
+use fractal_renderer_shared as shared;
+use shared::fractal::{FractalParams64, OrbitTrap};
+use shared::gradient::Gradient;
+use serde::{Deserialize, Serialize};
+
+use crate::animation::Animation;
+use crate::deep_zoom::FixedVec2;
+
+
+/// Version written in the scene files, files with a newer version can't be opened
+pub const SCENE_VERSION: u32 = 1;
+
+/// Everything needed to restore a view, saved as JSON.
+///
+/// Missing fields take their default value, so that files written by older versions can still be opened.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Scene
+{
+	pub version: u32,
+	/// Center of the view, with the precision of the deep zoom origin so that deep zoom locations are not truncated
+	pub center: FixedVec2,
+	pub zoom: f64,
+	/// Zoom of the view that is swapped in when switching between the normal and julia variations
+	pub secondary_zoom: f64,
+	pub deep_zoom: bool,
+	pub fractal: FractalParams64,
+	/// Source of the iteration formula of [`shared::fractal::FractalKind::Custom`]
+	pub formula: String,
+	/// Gradient of [`shared::fractal::ColorPalette::Custom`]
+	pub gradient: Gradient,
+	/// Keyframes of the zoom animation
+	pub animation: Animation,
+}
+
+impl Default for Scene
+{
+	fn default() -> Self
+	{
+		Self
+		{
+			version: SCENE_VERSION,
+			center: FixedVec2::default(),
+			zoom: 1.0,
+			secondary_zoom: 1.0,
+			deep_zoom: false,
+			fractal: FractalParams64::default(),
+			formula: String::from("z^2 + c"),
+			gradient: Gradient::default(),
+			animation: Animation::default(),
+		}
+	}
+}
+
+#[derive(Debug)]
+pub enum SceneError
+{
+	Io(std::io::Error),
+	Json(serde_json::Error),
+	UnsupportedVersion(u32),
+}
+
+impl Display for SceneError
+{
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+	{
+		match self
+		{
+			Self::Io(error) => write!(f, "{error}"),
+			Self::Json(error) => write!(f, "invalid scene file: {error}"),
+			Self::UnsupportedVersion(version) => write!(f, "the scene file version {version} is newer than the supported version {SCENE_VERSION}"),
+		}
+	}
+}
+
+impl std::error::Error for SceneError
+{
+
+}
+
+impl From<std::io::Error> for SceneError
+{
+	fn from(error: std::io::Error) -> Self
+	{
+		Self::Io(error)
+	}
+}
+
+impl From<serde_json::Error> for SceneError
+{
+	fn from(error: serde_json::Error) -> Self
+	{
+		Self::Json(error)
+	}
+}
+
+impl Scene
+{
+	pub fn from_json(json: &str) -> Result<Self, SceneError>
+	{
+		let mut value: serde_json::Value = serde_json::from_str(json)?;
+		upgrade_render_technique(&mut value);
+		let scene: Self = serde_json::from_value(value)?;
+		if scene.version > SCENE_VERSION
+		{
+			return Err(SceneError::UnsupportedVersion(scene.version));
+		}
+
+		Ok(scene)
+	}
+
+	pub fn to_json(&self) -> String
+	{
+		serde_json::to_string_pretty(self).expect("A scene can always be serialized")
+	}
+
+	pub fn load(path: impl AsRef<Path>) -> Result<Self, SceneError>
+	{
+		Self::from_json(&std::fs::read_to_string(path)?)
+	}
+
+	pub fn save(&self, path: impl AsRef<Path>) -> Result<(), SceneError>
+	{
+		std::fs::write(path, self.to_json())?;
+		Ok(())
+	}
+}
+
+// The point and cross orbit traps were render techniques before the trap could be configured, they are read as presets of the orbit trap
+fn upgrade_render_technique(scene: &mut serde_json::Value)
+{
+	let Some(fractal) = scene.get_mut("fractal").and_then(serde_json::Value::as_object_mut)
+	else
+	{
+		return;
+	};
+
+	let trap = match fractal.get("render_technique").and_then(serde_json::Value::as_str)
+	{
+		Some("OrbitTrapPoint") => OrbitTrap::POINT,
+		Some("OrbitTrapCross") => OrbitTrap::CROSS,
+		_ => return,
+	};
+
+	fractal.insert(String::from("render_technique"), serde_json::Value::from("OrbitTrap"));
+	fractal.insert(String::from("orbit_trap"), serde_json::to_value(trap).expect("An orbit trap can always be serialized"));
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+	use shared::fractal::{ColorPalette, FractalKind, RenderTechnique};
+	use shared::math::{Complex64, DDVec2, DoubleDouble, Fixed};
+	use glam::dvec2;
+	use crate::animation::Keyframe;
+
+	#[test]
+	fn scene_round_trips()
+	{
+		let mut scene = Scene
+		{
+			center: DDVec2::new("-1.7497591451303665015113569335438".parse().unwrap(), DoubleDouble::from(1.0e-20)).into(),
+			zoom: 2.5e-25,
+			secondary_zoom: 0.5,
+			deep_zoom: true,
+			formula: String::from("z^3 + sin(c)"),
+			..Scene::default()
+		};
+		scene.fractal.fractal_kind = FractalKind::Custom;
+		scene.fractal.render_technique = RenderTechnique::OrbitTrap;
+		scene.fractal.orbit_trap = OrbitTrap::CROSS;
+		scene.fractal.color_palette = ColorPalette::Custom;
+		scene.fractal.secondary_pos = Complex64::new(-0.4, 0.6);
+		scene.fractal.iteration_limit = 12345;
+		scene.animation.frame_rate = 60;
+		scene.animation.insert(Keyframe::from_scene(&scene, 2.0));
+
+		let json = scene.to_json();
+		let loaded = Scene::from_json(&json).unwrap();
+		assert_eq!(loaded.to_json(), json);
+
+		assert_eq!(loaded.center.x, scene.center.x);
+		assert_eq!(loaded.center.y, scene.center.y);
+		assert_eq!(loaded.zoom, scene.zoom);
+		assert_eq!(loaded.secondary_zoom, scene.secondary_zoom);
+		assert!(loaded.deep_zoom);
+		assert_eq!(loaded.fractal.fractal_kind, FractalKind::Custom);
+		assert_eq!(loaded.fractal.render_technique, RenderTechnique::OrbitTrap);
+		assert!(loaded.fractal.orbit_trap == OrbitTrap::CROSS);
+		assert_eq!(loaded.fractal.color_palette, ColorPalette::Custom);
+		assert!(loaded.fractal.secondary_pos == scene.fractal.secondary_pos);
+		assert_eq!(loaded.fractal.iteration_limit, 12345);
+		assert_eq!(loaded.formula, scene.formula);
+		assert_eq!(loaded.animation.frame_rate, 60);
+		assert_eq!(loaded.animation.keyframes().len(), 1);
+		assert_eq!(loaded.animation.keyframes()[0].time, 2.0);
+	}
+
+	#[test]
+	fn center_keeps_the_precision_of_the_deep_zoom_origin()
+	{
+		let coarse = FixedVec2::from(dvec2(-1.7497591451303665, 0.25)) + dvec2(1.0e-20, -3.0e-25);
+		let scene = Scene
+		{
+			center: coarse + dvec2(5.0e-45, 7.0e-60),
+			zoom: 1.0e-55,
+			deep_zoom: true,
+			..Scene::default()
+		};
+
+		let loaded = Scene::from_json(&scene.to_json()).unwrap();
+		assert_eq!(loaded.center, scene.center);
+		assert_eq!(loaded.center.x - coarse.x, Fixed::from_f64(5.0e-45));
+		assert_eq!(loaded.center.y - coarse.y, Fixed::from_f64(7.0e-60));
+	}
+
+	#[test]
+	fn newer_version_is_rejected()
+	{
+		let json = format!("{{ \"version\": {} }}", SCENE_VERSION + 1);
+		assert!(matches!(Scene::from_json(&json), Err(SceneError::UnsupportedVersion(version)) if version == SCENE_VERSION + 1));
+
+		assert!(Scene::from_json(&format!("{{ \"version\": {SCENE_VERSION} }}")).is_ok());
+	}
+
+	#[test]
+	fn missing_fields_take_their_default_value()
+	{
+		let scene = Scene::from_json("{ \"zoom\": 0.5, \"fractal\": { \"iteration_limit\": 500 } }").unwrap();
+		let default = Scene::default();
+
+		assert_eq!(scene.zoom, 0.5);
+		assert_eq!(scene.fractal.iteration_limit, 500);
+
+		assert_eq!(scene.version, SCENE_VERSION);
+		assert_eq!(scene.center.x, default.center.x);
+		assert_eq!(scene.center.y, default.center.y);
+		assert_eq!(scene.secondary_zoom, default.secondary_zoom);
+		assert!(!scene.deep_zoom);
+		assert_eq!(scene.fractal.fractal_kind, default.fractal.fractal_kind);
+		assert_eq!(scene.fractal.render_technique, default.fractal.render_technique);
+		assert_eq!(scene.fractal.color_frequency, default.fractal.color_frequency);
+		assert!(scene.fractal.lighting == default.fractal.lighting);
+		assert_eq!(scene.formula, default.formula);
+		assert!(scene.animation.keyframes().is_empty());
+		assert_eq!(scene.animation.frame_rate, default.animation.frame_rate);
+
+		assert!(Scene::from_json("{}").is_ok());
+	}
+
+	#[test]
+	fn invalid_json_is_rejected()
+	{
+		assert!(matches!(Scene::from_json("{ \"zoom\": "), Err(SceneError::Json(_))));
+		assert!(matches!(Scene::from_json("{ \"zoom\": \"far\" }"), Err(SceneError::Json(_))));
+	}
+
+	#[test]
+	fn legacy_orbit_trap_techniques_are_read_as_presets()
+	{
+		for (technique, trap) in [("OrbitTrapPoint", OrbitTrap::POINT), ("OrbitTrapCross", OrbitTrap::CROSS)]
+		{
+			let json = format!("{{ \"fractal\": {{ \"render_technique\": \"{technique}\" }} }}");
+			let scene = Scene::from_json(&json).unwrap();
+			assert_eq!(scene.fractal.render_technique, RenderTechnique::OrbitTrap);
+			assert!(scene.fractal.orbit_trap == trap);
+		}
+	}
+}
@@ -9,6 +9,10 @@ pub struct Target
 	pub device: wgpu::Device,
 	pub queue: wgpu::Queue,
 	pub supports_compute_shader: bool,
+	/// The adapter runs on the CPU, such as llvmpipe or WARP
+	pub is_software_adapter: bool,
+	/// The cells can be computed and drawn as [`crate::render::CellFormat::EscapeData`]
+	pub supports_escape_data: bool,
 }
 
 impl Target
@@ -48,6 +52,9 @@ impl Target
         let config = surface.get_default_config(&adapter, window_size.width, window_size.height).expect("Surface not supported by adapter");
 
 		let supports_compute_shader = adapter.get_downlevel_capabilities().flags.contains(wgpu::DownlevelFlags::COMPUTE_SHADERS);
+		let is_software_adapter = adapter.get_info().device_type == wgpu::DeviceType::Cpu;
+		let escape_data_usages = wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_SRC | wgpu::TextureUsages::COPY_DST;
+		let supports_escape_data = adapter.get_texture_format_features(crate::render::ESCAPE_DATA_TEXTURE_FORMAT).allowed_usages.contains(escape_data_usages);
 
 		let this = Self
 		{
@@ -57,6 +64,8 @@ impl Target
 			device,
 			queue,
 			supports_compute_shader,
+			is_software_adapter,
+			supports_escape_data,
 		};
 
         this.configure_surface();
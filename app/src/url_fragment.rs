@@ -0,0 +1,436 @@
+use std::fmt::Display;
+
+use fractal_renderer_shared as shared;
+use shared::fractal::{ColorPalette, FractalKind, FractalVariation, OrbitTrap, RenderTechnique};
+use shared::math::{Complex64, ComplexNumber, Fixed};
+
+use crate::scene::Scene;
+
+// The view is written as `key=value` pairs separated by `&`, such as `k=burning-ship&x=-1.76&y=-0.03&z=0.02`.
+// Values that are equal to their default are left out to keep the links short.
+//
+// k: fractal kind
+// v: variation
+// t: render technique
+// x, y: center, written as numbers separated by `~` whose exact sum keeps the precision of the deep zoom origin
+// z: zoom
+// d: present when the deep zoom is enabled
+// c: julia constant, written re,im
+// e: exponent of the multibrot, written re,im
+// p: color palette
+// f: color frequency
+// i: iteration limit, when it is not the default one of the fractal kind
+
+const KINDS: [(FractalKind, &str); 9] = [
+	(FractalKind::MandelbrotSet, "mandelbrot"),
+	(FractalKind::Multibrot, "multibrot"),
+	(FractalKind::Tricorn, "tricorn"),
+	(FractalKind::BurningShip, "burning-ship"),
+	(FractalKind::CosLeaf, "cos-leaf"),
+	(FractalKind::Custom, "custom"),
+	(FractalKind::Newton3, "newton3"),
+	(FractalKind::NewtonPolynomial, "newton-polynomial"),
+	(FractalKind::Lyapunov, "lyapunov"),
+];
+
+const VARIATIONS: [(FractalVariation, &str); 2] = [
+	(FractalVariation::Normal, "normal"),
+	(FractalVariation::JuliaSet, "julia"),
+];
+
+const TECHNIQUES: [(RenderTechnique, &str); 7] = [
+	(RenderTechnique::Normal, "normal"),
+	(RenderTechnique::NormalMap, "normal-map"),
+	(RenderTechnique::DistanceEstimate, "distance-estimate"),
+	(RenderTechnique::StripeAverage, "stripe-average"),
+	(RenderTechnique::CurvatureAverage, "curvature-average"),
+	(RenderTechnique::TriangleInequalityAverage, "triangle-inequality-average"),
+	(RenderTechnique::OrbitTrap, "orbit-trap"),
+];
+
+// The point and cross orbit traps were techniques before the trap could be configured
+const LEGACY_ORBIT_TRAPS: [(OrbitTrap, &str); 2] = [
+	(OrbitTrap::POINT, "orbit-trap-point"),
+	(OrbitTrap::CROSS, "orbit-trap-cross"),
+];
+
+const PALETTES: [(ColorPalette, &str); 4] = [
+	(ColorPalette::Default, "default"),
+	(ColorPalette::Flames, "flames"),
+	(ColorPalette::Temperature, "temperature"),
+	(ColorPalette::Custom, "custom"),
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FragmentError
+{
+	/// A pair is not written `key=value`
+	MissingValue(String),
+	InvalidValue
+	{
+		key: String,
+		value: String,
+	},
+}
+
+impl Display for FragmentError
+{
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result
+	{
+		match self
+		{
+			Self::MissingValue(pair) => write!(f, "missing value in \"{pair}\""),
+			Self::InvalidValue { key, value } => write!(f, "invalid value \"{value}\" for \"{key}\""),
+		}
+	}
+}
+
+impl std::error::Error for FragmentError
+{
+
+}
+
+/// Writes the view of `scene` in a URL fragment, without the leading `#`.
+///
+/// The custom formula, the polynomial, the lyapunov parameters and the gradient are not included, scene files should be used to share them.
+pub fn encode(scene: &Scene) -> String
+{
+	let default = Scene::default();
+	let fractal = &scene.fractal;
+	let mut pairs = vec![
+		format!("k={}", name(&KINDS, fractal.fractal_kind)),
+	];
+
+	if fractal.variation != default.fractal.variation
+	{
+		pairs.push(format!("v={}", name(&VARIATIONS, fractal.variation)));
+	}
+	if fractal.render_technique != default.fractal.render_technique
+	{
+		pairs.push(format!("t={}", name(&TECHNIQUES, fractal.render_technique)));
+	}
+
+	pairs.push(format!("x={}", format_fixed(scene.center.x)));
+	pairs.push(format!("y={}", format_fixed(scene.center.y)));
+	pairs.push(format!("z={}", format_number(scene.zoom)));
+	if scene.deep_zoom
+	{
+		pairs.push(String::from("d"));
+	}
+
+	if fractal.secondary_pos != default.fractal.secondary_pos
+	{
+		pairs.push(format!("c={}", format_complex(fractal.secondary_pos)));
+	}
+	if fractal.fractal_kind == FractalKind::Multibrot && fractal.exponent != default.fractal.exponent
+	{
+		pairs.push(format!("e={}", format_complex(fractal.exponent)));
+	}
+	if fractal.color_palette != default.fractal.color_palette
+	{
+		pairs.push(format!("p={}", name(&PALETTES, fractal.color_palette)));
+	}
+	if fractal.color_frequency != default.fractal.color_frequency
+	{
+		pairs.push(format!("f={}", format_number(fractal.color_frequency)));
+	}
+	if fractal.iteration_limit != fractal.fractal_kind.default_iteration_limit()
+	{
+		pairs.push(format!("i={}", fractal.iteration_limit));
+	}
+
+	pairs.join("&")
+}
+
+/// Reads a view written by [`encode`], with or without the leading `#`.
+///
+/// The values that are not in the fragment keep their default, and unknown keys are ignored so that links made by newer versions still open.
+pub fn decode(fragment: &str) -> Result<Scene, FragmentError>
+{
+	let mut scene = Scene::default();
+	let mut iteration_limit = None;
+
+	for pair in fragment.trim_start_matches('#').split('&').filter(|pair| !pair.is_empty())
+	{
+		if pair == "d"
+		{
+			scene.deep_zoom = true;
+			continue;
+		}
+
+		let Some((key, value)) = pair.split_once('=')
+		else
+		{
+			return Err(FragmentError::MissingValue(pair.to_string()));
+		};
+
+		let fractal = &mut scene.fractal;
+		let valid = match key
+		{
+			"k" => parse_name(&KINDS, value).map(|kind| fractal.fractal_kind = kind),
+			"v" => parse_name(&VARIATIONS, value).map(|variation| fractal.variation = variation),
+			"t" => match legacy_orbit_trap(value)
+			{
+				Some(trap) =>
+				{
+					fractal.render_technique = RenderTechnique::OrbitTrap;
+					fractal.orbit_trap = trap;
+					Some(())
+				},
+				None => parse_name(&TECHNIQUES, value).map(|technique| fractal.render_technique = technique),
+			},
+			"x" => parse_fixed(value).map(|x| scene.center.x = x),
+			"y" => parse_fixed(value).map(|y| scene.center.y = y),
+			"z" => value.parse().ok().filter(|zoom: &f64| zoom.is_finite() && *zoom > 0.0).map(|zoom| scene.zoom = zoom),
+			"c" => parse_complex(value).map(|constant| fractal.secondary_pos = constant),
+			"e" => parse_complex(value).map(|exponent| fractal.exponent = exponent),
+			"p" => parse_name(&PALETTES, value).map(|palette| fractal.color_palette = palette),
+			"f" => value.parse().ok().filter(|frequency: &f32| frequency.is_finite()).map(|frequency| fractal.color_frequency = frequency),
+			"i" => value.parse().ok().map(|limit| iteration_limit = Some(limit)),
+			_ => Some(()),
+		};
+
+		if valid.is_none()
+		{
+			return Err(FragmentError::InvalidValue { key: key.to_string(), value: value.to_string() });
+		}
+	}
+
+	scene.fractal.iteration_limit = iteration_limit.unwrap_or(scene.fractal.fractal_kind.default_iteration_limit());
+
+	Ok(scene)
+}
+
+/// The orbit trap of a render technique name written by older versions, see [`RenderTechnique::OrbitTrap`]
+pub fn legacy_orbit_trap(name: &str) -> Option<OrbitTrap>
+{
+	parse_name(&LEGACY_ORBIT_TRAPS, name)
+}
+
+fn name<T: PartialEq + Copy>(names: &[(T, &'static str)], value: T) -> &'static str
+{
+	names.iter().find(|(v, _)| *v == value).map(|(_, name)| *name).expect("Every value has a name")
+}
+
+fn parse_name<T: Copy>(names: &[(T, &str)], name: &str) -> Option<T>
+{
+	names.iter().find(|(_, n)| *n == name).map(|(value, _)| *value)
+}
+
+// The shortest of the decimal and scientific notations, both are read back exactly
+fn format_number<T: Display + std::fmt::LowerExp>(value: T) -> String
+{
+	let decimal = format!("{value}");
+	let scientific = format!("{value:e}");
+	if scientific.len() < decimal.len()
+	{
+		scientific
+	}
+	else
+	{
+		decimal
+	}
+}
+
+// The f64 terms of the value, which is a single number unless it needs more than the precision of an f64
+fn format_fixed<const N: usize>(value: Fixed<N>) -> String
+{
+	value.f64_terms().map(format_number).collect::<Vec<_>>().join("~")
+}
+
+// Also reads the `hi~lo` double-doubles written by older versions
+fn parse_fixed<const N: usize>(value: &str) -> Option<Fixed<N>>
+{
+	value.split('~').try_fold(Fixed::ZERO, |sum, term|
+	{
+		let term: f64 = term.parse().ok()?;
+		term.is_finite().then(|| sum + Fixed::from_f64(term))
+	})
+}
+
+fn format_complex(value: Complex64) -> String
+{
+	format!("{},{}", format_number(value.re()), format_number(value.im()))
+}
+
+fn parse_complex(value: &str) -> Option<Complex64>
+{
+	let (re, im) = value.split_once(',')?;
+	let (re, im): (f64, f64) = (re.parse().ok()?, im.parse().ok()?);
+	(re.is_finite() && im.is_finite()).then_some(Complex64::new(re, im))
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+	use shared::math::{DDVec2, DoubleDouble};
+	use glam::dvec2;
+	use crate::deep_zoom::FixedVec2;
+
+	fn round_trip(scene: &Scene) -> Scene
+	{
+		decode(&encode(scene)).expect("An encoded scene can be decoded")
+	}
+
+	#[test]
+	fn default_scene_only_writes_the_kind_and_view()
+	{
+		assert_eq!(encode(&Scene::default()), "k=mandelbrot&x=0&y=0&z=1");
+	}
+
+	#[test]
+	fn every_key_round_trips()
+	{
+		let mut scene = Scene::default();
+		scene.fractal.fractal_kind = FractalKind::Multibrot;
+		scene.fractal.variation = FractalVariation::JuliaSet;
+		scene.fractal.render_technique = RenderTechnique::DistanceEstimate;
+		scene.center = DDVec2::new(DoubleDouble::from(-0.75), DoubleDouble::from(0.125)).into();
+		scene.zoom = 1.5e-9;
+		scene.deep_zoom = true;
+		scene.fractal.secondary_pos = Complex64::new(-0.4, 0.6);
+		scene.fractal.exponent = Complex64::new(4.0, 0.5);
+		scene.fractal.color_palette = ColorPalette::Temperature;
+		scene.fractal.color_frequency = 2.5;
+		scene.fractal.iteration_limit = 12345;
+
+		let fragment = encode(&scene);
+		for key in ["k=", "v=", "t=", "x=", "y=", "z=", "c=", "e=", "p=", "f=", "i="]
+		{
+			assert!(fragment.split('&').any(|pair| pair.starts_with(key)), "{key} is missing from {fragment}");
+		}
+		assert!(fragment.split('&').any(|pair| pair == "d"));
+
+		let decoded = round_trip(&scene);
+		assert_eq!(decoded.fractal.fractal_kind, FractalKind::Multibrot);
+		assert_eq!(decoded.fractal.variation, FractalVariation::JuliaSet);
+		assert_eq!(decoded.fractal.render_technique, RenderTechnique::DistanceEstimate);
+		assert_eq!(decoded.center, scene.center);
+		assert_eq!(decoded.zoom, scene.zoom);
+		assert!(decoded.deep_zoom);
+		assert!(decoded.fractal.secondary_pos == scene.fractal.secondary_pos);
+		assert!(decoded.fractal.exponent == scene.fractal.exponent);
+		assert_eq!(decoded.fractal.color_palette, ColorPalette::Temperature);
+		assert_eq!(decoded.fractal.color_frequency, 2.5);
+		assert_eq!(decoded.fractal.iteration_limit, 12345);
+	}
+
+	#[test]
+	fn every_name_round_trips()
+	{
+		for (kind, _) in KINDS
+		{
+			let mut scene = Scene::default();
+			scene.fractal.fractal_kind = kind;
+			scene.fractal.iteration_limit = kind.default_iteration_limit();
+			let decoded = round_trip(&scene);
+			assert_eq!(decoded.fractal.fractal_kind, kind);
+			assert_eq!(decoded.fractal.iteration_limit, kind.default_iteration_limit());
+		}
+		for (technique, _) in TECHNIQUES
+		{
+			let mut scene = Scene::default();
+			scene.fractal.render_technique = technique;
+			assert_eq!(round_trip(&scene).fractal.render_technique, technique);
+		}
+		for (palette, _) in PALETTES
+		{
+			let mut scene = Scene::default();
+			scene.fractal.color_palette = palette;
+			assert_eq!(round_trip(&scene).fractal.color_palette, palette);
+		}
+	}
+
+	#[test]
+	fn legacy_orbit_traps_are_read_as_presets()
+	{
+		for (trap, name) in LEGACY_ORBIT_TRAPS
+		{
+			let scene = decode(&format!("k=mandelbrot&t={name}")).unwrap();
+			assert_eq!(scene.fractal.render_technique, RenderTechnique::OrbitTrap);
+			assert!(scene.fractal.orbit_trap == trap);
+			assert!(encode(&scene).contains("t=orbit-trap&"));
+		}
+	}
+
+	#[test]
+	fn center_keeps_the_double_double_precision()
+	{
+		let x: DoubleDouble = "-1.7497591451303665015113569335438".parse().unwrap();
+		let y: DoubleDouble = "0.0000000000000000123456789012345678".parse().unwrap();
+		assert_ne!(x.lo(), 0.0);
+
+		let scene = Scene
+		{
+			center: DDVec2::new(x, y).into(),
+			..Scene::default()
+		};
+		let fragment = encode(&scene);
+		assert!(fragment.contains(&format!("x={}~{}&", format_number(x.hi()), format_number(x.lo()))));
+		assert_eq!(round_trip(&scene).center, scene.center);
+	}
+
+	#[test]
+	fn center_keeps_the_precision_of_the_deep_zoom_origin()
+	{
+		let coarse = FixedVec2::from(dvec2(-1.7497591451303665, 0.25)) + dvec2(1.0e-20, -3.0e-25);
+		let scene = Scene
+		{
+			center: coarse + dvec2(5.0e-45, 7.0e-60),
+			zoom: 1.0e-55,
+			deep_zoom: true,
+			..Scene::default()
+		};
+
+		let decoded = round_trip(&scene);
+		assert_eq!(decoded.center, scene.center);
+		assert_eq!(decoded.center.x - coarse.x, Fixed::from_f64(5.0e-45));
+		assert_eq!(decoded.center.y - coarse.y, Fixed::from_f64(7.0e-60));
+	}
+
+	#[test]
+	fn leading_hash_and_empty_pairs_are_accepted()
+	{
+		let scene = decode("#k=tricorn&&z=0.5&").unwrap();
+		assert_eq!(scene.fractal.fractal_kind, FractalKind::Tricorn);
+		assert_eq!(scene.zoom, 0.5);
+		assert_eq!(scene.fractal.iteration_limit, FractalKind::Tricorn.default_iteration_limit());
+	}
+
+	#[test]
+	fn unknown_keys_are_ignored()
+	{
+		let scene = decode("k=burning-ship&future=42").unwrap();
+		assert_eq!(scene.fractal.fractal_kind, FractalKind::BurningShip);
+	}
+
+	#[test]
+	fn malformed_pairs_are_rejected()
+	{
+		assert_eq!(decode("k=mandelbrot&zoom").err(), Some(FragmentError::MissingValue(String::from("zoom"))));
+
+		for (key, value) in [
+			("k", "julia"),
+			("v", "other"),
+			("t", "stripes"),
+			("x", "left"),
+			("y", "1~up"),
+			("z", "0"),
+			("z", "-2"),
+			("z", "inf"),
+			("c", "0.5"),
+			("c", "nan,0"),
+			("e", "2,"),
+			("p", "rainbow"),
+			("f", "NaN"),
+			("i", "-1"),
+		]
+		{
+			assert_eq!(
+				decode(&format!("{key}={value}")).err(),
+				Some(FragmentError::InvalidValue { key: key.to_string(), value: value.to_string() }),
+			);
+		}
+	}
+}
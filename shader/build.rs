@@ -6,27 +6,28 @@ const TARGET: &str = "spirv-unknown-spv1.5";
 
 fn main() -> Result<(), Box<dyn std::error::Error>>
 {
-    build_shader("vertex32", false)?;
-    build_shader("vertex64", true)?;
-    build_shader("fragment", false)?;
+    build_shader("vertex32", &[])?;
+    build_shader("vertex64", &[Capability::Float64])?;
+    // The ranks of the histogram are in a 1D texture
+    build_shader("fragment", &[Capability::Sampled1D])?;
 
-    //build_shader("compute32", false)?;
-    //build_shader("compute64", true)?;
+    build_shader("compute32", &[])?;
+    build_shader("compute64", &[Capability::Float64])?;
     
-    build_shader("computation32", false)?;
-    build_shader("computation64", true)?;
+    build_shader("computation32", &[])?;
+    build_shader("computation64", &[Capability::Float64])?;
 
     Ok(())
 }
 
-fn build_shader(path_to_crate: impl AsRef<Path>, uses_float64: bool) -> Result<CompileResult, SpirvBuilderError>
+fn build_shader(path_to_crate: impl AsRef<Path>, capabilities: &[Capability]) -> Result<CompileResult, SpirvBuilderError>
 {
     let mut builder = SpirvBuilder::new(path_to_crate, TARGET)
         .deny_warnings(true);
 
-    if uses_float64
+    for &capability in capabilities
     {
-        builder = builder.capability(Capability::Float64);
+        builder = builder.capability(capability);
     }
 
     match std::env::var("PROFILE").as_deref()
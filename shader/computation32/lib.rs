@@ -4,7 +4,8 @@
 use fractal_renderer_shared as shared;
 use shared::color::srgb_to_linear;
 use spirv_std::spirv;
-use spirv_std::glam::{Vec2, Vec4, vec2};
+use spirv_std::arch::{ddx, ddy};
+use spirv_std::glam::{Vec2, Vec4, vec2, vec4};
 
 
 const VERTICES: [(Vec2, Vec2); 6] =
@@ -35,6 +36,13 @@ pub fn vertex(
 }
 
 
+// Size of the pixel being computed, the output texture is axis aligned
+fn pixel_uv_size(uv: Vec2) -> Vec2
+{
+    vec2(ddx(uv.x), ddy(uv.y)).abs()
+}
+
+
 #[spirv(fragment)]
 pub fn fragment(
     // Inputs
@@ -45,7 +53,27 @@ pub fn fragment(
 
     // Uniforms
     #[spirv(uniform, descriptor_set = 0, binding = 0)] params: &shared::compute::Params32,
+    #[spirv(uniform, descriptor_set = 0, binding = 1)] formula: &shared::formula::Formula32,
+    #[spirv(uniform, descriptor_set = 0, binding = 2)] gradient: &shared::gradient::Gradient,
+)
+{
+    *output_color = (srgb_to_linear(shared::compute::run_uv(input_uv, pixel_uv_size(input_uv), (*params).into(), formula, gradient)), 1.0).into();
+}
+
+
+#[spirv(fragment)]
+pub fn fragment_escape_data(
+    // Inputs
+    input_uv: Vec2,
+
+    // Outputs
+    output_data: &mut Vec4,
+
+    // Uniforms
+    #[spirv(uniform, descriptor_set = 0, binding = 0)] params: &shared::compute::Params32,
+    #[spirv(uniform, descriptor_set = 0, binding = 1)] formula: &shared::formula::Formula32,
 )
 {
-    *output_color = (srgb_to_linear(shared::compute::run_uv(input_uv, (*params).into())), 1.0).into();
+    let data = shared::compute::run_escape_data_uv(input_uv, pixel_uv_size(input_uv), (*params).into(), formula);
+    *output_data = vec4(data.value, data.coverage, data.interior_value, data.interior_coverage);
 }
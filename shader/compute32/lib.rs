@@ -15,10 +15,34 @@ pub fn compute_mandelbrot(
 
     // Outputs
     #[spirv(storage_buffer, descriptor_set = 0, binding = 1)] output: &mut [u32],
+
+    // Inputs
+    #[spirv(storage_buffer, descriptor_set = 0, binding = 2)] formula: &shared::formula::Formula32,
+    #[spirv(storage_buffer, descriptor_set = 0, binding = 3)] gradient: &shared::gradient::Gradient,
+)
+{
+    let size = uvec2(group_count.x * WORKGROUP_SIZE.x, group_count.y * WORKGROUP_SIZE.y);
+    let index = id.x + id.y * size.x;
+
+    output[index as usize] = shared::compute::run(id.xy(), size, (*params).into(), formula, gradient);
+}
+
+#[spirv(compute(threads(16, 16)))]
+pub fn compute_escape_data(
+    // Inputs
+    #[spirv(global_invocation_id)] id: UVec3,
+    #[spirv(num_workgroups)] group_count: UVec3,
+    #[spirv(uniform, descriptor_set = 0, binding = 0)] params : &shared::compute::Params32,
+
+    // Outputs
+    #[spirv(storage_buffer, descriptor_set = 0, binding = 1)] output: &mut [shared::compute::EscapeData],
+
+    // Inputs
+    #[spirv(storage_buffer, descriptor_set = 0, binding = 2)] formula: &shared::formula::Formula32,
 )
 {
     let size = uvec2(group_count.x * WORKGROUP_SIZE.x, group_count.y * WORKGROUP_SIZE.y);
     let index = id.x + id.y * size.x;
 
-    output[index as usize] = shared::compute::run(id.xy(), size, (*params).into());
+    output[index as usize] = shared::compute::run_escape_data(id.xy(), size, (*params).into(), formula);
 }
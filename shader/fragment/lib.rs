@@ -1,8 +1,18 @@
 #![no_std]
 #![deny(warnings)]
 
+use fractal_renderer_shared as shared;
+use shared::compute::EscapeData;
+use shared::fractal::ColorMapping;
+use shared::gradient::Gradient;
+use shared::histogram::histogram_bin;
+use shared::render::ColoringUniforms;
 use spirv_std::{spirv, Image, Sampler};
-use spirv_std::glam::{Vec2, Vec4};
+use spirv_std::glam::{Vec2, Vec3, Vec4, vec2};
+
+type EscapeDataTexture = Image!(2D, type=f32, sampled=true);
+/// Ranks of the bins of the histogram, see [`shared::histogram::Histogram::ranks`]
+type HistogramTexture = Image!(1D, type=f32, sampled=true);
 
 
 #[spirv(fragment)]
@@ -20,3 +30,68 @@ pub fn fragment(
 {
     *output_color = fractal_texture.sample(*sampler, input_uv);
 }
+
+
+/// Same as [`shared::histogram::Histogram::rank`] with the ranks in a texture
+fn histogram_rank(histogram: &HistogramTexture, key_range: Vec2, v: f32) -> f32
+{
+    let (bin, t) = histogram_bin(key_range, v);
+    let end: Vec4 = histogram.fetch(bin as i32);
+    let start = if bin == 0
+    {
+        0.0
+    }
+    else
+    {
+        let start: Vec4 = histogram.fetch(bin as i32 - 1);
+        start.x
+    };
+    start + (end.x - start) * t
+}
+
+fn texel_color(texture: &EscapeDataTexture, coord: Vec2, coloring: &ColoringUniforms, gradient: &Gradient, histogram: &HistogramTexture) -> Vec3
+{
+    // Clamped as floats, integer comparisons need the Int8 capability
+    let coord = coord.clamp(Vec2::ZERO, coloring.texture_size.as_vec2() - 1.0);
+    let texel: Vec4 = texture.fetch(coord.as_ivec2());
+    let data = EscapeData
+    {
+        value: texel.x,
+        coverage: texel.y,
+        interior_value: texel.z,
+        interior_coverage: texel.w,
+    };
+
+    let rank = match coloring.color.color_mapping
+    {
+        ColorMapping::Histogram => histogram_rank(histogram, coloring.histogram_key_range, data.value),
+        ColorMapping::Logarithmic => 0.0,
+    };
+    data.linear_color(coloring.color, gradient, rank)
+}
+
+/// Colors a cell containing escape data, the colors of the 4 nearest texels are blended like a linear sampler would
+#[spirv(fragment)]
+pub fn fragment_escape_data(
+    // Inputs
+    input_uv: Vec2,
+
+    // Outputs
+    output_color: &mut Vec4,
+
+    // Uniforms
+    #[spirv(uniform, descriptor_set = 0, binding = 2)] coloring: &ColoringUniforms,
+    #[spirv(uniform, descriptor_set = 0, binding = 3)] gradient: &Gradient,
+    #[spirv(descriptor_set = 0, binding = 4)] histogram: &HistogramTexture,
+    #[spirv(descriptor_set = 1, binding = 1)] fractal_texture: &EscapeDataTexture,
+)
+{
+    let texel_pos = input_uv * coloring.texture_size.as_vec2() - 0.5;
+    let corner = texel_pos.floor();
+    let t = texel_pos - corner;
+
+    let first_row = texel_color(fractal_texture, corner, coloring, gradient, histogram).lerp(texel_color(fractal_texture, corner + vec2(1.0, 0.0), coloring, gradient, histogram), t.x);
+    let second_row = texel_color(fractal_texture, corner + vec2(0.0, 1.0), coloring, gradient, histogram).lerp(texel_color(fractal_texture, corner + vec2(1.0, 1.0), coloring, gradient, histogram), t.x);
+
+    *output_color = (first_row.lerp(second_row, t.y), 1.0).into();
+}
@@ -34,5 +34,5 @@ pub fn vertex(
     
     *output_pos = (pos, 0.0, 1.0).into();
 
-    *output_uv = uv;
+    *output_uv = instance.uv_min + uv * instance.uv_size;
 }
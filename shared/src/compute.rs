@@ -1,7 +1,11 @@
-use glam::{DVec2, UVec2, Vec2 as FVec2, Vec3};
+use glam::{uvec2, DVec2, UVec2, Vec2 as FVec2, Vec3};
 use num_traits::AsPrimitive;
 use crate::math::*;
-use crate::fractal::{FractalParams32, FractalParams64, FractalParams};
+use crate::fractal::{ColorMapping, ColorParams, FractalParams32, FractalParams64, FractalParams};
+use crate::fractal::escape_time_method::{EscapeResult, SeriesApproximation};
+use crate::formula::Formula;
+use crate::gradient::Gradient;
+use crate::color::{linear_to_srgb, srgb_to_linear};
 
 #[cfg(feature = "bytemuck")]
 use bytemuck::NoUninit;
@@ -101,23 +105,211 @@ impl From<Params64> for Params<f64>
     }
 }
 
+/// The result of a pixel before it is colored, so that its colors can change without computing it again
+#[repr(C)]
+#[cfg_attr(feature = "bytemuck", derive(NoUninit))]
+#[derive(Copy, Clone, Default)]
+pub struct EscapeData
+{
+    /// Value of [`EscapeResult::Escaped`], averaged over the samples that escaped
+    pub value: f32,
+    /// Fraction of the samples that escaped, the pixel is black when none did
+    pub coverage: f32,
+    /// Value of [`EscapeResult::Interior`], averaged over the samples that stayed inside with a value
+    pub interior_value: f32,
+    /// Fraction of the samples that stayed inside with a value
+    pub interior_coverage: f32,
+}
+
+impl EscapeData
+{
+    /// The color of the pixel in linear space, `histogram_rank` is the rank of the value in the [`crate::histogram::Histogram`]
+    /// of the visible pixels, only used by [`ColorMapping::Histogram`]
+    pub fn linear_color(self, params: ColorParams, gradient: &Gradient, histogram_rank: f32) -> Vec3
+    {
+        let mut res = Vec3::ZERO;
+        if self.coverage > 0.0
+        {
+            let color = match params.color_mapping
+            {
+                ColorMapping::Logarithmic => crate::fractal::escaped_color(self.value, params, gradient),
+                ColorMapping::Histogram => crate::fractal::histogram_color(self.value, params, gradient, histogram_rank),
+            };
+            res += srgb_to_linear(color) * self.coverage;
+        }
+        if self.interior_coverage > 0.0
+        {
+            res += srgb_to_linear(crate::fractal::interior_color(self.interior_value, params, gradient)) * self.interior_coverage;
+        }
+        res
+    }
+}
+
 pub fn color_to_byte(color: f32) -> u32
 {
     (color * 255.5) as u32
 }
 
-pub fn run<S: Scalar>(id: UVec2, size: UVec2, params : Params<S>) -> u32
+fn pixel_uv<S: Scalar>(id: UVec2, size: UVec2) -> Vec2<S>
+where u32: AsPrimitive<S>
+{
+    Vec2::<S>::new(id.x.as_() + 0.5_f32.into(), id.y.as_() + 0.5_f32.into()) / Vec2::<S>::new(size.x.as_(), size.y.as_())
+}
+
+fn pixel_uv_size<S: Scalar>(size: UVec2) -> Vec2<S>
+where u32: AsPrimitive<S>
+{
+    Vec2::<S>::new(S::one() / size.x.as_(), S::one() / size.y.as_())
+}
+
+/// Averages the colors of a stratified grid of `samples_per_axis`² samples over the pixel centered on `uv`, in linear space
+fn supersample<S: Scalar>(uv: Vec2<S>, pixel_uv_size: Vec2<S>, samples_per_axis: u32, sample_color: impl Fn(Vec2<S>) -> Vec3) -> Vec3
+where u32: AsPrimitive<S>
+{
+    if samples_per_axis <= 1
+    {
+        return sample_color(uv);
+    }
+
+    let mut sum = Vec3::ZERO;
+    for y in 0..samples_per_axis
+    {
+        for x in 0..samples_per_axis
+        {
+            sum += srgb_to_linear(sample_color(sample_uv(uv, pixel_uv_size, uvec2(x, y), samples_per_axis)));
+        }
+    }
+
+    linear_to_srgb(sum / (samples_per_axis * samples_per_axis) as f32)
+}
+
+/// Same as [`supersample`] for escape results, the values of the samples that escaped and of the samples inside are averaged separately
+fn supersample_escape_data<S: Scalar>(uv: Vec2<S>, pixel_uv_size: Vec2<S>, samples_per_axis: u32, sample_escape: impl Fn(Vec2<S>) -> EscapeResult) -> EscapeData
+where u32: AsPrimitive<S>
+{
+    let samples_per_axis = if samples_per_axis <= 1 { 1 } else { samples_per_axis };
+
+    let mut sum = 0.0;
+    let mut escaped = 0;
+    let mut interior_sum = 0.0;
+    let mut interior = 0;
+    for y in 0..samples_per_axis
+    {
+        for x in 0..samples_per_axis
+        {
+            match sample_escape(sample_uv(uv, pixel_uv_size, uvec2(x, y), samples_per_axis))
+            {
+                EscapeResult::Escaped(v) =>
+                {
+                    sum += v;
+                    escaped += 1;
+                },
+                EscapeResult::Interior(v) =>
+                {
+                    interior_sum += v;
+                    interior += 1;
+                },
+                EscapeResult::StayedInside => {},
+            }
+        }
+    }
+
+    let sample_count = (samples_per_axis * samples_per_axis) as f32;
+    let average = |sum: f32, count: u32| if count == 0 { 0.0 } else { sum / count as f32 };
+    EscapeData
+    {
+        value: average(sum, escaped),
+        coverage: escaped as f32 / sample_count,
+        interior_value: average(interior_sum, interior),
+        interior_coverage: interior as f32 / sample_count,
+    }
+}
+
+/// The sample `id` of the stratified grid of `samples_per_axis`² samples over the pixel centered on `uv`
+fn sample_uv<S: Scalar>(uv: Vec2<S>, pixel_uv_size: Vec2<S>, id: UVec2, samples_per_axis: u32) -> Vec2<S>
 where u32: AsPrimitive<S>
 {
-    let uv = Vec2::<S>::new(id.x.as_() + 0.5_f32.into(), id.y.as_() + 0.5_f32.into()) / Vec2::<S>::new(size.x.as_(), size.y.as_());
-    let color = run_uv(uv, params);
+    let half: S = 0.5_f32.into();
+    let count: S = samples_per_axis.as_();
+    let offset = Vec2::<S>::new((id.x.as_() + half) / count - half, (id.y.as_() + half) / count - half);
+    uv + offset * pixel_uv_size
+}
+
+fn color_to_pixel(color: Vec3) -> u32
+{
     (color_to_byte(color.x) << 16) | (color_to_byte(color.y) << 8) | color_to_byte(color.z) | 0xff000000
 }
 
-pub fn run_uv<S: Scalar>(uv: Vec2::<S>, params : Params<S>) -> Vec3
+pub fn run<S: Scalar>(id: UVec2, size: UVec2, params : Params<S>, formula: &impl Formula<S>, gradient: &Gradient) -> u32
+where u32: AsPrimitive<S>
+{
+    color_to_pixel(run_uv(pixel_uv(id, size), pixel_uv_size(size), params, formula, gradient))
+}
+
+/// Same as [`run`], but the positions of `params` are relative to the reference point whose orbit is given
+pub fn run_perturbation<S: Scalar>(id: UVec2, size: UVec2, params : Params<S>, reference_orbit: &[Complex<S>], series: &SeriesApproximation<S>, gradient: &Gradient) -> u32
+where u32: AsPrimitive<S>
+{
+    color_to_pixel(run_perturbation_uv(pixel_uv(id, size), pixel_uv_size(size), params, reference_orbit, series, gradient))
+}
+
+/// The color of the pixel centered on `uv`, supersampled as set by [`FractalParams::samples_per_axis`]
+pub fn run_uv<S: Scalar>(uv: Vec2::<S>, pixel_uv_size: Vec2::<S>, params : Params<S>, formula: &impl Formula<S>, gradient: &Gradient) -> Vec3
+where u32: AsPrimitive<S>
+{
+    supersample(uv, pixel_uv_size, params.fractal.samples_per_axis, move |uv|
+    {
+        let pos = params.min_pos + uv * (params.max_pos - params.min_pos);
+
+        crate::fractal::compute_fractal_color(ComplexNumber::from_vector(pos), params.fractal, formula, gradient)
+    })
+}
+
+/// Same as [`run_uv`], but the positions of `params` are relative to the reference point whose orbit is given
+pub fn run_perturbation_uv<S: Scalar>(uv: Vec2::<S>, pixel_uv_size: Vec2::<S>, params : Params<S>, reference_orbit: &[Complex<S>], series: &SeriesApproximation<S>, gradient: &Gradient) -> Vec3
 where u32: AsPrimitive<S>
 {
-    let pos = params.min_pos + uv * (params.max_pos - params.min_pos);
+    supersample(uv, pixel_uv_size, params.fractal.samples_per_axis, move |uv|
+    {
+        let delta = params.min_pos + uv * (params.max_pos - params.min_pos);
+
+        crate::fractal::compute_perturbation_color(ComplexNumber::from_vector(delta), params.fractal, reference_orbit, series, gradient)
+    })
+}
+
+/// Same as [`run`], but the pixel is left to be colored later, see [`crate::fractal::FractalKind::is_escape_time`] for the supported fractals
+pub fn run_escape_data<S: Scalar>(id: UVec2, size: UVec2, params : Params<S>, formula: &impl Formula<S>) -> EscapeData
+where u32: AsPrimitive<S>
+{
+    run_escape_data_uv(pixel_uv(id, size), pixel_uv_size(size), params, formula)
+}
+
+/// Same as [`run_perturbation`], but the pixel is left to be colored later
+pub fn run_perturbation_escape_data<S: Scalar>(id: UVec2, size: UVec2, params : Params<S>, reference_orbit: &[Complex<S>], series: &SeriesApproximation<S>) -> EscapeData
+where u32: AsPrimitive<S>
+{
+    run_perturbation_escape_data_uv(pixel_uv(id, size), pixel_uv_size(size), params, reference_orbit, series)
+}
+
+pub fn run_escape_data_uv<S: Scalar>(uv: Vec2::<S>, pixel_uv_size: Vec2::<S>, params : Params<S>, formula: &impl Formula<S>) -> EscapeData
+where u32: AsPrimitive<S>
+{
+    supersample_escape_data(uv, pixel_uv_size, params.fractal.samples_per_axis, move |uv|
+    {
+        let pos = params.min_pos + uv * (params.max_pos - params.min_pos);
+
+        // The lit techniques are colored when computed, the escape data has no room for their normals
+        crate::fractal::compute_escape_result(ComplexNumber::from_vector(pos), params.fractal, formula).result
+    })
+}
+
+pub fn run_perturbation_escape_data_uv<S: Scalar>(uv: Vec2::<S>, pixel_uv_size: Vec2::<S>, params : Params<S>, reference_orbit: &[Complex<S>], series: &SeriesApproximation<S>) -> EscapeData
+where u32: AsPrimitive<S>
+{
+    supersample_escape_data(uv, pixel_uv_size, params.fractal.samples_per_axis, move |uv|
+    {
+        let delta = params.min_pos + uv * (params.max_pos - params.min_pos);
 
-    crate::fractal::compute_fractal_color(ComplexNumber::from_vector(pos), params.fractal)
+        crate::fractal::perturbation::compute_perturbation(ComplexNumber::from_vector(delta), params.fractal, reference_orbit, series)
+    })
 }
@@ -0,0 +1,503 @@
+use std::boxed::Box;
+
+use crate::math::*;
+use glam::DVec2;
+use super::{instruction, op, Formula64, MAX_OPERAND, FORMULA_CODE_LENGTH, FORMULA_CONSTANT_COUNT, FORMULA_STACK_SIZE};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum UnaryFunction
+{
+    Sqrt,
+    Exp,
+    Ln,
+    Sin,
+    Cos,
+}
+
+/// Expression tree of a formula, the constructors fold constants and remove neutral elements
+/// so that the derivatives stay small
+#[derive(Clone, PartialEq)]
+pub(super) enum Expression
+{
+    Z,
+    DZ,
+    C,
+    DC,
+    Constant(Complex64),
+    Add(Box<Expression>, Box<Expression>),
+    Sub(Box<Expression>, Box<Expression>),
+    Mul(Box<Expression>, Box<Expression>),
+    Div(Box<Expression>, Box<Expression>),
+    Neg(Box<Expression>),
+    PowInt(Box<Expression>, u32),
+    Pow(Box<Expression>, Box<Expression>),
+    Function(UnaryFunction, Box<Expression>),
+}
+
+/// Errors when compiling an expression that doesn't fit in a [`Formula64`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum CompileError
+{
+    CodeTooLong,
+    TooManyConstants,
+    StackTooDeep,
+    /// The exponent of an integer power overflows or doesn't fit in the operand of an instruction
+    ExponentTooLarge,
+}
+
+use Expression::*;
+
+impl Expression
+{
+    pub fn constant(value: Complex64) -> Self
+    {
+        Constant(value)
+    }
+
+    fn as_constant(&self) -> Option<Complex64>
+    {
+        match self
+        {
+            Constant(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    fn is(&self, value: f64) -> bool
+    {
+        self.as_constant() == Some(Complex64::new(value, 0.0))
+    }
+
+    pub fn add(a: Self, b: Self) -> Self
+    {
+        match (a.as_constant(), b.as_constant())
+        {
+            (Some(x), Some(y)) => Constant(x + y),
+            _ if a.is(0.0) => b,
+            _ if b.is(0.0) => a,
+            _ => Add(Box::new(a), Box::new(b)),
+        }
+    }
+
+    pub fn sub(a: Self, b: Self) -> Self
+    {
+        match (a.as_constant(), b.as_constant())
+        {
+            (Some(x), Some(y)) => Constant(x - y),
+            _ if b.is(0.0) => a,
+            _ if a.is(0.0) => Self::neg(b),
+            _ => Sub(Box::new(a), Box::new(b)),
+        }
+    }
+
+    pub fn mul(a: Self, b: Self) -> Self
+    {
+        match (a.as_constant(), b.as_constant())
+        {
+            (Some(x), Some(y)) => Constant(x * y),
+            _ if a.is(0.0) || b.is(0.0) => Constant(Complex64::ZERO),
+            _ if a.is(1.0) => b,
+            _ if b.is(1.0) => a,
+            _ if a.is(-1.0) => Self::neg(b),
+            _ if b.is(-1.0) => Self::neg(a),
+            _ => Mul(Box::new(a), Box::new(b)),
+        }
+    }
+
+    pub fn div(a: Self, b: Self) -> Self
+    {
+        match (a.as_constant(), b.as_constant())
+        {
+            (Some(x), Some(y)) => Constant(x / y),
+            _ if a.is(0.0) => Constant(Complex64::ZERO),
+            _ if b.is(1.0) => a,
+            _ => Div(Box::new(a), Box::new(b)),
+        }
+    }
+
+    pub fn neg(a: Self) -> Self
+    {
+        match a
+        {
+            Constant(x) => Constant(-x),
+            Neg(a) => *a,
+            _ => Neg(Box::new(a)),
+        }
+    }
+
+    pub fn pow(a: Self, b: Self) -> Result<Self, CompileError>
+    {
+        Ok(match b.as_constant()
+        {
+            // Small integer powers are computed with multiplications, which is faster and doesn't have a branch cut
+            Some(exponent) if exponent.im() == 0.0 && exponent.re().abs() <= 64.0 && exponent.re().fract() == 0.0 =>
+            {
+                let power = Self::pow_int(a, exponent.re().abs() as u32)?;
+                if exponent.re() < 0.0 { Self::div(Constant(Complex64::ONE), power) } else { power }
+            },
+            _ => match (a.as_constant(), b.as_constant())
+            {
+                (Some(x), Some(y)) => Constant(x.pow(y)),
+                _ => Pow(Box::new(a), Box::new(b)),
+            },
+        })
+    }
+
+    /// Fails when the exponent, multiplied by the one of a nested integer power, doesn't fit in an instruction
+    pub fn pow_int(a: Self, exponent: u32) -> Result<Self, CompileError>
+    {
+        Ok(match (a, exponent)
+        {
+            (_, 0) => Constant(Complex64::ONE),
+            (a, 1) => a,
+            (Constant(x), n) => Constant((0..n).fold(Complex64::ONE, |res, _| res * x)),
+            (PowInt(a, m), n) => Self::pow_int(*a, m.checked_mul(n).ok_or(CompileError::ExponentTooLarge)?)?,
+            (_, n) if n > MAX_OPERAND => return Err(CompileError::ExponentTooLarge),
+            (a, n) => PowInt(Box::new(a), n),
+        })
+    }
+
+    pub fn function(function: UnaryFunction, a: Self) -> Self
+    {
+        match a.as_constant()
+        {
+            Some(x) => Constant(match function
+            {
+                UnaryFunction::Sqrt => x.sqrt(),
+                UnaryFunction::Exp => x.exp(),
+                UnaryFunction::Ln => x.ln(),
+                UnaryFunction::Sin => x.sin(),
+                UnaryFunction::Cos => x.cos(),
+            }),
+            None => Function(function, Box::new(a)),
+        }
+    }
+
+    /// Derivative with respect to the pixel position, using the chain rule with `DZ` and `DC` as the derivatives of `Z` and `C`
+    pub fn derivative(&self) -> Result<Self, CompileError>
+    {
+        Ok(match self
+        {
+            Z => DZ,
+            C => DC,
+            // The derivatives are not differentiated again
+            DZ | DC | Constant(_) => Constant(Complex64::ZERO),
+            Add(a, b) => Self::add(a.derivative()?, b.derivative()?),
+            Sub(a, b) => Self::sub(a.derivative()?, b.derivative()?),
+            Mul(a, b) => Self::add(Self::mul(a.derivative()?, (**b).clone()), Self::mul((**a).clone(), b.derivative()?)),
+            // (a' b - a b') / b^2
+            Div(a, b) => Self::div(
+                Self::sub(Self::mul(a.derivative()?, (**b).clone()), Self::mul((**a).clone(), b.derivative()?)),
+                Self::pow_int((**b).clone(), 2)?),
+            Neg(a) => Self::neg(a.derivative()?),
+            // n a^(n-1) a'
+            PowInt(a, n) => Self::mul(
+                Self::mul(Constant(Complex64::new(*n as f64, 0.0)), Self::pow_int((**a).clone(), n - 1)?),
+                a.derivative()?),
+            // a^b (b' ln(a) + b a' / a)
+            Pow(a, b) => Self::mul(
+                self.clone(),
+                Self::add(
+                    Self::mul(b.derivative()?, Self::function(UnaryFunction::Ln, (**a).clone())),
+                    Self::div(Self::mul((**b).clone(), a.derivative()?), (**a).clone()))),
+            Function(function, a) =>
+            {
+                let outer = match function
+                {
+                    UnaryFunction::Sqrt => Self::div(Constant(Complex64::new(0.5, 0.0)), self.clone()),
+                    UnaryFunction::Exp => self.clone(),
+                    UnaryFunction::Ln => Self::div(Constant(Complex64::ONE), (**a).clone()),
+                    UnaryFunction::Sin => Self::function(UnaryFunction::Cos, (**a).clone()),
+                    UnaryFunction::Cos => Self::neg(Self::function(UnaryFunction::Sin, (**a).clone())),
+                };
+                Self::mul(outer, a.derivative()?)
+            },
+        })
+    }
+
+    /// The degree in `z` when the expression is a polynomial in `z`
+    pub fn degree(&self) -> Option<u32>
+    {
+        let constant_or_none = |degree: Option<u32>| (degree == Some(0)).then_some(0);
+
+        match self
+        {
+            Z => Some(1),
+            C | DZ | DC | Constant(_) => Some(0),
+            Add(a, b) | Sub(a, b) => Some(a.degree()?.max(b.degree()?)),
+            Mul(a, b) => a.degree()?.checked_add(b.degree()?),
+            Div(a, b) => if b.degree()? == 0 { a.degree() } else { None },
+            Neg(a) => a.degree(),
+            PowInt(a, n) => a.degree()?.checked_mul(*n),
+            Pow(a, b) => constant_or_none(Some(a.degree()?.max(b.degree()?))),
+            Function(_, a) => constant_or_none(a.degree()),
+        }
+    }
+
+    pub fn compile(&self, constants: &mut ConstantTable) -> Result<[u32; FORMULA_CODE_LENGTH], CompileError>
+    {
+        let mut code = [instruction(op::END, 0); FORMULA_CODE_LENGTH];
+        let mut length = 0;
+        let depth = self.emit(&mut code, &mut length, constants)?;
+
+        if depth > FORMULA_STACK_SIZE
+        {
+            return Err(CompileError::StackTooDeep);
+        }
+
+        Ok(code)
+    }
+
+    // Appends the postfix code of the expression, returns the stack size it needs
+    fn emit(&self, code: &mut [u32; FORMULA_CODE_LENGTH], length: &mut usize, constants: &mut ConstantTable) -> Result<usize, CompileError>
+    {
+        let (instruction, depth) = match self
+        {
+            Z => (instruction(op::Z, 0), 1),
+            DZ => (instruction(op::DZ, 0), 1),
+            C => (instruction(op::C, 0), 1),
+            DC => (instruction(op::DC, 0), 1),
+            Constant(value) => (instruction(op::CONSTANT, constants.index_of(*value)?), 1),
+            Add(a, b) | Sub(a, b) | Mul(a, b) | Div(a, b) | Pow(a, b) =>
+            {
+                let opcode = match self
+                {
+                    Add(..) => op::ADD,
+                    Sub(..) => op::SUB,
+                    Mul(..) => op::MUL,
+                    Div(..) => op::DIV,
+                    _ => op::POW,
+                };
+                let a_depth = a.emit(code, length, constants)?;
+                let b_depth = b.emit(code, length, constants)?;
+                (instruction(opcode, 0), a_depth.max(b_depth + 1))
+            },
+            Neg(a) => (instruction(op::NEG, 0), a.emit(code, length, constants)?),
+            PowInt(a, n) => (instruction(op::POW_INT, *n), a.emit(code, length, constants)?),
+            Function(function, a) =>
+            {
+                let opcode = match function
+                {
+                    UnaryFunction::Sqrt => op::SQRT,
+                    UnaryFunction::Exp => op::EXP,
+                    UnaryFunction::Ln => op::LN,
+                    UnaryFunction::Sin => op::SIN,
+                    UnaryFunction::Cos => op::COS,
+                };
+                (instruction(opcode, 0), a.emit(code, length, constants)?)
+            },
+        };
+
+        // The last instruction is kept as the end marker
+        if *length + 1 >= FORMULA_CODE_LENGTH
+        {
+            return Err(CompileError::CodeTooLong);
+        }
+        code[*length] = instruction;
+        *length += 1;
+
+        Ok(depth)
+    }
+}
+
+/// Constants shared by the code of a formula and of its derivative
+#[derive(Default)]
+pub(super) struct ConstantTable
+{
+    constants: [DVec2; FORMULA_CONSTANT_COUNT],
+    length: usize,
+}
+
+impl ConstantTable
+{
+    fn index_of(&mut self, value: Complex64) -> Result<u32, CompileError>
+    {
+        let value = value.to_vector();
+        if let Some(index) = self.constants[..self.length].iter().position(|&constant| constant == value)
+        {
+            return Ok(index as u32);
+        }
+
+        if self.length == FORMULA_CONSTANT_COUNT
+        {
+            return Err(CompileError::TooManyConstants);
+        }
+        self.constants[self.length] = value;
+        self.length += 1;
+        Ok(self.length as u32 - 1)
+    }
+}
+
+impl Formula64
+{
+    pub(super) fn compile(expression: &Expression) -> Result<Self, CompileError>
+    {
+        let mut constants = ConstantTable::default();
+        let code = expression.compile(&mut constants)?;
+        let derivative_code = expression.derivative()?.compile(&mut constants)?;
+
+        let potential_power = match expression.degree()
+        {
+            Some(degree) if degree >= 2 => degree as f32,
+            _ => 0.0,
+        };
+
+        Ok(Self::new(code, derivative_code, constants.constants, potential_power))
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use std::format;
+    use std::string::String;
+    use crate::formula::{Formula, FormulaInputs};
+    use crate::formula::ParseFormulaError;
+
+    fn constant(value: f64) -> Expression
+    {
+        Constant(Complex64::new(value, 0.0))
+    }
+
+    #[test]
+    fn constants_are_folded()
+    {
+        assert!(Expression::add(constant(1.0), constant(2.0)) == constant(3.0));
+        assert!(Expression::sub(constant(1.0), constant(2.0)) == constant(-1.0));
+        assert!(Expression::mul(constant(3.0), constant(2.0)) == constant(6.0));
+        assert!(Expression::div(constant(3.0), constant(2.0)) == constant(1.5));
+        assert!(Expression::neg(constant(3.0)) == constant(-3.0));
+        assert!(Expression::pow(constant(2.0), constant(10.0)).unwrap() == constant(1024.0));
+        assert!(Expression::pow(constant(4.0), constant(0.5)).unwrap() == constant(2.0));
+        assert!(Expression::function(UnaryFunction::Exp, constant(0.0)) == constant(1.0));
+        assert!(Expression::function(UnaryFunction::Sqrt, constant(-4.0)) == Constant(Complex64::new(0.0, 2.0)));
+    }
+
+    #[test]
+    fn neutral_elements_are_removed()
+    {
+        assert!(Expression::add(Z, constant(0.0)) == Z);
+        assert!(Expression::add(constant(0.0), C) == C);
+        assert!(Expression::sub(Z, constant(0.0)) == Z);
+        assert!(Expression::sub(constant(0.0), Z) == Neg(Box::new(Z)));
+        assert!(Expression::mul(Z, constant(1.0)) == Z);
+        assert!(Expression::mul(constant(0.0), Z) == constant(0.0));
+        assert!(Expression::mul(constant(-1.0), Z) == Neg(Box::new(Z)));
+        assert!(Expression::div(Z, constant(1.0)) == Z);
+        assert!(Expression::div(constant(0.0), Z) == constant(0.0));
+        assert!(Expression::neg(Expression::neg(Z)) == Z);
+    }
+
+    #[test]
+    fn integer_powers()
+    {
+        assert!(Expression::pow(Z, constant(3.0)).unwrap() == PowInt(Box::new(Z), 3));
+        assert!(Expression::pow(Z, constant(0.0)).unwrap() == constant(1.0));
+        assert!(Expression::pow(Z, constant(1.0)).unwrap() == Z);
+        assert!(Expression::pow(Z, constant(-1.0)).unwrap() == Div(Box::new(constant(1.0)), Box::new(Z)));
+        assert!(Expression::pow(Z, constant(2.5)).unwrap() == Pow(Box::new(Z), Box::new(constant(2.5))));
+        // Nested integer powers are merged
+        assert!(Expression::pow_int(PowInt(Box::new(Z), 3), 4).unwrap() == PowInt(Box::new(Z), 12));
+
+        assert_eq!(Expression::pow_int(Z, MAX_OPERAND).map(|_| ()), Ok(()));
+        assert_eq!(Expression::pow_int(Z, MAX_OPERAND + 1).map(|_| ()), Err(CompileError::ExponentTooLarge));
+        assert_eq!(Expression::pow_int(PowInt(Box::new(Z), 1 << 16), 1 << 16).map(|_| ()), Err(CompileError::ExponentTooLarge));
+    }
+
+    #[test]
+    fn symbolic_derivatives()
+    {
+        assert!(Z.derivative().unwrap() == DZ);
+        assert!(C.derivative().unwrap() == DC);
+        assert!(constant(2.0).derivative().unwrap() == constant(0.0));
+        // 3 z^2 dz
+        let cube = PowInt(Box::new(Z), 3);
+        let expected = Expression::mul(Expression::mul(constant(3.0), PowInt(Box::new(Z), 2)), DZ);
+        assert!(cube.derivative().unwrap() == expected);
+    }
+
+    #[test]
+    fn derivatives_match_finite_differences()
+    {
+        let formulas = [
+            "z^2 + c",
+            "z^3 - 2z + c",
+            "c z (1 - z)",
+            "z^-2 + c / z",
+            "z^2.5 + c",
+            "z^c",
+            "sqrt(z) + ln(c)",
+            "exp(z) + sin(c z) - cos(z / c)",
+            "tan(z) + sinh(c)",
+            "c cosh(z)",
+            "tanh(z c)",
+        ];
+
+        let (z, c) = (Complex64::new(0.3, 0.7), Complex64::new(-0.6, 0.4));
+        let (dz, dc) = (Complex64::new(1.2, -0.5), Complex64::new(0.8, 0.3));
+        let h = 1e-6;
+
+        for text in formulas
+        {
+            let formula: Formula64 = text.parse().unwrap();
+            let at = |t: f64| Formula::<f64>::evaluate(&formula, FormulaInputs { z: z + dz * t, dz, c: c + dc * t, dc });
+            let expected = (at(h) - at(-h)) / (2.0 * h);
+            let derivative = Formula::<f64>::evaluate_derivative(&formula, FormulaInputs { z, dz, c, dc });
+
+            let error = (derivative - expected).modulus() / expected.modulus().max(1.0);
+            assert!(error < 1e-7, "the derivative of {text} is off by {error}");
+        }
+    }
+
+    #[test]
+    fn code_too_long()
+    {
+        let text = String::from("z") + &" + z".repeat(FORMULA_CODE_LENGTH / 2);
+        assert_eq!(text.parse::<Formula64>().err(), Some(ParseFormulaError::TooLong));
+        assert_eq!(Formula64::compile(&sum(FORMULA_CODE_LENGTH / 2)).err(), Some(CompileError::CodeTooLong));
+
+        let text = String::from("z") + &" + z".repeat(FORMULA_CODE_LENGTH / 2 - 2);
+        assert!(text.parse::<Formula64>().is_ok());
+    }
+
+    // z + z + ... with `count` additions
+    fn sum(count: usize) -> Expression
+    {
+        (0..count).fold(Z, |sum, _| Expression::add(sum, Z))
+    }
+
+    #[test]
+    fn stack_too_deep()
+    {
+        // z + (z + (z + ...)) needs one more value on the stack for each parenthesis
+        let nested = |depth: usize| format!("{}z{}", "(z + ".repeat(depth), ")".repeat(depth));
+        assert_eq!(nested(FORMULA_STACK_SIZE).parse::<Formula64>().err(), Some(ParseFormulaError::TooComplex));
+        assert!(nested(FORMULA_STACK_SIZE - 1).parse::<Formula64>().is_ok());
+
+        let expression = (0..FORMULA_STACK_SIZE).fold(Z, |nested, _| Expression::add(C, nested));
+        assert_eq!(Formula64::compile(&expression).err(), Some(CompileError::StackTooDeep));
+        // The same additions without nesting need a stack of 2
+        assert!(Formula64::compile(&sum(FORMULA_STACK_SIZE)).is_ok());
+    }
+
+    #[test]
+    fn too_many_constants()
+    {
+        let terms = |count: usize| (2..count + 2).map(|n| format!("{n}z")).collect::<std::vec::Vec<_>>().join(" + ");
+        assert_eq!(terms(FORMULA_CONSTANT_COUNT + 1).parse::<Formula64>().err(), Some(ParseFormulaError::TooManyConstants));
+        assert!(terms(FORMULA_CONSTANT_COUNT).parse::<Formula64>().is_ok());
+
+        // Equal constants are shared, also with the derivative
+        assert!("2z + 2c + 2z^2".parse::<Formula64>().is_ok());
+    }
+
+    #[test]
+    fn exponent_too_large()
+    {
+        assert!("(z^64)^64 + c".parse::<Formula64>().is_ok());
+        assert_eq!("(((z^64)^64)^64)^64 + c".parse::<Formula64>().err(), Some(ParseFormulaError::ExponentTooLarge));
+        // The product of the exponents overflows
+        assert_eq!("(((((z^64)^64)^64)^64)^64)^64".parse::<Formula64>().err(), Some(ParseFormulaError::ExponentTooLarge));
+    }
+}
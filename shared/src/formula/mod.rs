@@ -0,0 +1,361 @@
+//! Iteration formulas entered at runtime, such as `z^3 - z + c`.
+//!
+//! The text is parsed and differentiated on the CPU, then compiled to a small stack based bytecode
+//! that is interpreted both on the CPU and in the shaders.
+
+#[cfg(feature = "std")]
+mod expression;
+#[cfg(feature = "std")]
+mod parser;
+
+#[cfg(feature = "std")]
+pub use parser::ParseFormulaError;
+
+use crate::math::*;
+use glam::{DVec2, UVec4, Vec4, Vec4Swizzles};
+#[cfg(feature = "bytemuck")]
+use bytemuck::NoUninit;
+
+/// Maximum number of instructions of the formula and of its derivative
+pub const FORMULA_CODE_LENGTH: usize = 128;
+/// The instructions are packed by 4, arrays of scalars are not aligned enough for uniform buffers
+pub const FORMULA_PACKED_CODE_LENGTH: usize = FORMULA_CODE_LENGTH / 4;
+/// Maximum number of distinct constants in a formula
+pub const FORMULA_CONSTANT_COUNT: usize = 16;
+/// Maximum number of values on the stack while evaluating a formula
+pub const FORMULA_STACK_SIZE: usize = 16;
+
+/// Opcodes of the bytecode, stored in the low byte of each instruction
+pub mod op
+{
+    pub const END: u32 = 0;
+    pub const Z: u32 = 1;
+    pub const DZ: u32 = 2;
+    pub const C: u32 = 3;
+    pub const DC: u32 = 4;
+    /// Pushes the constant whose index is the operand
+    pub const CONSTANT: u32 = 5;
+    pub const ADD: u32 = 6;
+    pub const SUB: u32 = 7;
+    pub const MUL: u32 = 8;
+    pub const DIV: u32 = 9;
+    pub const NEG: u32 = 10;
+    /// Raises to the power given by the operand
+    pub const POW_INT: u32 = 11;
+    pub const POW: u32 = 12;
+    pub const SQRT: u32 = 13;
+    pub const EXP: u32 = 14;
+    pub const LN: u32 = 15;
+    pub const SIN: u32 = 16;
+    pub const COS: u32 = 17;
+}
+
+const OPERAND_SHIFT: u32 = 8;
+/// Largest operand of an instruction, the bits above the opcode
+#[cfg(feature = "std")]
+const MAX_OPERAND: u32 = u32::MAX >> OPERAND_SHIFT;
+
+pub const fn instruction(opcode: u32, operand: u32) -> u32
+{
+    opcode | (operand << OPERAND_SHIFT)
+}
+
+fn pack_code(code: [u32; FORMULA_CODE_LENGTH]) -> [UVec4; FORMULA_PACKED_CODE_LENGTH]
+{
+    let mut res = [UVec4::ZERO; FORMULA_PACKED_CODE_LENGTH];
+    for i in 0..FORMULA_PACKED_CODE_LENGTH
+    {
+        res[i] = UVec4::new(code[4 * i], code[4 * i + 1], code[4 * i + 2], code[4 * i + 3]);
+    }
+    res
+}
+
+/// Values of the variables a formula can use
+#[derive(Clone, Copy)]
+pub struct FormulaInputs<S: Scalar>
+{
+    pub z: Complex<S>,
+    /// Derivative of `z` with respect to the pixel position
+    pub dz: Complex<S>,
+    pub c: Complex<S>,
+    /// Derivative of `c` with respect to the pixel position
+    pub dc: Complex<S>,
+}
+
+/// Compiled iteration formula, uploaded to the 32 bit shaders in a uniform buffer
+#[repr(C, align(16))]
+#[cfg_attr(feature = "bytemuck", derive(NoUninit))]
+#[derive(Copy, Clone)]
+pub struct Formula32
+{
+    pub code: [UVec4; FORMULA_PACKED_CODE_LENGTH],
+    pub derivative_code: [UVec4; FORMULA_PACKED_CODE_LENGTH],
+    // xy is the constant 2i and zw the constant 2i + 1, arrays of 2D vectors are not aligned enough for uniform buffers
+    pub constants: [Vec4; FORMULA_CONSTANT_COUNT / 2],
+    pub potential_power: f32,
+    padding0: u32,
+    padding1: u32,
+    padding2: u32,
+}
+
+/// Compiled iteration formula, used on the CPU and uploaded to the 64 bit shaders in a uniform buffer
+#[repr(C, align(16))]
+#[cfg_attr(feature = "bytemuck", derive(NoUninit))]
+#[derive(Copy, Clone)]
+pub struct Formula64
+{
+    pub code: [UVec4; FORMULA_PACKED_CODE_LENGTH],
+    /// Derivative of the formula with respect to the pixel position, using the derivatives of `z` and `c`
+    pub derivative_code: [UVec4; FORMULA_PACKED_CODE_LENGTH],
+    pub constants: [DVec2; FORMULA_CONSTANT_COUNT],
+    /// Degree of the formula in `z` when it is a polynomial, used to smooth the iteration count, 0 otherwise
+    pub potential_power: f32,
+    padding0: u32,
+    padding1: u32,
+    padding2: u32,
+}
+
+impl Default for Formula64
+{
+    /// The mandelbrot set, `z^2 + c`
+    fn default() -> Self
+    {
+        let mut code = [instruction(op::END, 0); FORMULA_CODE_LENGTH];
+        code[0] = instruction(op::Z, 0);
+        code[1] = instruction(op::POW_INT, 2);
+        code[2] = instruction(op::C, 0);
+        code[3] = instruction(op::ADD, 0);
+
+        // 2 * z * dz + dc
+        let mut derivative_code = [instruction(op::END, 0); FORMULA_CODE_LENGTH];
+        derivative_code[0] = instruction(op::CONSTANT, 0);
+        derivative_code[1] = instruction(op::Z, 0);
+        derivative_code[2] = instruction(op::MUL, 0);
+        derivative_code[3] = instruction(op::DZ, 0);
+        derivative_code[4] = instruction(op::MUL, 0);
+        derivative_code[5] = instruction(op::DC, 0);
+        derivative_code[6] = instruction(op::ADD, 0);
+
+        let mut constants = [DVec2::ZERO; FORMULA_CONSTANT_COUNT];
+        constants[0] = DVec2::new(2.0, 0.0);
+
+        Self::new(code, derivative_code, constants, 2.0)
+    }
+}
+
+impl Formula64
+{
+    pub fn new(code: [u32; FORMULA_CODE_LENGTH], derivative_code: [u32; FORMULA_CODE_LENGTH], constants: [DVec2; FORMULA_CONSTANT_COUNT], potential_power: f32) -> Self
+    {
+        Self
+        {
+            code: pack_code(code),
+            derivative_code: pack_code(derivative_code),
+            constants,
+            potential_power,
+            padding0: 0,
+            padding1: 0,
+            padding2: 0,
+        }
+    }
+}
+
+impl Default for Formula32
+{
+    fn default() -> Self
+    {
+        Formula64::default().into()
+    }
+}
+
+impl From<Formula64> for Formula32
+{
+    fn from(value: Formula64) -> Self
+    {
+        let mut constants = [Vec4::ZERO; FORMULA_CONSTANT_COUNT / 2];
+        for (i, pair) in constants.iter_mut().enumerate()
+        {
+            *pair = value.constants[2 * i].extend(value.constants[2 * i + 1].x).extend(value.constants[2 * i + 1].y).as_vec4();
+        }
+
+        Self
+        {
+            code: value.code,
+            derivative_code: value.derivative_code,
+            constants,
+            potential_power: value.potential_power,
+            padding0: 0,
+            padding1: 0,
+            padding2: 0,
+        }
+    }
+}
+
+/// A compiled formula evaluated with the scalar `S`, which is implemented by [`Formula32`] and [`Formula64`]
+/// so that the constants are only converted to single precision for the 32 bit shaders
+pub trait Formula<S: Scalar>: Copy
+{
+    fn code(&self) -> &[UVec4; FORMULA_PACKED_CODE_LENGTH];
+
+    fn derivative_code(&self) -> &[UVec4; FORMULA_PACKED_CODE_LENGTH];
+
+    fn constant(&self, index: usize) -> Complex<S>;
+
+    fn potential_power(&self) -> f32;
+
+    fn evaluate(&self, inputs: FormulaInputs<S>) -> Complex<S>
+    {
+        run(self, self.code(), inputs)
+    }
+
+    fn evaluate_derivative(&self, inputs: FormulaInputs<S>) -> Complex<S>
+    {
+        run(self, self.derivative_code(), inputs)
+    }
+}
+
+impl Formula<f32> for Formula32
+{
+    fn code(&self) -> &[UVec4; FORMULA_PACKED_CODE_LENGTH]
+    {
+        &self.code
+    }
+
+    fn derivative_code(&self) -> &[UVec4; FORMULA_PACKED_CODE_LENGTH]
+    {
+        &self.derivative_code
+    }
+
+    fn constant(&self, index: usize) -> Complex32
+    {
+        let pair = self.constants[index / 2];
+        Complex32::from_vector(if index % 2 == 0 { pair.xy() } else { pair.zw() })
+    }
+
+    fn potential_power(&self) -> f32
+    {
+        self.potential_power
+    }
+}
+
+impl Formula<f64> for Formula64
+{
+    fn code(&self) -> &[UVec4; FORMULA_PACKED_CODE_LENGTH]
+    {
+        &self.code
+    }
+
+    fn derivative_code(&self) -> &[UVec4; FORMULA_PACKED_CODE_LENGTH]
+    {
+        &self.derivative_code
+    }
+
+    fn constant(&self, index: usize) -> Complex64
+    {
+        Complex64::from_vector(self.constants[index])
+    }
+
+    fn potential_power(&self) -> f32
+    {
+        self.potential_power
+    }
+}
+
+impl Formula<DoubleDouble> for Formula64
+{
+    fn code(&self) -> &[UVec4; FORMULA_PACKED_CODE_LENGTH]
+    {
+        &self.code
+    }
+
+    fn derivative_code(&self) -> &[UVec4; FORMULA_PACKED_CODE_LENGTH]
+    {
+        &self.derivative_code
+    }
+
+    fn constant(&self, index: usize) -> ComplexDD
+    {
+        Complex64::from_vector(self.constants[index]).into()
+    }
+
+    fn potential_power(&self) -> f32
+    {
+        self.potential_power
+    }
+}
+
+fn run<S: Scalar, F: Formula<S>>(formula: &F, code: &[UVec4; FORMULA_PACKED_CODE_LENGTH], inputs: FormulaInputs<S>) -> Complex<S>
+{
+    let mut stack = [Complex::<S>::ZERO; FORMULA_STACK_SIZE];
+    let mut top = 0;
+
+    let mut i = 0;
+    while i < FORMULA_CODE_LENGTH
+    {
+        let instruction = code[i / 4].to_array()[i % 4];
+        let opcode = instruction & ((1 << OPERAND_SHIFT) - 1);
+        let operand = instruction >> OPERAND_SHIFT;
+        i += 1;
+
+        // Values pushed on the stack
+        let value = match opcode
+        {
+            op::Z => inputs.z,
+            op::DZ => inputs.dz,
+            op::C => inputs.c,
+            op::DC => inputs.dc,
+            op::CONSTANT => formula.constant(operand as usize),
+            op::ADD | op::SUB | op::MUL | op::DIV | op::POW =>
+            {
+                top -= 1;
+                let (a, b) = (stack[top - 1], stack[top]);
+                stack[top - 1] = match opcode
+                {
+                    op::ADD => a + b,
+                    op::SUB => a - b,
+                    op::MUL => a * b,
+                    op::DIV => a / b,
+                    _ => a.pow(b),
+                };
+                continue;
+            },
+            op::NEG | op::POW_INT | op::SQRT | op::EXP | op::LN | op::SIN | op::COS =>
+            {
+                let a = stack[top - 1];
+                stack[top - 1] = match opcode
+                {
+                    op::NEG => -a,
+                    op::POW_INT => pow_int::<S>(a, operand),
+                    op::SQRT => a.sqrt(),
+                    op::EXP => a.exp(),
+                    op::LN => a.ln(),
+                    op::SIN => a.sin(),
+                    _ => a.cos(),
+                };
+                continue;
+            },
+            _ => break,
+        };
+
+        stack[top] = value;
+        top += 1;
+    }
+
+    stack[0]
+}
+
+// Exponentiation by squaring
+fn pow_int<S: Scalar>(mut base: Complex<S>, mut exponent: u32) -> Complex<S>
+{
+    let mut res = Complex::<S>::ONE;
+    while exponent > 0
+    {
+        if exponent & 1 == 1
+        {
+            res *= base;
+        }
+        base = base.squared();
+        exponent >>= 1;
+    }
+    res
+}
@@ -0,0 +1,465 @@
+use core::str::FromStr;
+use std::vec::Vec;
+
+use crate::math::*;
+use super::Formula64;
+use super::expression::{CompileError, Expression, UnaryFunction};
+
+/// Errors when parsing a formula, the positions are byte offsets in the text
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseFormulaError
+{
+    UnexpectedCharacter(usize),
+    UnexpectedToken(usize),
+    UnexpectedEnd,
+    UnknownName(usize),
+    InvalidNumber(usize),
+    TooLong,
+    TooManyConstants,
+    TooComplex,
+    ExponentTooLarge,
+}
+
+impl core::fmt::Display for ParseFormulaError
+{
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result
+    {
+        match self
+        {
+            Self::UnexpectedCharacter(position) => write!(f, "unexpected character at position {position}"),
+            Self::UnexpectedToken(position) => write!(f, "unexpected symbol at position {position}"),
+            Self::UnexpectedEnd => f.write_str("unexpected end of the formula"),
+            Self::UnknownName(position) => write!(f, "unknown variable or function at position {position}"),
+            Self::InvalidNumber(position) => write!(f, "invalid number at position {position}"),
+            Self::TooLong => f.write_str("the formula or its derivative is too long"),
+            Self::TooManyConstants => f.write_str("the formula has too many constants"),
+            Self::TooComplex => f.write_str("the formula is too deeply nested"),
+            Self::ExponentTooLarge => f.write_str("an integer exponent of the formula or its derivative is too large"),
+        }
+    }
+}
+
+impl From<CompileError> for ParseFormulaError
+{
+    fn from(value: CompileError) -> Self
+    {
+        match value
+        {
+            CompileError::CodeTooLong => Self::TooLong,
+            CompileError::TooManyConstants => Self::TooManyConstants,
+            CompileError::StackTooDeep => Self::TooComplex,
+            CompileError::ExponentTooLarge => Self::ExponentTooLarge,
+        }
+    }
+}
+
+impl FromStr for Formula64
+{
+    type Err = ParseFormulaError;
+
+    /// Parses a formula of `z` and `c` such as `z^3 - z + c` or `cos(z / c)`.
+    ///
+    /// It supports `+ - * / ^`, implicit multiplications like `2z`, the constants `i`, `pi` and `e`,
+    /// and the functions `sqrt exp ln log sin cos tan sinh cosh tanh`.
+    fn from_str(s: &str) -> Result<Self, Self::Err>
+    {
+        let mut parser = Parser
+        {
+            tokens: tokenize(s)?,
+            index: 0,
+        };
+
+        let expression = parser.expression()?;
+        if let Some(&(position, _)) = parser.peek()
+        {
+            return Err(ParseFormulaError::UnexpectedToken(position));
+        }
+
+        Ok(Formula64::compile(&expression)?)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Token<'a>
+{
+    Number(f64),
+    Name(&'a str),
+    Operator(char),
+    OpenParenthesis,
+    CloseParenthesis,
+}
+
+fn tokenize(s: &str) -> Result<Vec<(usize, Token<'_>)>, ParseFormulaError>
+{
+    let mut tokens = Vec::new();
+    let mut chars = s.char_indices().peekable();
+
+    while let Some(&(start, c)) = chars.peek()
+    {
+        let token = match c
+        {
+            _ if c.is_whitespace() =>
+            {
+                chars.next();
+                continue;
+            },
+            '0'..='9' | '.' =>
+            {
+                let mut end = start;
+                let mut previous = c;
+                while let Some(&(i, c)) = chars.peek()
+                {
+                    // The sign of an exponent belongs to the number
+                    let sign_of_exponent = matches!(c, '+' | '-') && matches!(previous, 'e' | 'E');
+                    if !(c.is_ascii_digit() || c == '.' || matches!(c, 'e' | 'E') || sign_of_exponent)
+                    {
+                        break;
+                    }
+                    // 'e' is only an exponent when followed by a digit, after an optional sign: `2e` is 2 times e and `2e+c` is 2e + c
+                    let exponent = s[i + 1..].strip_prefix(['+', '-']).unwrap_or(&s[i + 1..]);
+                    if matches!(c, 'e' | 'E') && !exponent.starts_with(|next: char| next.is_ascii_digit())
+                    {
+                        break;
+                    }
+                    end = i + c.len_utf8();
+                    previous = c;
+                    chars.next();
+                }
+                Token::Number(s[start..end].parse().map_err(|_| ParseFormulaError::InvalidNumber(start))?)
+            },
+            _ if c.is_alphabetic() =>
+            {
+                let mut end = start;
+                while let Some(&(i, c)) = chars.peek()
+                {
+                    if !c.is_alphanumeric()
+                    {
+                        break;
+                    }
+                    end = i + c.len_utf8();
+                    chars.next();
+                }
+                Token::Name(&s[start..end])
+            },
+            '+' | '-' | '*' | '/' | '^' =>
+            {
+                chars.next();
+                Token::Operator(c)
+            },
+            '(' =>
+            {
+                chars.next();
+                Token::OpenParenthesis
+            },
+            ')' =>
+            {
+                chars.next();
+                Token::CloseParenthesis
+            },
+            _ => return Err(ParseFormulaError::UnexpectedCharacter(start)),
+        };
+
+        tokens.push((start, token));
+    }
+
+    Ok(tokens)
+}
+
+struct Parser<'a>
+{
+    tokens: Vec<(usize, Token<'a>)>,
+    index: usize,
+}
+
+impl<'a> Parser<'a>
+{
+    fn peek(&self) -> Option<&(usize, Token<'a>)>
+    {
+        self.tokens.get(self.index)
+    }
+
+    fn next(&mut self) -> Result<(usize, Token<'a>), ParseFormulaError>
+    {
+        let token = *self.peek().ok_or(ParseFormulaError::UnexpectedEnd)?;
+        self.index += 1;
+        Ok(token)
+    }
+
+    fn next_is(&mut self, token: Token) -> bool
+    {
+        let found = self.peek().is_some_and(|&(_, next)| next == token);
+        if found
+        {
+            self.index += 1;
+        }
+        found
+    }
+
+    // expression = term (('+' | '-') term)*
+    fn expression(&mut self) -> Result<Expression, ParseFormulaError>
+    {
+        let mut res = self.term()?;
+        loop
+        {
+            if self.next_is(Token::Operator('+'))
+            {
+                res = Expression::add(res, self.term()?);
+            }
+            else if self.next_is(Token::Operator('-'))
+            {
+                res = Expression::sub(res, self.term()?);
+            }
+            else
+            {
+                return Ok(res);
+            }
+        }
+    }
+
+    // term = unary (('*' | '/')? unary)*, without operator it is an implicit multiplication
+    fn term(&mut self) -> Result<Expression, ParseFormulaError>
+    {
+        let mut res = self.unary()?;
+        loop
+        {
+            if self.next_is(Token::Operator('*'))
+            {
+                res = Expression::mul(res, self.unary()?);
+            }
+            else if self.next_is(Token::Operator('/'))
+            {
+                res = Expression::div(res, self.unary()?);
+            }
+            else if matches!(self.peek(), Some((_, Token::Number(_) | Token::Name(_) | Token::OpenParenthesis)))
+            {
+                res = Expression::mul(res, self.power()?);
+            }
+            else
+            {
+                return Ok(res);
+            }
+        }
+    }
+
+    // unary = '-' unary | power
+    fn unary(&mut self) -> Result<Expression, ParseFormulaError>
+    {
+        if self.next_is(Token::Operator('-'))
+        {
+            Ok(Expression::neg(self.unary()?))
+        }
+        else
+        {
+            self.power()
+        }
+    }
+
+    // power = primary ('^' unary)?, which makes '^' right associative
+    fn power(&mut self) -> Result<Expression, ParseFormulaError>
+    {
+        let base = self.primary()?;
+        if self.next_is(Token::Operator('^'))
+        {
+            Ok(Expression::pow(base, self.unary()?)?)
+        }
+        else
+        {
+            Ok(base)
+        }
+    }
+
+    // primary = number | variable | function '(' expression ')' | '(' expression ')'
+    fn primary(&mut self) -> Result<Expression, ParseFormulaError>
+    {
+        let (position, token) = self.next()?;
+        match token
+        {
+            Token::Number(value) => Ok(Expression::constant(Complex64::new(value, 0.0))),
+            Token::OpenParenthesis => self.parenthesized_end(),
+            Token::Name(name) =>
+            {
+                let function = match name
+                {
+                    "z" => return Ok(Expression::Z),
+                    "c" => return Ok(Expression::C),
+                    "i" => return Ok(Expression::constant(Complex64::I)),
+                    "pi" => return Ok(Expression::constant(Complex64::new(core::f64::consts::PI, 0.0))),
+                    "e" => return Ok(Expression::constant(Complex64::new(core::f64::consts::E, 0.0))),
+                    "sqrt" | "exp" | "ln" | "log" | "sin" | "cos" | "tan" | "sinh" | "cosh" | "tanh" => name,
+                    _ => return Err(ParseFormulaError::UnknownName(position)),
+                };
+
+                let (open_position, open) = self.next()?;
+                if open != Token::OpenParenthesis
+                {
+                    return Err(ParseFormulaError::UnexpectedToken(open_position));
+                }
+                let a = self.parenthesized_end()?;
+
+                Ok(apply_function(function, a))
+            },
+            Token::Operator(_) | Token::CloseParenthesis => Err(ParseFormulaError::UnexpectedToken(position)),
+        }
+    }
+
+    // The expression after an opening parenthesis, and the closing parenthesis
+    fn parenthesized_end(&mut self) -> Result<Expression, ParseFormulaError>
+    {
+        let res = self.expression()?;
+        let (position, token) = self.next()?;
+        if token != Token::CloseParenthesis
+        {
+            return Err(ParseFormulaError::UnexpectedToken(position));
+        }
+        Ok(res)
+    }
+}
+
+// The functions without an instruction are written with the others
+fn apply_function(name: &str, a: Expression) -> Expression
+{
+    let exp = |a: Expression| Expression::function(UnaryFunction::Exp, a);
+    let sinh = |a: Expression| Expression::div(Expression::sub(exp(a.clone()), exp(Expression::neg(a))), Expression::constant(Complex64::new(2.0, 0.0)));
+    let cosh = |a: Expression| Expression::div(Expression::add(exp(a.clone()), exp(Expression::neg(a))), Expression::constant(Complex64::new(2.0, 0.0)));
+
+    match name
+    {
+        "sqrt" => Expression::function(UnaryFunction::Sqrt, a),
+        "exp" => exp(a),
+        "ln" | "log" => Expression::function(UnaryFunction::Ln, a),
+        "sin" => Expression::function(UnaryFunction::Sin, a),
+        "cos" => Expression::function(UnaryFunction::Cos, a),
+        "tan" => Expression::div(Expression::function(UnaryFunction::Sin, a.clone()), Expression::function(UnaryFunction::Cos, a)),
+        "sinh" => sinh(a),
+        "cosh" => cosh(a),
+        _ => Expression::div(sinh(a.clone()), cosh(a)),
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::formula::{Formula, FormulaInputs};
+
+    fn parse(s: &str) -> Expression
+    {
+        let mut parser = Parser { tokens: tokenize(s).unwrap(), index: 0 };
+        let expression = parser.expression().unwrap();
+        assert!(parser.peek().is_none(), "{s} was not parsed entirely");
+        expression
+    }
+
+    fn constant(value: f64) -> Expression
+    {
+        Expression::constant(Complex64::new(value, 0.0))
+    }
+
+    fn power(a: Expression, b: Expression) -> Expression
+    {
+        Expression::pow(a, b).unwrap()
+    }
+
+    #[test]
+    fn precedence()
+    {
+        use Expression::{C, Z};
+
+        assert!(parse("z + c * z") == Expression::add(Z, Expression::mul(C, Z)));
+        assert!(parse("z * c + z") == Expression::add(Expression::mul(Z, C), Z));
+        assert!(parse("z - c / z") == Expression::sub(Z, Expression::div(C, Z)));
+        assert!(parse("(z + c) * z") == Expression::mul(Expression::add(Z, C), Z));
+        assert!(parse("c * z^3") == Expression::mul(C, power(Z, constant(3.0))));
+        assert!(parse("-z^2") == Expression::neg(power(Z, constant(2.0))));
+        assert!(parse("z^-c") == power(Z, Expression::neg(C)));
+        // Implicit multiplications bind like the explicit ones, but the power binds tighter
+        assert!(parse("2z^2 + c") == Expression::add(Expression::mul(constant(2.0), power(Z, constant(2.0))), C));
+        assert!(parse("c z (1 - z)") == Expression::mul(Expression::mul(C, Z), Expression::sub(constant(1.0), Z)));
+        assert!(parse("sin(z) c") == Expression::mul(Expression::function(UnaryFunction::Sin, Z), C));
+    }
+
+    #[test]
+    fn associativity()
+    {
+        use Expression::{C, Z};
+
+        assert!(parse("z - c - z") == Expression::sub(Expression::sub(Z, C), Z));
+        assert!(parse("z / c / z") == Expression::div(Expression::div(Z, C), Z));
+        assert!(parse("z / c z") == Expression::mul(Expression::div(Z, C), Z));
+        // The power is right associative
+        assert!(parse("z^c^z") == power(Z, power(C, Z)));
+        assert!(parse("2^3^2") == constant(512.0));
+        assert!(parse("(2^3)^2") == constant(64.0));
+        assert!(parse("--z") == Z);
+    }
+
+    #[test]
+    fn numbers_and_names()
+    {
+        use Expression::Z;
+
+        assert!(parse("1.5e2") == constant(150.0));
+        assert!(parse("2.5E-1") == constant(0.25));
+        assert!(parse(".5") == constant(0.5));
+        // `2e` is 2 times e, not a number with an exponent
+        assert!(parse("2e") == constant(2.0 * core::f64::consts::E));
+        assert!(parse("2e z") == Expression::mul(constant(2.0 * core::f64::consts::E), Z));
+        // Nor is a sign that is not followed by a digit
+        assert!(parse("2e+c") == Expression::add(constant(2.0 * core::f64::consts::E), Expression::C));
+        assert!(parse("3e-z") == Expression::sub(constant(3.0 * core::f64::consts::E), Z));
+        assert!(parse("1e+2") == constant(100.0));
+        assert!(parse("pi i") == Expression::constant(Complex64::new(0.0, core::f64::consts::PI)));
+        assert!(parse("log(z)") == parse("ln(z)"));
+    }
+
+    #[test]
+    fn functions_evaluate_like_the_complex_functions()
+    {
+        let z = Complex64::new(0.3, -0.4);
+        let c = Complex64::new(-0.7, 0.2);
+        let inputs = FormulaInputs { z, dz: Complex64::ONE, c, dc: Complex64::ZERO };
+        let cases: [(&str, Complex64); 8] = [
+            ("z^2 + c", z * z + c),
+            ("z^3 - z / c", z * z * z - z / c),
+            ("sqrt(z) - exp(c)", z.sqrt() - c.exp()),
+            ("ln(z) * sin(c)", z.ln() * c.sin()),
+            ("tan(z)", z.sin() / z.cos()),
+            ("sinh(z)", (z.exp() - (-z).exp()) / 2.0),
+            ("cosh(z) / tanh(c)", (z.exp() + (-z).exp()) / (c.exp() - (-c).exp()) * (c.exp() + (-c).exp()) / 2.0),
+            ("z^c", z.pow(c)),
+        ];
+
+        for (text, expected) in cases
+        {
+            let formula: Formula64 = text.parse().unwrap();
+            let value = Formula::<f64>::evaluate(&formula, inputs);
+            assert!(value.fuzzy_eq(expected, 1e-12), "{text} is evaluated incorrectly");
+        }
+    }
+
+    #[test]
+    fn potential_power_is_the_degree()
+    {
+        let power = |text: &str| Formula::<f64>::potential_power(&text.parse::<Formula64>().unwrap());
+        assert_eq!(power("z^2 + c"), 2.0);
+        assert_eq!(power("z^3 - 2z^2 + c"), 3.0);
+        assert_eq!(power("(z^2 + c)^2 + c"), 4.0);
+        assert_eq!(power("z / c + c"), 0.0);
+        assert_eq!(power("sin(z) + c"), 0.0);
+    }
+
+    #[test]
+    fn syntax_errors()
+    {
+        let error = |text: &str| text.parse::<Formula64>().err();
+        assert_eq!(error("z # c"), Some(ParseFormulaError::UnexpectedCharacter(2)));
+        assert_eq!(error("z + * c"), Some(ParseFormulaError::UnexpectedToken(4)));
+        assert_eq!(error("z)"), Some(ParseFormulaError::UnexpectedToken(1)));
+        assert_eq!(error("sin z"), Some(ParseFormulaError::UnexpectedToken(4)));
+        assert_eq!(error("(z + c"), Some(ParseFormulaError::UnexpectedEnd));
+        assert_eq!(error("z +"), Some(ParseFormulaError::UnexpectedEnd));
+        assert_eq!(error(""), Some(ParseFormulaError::UnexpectedEnd));
+        assert_eq!(error("z + x"), Some(ParseFormulaError::UnknownName(4)));
+        assert_eq!(error("z + 1.2.3"), Some(ParseFormulaError::InvalidNumber(4)));
+    }
+}
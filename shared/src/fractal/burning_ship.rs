@@ -47,7 +47,7 @@ impl<C: ComplexNumber> Function<C> for DAbs
 }
 
 
-pub fn burning_ship<S: Scalar>(pos: Complex<S>, params: FractalParams<S>) -> EscapeResult
+pub fn burning_ship<S: Scalar>(pos: Complex<S>, params: FractalParams<S>) -> EscapeSample
 {
     compute_escape_time_fractal(pos, params, DEFAULT_BAILOUT_RADIUS, Some(2.0), |z, c|
     {
@@ -0,0 +1,73 @@
+use crate::math::*;
+use crate::formula::{Formula, FormulaInputs};
+use super::{escape_time_method::*, FractalParams};
+
+pub const ITERATION_COUNT: u32 = 1024;
+
+/// The formula applied to `z`, with the constant `c`
+#[derive(Clone, Copy)]
+pub struct FormulaFunction<'a, S: Scalar, F: Formula<S>>
+{
+    formula: &'a F,
+    c: C<S>,
+}
+
+#[derive(Clone, Copy)]
+pub struct FormulaDerivative<'a, S: Scalar, F: Formula<S>>(FormulaFunction<'a, S, F>);
+
+impl<'a, S: Scalar, F: Formula<S>> FormulaFunction<'a, S, F>
+{
+    fn inputs(&self, (z, dz): (Complex<S>, Complex<S>)) -> FormulaInputs<S>
+    {
+        FormulaInputs { z, dz, c: self.c.0, dc: self.c.1 }
+    }
+}
+
+impl<'a, S: Scalar, F: Formula<S>> Function<(Complex<S>, Complex<S>)> for FormulaFunction<'a, S, F>
+{
+    type Output = Complex<S>;
+
+    fn get(&self, x: (Complex<S>, Complex<S>)) -> Self::Output
+    {
+        self.formula.evaluate(self.inputs(x))
+    }
+}
+
+impl<'a, S: Scalar, F: Formula<S>> Differentiable<(Complex<S>, Complex<S>)> for FormulaFunction<'a, S, F>
+{
+    type Derivative = FormulaDerivative<'a, S, F>;
+
+    fn derivative(&self) -> Func<Self::Derivative>
+    {
+        Func(FormulaDerivative(*self))
+    }
+}
+
+impl<'a, S: Scalar, F: Formula<S>> Function<(Complex<S>, Complex<S>)> for FormulaDerivative<'a, S, F>
+{
+    type Output = Complex<S>;
+
+    fn get(&self, x: (Complex<S>, Complex<S>)) -> Self::Output
+    {
+        self.0.formula.evaluate_derivative(self.0.inputs(x))
+    }
+}
+
+pub fn custom<S: Scalar>(pos: Complex<S>, params: FractalParams<S>, formula: &impl Formula<S>) -> EscapeSample
+{
+    // The potential is only known for polynomials
+    let potential_power = formula.potential_power();
+    let (bailout_radius, potential_power) = if potential_power > 0.0
+    {
+        (DEFAULT_BAILOUT_RADIUS, Some(potential_power))
+    }
+    else
+    {
+        (100.0, None)
+    };
+
+    compute_escape_time_fractal(pos, params, bailout_radius, potential_power, |_z, c|
+    {
+        Func(FormulaFunction { formula, c: c.0 })
+    })
+}
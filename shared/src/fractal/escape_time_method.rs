@@ -1,9 +1,13 @@
+
 pub use core::cell::Cell;
 use core::marker::PhantomData;
 
 use crate::math::*;
+use glam::DVec2;
+#[cfg(feature = "bytemuck")]
+use bytemuck::NoUninit;
 
-use super::{FractalVariation, FractalParams};
+use super::{perturbation, FractalKind, FractalVariation, FractalParams, InteriorColoring, OrbitTrap, OrbitTrapColoring, OrbitTrapShape, RenderTechnique};
 
 
 pub const DEFAULT_BAILOUT_RADIUS: f32 = 1.0e8;
@@ -11,7 +15,41 @@ pub const DEFAULT_BAILOUT_RADIUS: f32 = 1.0e8;
 pub enum EscapeResult
 {
     Escaped(f32),
-    StayedInside
+    StayedInside,
+    /// Stayed inside, with the value shown by [`InteriorColoring`]
+    Interior(f32),
+}
+
+/// An [`EscapeResult`] with the normal of the surface at the point, lit by [`super::Lighting`].
+///
+/// The normal isn't in the escape result since the shaders only support enums whose variants hold the same single value.
+pub struct EscapeSample
+{
+    pub result: EscapeResult,
+    /// Angle of the normal, only computed by the techniques for which [`super::Lighting::lights`] is true
+    pub normal_angle: f32,
+}
+
+impl From<EscapeResult> for EscapeSample
+{
+    fn from(result: EscapeResult) -> Self
+    {
+        Self
+        {
+            result,
+            normal_angle: 0.0,
+        }
+    }
+}
+
+/// The end of the orbit of a point that stayed inside
+#[derive(Clone, Copy)]
+pub struct InteriorOrbit<S: Scalar>
+{
+    /// Last value of z
+    pub z: Complex<S>,
+    /// Period of the cycle found by the periodicity check, 0 if none was found
+    pub period: u32,
 }
 
 #[derive(Clone, Copy)]
@@ -19,11 +57,12 @@ pub struct Z<S: Scalar>(PhantomData<S>);
 #[derive(Clone, Copy)]
 pub struct DZ<S: Scalar>(PhantomData<S>);
 
+/// The constant and its derivative with respect to the pixel position
 #[repr(C)]
 #[derive(Clone, Copy)]
-pub struct C<S: Scalar>(Complex<S>);
+pub struct C<S: Scalar>(pub Complex<S>, pub Complex<S>);
 #[derive(Clone, Copy)]
-pub struct DC<S: Scalar>(PhantomData<S>);
+pub struct DC<S: Scalar>(Complex<S>);
 
 impl<C: ComplexNumber> Function<C> for Z<C::Scalar>
 {
@@ -91,7 +130,7 @@ impl<S: Scalar> Differentiable<(Complex<S>, Complex<S>)> for C<S>
 
     fn derivative(&self) -> Func<Self::Derivative>
     {
-        Func(DC(PhantomData))
+        Func(DC(self.1))
     }
 }
 
@@ -101,7 +140,7 @@ impl<S: Scalar> Function<(Complex<S>, Complex<S>)> for DC<S>
 
     fn get(&self, (_z, _dz): (Complex<S>, Complex<S>)) -> Self::Output
     {
-        Self::Output::ONE
+        self.0
     }
 }
 
@@ -112,14 +151,14 @@ where
     IF: Function<(Complex<S>, Complex<S>), Output = Complex<S>> + Differentiable<(Complex<S>, Complex<S>)>,
     IF::Derivative: Function<(Complex<S>, Complex<S>), Output = Complex<S>>,
 {
-    let fun = f(Func(Z(PhantomData)), Func(C(c)));
+    let fun = f(Func(Z(PhantomData)), Func(C(c, Complex::<S>::ZERO)));
     move |z| fun.get((z, Complex::<S>::ZERO))
 }
 
-pub fn compute_escape_time_fractal<S, F, IF>(pos: Complex<S>, params: FractalParams<S>, bailout_radius: f32, potential_power: Option<f32>, iteration_function: F) -> EscapeResult
+pub fn compute_escape_time_fractal<S, F, IF>(pos: Complex<S>, params: FractalParams<S>, bailout_radius: f32, potential_power: Option<f32>, iteration_function: F) -> EscapeSample
 where
     S: Scalar,
-    F: FnOnce(Func<Z<S>>, Func<C<S>>) -> Func<IF>,
+    F: FnOnce(Func<Z<S>>, Func<C<S>>) -> Func<IF> + Copy,
     IF: Function<(Complex<S>, Complex<S>), Output = Complex<S>> + Differentiable<(Complex<S>, Complex<S>)>,
     IF::Derivative: Function<(Complex<S>, Complex<S>), Output = Complex<S>>,
 {
@@ -132,101 +171,980 @@ where
     match params.render_technique
     {
         super::RenderTechnique::Normal =>
-            compute_escape_time::<S>(z, params.iteration_limit, bailout_radius, potential_power, partial_apply::<S, _, _>(iteration_function, c)),
-        super::RenderTechnique::OrbitTrapPoint =>
-            EscapeResult::Escaped(compute_orbit_trap::<S>(z, params.iteration_limit, partial_apply::<S, _, _>(iteration_function, c), |z| z.modulus_squared())),
-        super::RenderTechnique::OrbitTrapCross =>
-            EscapeResult::Escaped(compute_orbit_trap::<S>(z, params.iteration_limit, partial_apply::<S, _, _>(iteration_function, c), |z| z.re().abs().min(z.im().abs()))),
+        {
+            match compute_escape_time::<S>(z, params.iteration_limit, bailout_radius, potential_power, partial_apply::<S, _, _>(iteration_function, c))
+            {
+                (EscapeResult::StayedInside, orbit) => compute_interior::<S, _, _>(orbit, c, params, iteration_function).into(),
+                (res, _orbit) => res.into(),
+            }
+        },
+        super::RenderTechnique::StripeAverage | super::RenderTechnique::CurvatureAverage | super::RenderTechnique::TriangleInequalityAverage =>
+            compute_orbit_average::<S>(z, c, params.render_technique, params.iteration_limit, bailout_radius, potential_power, partial_apply::<S, _, _>(iteration_function, c)).into(),
+        super::RenderTechnique::OrbitTrap =>
+            EscapeResult::Escaped(compute_orbit_trap::<S>(z, params.orbit_trap, params.iteration_limit, bailout_radius, partial_apply::<S, _, _>(iteration_function, c))).into(),
         super::RenderTechnique::NormalMap =>
-            compute_normal_map::<S, _, _>(z, c, params.iteration_limit, bailout_radius, iteration_function),
+            compute_normal_map::<S, _, _>(z, c, params.iteration_limit, bailout_radius, potential_power, iteration_function),
+        super::RenderTechnique::DistanceEstimate =>
+            compute_distance_estimate::<S, _, _>(z, c, params, bailout_radius, iteration_function),
     }
 }
 
-pub fn compute_escape_time<S: Scalar>(mut z: Complex<S>, iteration_count: u32, bailout_radius: f32, potential_power: Option<f32>, mut iteration_function: impl FnMut(Complex<S>) -> Complex<S>) -> EscapeResult
+/// Iterates until the point escapes, when it stays inside the end of its orbit is also returned
+pub fn compute_escape_time<S: Scalar>(mut z: Complex<S>, iteration_count: u32, bailout_radius: f32, potential_power: Option<f32>, mut iteration_function: impl FnMut(Complex<S>) -> Complex<S>) -> (EscapeResult, InteriorOrbit<S>)
 {
     let bailout_radius: S = bailout_radius.into();
     let bailout_squared = bailout_radius * bailout_radius;
-    let log_p = potential_power.map(ln);
     let mut prev_z = z;
+    let mut prev_iteration = 0;
     for i in 1..=iteration_count
     {
         let length_squared = z.modulus_squared();
         if length_squared > bailout_squared
         {
-            return EscapeResult::Escaped(if let Some(log_p) = log_p
-            {
-                let log_zn = log2(length_squared.as_()) / 2.0;
-                (i as f32 + 1.0 - ln(log_zn) / log_p).max(1.0)
-            }
-            else
-            {
-                i as f32
-            });
+            return (EscapeResult::Escaped(smooth_iteration_count(i, length_squared, potential_power)), InteriorOrbit { z, period: 0 });
         }
         z = iteration_function(z);
 
         // Periodicity checking: check for cycles with previously saved z
         if ComplexNumber::fuzzy_eq(z,  prev_z, 1.0e-20_f32.into())
         {
-            return EscapeResult::StayedInside;
+            // The cycle found can be several times the period of the orbit, look for the first return close to z
+            let cycle_length = i - prev_iteration;
+            let mut period = cycle_length;
+            let mut cycle_z = z;
+            for j in 1..cycle_length
+            {
+                cycle_z = iteration_function(cycle_z);
+                if ComplexNumber::fuzzy_eq(cycle_z, z, 1.0e-6_f32.into())
+                {
+                    period = j;
+                    break;
+                }
+            }
+
+            return (EscapeResult::StayedInside, InteriorOrbit { z, period });
         }
 
         // Save z every 32 iteration
         if i % 32 == 7
         {
             prev_z = z;
+            prev_iteration = i;
         }
     }
 
-    EscapeResult::StayedInside
+    (EscapeResult::StayedInside, InteriorOrbit { z, period: 0 })
+}
+
+/// Computes the value of [`FractalParams::interior_coloring`] for a point that stayed inside, the modes that need the cycle of the point
+/// stay black when the periodicity check didn't find it
+pub fn compute_interior<S, F, IF>(orbit: InteriorOrbit<S>, c: Complex<S>, params: FractalParams<S>, iteration_function: F) -> EscapeResult
+where
+    S: Scalar,
+    F: FnOnce(Func<Z<S>>, Func<C<S>>) -> Func<IF> + Copy,
+    IF: Function<(Complex<S>, Complex<S>), Output = Complex<S>> + Differentiable<(Complex<S>, Complex<S>)>,
+    IF::Derivative: Function<(Complex<S>, Complex<S>), Output = Complex<S>>,
+{
+    match params.interior_coloring
+    {
+        InteriorColoring::Black => EscapeResult::StayedInside,
+        InteriorColoring::FinalModulus => EscapeResult::Interior(orbit.z.modulus().as_()),
+        InteriorColoring::FinalAngle => EscapeResult::Interior(orbit.z.argument().as_()),
+        _ if orbit.period == 0 => EscapeResult::StayedInside,
+        InteriorColoring::Period => EscapeResult::Interior(orbit.period as f32),
+        InteriorColoring::Multiplier =>
+        {
+            let (_z, multiplier) = compute_cycle::<S, _, _>(orbit.z, c, orbit.period, iteration_function);
+            EscapeResult::Interior(multiplier.modulus().as_())
+        },
+        InteriorColoring::DistanceEstimate => match params.variation
+        {
+            FractalVariation::Normal => EscapeResult::Interior(compute_interior_distance::<S, _, _>(orbit, c, params.pixel_size, iteration_function)),
+            // The distance is estimated with respect to c, which doesn't change in a julia set
+            FractalVariation::JuliaSet => EscapeResult::StayedInside,
+        },
+    }
 }
 
-pub fn compute_orbit_trap<S: Scalar>(mut z: Complex<S>, iteration_count: u32, mut iteration_function: impl FnMut(Complex<S>) -> Complex<S>, mut distance_function: impl FnMut(Complex<S>) -> S) -> f32
+/// Applies the iteration function `period` times from `z`, returns the result and its derivative with respect to `z`.
+/// When `z` is on a cycle of that period, the derivative is the multiplier of the cycle.
+fn compute_cycle<S, F, IF>(mut z: Complex<S>, c: Complex<S>, period: u32, iteration_function: F) -> (Complex<S>, Complex<S>)
+where
+    S: Scalar,
+    F: FnOnce(Func<Z<S>>, Func<C<S>>) -> Func<IF>,
+    IF: Function<(Complex<S>, Complex<S>), Output = Complex<S>> + Differentiable<(Complex<S>, Complex<S>)>,
+    IF::Derivative: Function<(Complex<S>, Complex<S>), Output = Complex<S>>,
 {
-    let mut dist: S = 3.4e38_f32.into(); // Just under f32::MAX to avoid precision issues causing overflow
+    let iter_fn = iteration_function(Func(Z(PhantomData)), Func(C(c, Complex::<S>::ZERO)));
+    let derivative = iter_fn.derivative();
+
+    let mut dz = Complex::<S>::ONE;
+    for _i in 0..period
+    {
+        dz = derivative.get((z, dz));
+        z = iter_fn.get((z, dz));
+    }
+
+    (z, dz)
+}
+
+/// Estimates the distance from `c` to the boundary of its hyperbolic component `(1 - |m|²) / |dm/dc|`, where `m` is the multiplier of the
+/// attracting cycle, in pixels of size `pixel_size`.
+///
+/// The derivative of the multiplier is a finite difference over a pixel, the cycle of the neighbor is found from the cycle of `c` with Newton's method.
+pub fn compute_interior_distance<S, F, IF>(orbit: InteriorOrbit<S>, c: Complex<S>, pixel_size: S, iteration_function: F) -> f32
+where
+    S: Scalar,
+    F: FnOnce(Func<Z<S>>, Func<C<S>>) -> Func<IF> + Copy,
+    IF: Function<(Complex<S>, Complex<S>), Output = Complex<S>> + Differentiable<(Complex<S>, Complex<S>)>,
+    IF::Derivative: Function<(Complex<S>, Complex<S>), Output = Complex<S>>,
+{
+    const NEWTON_STEPS: u32 = 4;
+
+    let (_z, multiplier) = compute_cycle::<S, _, _>(orbit.z, c, orbit.period, iteration_function);
+
+    let h = Complex::<S>::from_cartesian(pixel_size, S::zero());
+    let neighbor_c = c + h;
+    let mut neighbor_z = orbit.z;
+    for _i in 0..NEWTON_STEPS
+    {
+        let (cycle_z, cycle_dz) = compute_cycle::<S, _, _>(neighbor_z, neighbor_c, orbit.period, iteration_function);
+        neighbor_z -= (cycle_z - neighbor_z) / (cycle_dz - Complex::<S>::ONE);
+    }
+    let (_z, neighbor_multiplier) = compute_cycle::<S, _, _>(neighbor_z, neighbor_c, orbit.period, iteration_function);
+
+    let multiplier_derivative: Complex<S> = (neighbor_multiplier - multiplier) / h;
+    let distance = (S::one() - multiplier.modulus_squared()) / multiplier_derivative.modulus();
+    (distance / pixel_size).as_()
+}
+
+/// Interpolates the iteration count of an escaped point using its potential when `potential_power` is known
+pub fn smooth_iteration_count<S: Scalar>(iteration: u32, length_squared: S, potential_power: Option<f32>) -> f32
+{
+    if let Some(potential_power) = potential_power
+    {
+        let log_zn = log2(length_squared.as_()) / 2.0;
+        (iteration as f32 + 1.0 - ln(log_zn) / ln(potential_power)).max(1.0)
+    }
+    else
+    {
+        iteration as f32
+    }
+}
+
+/// Closest approach of an orbit to an [`OrbitTrap`]
+#[derive(Clone, Copy)]
+pub struct OrbitTrapApproach<S: Scalar>
+{
+    trap: OrbitTrap,
+    center: Complex<S>,
+    /// Rotates the positions to the frame of the trap
+    rotation: Complex<S>,
+    distance: S,
+    iteration: u32,
+    /// Position of the closest point of the orbit in the frame of the trap
+    offset: Complex<S>,
+}
+
+impl<S: Scalar> OrbitTrapApproach<S>
+{
+    pub fn new(trap: OrbitTrap) -> Self
+    {
+        Self
+        {
+            trap,
+            center: Complex::<S>::from_complex32(Complex32::new(trap.center.x, trap.center.y)),
+            rotation: Complex::<S>::from_complex32(Complex32::from_polar(1.0, -trap.rotation * core::f32::consts::TAU / 360.0)),
+            distance: 3.4e38_f32.into(), // Just under f32::MAX to avoid precision issues causing overflow
+            iteration: 0,
+            offset: Complex::<S>::ZERO,
+        }
+    }
+
+    /// Adds the value of the orbit after `iteration` iterations
+    pub fn add(&mut self, iteration: u32, z: Complex<S>)
+    {
+        let offset = (z - self.center) * self.rotation;
+        let (x, y) = (offset.re().abs(), offset.im().abs());
+        let size: S = self.trap.size.into();
+        let distance = match self.trap.shape
+        {
+            OrbitTrapShape::Point => offset.modulus(),
+            OrbitTrapShape::Line => y,
+            OrbitTrapShape::Circle => (offset.modulus() - size).abs(),
+            OrbitTrapShape::Cross => x.min(y),
+            OrbitTrapShape::Square => (x.max(y) - size).abs(),
+        };
+
+        if distance < self.distance
+        {
+            self.distance = distance;
+            self.iteration = iteration;
+            self.offset = offset;
+        }
+    }
+
+    /// The value shown by the coloring of the trap
+    pub fn value(&self) -> f32
+    {
+        match self.trap.coloring
+        {
+            OrbitTrapColoring::Distance => self.distance.as_(),
+            OrbitTrapColoring::Iteration => self.iteration as f32,
+            OrbitTrapColoring::Angle =>
+            {
+                let angle: f32 = self.offset.argument().as_();
+                angle / core::f32::consts::TAU + 0.5
+            },
+        }
+    }
+}
 
+/// Follows the orbit until it escapes, the result is the value of its closest approach to the trap
+pub fn compute_orbit_trap<S: Scalar>(mut z: Complex<S>, trap: OrbitTrap, iteration_count: u32, bailout_radius: f32, mut iteration_function: impl FnMut(Complex<S>) -> Complex<S>) -> f32
+{
+    let bailout_radius: S = bailout_radius.into();
+    let bailout_squared = bailout_radius * bailout_radius;
+
+    let mut approach = OrbitTrapApproach::<S>::new(trap);
+    for i in 1..=iteration_count
+    {
+        z = iteration_function(z);
+        approach.add(i, z);
+        if z.modulus_squared() > bailout_squared
+        {
+            break;
+        }
+    }
+
+    approach.value()
+}
+
+/// Number of stripes of [`RenderTechnique::StripeAverage`] per turn around the origin
+const STRIPE_DENSITY: f32 = 5.0;
+
+/// Running average of a statistic of the orbit, for [`RenderTechnique::StripeAverage`], [`RenderTechnique::CurvatureAverage`]
+/// and [`RenderTechnique::TriangleInequalityAverage`]
+#[derive(Clone, Copy)]
+pub struct OrbitAverage
+{
+    render_technique: RenderTechnique,
+    c: Complex32,
+    /// The last two values of z, the most recent first
+    prev_z: [Complex32; 2],
+    /// Number of values of z added, the statistics start once two values are known
+    count: u32,
+    sum: f32,
+    /// Sum without the last statistic
+    prev_sum: f32,
+}
+
+impl OrbitAverage
+{
+    pub fn new(render_technique: RenderTechnique, c: Complex32) -> Self
+    {
+        Self
+        {
+            render_technique,
+            c,
+            prev_z: [Complex32::ZERO; 2],
+            count: 0,
+            sum: 0.0,
+            prev_sum: 0.0,
+        }
+    }
+
+    /// Adds the next value of the orbit
+    pub fn add(&mut self, z: Complex32)
+    {
+        if self.count >= 2
+        {
+            let statistic = match self.render_technique
+            {
+                RenderTechnique::StripeAverage => 0.5 * (STRIPE_DENSITY * z.argument()).sin() + 0.5,
+                RenderTechnique::CurvatureAverage =>
+                {
+                    let prev_step = self.prev_z[0] - self.prev_z[1];
+                    if prev_step.modulus_squared() > 0.0
+                    {
+                        abs(((z - self.prev_z[0]) / prev_step).argument()) / core::f32::consts::PI
+                    }
+                    else
+                    {
+                        0.0
+                    }
+                },
+                // Where |z| falls between the bounds given by the triangle inequality for the sum of the previous term and c
+                RenderTechnique::TriangleInequalityAverage =>
+                {
+                    let term = (z - self.c).modulus();
+                    let c = self.c.modulus();
+                    let z_modulus = z.modulus();
+                    if term >= c
+                    {
+                        // |z| - |term| is computed as (|z|² - |term|²) / (|z| + |term|), the difference cancels out once |z| is large
+                        let dot = z.re() * self.c.re() + z.im() * self.c.im();
+                        let z_minus_term = (2.0 * dot - c * c) / (z_modulus + term);
+                        if c > 0.0 { (z_minus_term + c) / (2.0 * c) } else { 0.0 }
+                    }
+                    else
+                    {
+                        (z_modulus - (c - term)) / (2.0 * term)
+                    }
+                },
+                _ => 0.0,
+            };
+
+            self.prev_sum = self.sum;
+            self.sum += statistic;
+        }
+
+        self.prev_z = [z, self.prev_z[0]];
+        self.count += 1;
+    }
+
+    /// The average once the orbit escaped with `length_squared`, interpolated between the averages with and without the last statistic
+    /// like [`smooth_iteration_count`]
+    pub fn escaped_value(&self, length_squared: f32, bailout_radius: f32, potential_power: Option<f32>) -> f32
+    {
+        if self.count < 3
+        {
+            return 0.0;
+        }
+
+        let statistics = (self.count - 2) as f32;
+        let average = self.sum / statistics;
+        let prev_average = if statistics > 1.0 { self.prev_sum / (statistics - 1.0) } else { average };
+
+        let t = match potential_power
+        {
+            Some(potential_power) => (1.0 + ln(ln(bailout_radius * bailout_radius) / ln(length_squared)) / ln(potential_power)).clamp(0.0, 1.0),
+            None => 1.0,
+        };
+        prev_average + (average - prev_average) * t
+    }
+}
+
+/// Iterates until the point escapes, averaging a statistic of the orbit with [`OrbitAverage`]
+pub fn compute_orbit_average<S: Scalar>(mut z: Complex<S>, c: Complex<S>, render_technique: RenderTechnique, iteration_count: u32, bailout_radius: f32, potential_power: Option<f32>, mut iteration_function: impl FnMut(Complex<S>) -> Complex<S>) -> EscapeResult
+{
+    let bailout_squared: S = (bailout_radius * bailout_radius).into();
+    let mut average = OrbitAverage::new(render_technique, c.to_complex32());
     for _i in 0..iteration_count
     {
+        let length_squared = z.modulus_squared();
+        if length_squared > bailout_squared
+        {
+            return EscapeResult::Escaped(average.escaped_value(length_squared.as_(), bailout_radius, potential_power));
+        }
         z = iteration_function(z);
-        dist = dist.min(distance_function(z));
+        average.add(z.to_complex32());
     }
 
-    dist.as_()
+    EscapeResult::StayedInside
 }
 
-pub fn compute_normal_map<S, F, IF>(mut z: Complex<S>, c: Complex<S>, iteration_count: u32, bailout_radius: f32, iteration_function: F) -> EscapeResult
+/// Computes the smooth iteration count with the normal of the surface given by the potential, the direction of `z / dz`
+pub fn compute_normal_map<S, F, IF>(mut z: Complex<S>, c: Complex<S>, iteration_count: u32, bailout_radius: f32, potential_power: Option<f32>, iteration_function: F) -> EscapeSample
 where
     S: Scalar,
     F: FnOnce(Func<Z<S>>, Func<C<S>>) -> Func<IF>,
     IF: Function<(Complex<S>, Complex<S>), Output = Complex<S>> + Differentiable<(Complex<S>, Complex<S>)>,
     IF::Derivative: Function<(Complex<S>, Complex<S>), Output = Complex<S>>,
 {
-    let light_angle = 45.0;
-    let light_dir = Complex::<S>::from_complex32(Complex32::from_polar(1.0, light_angle * core::f32::consts::TAU / 360.0));
-
     let bailout_radius: S = bailout_radius.into();
     let bailout_squared = bailout_radius * bailout_radius;
 
-    let iter_fn = iteration_function(Func(Z(PhantomData)), Func(C(c)));
+    let iter_fn = iteration_function(Func(Z(PhantomData)), Func(C(c, Complex::<S>::ONE)));
     let derivative = iter_fn.derivative();
 
     let mut dz = Complex::<S>::ZERO;
-    for _i in 0..iteration_count
+    for i in 1..=iteration_count
     {
         let length_squared = z.modulus_squared();
         if length_squared > bailout_squared
         {
-            let u: Complex<S> = z / dz;
-            let u = u / u.modulus();
+            let normal: Complex<S> = z / dz;
+            return EscapeSample
+            {
+                result: EscapeResult::Escaped(smooth_iteration_count(i, length_squared, potential_power)),
+                normal_angle: normal.argument().as_(),
+            };
+        }
+        dz = derivative.get((z, dz));
+        z = iter_fn.get((z, dz));
+    }
+
+    EscapeResult::StayedInside.into()
+}
+
+/// Computes the exterior distance estimate `|z| ln|z| / |dz|`, where `dz` is the derivative of `z` with respect to the pixel position.
+///
+/// The result is the distance to the boundary of the set in pixels of size `params.pixel_size`, with the normal of the surface when
+/// it is lit by `params.lighting`.
+pub fn compute_distance_estimate<S, F, IF>(mut z: Complex<S>, c: Complex<S>, params: FractalParams<S>, bailout_radius: f32, iteration_function: F) -> EscapeSample
+where
+    S: Scalar,
+    F: FnOnce(Func<Z<S>>, Func<C<S>>) -> Func<IF>,
+    IF: Function<(Complex<S>, Complex<S>), Output = Complex<S>> + Differentiable<(Complex<S>, Complex<S>)>,
+    IF::Derivative: Function<(Complex<S>, Complex<S>), Output = Complex<S>>,
+{
+    // The pixel position is c for the normal variation, and the starting z for the julia set
+    let (mut dz, dc) = match params.variation
+    {
+        FractalVariation::Normal => (Complex::<S>::ZERO, Complex::<S>::ONE),
+        FractalVariation::JuliaSet => (Complex::<S>::ONE, Complex::<S>::ZERO),
+    };
+
+    let bailout_radius: S = bailout_radius.into();
+    let bailout_squared = bailout_radius * bailout_radius;
+
+    let iter_fn = iteration_function(Func(Z(PhantomData)), Func(C(c, dc)));
+    let derivative = iter_fn.derivative();
 
-            let t: f32 = u.to_vector().dot(light_dir.to_vector()).as_();
-            let t = (t + 1.0) / 2.0;
+    let lit = params.lighting.lights(params.render_technique);
 
-            return EscapeResult::Escaped(t);
+    for _i in 0..params.iteration_limit
+    {
+        let length_squared = z.modulus_squared();
+        if length_squared > bailout_squared
+        {
+            let ln_modulus = ln(length_squared.as_()) * 0.5;
+            let ratio: f32 = (z.modulus() / (dz.modulus() * params.pixel_size)).as_();
+
+            // The normal is only needed when lit
+            let normal_angle = if lit { (z / dz).argument().as_() } else { 0.0 };
+            return EscapeSample
+            {
+                result: EscapeResult::Escaped(ratio * ln_modulus),
+                normal_angle,
+            };
         }
         dz = derivative.get((z, dz));
         z = iter_fn.get((z, dz));
     }
 
-    EscapeResult::StayedInside
+    EscapeResult::StayedInside.into()
+}
+
+
+/// Highest total degree of the terms of the series approximation
+pub const SERIES_ORDER: usize = 4;
+/// Number of terms `u^i conj(u)^j` with `1 <= i + j <= SERIES_ORDER`
+pub const SERIES_TERMS: usize = (SERIES_ORDER + 1) * (SERIES_ORDER + 2) / 2 - 1;
+
+// Terms are sorted by degree, then by power of conj(u)
+const fn series_term_index(i: usize, j: usize) -> usize
+{
+    let degree = i + j;
+    degree * (degree + 1) / 2 - 1 + j
+}
+
+/// Bivariate polynomial in `u` and `conj(u)` without constant term, truncated to [`SERIES_ORDER`].
+///
+/// Using `conj(u)` as a second variable allows approximating the fractals that are not analytic, like the tricorn.
+#[derive(Clone, Copy)]
+struct SeriesPolynomial<S: Scalar>([Complex<S>; SERIES_TERMS]);
+
+impl<S: Scalar> SeriesPolynomial<S>
+{
+    fn zero() -> Self
+    {
+        Self([Complex::<S>::ZERO; SERIES_TERMS])
+    }
+
+    // a * u
+    fn linear(a: Complex<S>) -> Self
+    {
+        let mut res = Self::zero();
+        res.0[series_term_index(1, 0)] = a;
+        res
+    }
+
+    fn add(self, rhs: Self) -> Self
+    {
+        let mut res = self;
+        for k in 0..SERIES_TERMS
+        {
+            res.0[k] += rhs.0[k];
+        }
+        res
+    }
+
+    fn scale(self, factor: Complex<S>) -> Self
+    {
+        let mut res = self;
+        for k in 0..SERIES_TERMS
+        {
+            res.0[k] *= factor;
+        }
+        res
+    }
+
+    fn mul(self, rhs: Self) -> Self
+    {
+        let mut res = Self::zero();
+        for degree_a in 1..SERIES_ORDER
+        {
+            for j_a in 0..=degree_a
+            {
+                let a = self.0[series_term_index(degree_a - j_a, j_a)];
+                for degree_b in 1..=(SERIES_ORDER - degree_a)
+                {
+                    for j_b in 0..=degree_b
+                    {
+                        let b = rhs.0[series_term_index(degree_b - j_b, j_b)];
+                        res.0[series_term_index(degree_a - j_a + degree_b - j_b, j_a + j_b)] += a * b;
+                    }
+                }
+            }
+        }
+        res
+    }
+
+    // conj(P(u, conj(u))) = conj(P)(conj(u), u)
+    fn conjugate(self) -> Self
+    {
+        let mut res = Self::zero();
+        for degree in 1..=SERIES_ORDER
+        {
+            for j in 0..=degree
+            {
+                res.0[series_term_index(j, degree - j)] = self.0[series_term_index(degree - j, j)].conjugate();
+            }
+        }
+        res
+    }
+
+    // Sum of the magnitudes of the coefficients of the given degree, which bounds the terms of that degree for |u| <= 1
+    fn degree_magnitude(&self, degree: usize) -> S
+    {
+        let mut res = S::zero();
+        for j in 0..=degree
+        {
+            res += self.0[series_term_index(degree - j, j)].modulus();
+        }
+        res
+    }
+
+    /// Evaluates the polynomial from the values of the terms `u^i conj(u)^j`, see [`series_terms`]
+    // The shaders don't support iterators over local arrays
+    #[allow(clippy::needless_range_loop)]
+    fn evaluate(&self, terms: &[Complex<S>; SERIES_TERMS]) -> Complex<S>
+    {
+        let mut res = Complex::<S>::ZERO;
+        for k in 0..SERIES_TERMS
+        {
+            res += self.0[k] * terms[k];
+        }
+        res
+    }
+}
+
+fn series_terms<S: Scalar>(u: Complex<S>) -> [Complex<S>; SERIES_TERMS]
+{
+    let mut terms = [Complex::<S>::ZERO; SERIES_TERMS];
+    let mut u_pow = Complex::<S>::ONE;
+    for i in 0..=SERIES_ORDER
+    {
+        let mut term = u_pow;
+        for j in 0..=(SERIES_ORDER - i)
+        {
+            if i + j > 0
+            {
+                terms[series_term_index(i, j)] = term;
+            }
+            term *= u.conjugate();
+        }
+        u_pow *= u;
+    }
+    terms
+}
+
+fn series_step<S: Scalar>(fractal_kind: FractalKind, power: u32, reference: Complex<S>, dz: SeriesPolynomial<S>, dc: SeriesPolynomial<S>) -> SeriesPolynomial<S>
+{
+    let two: Complex<S> = 2.0_f32.into();
+    match fractal_kind
+    {
+        // Same formulas as perturbation::perturbation_step
+        FractalKind::Multibrot =>
+        {
+            // sum(binomial(n, k) Z^(n-k) dz^k), the powers of dz above the order of the series are 0
+            let mut res = dc;
+            let mut dz_power = dz;
+            let mut binomial = 1.0_f32;
+            for k in 1..=(power as usize).min(SERIES_ORDER)
+            {
+                binomial = binomial * (power as usize + 1 - k) as f32 / k as f32;
+                let reference_power = (k..power as usize).fold(Complex::<S>::ONE, |res, _| res * reference);
+                res = res.add(dz_power.scale(reference_power * Complex::<S>::from(binomial)));
+                dz_power = dz_power.mul(dz);
+            }
+            res
+        },
+        FractalKind::Tricorn => dz.scale(reference * two).add(dz.mul(dz)).conjugate().add(dc),
+        _ => dz.scale(reference * two).add(dz.mul(dz)).add(dc),
+    }
+}
+
+/// Returns true if the fractal kind can skip iterations with [`compute_series_approximation`]
+pub fn supports_series_approximation(fractal_kind: FractalKind) -> bool
+{
+    matches!(fractal_kind, FractalKind::MandelbrotSet | FractalKind::Multibrot | FractalKind::Tricorn)
+}
+
+/// Approximation of the difference with the reference orbit after some iterations, as a polynomial of `u = delta / scale`
+#[derive(Clone, Copy)]
+pub struct SeriesApproximation<S: Scalar>
+{
+    pub coefficients: [Complex<S>; SERIES_TERMS],
+    pub scale: S,
+    pub skipped_iterations: u32,
+}
+
+impl<S: Scalar> SeriesApproximation<S>
+{
+    /// Doesn't skip any iteration
+    pub fn none() -> Self
+    {
+        Self
+        {
+            coefficients: [Complex::<S>::ZERO; SERIES_TERMS],
+            scale: S::one(),
+            skipped_iterations: 0,
+        }
+    }
+
+    /// The approximate difference with the reference orbit after [`Self::skipped_iterations`] iterations
+    pub fn evaluate(&self, delta: Complex<S>) -> Complex<S>
+    {
+        let u: Complex<S> = delta / self.scale;
+        SeriesPolynomial::<S>(self.coefficients).evaluate(&series_terms::<S>(u))
+    }
+}
+
+#[repr(C, align(16))]
+#[cfg_attr(feature = "bytemuck", derive(NoUninit))]
+#[derive(Copy, Clone)]
+pub struct SeriesApproximation64
+{
+    // Complex64 is not Pod, which is required for arrays
+    pub coefficients: [DVec2; SERIES_TERMS],
+    pub scale: f64,
+    pub skipped_iterations: u32,
+    padding0: u32,
+}
+
+impl Default for SeriesApproximation64
+{
+    fn default() -> Self
+    {
+        SeriesApproximation::none().into()
+    }
+}
+
+impl From<SeriesApproximation<f64>> for SeriesApproximation64
+{
+    fn from(value: SeriesApproximation<f64>) -> Self
+    {
+        Self
+        {
+            coefficients: value.coefficients.map(|coefficient| coefficient.to_vector()),
+            scale: value.scale,
+            skipped_iterations: value.skipped_iterations,
+            padding0: 0,
+        }
+    }
+}
+
+impl From<SeriesApproximation64> for SeriesApproximation<f64>
+{
+    // The shaders don't support array::map
+    #[allow(clippy::needless_range_loop)]
+    fn from(value: SeriesApproximation64) -> Self
+    {
+        let mut coefficients = [Complex64::ZERO; SERIES_TERMS];
+        for k in 0..SERIES_TERMS
+        {
+            coefficients[k] = Complex64::from_vector(value.coefficients[k]);
+        }
+
+        Self
+        {
+            coefficients,
+            scale: value.scale,
+            skipped_iterations: value.skipped_iterations,
+        }
+    }
+}
+
+/// Computes the series approximation of the difference with the reference orbit for the points at `probes` from the reference point,
+/// until the first iteration where it stops being valid.
+///
+/// The probes should surround the area where the approximation is used, typically the corners, the middle of the edges and the center of a cell.
+/// The approximation stops being valid when its highest degree terms are no longer negligible compared to `tolerance`.
+/// Each probe is also iterated with the perturbation, the approximation stops being valid when its relative error on one of them exceeds `tolerance`,
+/// or when one of them escapes or needs to be rebased.
+/// `scale` should be about the largest distance to the reference point, it keeps the coefficients in range at deep zooms.
+pub fn compute_series_approximation<S: Scalar, const P: usize>(params: FractalParams<S>, orbit: &[Complex<S>], scale: S, probes: [Complex<S>; P], tolerance: S) -> SeriesApproximation<S>
+{
+    let mut res = SeriesApproximation::none();
+    res.scale = scale;
+
+    // The orbit traps need every iteration
+    if params.render_technique != RenderTechnique::Normal || !supports_series_approximation(params.fractal_kind)
+    {
+        return res;
+    }
+
+    let scale_linear = SeriesPolynomial::<S>::linear(Complex::<S>::from_cartesian(scale, S::zero()));
+    let (mut series, series_dc) = match params.variation
+    {
+        FractalVariation::Normal => (SeriesPolynomial::zero(), scale_linear),
+        FractalVariation::JuliaSet => (scale_linear, SeriesPolynomial::zero()),
+    };
+    let (mut probe_dz, probe_dc) = match params.variation
+    {
+        FractalVariation::Normal => ([Complex::<S>::ZERO; P], probes),
+        FractalVariation::JuliaSet => (probes, [Complex::<S>::ZERO; P]),
+    };
+    let probe_terms = probes.map(|delta| series_terms::<S>(delta / scale));
+
+    let power = perturbation::power::<S>(params.fractal_kind, params.exponent);
+    let (bailout_radius, _) = perturbation::bailout_and_potential_power(power);
+    let bailout_radius: S = bailout_radius.into();
+    let bailout_squared = bailout_radius * bailout_radius;
+    let tolerance_squared = tolerance * tolerance;
+
+    let max_skip = (orbit.len().max(1) - 1).min(params.iteration_limit.max(1) as usize - 1);
+    for (n, &reference) in orbit.iter().enumerate().take(max_skip)
+    {
+        for p in 0..P
+        {
+            let z = reference + probe_dz[p];
+            let length_squared = z.modulus_squared();
+            if length_squared > bailout_squared || length_squared < probe_dz[p].modulus_squared()
+            {
+                return res;
+            }
+            probe_dz[p] = perturbation::perturbation_step::<S>(params.fractal_kind, power, reference, probe_dz[p], probe_dc[p]);
+        }
+
+        let next_series = series_step(params.fractal_kind, power, reference, series, series_dc);
+
+        // The truncated terms are about the size of the highest degree terms, which must stay negligible
+        if next_series.degree_magnitude(SERIES_ORDER) > tolerance * next_series.degree_magnitude(1)
+        {
+            return res;
+        }
+
+        for p in 0..P
+        {
+            let error: Complex<S> = next_series.evaluate(&probe_terms[p]) - probe_dz[p];
+            if error.modulus_squared() > tolerance_squared * probe_dz[p].modulus_squared()
+            {
+                return res;
+            }
+        }
+
+        series = next_series;
+        res.coefficients = series.0;
+        res.skipped_iterations = n as u32 + 1;
+    }
+
+    res
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::fractal::FractalParams64;
+    use crate::fractal::perturbation::compute_reference_orbit;
+
+    fn params(fractal_kind: FractalKind) -> FractalParams64
+    {
+        FractalParams64 { fractal_kind, iteration_limit: 1000, ..Default::default() }
+    }
+
+    fn reference_orbit(reference: Complex64, params: &FractalParams64) -> ([Complex64; 1000], usize)
+    {
+        let mut orbit = [Complex64::ZERO; 1000];
+        let length = compute_reference_orbit::<4>(Fixed::from_f64(reference.re()), Fixed::from_f64(reference.im()), params, &mut orbit);
+        (orbit, length)
+    }
+
+    // The corners, the middle of the edges and the center of a square of radius `scale`
+    fn probes(scale: f64) -> [Complex64; 9]
+    {
+        let mut res = [Complex64::ZERO; 9];
+        for (k, probe) in res.iter_mut().enumerate()
+        {
+            *probe = Complex64::new((k % 3) as f64 - 1.0, (k / 3) as f64 - 1.0) * Complex64::from(scale);
+        }
+        res
+    }
+
+    fn series(fractal_kind: FractalKind, reference: Complex64, scale: f64) -> SeriesApproximation<f64>
+    {
+        let params = params(fractal_kind);
+        let (orbit, length) = reference_orbit(reference, &params);
+        compute_series_approximation::<f64, 9>(params.into(), &orbit[..length], scale, probes(scale), 1.0e-6)
+    }
+
+    #[test]
+    fn term_indices_are_sorted_by_degree()
+    {
+        let mut index = 0;
+        for degree in 1..=SERIES_ORDER
+        {
+            for j in 0..=degree
+            {
+                assert_eq!(series_term_index(degree - j, j), index);
+                index += 1;
+            }
+        }
+        assert_eq!(index, SERIES_TERMS);
+
+        let u = Complex64::new(0.5, -2.0);
+        let terms = series_terms::<f64>(u);
+        assert!(terms[series_term_index(1, 0)] == u);
+        assert!(terms[series_term_index(0, 1)] == u.conjugate());
+        assert!(terms[series_term_index(2, 1)].fuzzy_eq(u * u * u.conjugate(), 1.0e-12));
+    }
+
+    #[test]
+    fn coefficients_of_the_mandelbrot_set_at_the_origin()
+    {
+        // Around 0, dz(n + 1) = dz(n)^2 + s u: dz(1) = s u, dz(2) = s u + s^2 u^2 and dz(3) = s u + s^2 u^2 + 2 s^3 u^3 + s^4 u^4
+        let s = 0.25;
+        let params: FractalParams<f64> = params(FractalKind::MandelbrotSet).into();
+        let orbit = [Complex64::ZERO; 4];
+        let mut expected = [Complex64::ZERO; SERIES_TERMS];
+        expected[series_term_index(1, 0)] = Complex64::from(s);
+        expected[series_term_index(2, 0)] = Complex64::from(s * s);
+
+        // The third iteration has a term of degree 4 that is not negligible
+        let res = compute_series_approximation::<f64, 9>(params, &orbit, s, probes(1.0e-3), 1.0e-6);
+        assert_eq!(res.skipped_iterations, 2);
+        for (k, (coefficient, expected)) in res.coefficients.iter().zip(expected).enumerate()
+        {
+            assert!(coefficient.fuzzy_eq(expected, 1.0e-15), "term {k}");
+        }
+
+        let res = compute_series_approximation::<f64, 9>(params, &orbit, s, probes(1.0e-3), 1.0);
+        assert_eq!(res.skipped_iterations, 3);
+        expected[series_term_index(3, 0)] = Complex64::from(2.0 * s * s * s);
+        expected[series_term_index(4, 0)] = Complex64::from(s * s * s * s);
+        for (k, (coefficient, expected)) in res.coefficients.iter().zip(expected).enumerate()
+        {
+            assert!(coefficient.fuzzy_eq(expected, 1.0e-15), "term {k}");
+        }
+    }
+
+    #[test]
+    fn series_matches_the_perturbation()
+    {
+        let reference = Complex64::new(-0.75, 0.12);
+        let scale = 1.0e-7;
+        let delta = Complex64::new(0.6e-7, -0.3e-7);
+        for kind in [FractalKind::MandelbrotSet, FractalKind::Multibrot, FractalKind::Tricorn]
+        {
+            let res = series(kind, reference, scale);
+            assert!(res.skipped_iterations > 5, "{kind:?}");
+
+            let (orbit, _) = reference_orbit(reference, &params(kind));
+            let power = perturbation::power::<f64>(kind, params(kind).exponent);
+            let mut dz = Complex64::ZERO;
+            for &z in &orbit[..res.skipped_iterations as usize]
+            {
+                dz = perturbation::perturbation_step::<f64>(kind, power, z, dz, delta);
+            }
+            assert!(res.evaluate(delta).fuzzy_eq(dz, 1.0e-5 * dz.modulus()), "{kind:?}");
+        }
+    }
+
+    #[test]
+    fn julia_sets_start_from_the_position()
+    {
+        let mut params = params(FractalKind::MandelbrotSet);
+        params.variation = FractalVariation::JuliaSet;
+        params.secondary_pos = Complex64::new(-0.4, 0.6);
+        let reference = Complex64::new(0.1, -0.2);
+        let (orbit, length) = reference_orbit(reference, &params);
+        let scale = 1.0e-8;
+        let res = compute_series_approximation::<f64, 9>(params.into(), &orbit[..length], scale, probes(scale), 1.0e-6);
+        assert!(res.skipped_iterations > 0);
+
+        let delta = Complex64::new(-0.5e-8, 0.8e-8);
+        let mut dz = delta;
+        for &z in &orbit[..res.skipped_iterations as usize]
+        {
+            dz = perturbation::perturbation_step::<f64>(FractalKind::MandelbrotSet, 2, z, dz, Complex64::ZERO);
+        }
+        assert!(res.evaluate(delta).fuzzy_eq(dz, 1.0e-5 * dz.modulus()));
+    }
+
+    #[test]
+    fn skip_count()
+    {
+        let reference = Complex64::new(-0.75, 0.12);
+        let (_, length) = reference_orbit(reference, &params(FractalKind::MandelbrotSet));
+
+        // Smaller areas are approximated for longer, but never past the escape of the reference
+        let (wide, narrow) = (series(FractalKind::MandelbrotSet, reference, 1.0e-4), series(FractalKind::MandelbrotSet, reference, 1.0e-12));
+        assert!(wide.skipped_iterations < narrow.skipped_iterations);
+        assert!((narrow.skipped_iterations as usize) < length);
+
+        // The burning ship is not analytic even in conj(u), and the orbit traps need every iteration
+        assert_eq!(series(FractalKind::BurningShip, reference, 1.0e-12).skipped_iterations, 0);
+        let mut params = params(FractalKind::MandelbrotSet);
+        params.render_technique = RenderTechnique::OrbitTrap;
+        let (orbit, length) = reference_orbit(reference, &params);
+        let res = compute_series_approximation::<f64, 9>(params.into(), &orbit[..length], 1.0e-12, probes(1.0e-12), 1.0e-6);
+        assert_eq!(res.skipped_iterations, 0);
+
+        // Without skipped iterations the difference starts at 0
+        assert!(SeriesApproximation::<f64>::none().evaluate(Complex64::new(1.0, 2.0)) == Complex64::ZERO);
+    }
+
+    // The statistic of the triangle inequality average for the value `z` of the orbit
+    fn triangle_inequality_statistic(z: Complex32, c: Complex32) -> f32
+    {
+        let mut average = OrbitAverage::new(RenderTechnique::TriangleInequalityAverage, c);
+        average.add(Complex32::ZERO);
+        average.add(Complex32::ZERO);
+        average.add(z);
+        average.escaped_value(1.0e10, DEFAULT_BAILOUT_RADIUS, None)
+    }
+
+    // Where |z| falls between ||z - c| - |c|| and |z - c| + |c|, computed directly in double precision
+    fn triangle_inequality_definition(z: Complex32, c: Complex32) -> f64
+    {
+        let (z, c) = (z.to_complex64(), c.to_complex64());
+        let term = (z - c).modulus();
+        let lower = (term - c.modulus()).abs();
+        let upper = term + c.modulus();
+        (z.modulus() - lower) / (upper - lower)
+    }
+
+    #[test]
+    fn triangle_inequality_closed_form()
+    {
+        let c = Complex32::new(-0.7, 0.3);
+        for (re, im) in [(0.1, 0.2), (-0.5, 0.25), (2.0, -1.0), (-3.0, 0.5), (40.0, 30.0), (-1.0e4, 2.0e4), (3.0e6, -1.0e6)]
+        {
+            let z = Complex32::new(re, im);
+            let (statistic, expected) = (triangle_inequality_statistic(z, c), triangle_inequality_definition(z, c));
+            assert!((statistic as f64 - expected).abs() < 1.0e-4, "{re} {im}: {statistic} {expected}");
+            assert!((-1.0e-6..=1.0 + 1.0e-6).contains(&statistic));
+        }
+
+        // |z| is at the bounds when z - c and c are aligned
+        assert!((triangle_inequality_statistic(Complex32::new(-2.1, 0.9), c) - 1.0).abs() < 1.0e-5);
+        assert!(triangle_inequality_statistic(Complex32::new(0.7, -0.3) * Complex32::new(0.5, 0.0), c).abs() < 1.0e-5);
+
+        // Without c the bounds are equal
+        assert_eq!(triangle_inequality_statistic(Complex32::new(2.0, 1.0), Complex32::ZERO), 0.0);
+    }
 }
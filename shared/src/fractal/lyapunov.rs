@@ -1,16 +1,161 @@
+use core::fmt::{Display, Formatter};
+use core::str::FromStr;
+
 use crate::math::*;
+#[cfg(feature = "bytemuck")]
+use bytemuck::NoUninit;
+
+use super::FractalParams;
 
 pub const ITERATION_COUNT: u32 = 1024;
 
-pub fn lyapunov<S: Scalar, const L: usize>(sequence: &[bool; L], pos: Vec2<S>, iteration_count: u32) -> f32
+/// Maximum number of letters of a [`LyapunovSequence`]
+pub const MAX_SEQUENCE_LENGTH: usize = 32;
+
+/// Sequence of the parameters `a` and `b` used by the successive iterations, written as a string of A and B such as `AABAB`
+#[repr(C)]
+#[cfg_attr(feature = "bytemuck", derive(NoUninit))]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct LyapunovSequence
+{
+    /// The bit `i` is set when the letter `i` is B
+    pub bits: u32,
+    pub length: u32,
+    padding0: u32,
+    padding1: u32,
+}
+
+impl Default for LyapunovSequence
+{
+    /// `AB`
+    fn default() -> Self
+    {
+        Self { bits: 0b10, length: 2, padding0: 0, padding1: 0 }
+    }
+}
+
+impl LyapunovSequence
+{
+    pub fn is_b(&self, index: usize) -> bool
+    {
+        self.bits & (1 << (index % self.length as usize)) != 0
+    }
+}
+
+// Written as its string of letters
+#[cfg(feature = "serde")]
+impl serde::Serialize for LyapunovSequence
+{
+    fn serialize<Ser: serde::Serializer>(&self, serializer: Ser) -> Result<Ser::Ok, Ser::Error>
+    {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for LyapunovSequence
+{
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error>
+    {
+        struct Visitor;
+
+        impl serde::de::Visitor<'_> for Visitor
+        {
+            type Value = LyapunovSequence;
+
+            fn expecting(&self, f: &mut Formatter<'_>) -> core::fmt::Result
+            {
+                f.write_str("a sequence of A and B")
+            }
+
+            fn visit_str<E: serde::de::Error>(self, value: &str) -> Result<Self::Value, E>
+            {
+                value.parse().map_err(E::custom)
+            }
+        }
+
+        deserializer.deserialize_str(Visitor)
+    }
+}
+
+/// Errors when parsing a [`LyapunovSequence`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseLyapunovSequenceError
+{
+    Empty,
+    TooLong,
+    InvalidLetter(char),
+}
+
+impl Display for ParseLyapunovSequenceError
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result
+    {
+        match self
+        {
+            Self::Empty => f.write_str("the sequence is empty"),
+            Self::TooLong => write!(f, "the sequence is longer than {MAX_SEQUENCE_LENGTH} letters"),
+            Self::InvalidLetter(letter) => write!(f, "invalid letter '{letter}', only A and B are allowed"),
+        }
+    }
+}
+
+impl FromStr for LyapunovSequence
+{
+    type Err = ParseLyapunovSequenceError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err>
+    {
+        let mut res = Self { bits: 0, length: 0, padding0: 0, padding1: 0 };
+        for letter in s.trim().chars()
+        {
+            if res.length as usize == MAX_SEQUENCE_LENGTH
+            {
+                return Err(ParseLyapunovSequenceError::TooLong);
+            }
+
+            match letter.to_ascii_uppercase()
+            {
+                'A' => {},
+                'B' => res.bits |= 1 << res.length,
+                _ => return Err(ParseLyapunovSequenceError::InvalidLetter(letter)),
+            }
+            res.length += 1;
+        }
+
+        if res.length == 0
+        {
+            return Err(ParseLyapunovSequenceError::Empty);
+        }
+
+        Ok(res)
+    }
+}
+
+impl Display for LyapunovSequence
 {
-    let iteration_count = iteration_count as usize;
-    
-    let zero = S::zero();
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result
+    {
+        for i in 0..self.length as usize
+        {
+            f.write_str(if self.is_b(i) { "B" } else { "A" })?;
+        }
+        Ok(())
+    }
+}
+
+/// Computes the lyapunov exponent of the logistic map with the parameters `a = pos.x` and `b = pos.y`.
+///
+/// The points outside the region of the parameters return 0.
+pub fn lyapunov<S: Scalar>(pos: Vec2<S>, params: FractalParams<S>) -> f32
+{
+    let iteration_count = params.iteration_limit as usize;
+    let sequence = params.lyapunov_sequence;
+
     let one = S::one();
     let two: S = 2.0_f32.into();
-    let four = 4.0_f32.into();
-    if pos.x() < zero || pos.y() < zero || pos.x() > four || pos.y() > four
+    let (min, max) = (params.lyapunov_region_min, params.lyapunov_region_max);
+    if pos.x() < min.x() || pos.y() < min.y() || pos.x() > max.x() || pos.y() > max.y()
     {
         return 0.0;
     }
@@ -20,7 +165,7 @@ pub fn lyapunov<S: Scalar, const L: usize>(sequence: &[bool; L], pos: Vec2<S>, i
 
     for i in 0..iteration_count
     {
-        let rn = if sequence[i % L] { pos.y() } else { pos.x() };
+        let rn = if sequence.is_b(i) { pos.y() } else { pos.x() };
 
         if i != 0
         {
@@ -31,4 +176,68 @@ pub fn lyapunov<S: Scalar, const L: usize>(sequence: &[bool; L], pos: Vec2<S>, i
     }
 
     lyapunov_exp / iteration_count as f32
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use std::string::{String, ToString};
+
+    #[test]
+    fn parse_letters()
+    {
+        let sequence: LyapunovSequence = "AABAB".parse().unwrap();
+        assert_eq!((sequence.bits, sequence.length), (0b10100, 5));
+        assert_eq!("ab".parse(), Ok(LyapunovSequence::default()));
+        assert_eq!(" BBA\n".parse::<LyapunovSequence>().map(|s| s.bits), Ok(0b011));
+
+        assert!(!sequence.is_b(0));
+        assert!(sequence.is_b(2));
+        // The sequence repeats
+        assert!(sequence.is_b(7));
+        assert!(!sequence.is_b(8));
+    }
+
+    #[test]
+    fn only_a_and_b_are_accepted()
+    {
+        assert_eq!("ABC".parse::<LyapunovSequence>(), Err(ParseLyapunovSequenceError::InvalidLetter('C')));
+        assert_eq!("A B".parse::<LyapunovSequence>(), Err(ParseLyapunovSequenceError::InvalidLetter(' ')));
+        assert_eq!("AB1".parse::<LyapunovSequence>(), Err(ParseLyapunovSequenceError::InvalidLetter('1')));
+        assert_eq!("ÅB".parse::<LyapunovSequence>(), Err(ParseLyapunovSequenceError::InvalidLetter('Å')));
+    }
+
+    #[test]
+    fn empty_sequence_is_rejected()
+    {
+        assert_eq!("".parse::<LyapunovSequence>(), Err(ParseLyapunovSequenceError::Empty));
+        assert_eq!("  ".parse::<LyapunovSequence>(), Err(ParseLyapunovSequenceError::Empty));
+    }
+
+    #[test]
+    fn length_is_limited()
+    {
+        let longest: String = "AB".repeat(MAX_SEQUENCE_LENGTH / 2);
+        let sequence: LyapunovSequence = longest.parse().unwrap();
+        assert_eq!(sequence.length as usize, MAX_SEQUENCE_LENGTH);
+        assert_eq!(sequence.bits, 0xAAAA_AAAA);
+
+        let too_long = longest + "A";
+        assert_eq!(too_long.parse::<LyapunovSequence>(), Err(ParseLyapunovSequenceError::TooLong));
+    }
+
+    #[test]
+    fn display_round_trips()
+    {
+        assert_eq!(LyapunovSequence::default().to_string(), "AB");
+        assert_eq!("bbaab".parse::<LyapunovSequence>().unwrap().to_string(), "BBAAB");
+
+        for text in ["A", "B", "AABAB", "BBBBBBAAAAAA", &"BA".repeat(MAX_SEQUENCE_LENGTH / 2)]
+        {
+            let sequence: LyapunovSequence = text.parse().unwrap();
+            assert_eq!(sequence.to_string(), text);
+            assert_eq!(sequence.to_string().parse(), Ok(sequence));
+        }
+    }
+}
@@ -3,12 +3,14 @@ use super::FractalParams;
 use super::RenderTechnique;
 use super::escape_time_method::*;
 use super::FractalVariation;
+use super::InteriorColoring;
 
 pub const ITERATION_COUNT: u32 = 1024;
 
-pub fn mandelbrot_value<S: Scalar>(pos: Complex<S>, params: FractalParams<S>) -> EscapeResult
+pub fn mandelbrot_value<S: Scalar>(pos: Complex<S>, params: FractalParams<S>) -> EscapeSample
 {
-    if params.variation == FractalVariation::Normal && params.render_technique == RenderTechnique::Normal
+    // The interior coloring needs the orbit of the points inside
+    if params.variation == FractalVariation::Normal && params.render_technique == RenderTechnique::Normal && params.interior_coloring == InteriorColoring::Black
     {
         // Cardioid / bulb checking
         let quarter: S = 0.25_f32.into();
@@ -17,7 +19,7 @@ pub fn mandelbrot_value<S: Scalar>(pos: Complex<S>, params: FractalParams<S>) ->
         if q * (q + (pos.re() - quarter)) <= quarter * pos.im() * pos.im() // the point is within the cardioid
             || (pos + ComplexNumber::ONE).modulus_squared() < quarter * quarter // the point is within the period-2 bulb
         {
-            return EscapeResult::StayedInside;
+            return EscapeResult::StayedInside.into();
         }
     }
 
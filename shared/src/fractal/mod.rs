@@ -4,29 +4,43 @@ pub mod multibrot;
 pub mod tricorn;
 pub mod burning_ship;
 pub mod cos_leaf;
+pub mod custom;
 pub mod newton;
 pub mod lyapunov;
+pub mod perturbation;
 
 use crate::math::*;
-use glam::{Vec3, vec3};
+use crate::formula::Formula;
+use crate::gradient::Gradient;
+use glam::{Vec2 as FVec2, DVec2, Vec3, vec3};
 #[cfg(feature = "bytemuck")]
 use bytemuck::NoUninit;
-use self::escape_time_method::EscapeResult;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use self::escape_time_method::{EscapeResult, EscapeSample, SeriesApproximation};
+use self::newton::{Polynomial, Polynomial32, Polynomial64};
+use self::lyapunov::LyapunovSequence;
 
 #[repr(u32)]
 #[cfg_attr(feature = "bytemuck", derive(NoUninit))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum FractalKind
 {
     // Escape time
     MandelbrotSet,
-    Multibrot3,
+    /// Generalization of the mandelbrot set with the exponent of [`FractalParams::exponent`]
+    Multibrot,
     Tricorn,
     BurningShip,
     CosLeaf,
+    /// Iterates the formula entered at runtime
+    Custom,
 
     // Other
     Newton3,
+    /// Newton fractal of the polynomial of [`FractalParams::polynomial`]
+    NewtonPolynomial,
     Lyapunov,
 }
 
@@ -39,18 +53,32 @@ impl FractalKind
         match self
         {
             MandelbrotSet => mandelbrot::ITERATION_COUNT,
-            Multibrot3 => multibrot::ITERATION_COUNT,
+            Multibrot => multibrot::ITERATION_COUNT,
             Tricorn => tricorn::ITERATION_COUNT,
             BurningShip => burning_ship::ITERATION_COUNT,
             CosLeaf => cos_leaf::ITERATION_COUNT,
-            Newton3 => newton::ITERATION_COUNT,
+            Custom => custom::ITERATION_COUNT,
+            Newton3 | NewtonPolynomial => newton::ITERATION_COUNT,
             Lyapunov => lyapunov::ITERATION_COUNT,
         }
     }
+
+    /// Whether the pixels are colored from an [`EscapeResult`], the other fractals compute their colors directly
+    pub fn is_escape_time(&self) -> bool
+    {
+        use FractalKind::*;
+
+        match self
+        {
+            MandelbrotSet | Multibrot | Tricorn | BurningShip | CosLeaf | Custom => true,
+            Newton3 | NewtonPolynomial | Lyapunov => false,
+        }
+    }
 }
 
 #[repr(u32)]
 #[cfg_attr(feature = "bytemuck", derive(NoUninit))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum FractalVariation
 {
@@ -60,23 +88,260 @@ pub enum FractalVariation
 
 #[repr(u32)]
 #[cfg_attr(feature = "bytemuck", derive(NoUninit))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum RenderTechnique
 {
     Normal,
-    OrbitTrapPoint,
-    OrbitTrapCross,
     NormalMap,
+    /// Colors by the estimated distance to the boundary of the set, in pixels
+    DistanceEstimate,
+    /// Colors by the average of the angle of the orbit, which draws stripes around the set
+    StripeAverage,
+    /// Colors by the average of the turning angle of the orbit
+    CurvatureAverage,
+    /// Colors by the average position of |z| between the bounds of the triangle inequality of each iteration
+    TriangleInequalityAverage,
+    /// Colors by the closest approach of the orbit to the [`OrbitTrap`]
+    OrbitTrap,
 }
 
 #[repr(u32)]
 #[cfg_attr(feature = "bytemuck", derive(NoUninit))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum ColorPalette
 {
     Default,
     Flames,
     Temperature,
+    /// The gradient defined at runtime
+    Custom,
+}
+
+/// What is shown inside the set, computed from the end of the orbit of the points that stay inside
+#[repr(u32)]
+#[cfg_attr(feature = "bytemuck", derive(NoUninit))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum InteriorColoring
+{
+    Black,
+    /// Period of the attracting cycle found by the periodicity check
+    Period,
+    /// Modulus of the last z
+    FinalModulus,
+    /// Angle of the last z
+    FinalAngle,
+    /// Modulus of the multiplier of the attracting cycle, 0 at the centers of the components and 1 on their boundaries
+    Multiplier,
+    /// Estimated distance to the boundary of the hyperbolic component, in pixels
+    DistanceEstimate,
+}
+
+/// How the escape values are mapped to the positions in the palette
+#[repr(u32)]
+#[cfg_attr(feature = "bytemuck", derive(NoUninit))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ColorMapping
+{
+    /// By the logarithm of the value
+    Logarithmic,
+    /// By the rank of the value among the visible pixels, see [`crate::histogram`]
+    Histogram,
+}
+
+/// Blinn-Phong lighting of the surface whose normals are given by [`EscapeSample::normal_angle`]
+#[repr(C, align(16))]
+#[cfg_attr(feature = "bytemuck", derive(NoUninit))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Lighting
+{
+    /// Whether [`RenderTechnique::DistanceEstimate`] is lit, 0 or 1 since the shaders can't read booleans from buffers.
+    /// [`RenderTechnique::NormalMap`] is always lit.
+    pub distance_estimate: u32,
+    /// Direction of the light in the plane of the view, in degrees counterclockwise from the right
+    pub azimuth: f32,
+    /// Angle of the light above the plane of the view, in degrees
+    pub elevation: f32,
+    pub ambient: f32,
+    pub diffuse: f32,
+    pub specular: f32,
+    /// Exponent of the specular highlights, higher values make them smaller
+    pub shininess: f32,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    padding0: u32,
+}
+
+impl Default for Lighting
+{
+    fn default() -> Self
+    {
+        Self
+        {
+            distance_estimate: 0,
+            azimuth: 45.0,
+            elevation: 45.0,
+            ambient: 0.2,
+            diffuse: 0.8,
+            specular: 0.3,
+            shininess: 20.0,
+            padding0: 0,
+        }
+    }
+}
+
+impl Lighting
+{
+    /// Whether the results of `render_technique` have normals
+    pub fn lights(&self, render_technique: RenderTechnique) -> bool
+    {
+        match render_technique
+        {
+            RenderTechnique::NormalMap => true,
+            RenderTechnique::DistanceEstimate => self.distance_estimate != 0,
+            _ => false,
+        }
+    }
+
+    /// Lights `color` on a surface whose normal leans by 45° in the direction `normal_angle`, in radians
+    pub fn light(&self, color: Vec3, normal_angle: f32) -> Vec3
+    {
+        let to_radians = core::f32::consts::TAU / 360.0;
+        let (azimuth, elevation) = (self.azimuth * to_radians, self.elevation * to_radians);
+        let light = vec3(elevation.cos() * azimuth.cos(), elevation.cos() * azimuth.sin(), elevation.sin());
+        let normal = vec3(normal_angle.cos(), normal_angle.sin(), 1.0) * core::f32::consts::FRAC_1_SQRT_2;
+        // The view looks straight down on the surface
+        let half_vector = (light + Vec3::Z).normalize();
+
+        let diffuse = normal.dot(light).max(0.0);
+        let specular = pow(normal.dot(half_vector).max(0.0), self.shininess);
+        // The highlights would overflow the channels of the image
+        (color * (self.ambient + self.diffuse * diffuse) + Vec3::splat(self.specular * specular)).min(Vec3::ONE)
+    }
+}
+
+/// Shape of the [`OrbitTrap`]
+#[repr(u32)]
+#[cfg_attr(feature = "bytemuck", derive(NoUninit))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum OrbitTrapShape
+{
+    Point,
+    /// Line through the center in the direction of the rotation
+    Line,
+    Circle,
+    /// The line and its perpendicular through the center
+    Cross,
+    /// Outline of a square
+    Square,
+}
+
+/// What [`RenderTechnique::OrbitTrap`] shows of the closest approach of the orbit to the trap
+#[repr(u32)]
+#[cfg_attr(feature = "bytemuck", derive(NoUninit))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum OrbitTrapColoring
+{
+    /// Distance to the trap
+    Distance,
+    /// Iteration at which the orbit is the closest to the trap
+    Iteration,
+    /// Angle of the closest point of the orbit around the center of the trap
+    Angle,
+}
+
+/// Trap of [`RenderTechnique::OrbitTrap`], in the plane of the orbits
+#[repr(C, align(16))]
+#[cfg_attr(feature = "bytemuck", derive(NoUninit))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+#[derive(Copy, Clone, PartialEq)]
+pub struct OrbitTrap
+{
+    pub shape: OrbitTrapShape,
+    pub coloring: OrbitTrapColoring,
+    pub center: FVec2,
+    /// Counterclockwise rotation around the center, in degrees
+    pub rotation: f32,
+    /// Radius of the circle and half the side of the square
+    pub size: f32,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    padding0: u32,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    padding1: u32,
+}
+
+impl OrbitTrap
+{
+    /// Point at the origin
+    pub const POINT: Self = Self
+    {
+        shape: OrbitTrapShape::Point,
+        coloring: OrbitTrapColoring::Distance,
+        center: FVec2::ZERO,
+        rotation: 0.0,
+        size: 1.0,
+        padding0: 0,
+        padding1: 0,
+    };
+
+    /// Cross on the axes
+    pub const CROSS: Self = Self
+    {
+        shape: OrbitTrapShape::Cross,
+        ..Self::POINT
+    };
+}
+
+impl Default for OrbitTrap
+{
+    fn default() -> Self
+    {
+        Self::POINT
+    }
+}
+
+/// The parameters used to color an [`EscapeResult`], they can change without computing the escape results again
+#[repr(C)]
+#[cfg_attr(feature = "bytemuck", derive(NoUninit))]
+#[derive(Copy, Clone)]
+pub struct ColorParams
+{
+    pub render_technique: RenderTechnique,
+    pub color_palette: ColorPalette,
+    pub color_frequency: f32,
+    pub color_mapping: ColorMapping,
+    pub interior_coloring: InteriorColoring,
+    pub orbit_trap_coloring: OrbitTrapColoring,
+    padding0: u32,
+    padding1: u32,
+    pub lighting: Lighting,
+}
+
+impl ColorParams
+{
+    /// Parameters with the [`ColorMapping::Logarithmic`] mapping
+    pub fn new(render_technique: RenderTechnique, color_palette: ColorPalette, color_frequency: f32, interior_coloring: InteriorColoring, orbit_trap_coloring: OrbitTrapColoring, lighting: Lighting) -> Self
+    {
+        Self
+        {
+            render_technique,
+            color_palette,
+            color_frequency,
+            color_mapping: ColorMapping::Logarithmic,
+            interior_coloring,
+            orbit_trap_coloring,
+            padding0: 0,
+            padding1: 0,
+            lighting,
+        }
+    }
 }
 
 #[repr(C)]
@@ -91,6 +356,25 @@ pub struct FractalParams32
     pub iteration_limit: u32,
     pub color_palette: ColorPalette,
     pub color_frequency: f32,
+    /// Size of a pixel in the fractal space, only used by [`RenderTechnique::DistanceEstimate`]
+    pub pixel_size: f32,
+    /// The color of a pixel is averaged from a grid of `samples_per_axis`² samples
+    pub samples_per_axis: u32,
+    pub interior_coloring: InteriorColoring,
+    padding0: u32,
+    padding1: u32,
+    padding2: u32,
+    /// Exponent of [`FractalKind::Multibrot`]
+    pub exponent: Complex32,
+    /// Polynomial of [`FractalKind::NewtonPolynomial`]
+    pub polynomial: Polynomial32,
+    pub lighting: Lighting,
+    pub orbit_trap: OrbitTrap,
+    /// Region of the parameters `a` and `b` of [`FractalKind::Lyapunov`]
+    pub lyapunov_region_min: FVec2,
+    pub lyapunov_region_max: FVec2,
+    /// Sequence of [`FractalKind::Lyapunov`]
+    pub lyapunov_sequence: LyapunovSequence,
 }
 
 impl Default for FractalParams32
@@ -106,12 +390,27 @@ impl Default for FractalParams32
             iteration_limit: 0,
             color_palette: ColorPalette::Default,
             color_frequency: 1.0,
+            pixel_size: 1.0,
+            samples_per_axis: 1,
+            interior_coloring: InteriorColoring::Black,
+            padding0: 0,
+            padding1: 0,
+            padding2: 0,
+            exponent: Complex32::new(3.0, 0.0),
+            polynomial: Polynomial32::default(),
+            lighting: Lighting::default(),
+            orbit_trap: OrbitTrap::default(),
+            lyapunov_region_min: FVec2::ZERO,
+            lyapunov_region_max: FVec2::splat(4.0),
+            lyapunov_sequence: LyapunovSequence::default(),
         }
     }
 }
 
 #[repr(C)]
 #[cfg_attr(feature = "bytemuck", derive(NoUninit))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
 #[derive(Copy, Clone)]
 pub struct FractalParams64
 {
@@ -122,8 +421,29 @@ pub struct FractalParams64
     pub iteration_limit: u32,
     pub color_palette: ColorPalette,
     pub color_frequency: f32,
-    padding0: u32,
+    /// The color of a pixel is averaged from a grid of `samples_per_axis`² samples
+    pub samples_per_axis: u32,
+    /// How the points that stay inside are colored, with [`RenderTechnique::Normal`]
+    pub interior_coloring: InteriorColoring,
+    #[cfg_attr(feature = "serde", serde(skip))]
     padding1: u32,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    padding2: u32,
+    /// Size of a pixel in the fractal space, only used by [`RenderTechnique::DistanceEstimate`]
+    pub pixel_size: f64,
+    /// Exponent of [`FractalKind::Multibrot`]
+    pub exponent: Complex64,
+    /// Lighting of [`RenderTechnique::NormalMap`] and [`RenderTechnique::DistanceEstimate`]
+    pub lighting: Lighting,
+    /// Trap of [`RenderTechnique::OrbitTrap`]
+    pub orbit_trap: OrbitTrap,
+    /// Polynomial of [`FractalKind::NewtonPolynomial`]
+    pub polynomial: Polynomial64,
+    /// Sequence of [`FractalKind::Lyapunov`]
+    pub lyapunov_sequence: LyapunovSequence,
+    /// Region of the parameters `a` and `b` of [`FractalKind::Lyapunov`]
+    pub lyapunov_region_min: DVec2,
+    pub lyapunov_region_max: DVec2,
 }
 
 impl Default for FractalParams64
@@ -139,12 +459,30 @@ impl Default for FractalParams64
             iteration_limit: FractalKind::MandelbrotSet.default_iteration_limit(),
             color_palette: ColorPalette::Default,
             color_frequency: 1.0,
-            padding0: 0,
+            samples_per_axis: 1,
+            interior_coloring: InteriorColoring::Black,
             padding1: 0,
+            padding2: 0,
+            pixel_size: 1.0,
+            exponent: Complex64::new(3.0, 0.0),
+            lighting: Lighting::default(),
+            orbit_trap: OrbitTrap::default(),
+            polynomial: Polynomial64::default(),
+            lyapunov_sequence: LyapunovSequence::default(),
+            lyapunov_region_min: DVec2::ZERO,
+            lyapunov_region_max: DVec2::splat(4.0),
         }
     }
 }
 
+impl FractalParams64
+{
+    pub fn color_params(&self) -> ColorParams
+    {
+        ColorParams::new(self.render_technique, self.color_palette, self.color_frequency, self.interior_coloring, self.orbit_trap.coloring, self.lighting)
+    }
+}
+
 impl From<FractalParams64> for FractalParams32
 {
     fn from(value: FractalParams64) -> Self
@@ -158,6 +496,19 @@ impl From<FractalParams64> for FractalParams32
             iteration_limit: value.iteration_limit,
             color_palette: value.color_palette,
             color_frequency: value.color_frequency,
+            pixel_size: value.pixel_size as f32,
+            samples_per_axis: value.samples_per_axis,
+            interior_coloring: value.interior_coloring,
+            padding0: 0,
+            padding1: 0,
+            padding2: 0,
+            exponent: value.exponent.to_complex32(),
+            polynomial: value.polynomial.into(),
+            lighting: value.lighting,
+            orbit_trap: value.orbit_trap,
+            lyapunov_region_min: value.lyapunov_region_min.as_vec2(),
+            lyapunov_region_max: value.lyapunov_region_max.as_vec2(),
+            lyapunov_sequence: value.lyapunov_sequence,
         }
     }
 }
@@ -173,6 +524,24 @@ pub struct FractalParams<S: Scalar>
     pub iteration_limit: u32,
     pub color_palette: ColorPalette,
     pub color_frequency: f32,
+    pub samples_per_axis: u32,
+    pub interior_coloring: InteriorColoring,
+    pub lighting: Lighting,
+    pub orbit_trap: OrbitTrap,
+    pub pixel_size: S,
+    pub exponent: Complex<S>,
+    pub polynomial: Polynomial<S>,
+    pub lyapunov_sequence: LyapunovSequence,
+    pub lyapunov_region_min: Vec2<S>,
+    pub lyapunov_region_max: Vec2<S>,
+}
+
+impl<S: Scalar> FractalParams<S>
+{
+    pub fn color_params(&self) -> ColorParams
+    {
+        ColorParams::new(self.render_technique, self.color_palette, self.color_frequency, self.interior_coloring, self.orbit_trap.coloring, self.lighting)
+    }
 }
 
 impl From<FractalParams32> for FractalParams<f32>
@@ -188,6 +557,16 @@ impl From<FractalParams32> for FractalParams<f32>
             iteration_limit: value.iteration_limit,
             color_palette: value.color_palette,
             color_frequency: value.color_frequency,
+            samples_per_axis: value.samples_per_axis,
+            interior_coloring: value.interior_coloring,
+            lighting: value.lighting,
+            orbit_trap: value.orbit_trap,
+            pixel_size: value.pixel_size,
+            exponent: value.exponent,
+            polynomial: value.polynomial.into(),
+            lyapunov_sequence: value.lyapunov_sequence,
+            lyapunov_region_min: value.lyapunov_region_min,
+            lyapunov_region_max: value.lyapunov_region_max,
         }
     }
 }
@@ -205,58 +584,181 @@ impl From<FractalParams64> for FractalParams<f64>
             iteration_limit: value.iteration_limit,
             color_palette: value.color_palette,
             color_frequency: value.color_frequency,
+            samples_per_axis: value.samples_per_axis,
+            interior_coloring: value.interior_coloring,
+            lighting: value.lighting,
+            orbit_trap: value.orbit_trap,
+            pixel_size: value.pixel_size,
+            exponent: value.exponent,
+            polynomial: value.polynomial.into(),
+            lyapunov_sequence: value.lyapunov_sequence,
+            lyapunov_region_min: value.lyapunov_region_min,
+            lyapunov_region_max: value.lyapunov_region_max,
         }
     }
 }
 
-pub fn compute_fractal_color<S: Scalar>(pos: Complex<S>, params: FractalParams<S>) -> Vec3
+impl From<FractalParams64> for FractalParams<DoubleDouble>
 {
-    let res = match params.fractal_kind
+    fn from(value: FractalParams64) -> Self
     {
-        FractalKind::MandelbrotSet => mandelbrot::mandelbrot_value(pos, params),
-        FractalKind::Multibrot3 => multibrot::multibrot3(pos, params),
-        FractalKind::Tricorn => tricorn::tricorn(pos, params),
-        FractalKind::BurningShip => burning_ship::burning_ship(pos, params),
-        FractalKind::CosLeaf => cos_leaf::cos_leaf(pos, params),
-        FractalKind::Newton3 => return newton::newton3(pos, params),
+        Self
+        {
+            secondary_pos: value.secondary_pos.into(),
+            fractal_kind: value.fractal_kind,
+            variation: value.variation,
+            render_technique: value.render_technique,
+            iteration_limit: value.iteration_limit,
+            color_palette: value.color_palette,
+            color_frequency: value.color_frequency,
+            samples_per_axis: value.samples_per_axis,
+            interior_coloring: value.interior_coloring,
+            lighting: value.lighting,
+            orbit_trap: value.orbit_trap,
+            pixel_size: value.pixel_size.into(),
+            exponent: value.exponent.into(),
+            polynomial: value.polynomial.into(),
+            lyapunov_sequence: value.lyapunov_sequence,
+            lyapunov_region_min: value.lyapunov_region_min.into(),
+            lyapunov_region_max: value.lyapunov_region_max.into(),
+        }
+    }
+}
+
+pub fn compute_fractal_color<S: Scalar>(pos: Complex<S>, params: FractalParams<S>, formula: &impl Formula<S>, gradient: &Gradient) -> Vec3
+{
+    match params.fractal_kind
+    {
+        FractalKind::Newton3 => newton::newton3(pos, params),
+        FractalKind::NewtonPolynomial => newton::newton_polynomial(pos, params, gradient),
         FractalKind::Lyapunov =>
         {
-            let v = lyapunov::lyapunov::<S, 2>(&[false, true], pos.to_vector(), params.iteration_limit);
+            let v = lyapunov::lyapunov(pos.to_vector(), params);
             let y: f32 = if v >= 0.0 { 0.0 } else { sqrt(exp(v)) };
             let r = y;
             let g = 1.0 - pow(1.0 - y, 0.55);
             let b = if v <= 0.0 { 0.0 } else { 1.0 - pow(exp(-v), 3.0) };
-            return vec3(r, g, b);
+            vec3(r, g, b)
         },
-    };
+        _ => escape_sample_color(compute_escape_result(pos, params, formula), params.color_params(), gradient),
+    }
+}
+
+/// Computes the escape result of the fractals where [`FractalKind::is_escape_time`] is true
+pub fn compute_escape_result<S: Scalar>(pos: Complex<S>, params: FractalParams<S>, formula: &impl Formula<S>) -> EscapeSample
+{
+    match params.fractal_kind
+    {
+        FractalKind::MandelbrotSet => mandelbrot::mandelbrot_value(pos, params),
+        FractalKind::Multibrot => multibrot::multibrot(pos, params),
+        FractalKind::Tricorn => tricorn::tricorn(pos, params),
+        FractalKind::BurningShip => burning_ship::burning_ship(pos, params),
+        FractalKind::CosLeaf => cos_leaf::cos_leaf(pos, params),
+        FractalKind::Custom => custom::custom(pos, params, formula),
+        // Colored directly by compute_fractal_color
+        FractalKind::Newton3 | FractalKind::NewtonPolynomial | FractalKind::Lyapunov => EscapeResult::StayedInside.into(),
+    }
+}
 
+/// Computes the color of a point relative to a reference point, see [`perturbation::compute_perturbation`]
+pub fn compute_perturbation_color<S: Scalar>(delta: Complex<S>, params: FractalParams<S>, reference_orbit: &[Complex<S>], series: &SeriesApproximation<S>, gradient: &Gradient) -> Vec3
+{
+    escape_result_color(perturbation::compute_perturbation(delta, params, reference_orbit, series), params.color_params(), gradient)
+}
+
+fn escape_result_color(res: EscapeResult, params: ColorParams, gradient: &Gradient) -> Vec3
+{
     match res
     {
         EscapeResult::StayedInside => vec3(0.0, 0.0, 0.0),
-        EscapeResult::Escaped(v) =>
+        EscapeResult::Escaped(v) => escaped_color(v, params, gradient),
+        EscapeResult::Interior(v) => interior_color(v, params, gradient),
+    }
+}
+
+fn escape_sample_color(sample: EscapeSample, params: ColorParams, gradient: &Gradient) -> Vec3
+{
+    match sample.result
+    {
+        EscapeResult::Escaped(v) if params.lighting.lights(params.render_technique) => params.lighting.light(escaped_color(v, params, gradient), sample.normal_angle),
+        res => escape_result_color(res, params, gradient),
+    }
+}
+
+/// The color of a point that stayed inside with the value `v` of [`EscapeResult::Interior`]
+pub fn interior_color(v: f32, params: ColorParams, gradient: &Gradient) -> Vec3
+{
+    // The values are mapped to one period of the palette, and repeated by the color frequency
+    let palette_pos = match params.interior_coloring
+    {
+        InteriorColoring::Black => return Vec3::ZERO,
+        // One color per period
+        InteriorColoring::Period => (v - 1.0) / PALETTE_LENGTH,
+        // The modulus is below 2 inside the Mandelbrot set
+        InteriorColoring::FinalModulus => v * 0.5,
+        InteriorColoring::FinalAngle => v / core::f32::consts::TAU + 0.5,
+        InteriorColoring::Multiplier => v,
+        InteriorColoring::DistanceEstimate =>
         {
-            if params.render_technique == RenderTechnique::NormalMap
-            {
-                let g = v * 0.9 + 0.1;
-                Vec3::splat(g)
-            }
-            else
-            {
-                let v = ln(v);
-
-                let v = v * params.color_frequency;
-
-                match params.color_palette
-                {
-                    // orange purple blue cyan white yellow
-                    ColorPalette::Default => sample_palette(v, &[vec3(1.0, 0.5, 0.0), vec3(0.5, 0.0, 1.0), vec3(0.0, 0.0, 1.0), vec3(0.0, 1.0, 1.0), vec3(1.0, 1.0, 1.0), vec3(1.0, 1.0, 0.0)]),
-                    // yellow red black red yellow white
-                    ColorPalette::Flames => sample_palette(v, &[vec3(1.0, 1.0, 0.0), vec3(1.0, 0.0, 0.0), vec3(0.0, 0.0, 0.0), vec3(1.0, 0.0, 0.0), vec3(1.0, 1.0, 0.0), vec3(1.0, 1.0, 1.0)]),
-                    // cyan purple black red yellow white
-                    ColorPalette::Temperature => sample_palette(v, &[vec3(0.0, 1.0, 1.0), vec3(0.5, 0.0, 1.0), vec3(0.0, 0.0, 0.0), vec3(1.0, 0.0, 0.0), vec3(1.0, 1.0, 0.0), vec3(1.0, 1.0, 1.0)]),
-                }
-            }
+            // Like the exterior distance estimate, the boundary is drawn in black
+            let t = v.clamp(0.0, 1.0);
+            let t = t*t * (3.0 - 2.0*t);
+            return palette_color(ln(1.0 + v) * params.color_frequency, params.color_palette, gradient) * t;
         },
+    };
+
+    palette_color(palette_pos * PALETTE_LENGTH * params.color_frequency, params.color_palette, gradient)
+}
+
+/// The color of a point that escaped with the value `v` of [`EscapeResult::Escaped`], with the [`ColorMapping::Logarithmic`] mapping
+pub fn escaped_color(v: f32, params: ColorParams, gradient: &Gradient) -> Vec3
+{
+    let palette_pos = match params.render_technique
+    {
+        RenderTechnique::DistanceEstimate => ln(1.0 + v),
+        // The averages and the angles are between 0 and 1, they are spread over the palette
+        RenderTechnique::StripeAverage | RenderTechnique::CurvatureAverage | RenderTechnique::TriangleInequalityAverage => v * PALETTE_LENGTH,
+        RenderTechnique::OrbitTrap if params.orbit_trap_coloring == OrbitTrapColoring::Angle => v * PALETTE_LENGTH,
+        _ => ln(v),
+    };
+    shaded_color(v, palette_pos * params.color_frequency, params, gradient)
+}
+
+/// Same as [`escaped_color`] with the [`ColorMapping::Histogram`] mapping, the palette is spread once over the ranks of the values, `rank` is the one of `v`
+pub fn histogram_color(v: f32, params: ColorParams, gradient: &Gradient, rank: f32) -> Vec3
+{
+    shaded_color(v, rank * PALETTE_LENGTH * params.color_frequency, params, gradient)
+}
+
+fn shaded_color(v: f32, palette_pos: f32, params: ColorParams, gradient: &Gradient) -> Vec3
+{
+    match params.render_technique
+    {
+        RenderTechnique::DistanceEstimate =>
+        {
+            // The boundary is drawn in black with a thickness of about one pixel
+            let t = v.clamp(0.0, 1.0);
+            let t = t*t * (3.0 - 2.0*t);
+            palette_color(palette_pos, params.color_palette, gradient) * t
+        },
+        _ => palette_color(palette_pos, params.color_palette, gradient),
+    }
+}
+
+/// Number of colors of the palettes, [`palette_color`] repeats with this period
+const PALETTE_LENGTH: f32 = 6.0;
+
+fn palette_color(v: f32, color_palette: ColorPalette, gradient: &Gradient) -> Vec3
+{
+    match color_palette
+    {
+        // orange purple blue cyan white yellow
+        ColorPalette::Default => sample_palette(v, &[vec3(1.0, 0.5, 0.0), vec3(0.5, 0.0, 1.0), vec3(0.0, 0.0, 1.0), vec3(0.0, 1.0, 1.0), vec3(1.0, 1.0, 1.0), vec3(1.0, 1.0, 0.0)]),
+        // yellow red black red yellow white
+        ColorPalette::Flames => sample_palette(v, &[vec3(1.0, 1.0, 0.0), vec3(1.0, 0.0, 0.0), vec3(0.0, 0.0, 0.0), vec3(1.0, 0.0, 0.0), vec3(1.0, 1.0, 0.0), vec3(1.0, 1.0, 1.0)]),
+        // cyan purple black red yellow white
+        ColorPalette::Temperature => sample_palette(v, &[vec3(0.0, 1.0, 1.0), vec3(0.5, 0.0, 1.0), vec3(0.0, 0.0, 0.0), vec3(1.0, 0.0, 0.0), vec3(1.0, 1.0, 0.0), vec3(1.0, 1.0, 1.0)]),
+        ColorPalette::Custom => gradient.sample(v / PALETTE_LENGTH),
     }
 }
 
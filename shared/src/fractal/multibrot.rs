@@ -3,10 +3,42 @@ use super::{escape_time_method::*, FractalParams};
 
 pub const ITERATION_COUNT: u32 = 1024;
 
-pub fn multibrot3<S: Scalar>(pos: Complex<S>, params: FractalParams<S>) -> EscapeResult
+/// Largest exponent that can be rendered with the perturbation
+pub const MAX_PERTURBATION_EXPONENT: u32 = 16;
+
+/// Largest bailout radius for which the squared modulus still fits in a `f32` after one more iteration
+pub fn bailout_radius(power: f32) -> f32
+{
+    if power > 2.25
+    {
+        pow(10.0, 18.0 / power)
+    }
+    else
+    {
+        DEFAULT_BAILOUT_RADIUS
+    }
+}
+
+/// The exponent as an integer, when it is a real integer that the perturbation supports
+pub fn integer_exponent<S: Scalar>(exponent: Complex<S>) -> Option<u32>
 {
-    compute_escape_time_fractal(pos, params, 1.0e6, Some(3.0), |z, c|
+    let re: f32 = exponent.re().as_();
+    let is_integer = exponent.im() == S::zero() && exponent.re().fract() == S::zero();
+    // Written without a range, the shaders can't take a reference to a local float
+    (is_integer && re >= 2.0 && re <= MAX_PERTURBATION_EXPONENT as f32).then_some(re as u32)
+}
+
+/// Iterates `z^exponent + c`, the exponent can be any complex number and changes continuously
+pub fn multibrot<S: Scalar>(pos: Complex<S>, params: FractalParams<S>) -> EscapeSample
+{
+    let exponent = params.exponent;
+
+    // The modulus grows with the real part of the exponent, the imaginary part only rotates
+    let power: f32 = exponent.re().as_();
+    let potential_power = if power > 1.0 { Some(power) } else { None };
+
+    compute_escape_time_fractal(pos, params, bailout_radius(power), potential_power, |z, c|
     {
-        (z.squared() * z) + c
+        z.complex_pow_const(exponent) + c
     })
 }
@@ -1,14 +1,367 @@
 #![allow(clippy::needless_range_loop)]
 
-use glam::{vec3, Vec3};
+use glam::{vec3, DVec2, Vec3, Vec4};
 use num_traits::AsPrimitive;
+#[cfg(feature = "bytemuck")]
+use bytemuck::NoUninit;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
 use crate::math::*;
 
-use super::{FractalParams, FractalVariation};
+use super::{palette_color, FractalParams, FractalVariation, PALETTE_LENGTH};
+use crate::gradient::Gradient;
 
 pub const ITERATION_COUNT: u32 = 128;
 
+/// Highest degree of the polynomials of [`super::FractalKind::NewtonPolynomial`]
+pub const MAX_DEGREE: usize = 8;
+
+/// A polynomial and its roots, which are found on the CPU with [`Polynomial::from_coefficients`]
+#[derive(Copy, Clone)]
+pub struct Polynomial<S: Scalar>
+{
+    /// Coefficients from the constant term to the term of highest degree
+    pub coefficients: [Complex<S>; MAX_DEGREE + 1],
+    pub roots: [Complex<S>; MAX_DEGREE],
+    pub degree: u32,
+}
+
+#[repr(C, align(16))]
+#[cfg_attr(feature = "bytemuck", derive(NoUninit))]
+#[derive(Copy, Clone)]
+pub struct Polynomial32
+{
+    // xy is the coefficient of degree i and zw the root i, arrays of 2D vectors are not aligned enough for uniform buffers
+    pub terms: [Vec4; MAX_DEGREE + 1],
+    pub degree: u32,
+    padding0: u32,
+    padding1: u32,
+    padding2: u32,
+}
+
+#[repr(C, align(16))]
+#[cfg_attr(feature = "bytemuck", derive(NoUninit))]
+// Only the coefficients are stored, the roots are found again when loading
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(from = "[Complex64; MAX_DEGREE + 1]", into = "[Complex64; MAX_DEGREE + 1]"))]
+#[derive(Copy, Clone)]
+pub struct Polynomial64
+{
+    // Stored as vectors since the arrays need Pod elements
+    pub coefficients: [DVec2; MAX_DEGREE + 1],
+    pub roots: [DVec2; MAX_DEGREE],
+    pub degree: u32,
+    padding0: u32,
+    padding1: u32,
+    padding2: u32,
+}
+
+impl Default for Polynomial64
+{
+    /// `z^3 - 2z + 2`, which has areas where the method doesn't converge
+    fn default() -> Self
+    {
+        let coefficients = [2.0, -2.0, 0.0, 1.0].map(|c| Complex64::new(c, 0.0));
+        Polynomial::<f64>::from_coefficients(&coefficients).into()
+    }
+}
+
+impl From<[Complex64; MAX_DEGREE + 1]> for Polynomial64
+{
+    fn from(coefficients: [Complex64; MAX_DEGREE + 1]) -> Self
+    {
+        Polynomial::<f64>::from_coefficients(&coefficients).into()
+    }
+}
+
+impl From<Polynomial64> for [Complex64; MAX_DEGREE + 1]
+{
+    fn from(value: Polynomial64) -> Self
+    {
+        let mut coefficients = [Complex64::ZERO; MAX_DEGREE + 1];
+        for i in 0..=MAX_DEGREE
+        {
+            coefficients[i] = Complex64::from_vector(value.coefficients[i]);
+        }
+        coefficients
+    }
+}
+
+impl Default for Polynomial32
+{
+    fn default() -> Self
+    {
+        Polynomial64::default().into()
+    }
+}
+
+impl From<Polynomial<f64>> for Polynomial64
+{
+    fn from(value: Polynomial<f64>) -> Self
+    {
+        let mut coefficients = [DVec2::ZERO; MAX_DEGREE + 1];
+        let mut roots = [DVec2::ZERO; MAX_DEGREE];
+        for i in 0..=MAX_DEGREE
+        {
+            coefficients[i] = value.coefficients[i].to_vector();
+            if i < MAX_DEGREE
+            {
+                roots[i] = value.roots[i].to_vector();
+            }
+        }
+
+        Self
+        {
+            coefficients,
+            roots,
+            degree: value.degree,
+            padding0: 0,
+            padding1: 0,
+            padding2: 0,
+        }
+    }
+}
+
+impl From<Polynomial64> for Polynomial32
+{
+    fn from(value: Polynomial64) -> Self
+    {
+        let mut terms = [Vec4::ZERO; MAX_DEGREE + 1];
+        for i in 0..=MAX_DEGREE
+        {
+            let root = if i < MAX_DEGREE { value.roots[i] } else { DVec2::ZERO };
+            terms[i] = value.coefficients[i].extend(root.x).extend(root.y).as_vec4();
+        }
+
+        Self
+        {
+            terms,
+            degree: value.degree,
+            padding0: 0,
+            padding1: 0,
+            padding2: 0,
+        }
+    }
+}
+
+// The shaders don't support array::map
+impl From<Polynomial32> for Polynomial<f32>
+{
+    fn from(value: Polynomial32) -> Self
+    {
+        let mut coefficients = [Complex32::ZERO; MAX_DEGREE + 1];
+        let mut roots = [Complex32::ZERO; MAX_DEGREE];
+        for i in 0..=MAX_DEGREE
+        {
+            let term = value.terms[i];
+            coefficients[i] = Complex32::new(term.x, term.y);
+            if i < MAX_DEGREE
+            {
+                roots[i] = Complex32::new(term.z, term.w);
+            }
+        }
+
+        Self
+        {
+            coefficients,
+            roots,
+            degree: value.degree,
+        }
+    }
+}
+
+impl From<Polynomial64> for Polynomial<f64>
+{
+    fn from(value: Polynomial64) -> Self
+    {
+        let mut coefficients = [Complex64::ZERO; MAX_DEGREE + 1];
+        let mut roots = [Complex64::ZERO; MAX_DEGREE];
+        for i in 0..=MAX_DEGREE
+        {
+            coefficients[i] = Complex64::from_vector(value.coefficients[i]);
+            if i < MAX_DEGREE
+            {
+                roots[i] = Complex64::from_vector(value.roots[i]);
+            }
+        }
+
+        Self
+        {
+            coefficients,
+            roots,
+            degree: value.degree,
+        }
+    }
+}
+
+impl From<Polynomial64> for Polynomial<DoubleDouble>
+{
+    fn from(value: Polynomial64) -> Self
+    {
+        let polynomial: Polynomial<f64> = value.into();
+        let mut coefficients = [ComplexDD::ZERO; MAX_DEGREE + 1];
+        let mut roots = [ComplexDD::ZERO; MAX_DEGREE];
+        for i in 0..=MAX_DEGREE
+        {
+            coefficients[i] = polynomial.coefficients[i].into();
+            if i < MAX_DEGREE
+            {
+                roots[i] = polynomial.roots[i].into();
+            }
+        }
+
+        Self
+        {
+            coefficients,
+            roots,
+            degree: value.degree,
+        }
+    }
+}
+
+const MAX_ROOT_ITERATIONS: u32 = 1000;
+
+impl<S: Scalar> Polynomial<S>
+{
+    /// Creates the polynomial from its coefficients, starting with the constant term, and finds its roots.
+    ///
+    /// The coefficients above [`MAX_DEGREE`] are ignored.
+    pub fn from_coefficients(coefficients: &[Complex<S>]) -> Self
+    {
+        let mut res = Self
+        {
+            coefficients: [Complex::<S>::ZERO; MAX_DEGREE + 1],
+            roots: [Complex::<S>::ZERO; MAX_DEGREE],
+            degree: 0,
+        };
+
+        for i in 0..coefficients.len().min(MAX_DEGREE + 1)
+        {
+            res.coefficients[i] = coefficients[i];
+            if coefficients[i] != Complex::<S>::ZERO
+            {
+                res.degree = i as u32;
+            }
+        }
+
+        res.find_roots();
+        res
+    }
+
+    pub fn evaluate(&self, z: Complex<S>) -> Complex<S>
+    {
+        let mut res = Complex::<S>::ZERO;
+        for i in (0..=self.degree as usize).rev()
+        {
+            res = res * z + self.coefficients[i];
+        }
+        res
+    }
+
+    pub fn evaluate_derivative(&self, z: Complex<S>) -> Complex<S>
+    {
+        let mut res = Complex::<S>::ZERO;
+        for i in (1..=self.degree as usize).rev()
+        {
+            res = res * z + self.coefficients[i] * Complex::<S>::from(i as u32);
+        }
+        res
+    }
+
+    // Durand-Kerner method, which refines the approximations of all the roots at the same time, returns the number of iterations
+    fn find_roots(&mut self) -> u32
+    {
+        let degree = self.degree as usize;
+        if degree == 0
+        {
+            return 0;
+        }
+
+        // The roots are within the Cauchy bound, the starting points are spread on that circle without symmetry with the real axis
+        let leading = self.coefficients[degree];
+        let mut bound = S::zero();
+        for i in 0..degree
+        {
+            bound = bound.max((self.coefficients[i] / leading).modulus());
+        }
+        let radius = S::one() + bound;
+        let angle: S = (core::f32::consts::TAU / degree as f32).into();
+        let offset: S = 0.4_f32.into();
+        for i in 0..degree
+        {
+            let index: S = (i as f32).into();
+            self.roots[i] = Complex::<S>::from_polar(radius, angle * index + offset);
+        }
+
+        // The simple roots converge quadratically, their error is about the square of the last change.
+        // The repeated roots converge linearly and can't be found more precisely than the square root of the precision anyway
+        let tolerance = Exp::sqrt(S::epsilon());
+        for iteration in 1..=MAX_ROOT_ITERATIONS
+        {
+            let mut max_change = S::zero();
+            for i in 0..degree
+            {
+                let mut denominator = leading;
+                for j in 0..degree
+                {
+                    if j != i
+                    {
+                        denominator *= self.roots[i] - self.roots[j];
+                    }
+                }
+
+                let delta = self.evaluate(self.roots[i]) / denominator;
+                self.roots[i] -= delta;
+                max_change = max_change.max(delta.modulus() / (S::one() + self.roots[i].modulus()));
+            }
+
+            if max_change < tolerance
+            {
+                return iteration;
+            }
+        }
+        MAX_ROOT_ITERATIONS
+    }
+}
+
+/// The polynomial as a function, to use with [`newton`]
+#[derive(Clone, Copy)]
+pub struct PolynomialFunction<'a, S: Scalar>(&'a Polynomial<S>);
+
+#[derive(Clone, Copy)]
+pub struct PolynomialDerivative<'a, S: Scalar>(&'a Polynomial<S>);
+
+impl<'a, S: Scalar> Function<Complex<S>> for PolynomialFunction<'a, S>
+{
+    type Output = Complex<S>;
+
+    fn get(&self, z: Complex<S>) -> Self::Output
+    {
+        self.0.evaluate(z)
+    }
+}
+
+impl<'a, S: Scalar> Differentiable<Complex<S>> for PolynomialFunction<'a, S>
+{
+    type Derivative = PolynomialDerivative<'a, S>;
+
+    fn derivative(&self) -> Func<Self::Derivative>
+    {
+        Func(PolynomialDerivative(self.0))
+    }
+}
+
+impl<'a, S: Scalar> Function<Complex<S>> for PolynomialDerivative<'a, S>
+{
+    type Output = Complex<S>;
+
+    fn get(&self, z: Complex<S>) -> Self::Output
+    {
+        self.0.evaluate_derivative(z)
+    }
+}
+
 pub fn newton3<S: Scalar>(pos: Complex<S>, params: FractalParams<S>) -> Vec3
 {
     // f(z) = z^3 - 1
@@ -18,23 +371,45 @@ pub fn newton3<S: Scalar>(pos: Complex<S>, params: FractalParams<S>) -> Vec3
         FractalVariation::Normal => (pos, ComplexNumber::ZERO),
         FractalVariation::JuliaSet => (params.secondary_pos, pos),
     };
-    newton::<S, _, 3>(params.iteration_limit, ComplexNumber::ONE, c, z,
-        [
-            ComplexNumber::from_complex32(Complex32::new(1.0, 0.0)),
-            ComplexNumber::from_complex32(Complex32::new(-0.5, 3.0f32.sqrt() / 2.0)),
-            ComplexNumber::from_complex32(Complex32::new(-0.5, -(3.0f32.sqrt()) / 2.0)),
-        ],
-        [
-            vec3(1.0, 0.0, 0.0),
-            vec3(0.0, 1.0, 0.0),
-            vec3(0.0, 0.0, 1.0),
-        ],
+    let roots: [Complex<S>; 3] = [
+        ComplexNumber::from_complex32(Complex32::new(1.0, 0.0)),
+        ComplexNumber::from_complex32(Complex32::new(-0.5, 3.0f32.sqrt() / 2.0)),
+        ComplexNumber::from_complex32(Complex32::new(-0.5, -(3.0f32.sqrt()) / 2.0)),
+    ];
+    let root_colors = [
+        vec3(1.0, 0.0, 0.0),
+        vec3(0.0, 1.0, 0.0),
+        vec3(0.0, 0.0, 1.0),
+    ];
+    newton::<S, _>(params.iteration_limit, ComplexNumber::ONE, c, z, roots.len(), |i| (roots[i], root_colors[i]),
     Func::make(|z| z.squared() * z - Complex::<S>::ONE),
     //|z| 3.0 * z.squared(),
     )
 }
 
-pub fn newton<S, F, const T: usize>(iteration_count: u32, a: Complex<S>, c: Complex<S>, z0: Complex<S>, roots: [Complex<S>; T], root_colors: [Vec3; T], function: Func<F>) -> Vec3
+/// Newton fractal of the polynomial of the parameters, its roots are spread on the color palette
+pub fn newton_polynomial<S: Scalar>(pos: Complex<S>, params: FractalParams<S>, gradient: &Gradient) -> Vec3
+{
+    let (z, c) = match params.variation
+    {
+        FractalVariation::Normal => (pos, ComplexNumber::ZERO),
+        FractalVariation::JuliaSet => (params.secondary_pos, pos),
+    };
+    let polynomial = &params.polynomial;
+    let degree = polynomial.degree as usize;
+    if degree == 0
+    {
+        return Vec3::ZERO;
+    }
+
+    let root = |i: usize| (polynomial.roots[i], palette_color(i as f32 * PALETTE_LENGTH / degree as f32, params.color_palette, gradient));
+    newton::<S, _>(params.iteration_limit, ComplexNumber::ONE, c, z, degree, root, Func(PolynomialFunction(polynomial)))
+}
+
+/// Applies the Newton method from `z0` and mixes the colors of the roots, weighted by their closeness to the result.
+///
+/// `root(i)` gives the root `i` and its color, the shaders can neither make slices of local arrays nor iterate over tuples.
+pub fn newton<S, F>(iteration_count: u32, a: Complex<S>, c: Complex<S>, z0: Complex<S>, root_count: usize, root: impl Fn(usize) -> (Complex<S>, Vec3), function: Func<F>) -> Vec3
 where
     S: Scalar,
     F: Function<Complex<S>, Output = Complex<S>> + Differentiable<Complex<S>>,
@@ -55,26 +430,114 @@ where
         }
     }
 
-    let mut dists = [S::zero(); T];
+    let mut sum = S::zero();
+    let mut color = Vec3::ZERO;
 
-    for i in 0..T
+    for i in 0..root_count
     {
-        dists[i] = (roots[i] - z).modulus_squared().inv().min(1.0e10_f32.into());
+        let (root, root_color) = root(i);
+        let weight = (root - z).modulus_squared().inv().min(1.0e10_f32.into());
+        sum += weight;
+        color += AsPrimitive::<f32>::as_(weight) * root_color;
     }
 
-    let mut sum = S::zero();
+    color / AsPrimitive::<f32>::as_(sum)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    // The coefficients of the product of `z - root`, starting with the constant term
+    fn coefficients_of_roots<S: Scalar>(roots: &[Complex<S>]) -> [Complex<S>; MAX_DEGREE + 1]
+    {
+        let mut res = [Complex::<S>::ZERO; MAX_DEGREE + 1];
+        res[0] = Complex::<S>::ONE;
+        for (degree, &root) in roots.iter().enumerate()
+        {
+            for i in (0..=degree + 1).rev()
+            {
+                let shifted = if i > 0 { res[i - 1] } else { Complex::<S>::ZERO };
+                res[i] = shifted - res[i] * root;
+            }
+        }
+        res
+    }
 
-    for i in 0..T
+    // Every expected root is found, as many times as its multiplicity
+    fn assert_roots<S: Scalar>(polynomial: &Polynomial<S>, expected: &[Complex<S>], tolerance: S)
     {
-        sum += dists[i];
+        assert_eq!(polynomial.degree as usize, expected.len());
+        let mut found = [false; MAX_DEGREE];
+        for &root in expected
+        {
+            let i = (0..expected.len())
+                .find(|&i| !found[i] && polynomial.roots[i].fuzzy_eq(root, tolerance))
+                .expect("root not found");
+            found[i] = true;
+        }
     }
 
-    let mut color = Vec3::ZERO;
+    #[test]
+    fn simple_roots()
+    {
+        let roots = [
+            Complex64::new(1.0, 0.0),
+            Complex64::new(-2.0, 0.0),
+            Complex64::new(0.0, 1.0),
+            Complex64::new(0.5, 0.5),
+            Complex64::new(-0.3, -1.7),
+        ];
+        let mut polynomial = Polynomial::<f64>::from_coefficients(&coefficients_of_roots::<f64>(&roots));
+        assert_roots(&polynomial, &roots, 1.0e-13);
+        assert!(polynomial.find_roots() < 50);
+    }
+
+    #[test]
+    fn real_coefficients()
+    {
+        // z^3 - 2z + 2 has a real root and a pair of conjugate roots
+        let polynomial: Polynomial<f64> = Polynomial64::default().into();
+        assert_eq!(polynomial.degree, 3);
+        for &root in &polynomial.roots[..3]
+        {
+            assert!(polynomial.evaluate(root).modulus() < 1.0e-13);
+            assert!(polynomial.roots[..3].iter().any(|&other| other.fuzzy_eq(root.conjugate(), 1.0e-13)));
+        }
+    }
 
-    for i in 0..T
+    #[test]
+    fn repeated_roots()
     {
-        color += AsPrimitive::<f32>::as_(dists[i] / sum) * root_colors[i]
+        // Roots of multiplicity m can only be found to about the m-th root of the precision
+        let (one, minus_one) = (Complex64::new(1.0, 0.0), Complex64::new(-1.0, 0.0));
+        let mut polynomial = Polynomial::<f64>::from_coefficients(&coefficients_of_roots::<f64>(&[one, one, minus_one]));
+        assert_roots(&polynomial, &[one, one, minus_one], 1.0e-7);
+        assert!(polynomial.find_roots() < 50);
+
+        let i = Complex64::new(0.0, 1.0);
+        let polynomial = Polynomial::<f64>::from_coefficients(&coefficients_of_roots::<f64>(&[i, i, i, -i]));
+        assert_roots(&polynomial, &[i, i, i, -i], 1.0e-4);
     }
 
-    color
-}
\ No newline at end of file
+    #[test]
+    fn single_precision_converges()
+    {
+        let roots = [Complex32::new(1.0, 0.0), Complex32::new(-2.0, 0.0), Complex32::new(0.0, 1.0), Complex32::new(0.5, 0.5)];
+        let mut polynomial = Polynomial::<f32>::from_coefficients(&coefficients_of_roots::<f32>(&roots));
+        assert_roots(&polynomial, &roots, 1.0e-5);
+        // The tolerance depends on the precision, it can be reached in single precision too
+        assert!(polynomial.find_roots() < 50);
+    }
+
+    #[test]
+    fn constant_and_linear()
+    {
+        let polynomial = Polynomial::<f64>::from_coefficients(&[Complex64::new(3.0, 0.0)]);
+        assert_eq!(polynomial.degree, 0);
+
+        let polynomial = Polynomial::<f64>::from_coefficients(&[Complex64::new(3.0, 1.0), Complex64::new(2.0, 0.0)]);
+        assert_roots(&polynomial, &[Complex64::new(-1.5, -0.5)], 1.0e-15);
+    }
+}
@@ -0,0 +1,399 @@
+use crate::math::*;
+use super::{escape_time_method::*, multibrot, FractalKind, FractalParams, FractalParams64, FractalVariation, RenderTechnique};
+
+/// Returns true if the fractal can be rendered with [`compute_perturbation`]
+pub fn supports_perturbation(params: &FractalParams64) -> bool
+{
+    // The derivative of the orbit isn't perturbed, the normals and the distance estimate can't be computed
+    if matches!(params.render_technique, RenderTechnique::NormalMap | RenderTechnique::DistanceEstimate)
+    {
+        return false;
+    }
+
+    match params.fractal_kind
+    {
+        FractalKind::MandelbrotSet | FractalKind::Tricorn | FractalKind::BurningShip => true,
+        FractalKind::Multibrot => multibrot::integer_exponent::<f64>(params.exponent).is_some(),
+        _ => false,
+    }
+}
+
+/// The power of `z` in the iteration, which is 2 except for the multibrot
+pub(super) fn power<S: Scalar>(fractal_kind: FractalKind, exponent: Complex<S>) -> u32
+{
+    match fractal_kind
+    {
+        FractalKind::Multibrot => multibrot::integer_exponent::<S>(exponent).unwrap_or(2),
+        _ => 2,
+    }
+}
+
+pub(super) fn bailout_and_potential_power(power: u32) -> (f32, f32)
+{
+    (multibrot::bailout_radius(power as f32), power as f32)
+}
+
+#[derive(Copy, Clone)]
+struct ComplexFixed<const N: usize>
+{
+    re: Fixed<N>,
+    im: Fixed<N>,
+}
+
+impl<const N: usize> ComplexFixed<N>
+{
+    fn from_complex64(value: Complex64) -> Self
+    {
+        Self { re: Fixed::from_f64(value.re()), im: Fixed::from_f64(value.im()) }
+    }
+
+    fn to_complex64(self) -> Complex64
+    {
+        Complex64::new(self.re.to_f64(), self.im.to_f64())
+    }
+
+    fn add(self, rhs: Self) -> Self
+    {
+        Self { re: self.re + rhs.re, im: self.im + rhs.im }
+    }
+
+    fn mul(self, rhs: Self) -> Self
+    {
+        Self
+        {
+            re: self.re * rhs.re - self.im * rhs.im,
+            im: self.re * rhs.im + self.im * rhs.re,
+        }
+    }
+
+    fn squared(self) -> Self
+    {
+        let re_im = self.re * self.im;
+        Self { re: self.re.squared() - self.im.squared(), im: re_im + re_im }
+    }
+}
+
+fn reference_step<const N: usize>(fractal_kind: FractalKind, power: u32, z: ComplexFixed<N>, c: ComplexFixed<N>) -> ComplexFixed<N>
+{
+    match fractal_kind
+    {
+        FractalKind::Multibrot => (1..power).fold(z, |res, _| res.mul(z)).add(c),
+        FractalKind::Tricorn => ComplexFixed { re: z.re, im: -z.im }.squared().add(c),
+        FractalKind::BurningShip => ComplexFixed { re: z.re.abs(), im: z.im.abs() }.squared().add(c),
+        _ => z.squared().add(c),
+    }
+}
+
+/// Computes the orbit of the reference point `(re, im)` with `N` limbs of precision and stores it in `orbit`.
+///
+/// The orbit stops one iteration after escaping or when `orbit` is full, the number of stored values is returned.
+pub fn compute_reference_orbit<const N: usize>(re: Fixed<N>, im: Fixed<N>, params: &FractalParams64, orbit: &mut [Complex64]) -> usize
+{
+    let pos = ComplexFixed { re, im };
+    let (mut z, c) = match params.variation
+    {
+        FractalVariation::Normal => (ComplexFixed { re: Fixed::ZERO, im: Fixed::ZERO }, pos),
+        FractalVariation::JuliaSet => (pos, ComplexFixed::from_complex64(params.secondary_pos)),
+    };
+
+    let power = power::<f64>(params.fractal_kind, params.exponent);
+    let (bailout_radius, _) = bailout_and_potential_power(power);
+    let bailout_squared = (bailout_radius as f64) * (bailout_radius as f64);
+
+    let Some(first) = orbit.first_mut()
+    else
+    {
+        return 0;
+    };
+    *first = z.to_complex64();
+
+    for (i, value) in orbit.iter_mut().enumerate().skip(1)
+    {
+        z = reference_step(params.fractal_kind, power, z, c);
+        *value = z.to_complex64();
+
+        if value.modulus_squared() > bailout_squared
+        {
+            return i + 1;
+        }
+    }
+
+    orbit.len()
+}
+
+// |a + b| - |a|, without cancellation
+fn diff_abs<S: Scalar>(a: S, b: S) -> S
+{
+    let zero = S::zero();
+    let two: S = 2.0_f32.into();
+    if a >= zero
+    {
+        if a + b >= zero { b } else { -(two * a + b) }
+    }
+    else if a + b > zero
+    {
+        two * a + b
+    }
+    else
+    {
+        -b
+    }
+}
+
+pub(super) fn perturbation_step<S: Scalar>(fractal_kind: FractalKind, power: u32, reference: Complex<S>, dz: Complex<S>, dc: Complex<S>) -> Complex<S>
+{
+    let two: S = 2.0_f32.into();
+    match fractal_kind
+    {
+        FractalKind::Multibrot => multibrot_difference::<S>(power, reference, dz) + dc,
+        // conj(Z + dz)^2 - conj(Z)^2 = conj(2 Z dz + dz^2)
+        FractalKind::Tricorn => (reference * dz * two + dz.squared()).conjugate() + dc,
+        FractalKind::BurningShip =>
+        {
+            let (x, y) = (reference.re(), reference.im());
+            let (dx, dy) = (dz.re(), dz.im());
+            let re = (two * x + dx) * dx - (two * y + dy) * dy;
+            let im = two * diff_abs(x * y, x * dy + dx * y + dx * dy);
+            Complex::<S>::from_cartesian(re, im) + dc
+        },
+        _ => reference * dz * two + dz.squared() + dc,
+    }
+}
+
+// (Z + dz)^n - Z^n = dz * sum(binomial(n, k) Z^(n-k) dz^(k-1)) for k in 1..=n, evaluated with the Horner method
+fn multibrot_difference<S: Scalar>(power: u32, reference: Complex<S>, dz: Complex<S>) -> Complex<S>
+{
+    let mut res = Complex::<S>::ONE;
+    let mut reference_power = Complex::<S>::ONE;
+    let mut binomial = 1.0_f32;
+    for k in (1..power).rev()
+    {
+        binomial = binomial * (k + 1) as f32 / (power - k) as f32;
+        reference_power *= reference;
+        res = res * dz + reference_power * Complex::<S>::from(binomial);
+    }
+    dz * res
+}
+
+/// Computes the escape time of the point at `delta` from the reference point whose orbit is given.
+///
+/// Only the low precision difference with the reference orbit is iterated, starting after the iterations skipped by `series`.
+/// When the orbit gets closer to zero than the difference, or when the end of the reference orbit is reached,
+/// the difference is rebased on the start of the reference orbit, which avoids the glitches of the classic method.
+pub fn compute_perturbation<S: Scalar>(delta: Complex<S>, params: FractalParams<S>, orbit: &[Complex<S>], series: &SeriesApproximation<S>) -> EscapeResult
+{
+    if orbit.len() < 2
+    {
+        return EscapeResult::StayedInside;
+    }
+
+    let (mut dz, dc) = match params.variation
+    {
+        FractalVariation::Normal => (Complex::<S>::ZERO, delta),
+        FractalVariation::JuliaSet => (delta, Complex::<S>::ZERO),
+    };
+
+    // Start where the series approximation stops being valid
+    let mut n = series.skipped_iterations as usize;
+    if n > 0
+    {
+        dz = series.evaluate(delta);
+    }
+
+    let power = power::<S>(params.fractal_kind, params.exponent);
+    let (bailout_radius, potential_power) = bailout_and_potential_power(power);
+    let bailout_squared: S = (bailout_radius * bailout_radius).into();
+
+    // The orbit of the reference starts at 0 in the normal variation, its first iteration is its constant
+    let c = match params.variation
+    {
+        FractalVariation::Normal => orbit[1] + delta,
+        FractalVariation::JuliaSet => params.secondary_pos,
+    };
+    let mut average = OrbitAverage::new(params.render_technique, c.to_complex32());
+    let mut approach = OrbitTrapApproach::<S>::new(params.orbit_trap);
+
+    for i in (series.skipped_iterations + 1)..=params.iteration_limit
+    {
+        let z = orbit[n] + dz;
+        let length_squared = z.modulus_squared();
+
+        match params.render_technique
+        {
+            RenderTechnique::OrbitTrap =>
+            {
+                // z is the value after i - 1 iterations
+                if i > 1
+                {
+                    approach.add(i - 1, z);
+                }
+                if length_squared > bailout_squared
+                {
+                    return EscapeResult::Escaped(approach.value());
+                }
+            },
+            RenderTechnique::StripeAverage | RenderTechnique::CurvatureAverage | RenderTechnique::TriangleInequalityAverage =>
+            {
+                // z is the value after i - 1 iterations, the average starts after the first one like with the classic method
+                if i > 1
+                {
+                    average.add(z.to_complex32());
+                }
+                if length_squared > bailout_squared
+                {
+                    return EscapeResult::Escaped(average.escaped_value(length_squared.as_(), bailout_radius, Some(potential_power)));
+                }
+            },
+            _ =>
+            {
+                if length_squared > bailout_squared
+                {
+                    return EscapeResult::Escaped(smooth_iteration_count(i, length_squared, Some(potential_power)));
+                }
+            },
+        }
+
+        if n + 1 >= orbit.len() || length_squared < dz.modulus_squared()
+        {
+            dz = z - orbit[0];
+            n = 0;
+        }
+
+        dz = perturbation_step::<S>(params.fractal_kind, power, orbit[n], dz, dc);
+        n += 1;
+    }
+
+    match params.render_technique
+    {
+        RenderTechnique::OrbitTrap => EscapeResult::Escaped(approach.value()),
+        _ => EscapeResult::StayedInside,
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::formula::Formula64;
+    use crate::fractal::compute_escape_result;
+
+    const KINDS: [FractalKind; 4] = [FractalKind::MandelbrotSet, FractalKind::Multibrot, FractalKind::Tricorn, FractalKind::BurningShip];
+
+    fn params(fractal_kind: FractalKind) -> FractalParams64
+    {
+        FractalParams64 { fractal_kind, iteration_limit: 1000, ..Default::default() }
+    }
+
+    // The iteration computed directly, the multibrot has the default exponent of 3
+    fn iterate(fractal_kind: FractalKind, z: Complex64, c: Complex64) -> Complex64
+    {
+        match fractal_kind
+        {
+            FractalKind::Multibrot => z * z * z + c,
+            FractalKind::Tricorn => z.conjugate().squared() + c,
+            FractalKind::BurningShip => Complex64::new(z.re().abs(), z.im().abs()).squared() + c,
+            _ => z.squared() + c,
+        }
+    }
+
+    #[test]
+    fn techniques_using_the_derivative_are_not_supported()
+    {
+        for fractal_kind in KINDS
+        {
+            assert!(supports_perturbation(&params(fractal_kind)));
+            for render_technique in [RenderTechnique::NormalMap, RenderTechnique::DistanceEstimate]
+            {
+                assert!(!supports_perturbation(&FractalParams64 { render_technique, ..params(fractal_kind) }));
+            }
+        }
+    }
+
+    fn escape_value(res: EscapeResult) -> Option<f32>
+    {
+        match res
+        {
+            EscapeResult::Escaped(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    fn direct(pos: Complex64, params: FractalParams64) -> Option<f32>
+    {
+        escape_value(compute_escape_result::<f64>(pos, params.into(), &Formula64::default()).result)
+    }
+
+    fn perturbed(reference: Complex64, delta: Complex64, params: FractalParams64) -> Option<f32>
+    {
+        let mut orbit = [Complex64::ZERO; 1001];
+        let length = compute_reference_orbit::<4>(Fixed::from_f64(reference.re()), Fixed::from_f64(reference.im()), &params, &mut orbit);
+        escape_value(compute_perturbation::<f64>(delta, params.into(), &orbit[..length], &SeriesApproximation::none()))
+    }
+
+    #[test]
+    fn step_is_the_difference_of_the_iterations()
+    {
+        let c = Complex64::new(-0.6, 0.3);
+        let dc = Complex64::new(-5.0e-4, 1.0e-3);
+        // The second reference is close to the real axis, the burning ship difference changes sign there
+        for reference in [Complex64::new(0.3, -0.4), Complex64::new(-0.2, 5.0e-4)]
+        {
+            for dz in [Complex64::new(1.0e-3, -2.0e-3), Complex64::new(-3.0e-4, 1.0e-3)]
+            {
+                for kind in KINDS
+                {
+                    let expected = iterate(kind, reference + dz, c + dc) - iterate(kind, reference, c);
+                    let power = power::<f64>(kind, params(kind).exponent);
+                    let step = perturbation_step::<f64>(kind, power, reference, dz, dc);
+                    assert!(step.fuzzy_eq(expected, 1.0e-15), "{kind:?}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn reference_orbit_stops_after_escaping()
+    {
+        let params = params(FractalKind::MandelbrotSet);
+        let mut orbit = [Complex64::ZERO; 100];
+        let length = compute_reference_orbit::<4>(Fixed::from_f64(1.0), Fixed::ZERO, &params, &mut orbit);
+        // 0, 1, 2, 5, 26, 677, ... until |z| is above the bailout radius
+        assert!(orbit[..4] == [Complex64::ZERO, Complex64::ONE, Complex64::new(2.0, 0.0), Complex64::new(5.0, 0.0)]);
+        assert!(orbit[length - 1].modulus() > DEFAULT_BAILOUT_RADIUS as f64);
+        assert!(orbit[length - 2].modulus() <= DEFAULT_BAILOUT_RADIUS as f64);
+
+        // A point inside fills the orbit
+        assert_eq!(compute_reference_orbit::<4>(Fixed::from_f64(-0.1), Fixed::ZERO, &params, &mut orbit), orbit.len());
+    }
+
+    #[test]
+    fn perturbation_matches_the_direct_iteration()
+    {
+        let reference = Complex64::new(-0.75, 0.12);
+        for kind in KINDS
+        {
+            let params = params(kind);
+            for delta in [Complex64::new(1.0e-3, 0.0), Complex64::new(0.0, -2.0e-3), Complex64::new(-1.0e-3, 1.0e-3)]
+            {
+                let (expected, value) = (direct(reference + delta, params), perturbed(reference, delta, params));
+                let (expected, value) = (expected.unwrap(), value.unwrap());
+                assert!((expected - value).abs() < 1.0e-3 * expected, "{kind:?}: {expected} {value}");
+            }
+        }
+    }
+
+    #[test]
+    fn rebasing_continues_after_the_end_of_the_reference()
+    {
+        let params = params(FractalKind::MandelbrotSet);
+        // The reference escapes after a few iterations, the points near it are iterated past the end of its orbit
+        let reference = Complex64::new(0.5, 0.0);
+        let pos = Complex64::new(0.26, 0.0);
+        let (expected, value) = (direct(pos, params).unwrap(), perturbed(reference, pos - reference, params).unwrap());
+        assert!(expected > 20.0);
+        assert!((expected - value).abs() < 1.0e-3 * expected, "{expected} {value}");
+
+        // And the ones inside stay inside
+        assert_eq!(perturbed(reference, Complex64::new(-0.6, 0.1) - reference, params), None);
+    }
+}
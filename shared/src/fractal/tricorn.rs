@@ -49,7 +49,7 @@ impl<C: ComplexNumber> Function<C> for DConjugate
 }
 
 
-pub fn tricorn<S: Scalar>(pos: Complex<S>, params: FractalParams<S>) -> EscapeResult
+pub fn tricorn<S: Scalar>(pos: Complex<S>, params: FractalParams<S>) -> EscapeSample
 {
     compute_escape_time_fractal(pos, params, DEFAULT_BAILOUT_RADIUS, Some(2.0), |z, c|
     {
@@ -0,0 +1,244 @@
+//! Color gradients defined at runtime, used by [`crate::fractal::ColorPalette::Custom`].
+//!
+//! The gradient is uploaded to the shaders in a uniform buffer, like the formula of the custom fractal.
+
+use crate::math::*;
+use glam::{vec3, Vec3, Vec4};
+#[cfg(feature = "bytemuck")]
+use bytemuck::NoUninit;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Maximum number of stops of a gradient
+pub const MAX_GRADIENT_STOPS: usize = 16;
+
+/// How the colors are blended between two stops
+#[repr(u32)]
+#[cfg_attr(feature = "bytemuck", derive(NoUninit))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum GradientInterpolation
+{
+    Linear,
+    /// Eases in and out of each stop, like the built-in palettes
+    Smooth,
+    /// Keeps the color of a stop until the next one
+    Constant,
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Copy, Clone, PartialEq)]
+pub struct GradientStop
+{
+    /// sRGB color
+    pub color: Vec3,
+    /// Position in the gradient, between 0 and 1
+    pub position: f32,
+}
+
+impl GradientStop
+{
+    pub fn new(position: f32, color: Vec3) -> Self
+    {
+        Self { color, position }
+    }
+}
+
+/// Gradient repeating with a period of 1, the last stop blends back into the first one
+#[repr(C)]
+#[cfg_attr(feature = "bytemuck", derive(NoUninit))]
+#[derive(Copy, Clone, PartialEq)]
+pub struct Gradient
+{
+    // Stored as vectors since the arrays need Pod elements, with the color in xyz and the position in w.
+    // Sorted by position, only the first `stop_count` are used
+    stops: [Vec4; MAX_GRADIENT_STOPS],
+    pub stop_count: u32,
+    pub interpolation: GradientInterpolation,
+    padding0: u32,
+    padding1: u32,
+}
+
+impl Default for Gradient
+{
+    /// The colors of [`crate::fractal::ColorPalette::Default`]
+    fn default() -> Self
+    {
+        Self::new(&[
+            GradientStop::new(0.0 / 6.0, vec3(1.0, 0.5, 0.0)),
+            GradientStop::new(1.0 / 6.0, vec3(0.5, 0.0, 1.0)),
+            GradientStop::new(2.0 / 6.0, vec3(0.0, 0.0, 1.0)),
+            GradientStop::new(3.0 / 6.0, vec3(0.0, 1.0, 1.0)),
+            GradientStop::new(4.0 / 6.0, vec3(1.0, 1.0, 1.0)),
+            GradientStop::new(5.0 / 6.0, vec3(1.0, 1.0, 0.0)),
+        ], GradientInterpolation::Smooth)
+    }
+}
+
+impl Gradient
+{
+    /// Sorts the stops by position, the positions are clamped between 0 and 1 and the stops after [`MAX_GRADIENT_STOPS`] are ignored
+    pub fn new(stops: &[GradientStop], interpolation: GradientInterpolation) -> Self
+    {
+        let mut sorted_stops = [GradientStop::new(0.0, Vec3::ZERO); MAX_GRADIENT_STOPS];
+        let stop_count = stops.len().min(MAX_GRADIENT_STOPS);
+        for (stop, new_stop) in sorted_stops.iter_mut().zip(stops)
+        {
+            *stop = GradientStop::new(new_stop.position.clamp(0.0, 1.0), new_stop.color.clamp(Vec3::ZERO, Vec3::ONE));
+        }
+        sorted_stops[..stop_count].sort_unstable_by(|a, b| a.position.total_cmp(&b.position));
+
+        let mut res = Self
+        {
+            stops: [Vec4::ZERO; MAX_GRADIENT_STOPS],
+            stop_count: stop_count as u32,
+            interpolation,
+            padding0: 0,
+            padding1: 0,
+        };
+
+        for (stop, sorted_stop) in res.stops.iter_mut().zip(sorted_stops)
+        {
+            *stop = sorted_stop.color.extend(sorted_stop.position);
+        }
+
+        res
+    }
+
+    pub fn stop(&self, index: usize) -> GradientStop
+    {
+        let stop = self.stops[index];
+        GradientStop::new(stop.w, stop.truncate())
+    }
+
+    /// The stops sorted by position
+    pub fn stops(&self) -> impl Iterator<Item = GradientStop> + '_
+    {
+        (0..self.stop_count as usize).map(|i| self.stop(i))
+    }
+
+    /// The color at `t`, the gradient repeats every unit
+    pub fn sample(&self, t: f32) -> Vec3
+    {
+        let count = self.stop_count as usize;
+        if count == 0
+        {
+            return Vec3::ZERO;
+        }
+
+        let t = rem_euclid(t, 1.0);
+
+        // The segment starts at the last stop before t, before the first stop it starts at the last one
+        let mut start = count - 1;
+        for i in 0..count
+        {
+            if self.stops[i].w > t
+            {
+                break;
+            }
+            start = i;
+        }
+        let end = if start + 1 == count { 0 } else { start + 1 };
+
+        let (start, end) = (self.stop(start), self.stop(end));
+        let mut length = end.position - start.position;
+        if length <= 0.0
+        {
+            length += 1.0;
+        }
+        let mut offset = t - start.position;
+        if offset < 0.0
+        {
+            offset += 1.0;
+        }
+
+        let f = (offset / length).clamp(0.0, 1.0);
+        let f = match self.interpolation
+        {
+            GradientInterpolation::Linear => f,
+            GradientInterpolation::Smooth => f*f * (3.0 - 2.0*f),
+            GradientInterpolation::Constant => 0.0,
+        };
+
+        start.color + (end.color - start.color) * f
+    }
+}
+
+// Written as the interpolation and the list of the used stops
+#[cfg(feature = "serde")]
+impl Serialize for Gradient
+{
+    fn serialize<Ser: serde::Serializer>(&self, serializer: Ser) -> Result<Ser::Ok, Ser::Error>
+    {
+        use serde::ser::SerializeStruct;
+
+        struct Stops<'a>(&'a Gradient);
+
+        impl Serialize for Stops<'_>
+        {
+            fn serialize<Ser: serde::Serializer>(&self, serializer: Ser) -> Result<Ser::Ok, Ser::Error>
+            {
+                serializer.collect_seq(self.0.stops())
+            }
+        }
+
+        let mut state = serializer.serialize_struct("Gradient", 2)?;
+        state.serialize_field("interpolation", &self.interpolation)?;
+        state.serialize_field("stops", &Stops(self))?;
+        state.end()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for Gradient
+{
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error>
+    {
+        struct Stops([GradientStop; MAX_GRADIENT_STOPS], usize);
+
+        impl<'de> Deserialize<'de> for Stops
+        {
+            fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error>
+            {
+                struct Visitor;
+
+                impl<'de> serde::de::Visitor<'de> for Visitor
+                {
+                    type Value = Stops;
+
+                    fn expecting(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result
+                    {
+                        write!(f, "a list of at most {MAX_GRADIENT_STOPS} stops")
+                    }
+
+                    fn visit_seq<A: serde::de::SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error>
+                    {
+                        let mut stops = Stops([GradientStop::new(0.0, Vec3::ZERO); MAX_GRADIENT_STOPS], 0);
+                        while let Some(stop) = seq.next_element()?
+                        {
+                            if stops.1 == MAX_GRADIENT_STOPS
+                            {
+                                return Err(serde::de::Error::invalid_length(stops.1 + 1, &self));
+                            }
+                            stops.0[stops.1] = stop;
+                            stops.1 += 1;
+                        }
+                        Ok(stops)
+                    }
+                }
+
+                deserializer.deserialize_seq(Visitor)
+            }
+        }
+
+        #[derive(Deserialize)]
+        struct Description
+        {
+            interpolation: GradientInterpolation,
+            stops: Stops,
+        }
+
+        let description = Description::deserialize(deserializer)?;
+        Ok(Self::new(&description.stops.0[..description.stops.1], description.interpolation))
+    }
+}
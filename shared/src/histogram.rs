@@ -0,0 +1,247 @@
+//! Histogram coloring, used by [`crate::fractal::ColorMapping::Histogram`].
+//!
+//! Every cell has a [`CellHistogram`] of its escape values, the histograms of the visible cells are merged in a [`Histogram`]
+//! whose ranks are uploaded to the shaders in a texture, with its range of keys in [`crate::render::ColoringUniforms`].
+
+use crate::math::*;
+use crate::compute::EscapeData;
+use glam::{vec2, Vec2};
+
+/// Number of bins of the histogram of a cell, over the range of the values of the cell
+pub const CELL_HISTOGRAM_BINS: usize = 256;
+/// Number of bins of the merged histogram, over the range of the values of every visible cell
+pub const HISTOGRAM_BINS: usize = 1024;
+
+/// The values are binned by their logarithm, the iteration counts spread over several orders of magnitude
+fn histogram_key(v: f32) -> f32
+{
+    ln(1.0 + v.max(0.0))
+}
+
+/// Distribution of the escape values of the pixels of a cell
+#[derive(Clone)]
+pub struct CellHistogram
+{
+    min_key: f32,
+    max_key: f32,
+    counts: [u32; CELL_HISTOGRAM_BINS],
+}
+
+impl CellHistogram
+{
+    /// The histogram of the pixels that escaped
+    pub fn new(data: impl Iterator<Item = EscapeData> + Clone) -> Self
+    {
+        let keys = data.filter(|data| data.coverage > 0.0).map(|data| histogram_key(data.value));
+
+        let mut res = Self
+        {
+            min_key: f32::INFINITY,
+            max_key: f32::NEG_INFINITY,
+            counts: [0; CELL_HISTOGRAM_BINS],
+        };
+        for key in keys.clone()
+        {
+            res.min_key = res.min_key.min(key);
+            res.max_key = res.max_key.max(key);
+        }
+
+        for key in keys
+        {
+            let bin = ((key - res.min_key) / (res.max_key - res.min_key) * CELL_HISTOGRAM_BINS as f32) as usize;
+            res.counts[bin.min(CELL_HISTOGRAM_BINS - 1)] += 1;
+        }
+
+        res
+    }
+
+    /// Whether no pixel escaped
+    pub fn is_empty(&self) -> bool
+    {
+        self.min_key > self.max_key
+    }
+
+    /// The key at the center of each bin, with the number of pixels in it
+    fn bins(&self) -> impl Iterator<Item = (f32, u32)> + '_
+    {
+        let bin_size = (self.max_key - self.min_key) / CELL_HISTOGRAM_BINS as f32;
+        self.counts.iter().enumerate().map(move |(i, count)| (self.min_key + (i as f32 + 0.5) * bin_size, *count))
+    }
+}
+
+/// The bin of a histogram over `key_range` containing `v`, with the position of `v` inside it between 0 and 1
+pub fn histogram_bin(key_range: Vec2, v: f32) -> (usize, f32)
+{
+    let range = key_range.y - key_range.x;
+    if range <= 0.0
+    {
+        // Every value has the rank of the first bin
+        return (0, 1.0);
+    }
+
+    let position = ((histogram_key(v) - key_range.x) / range * HISTOGRAM_BINS as f32).clamp(0.0, HISTOGRAM_BINS as f32);
+    let bin = floor(position).min(HISTOGRAM_BINS as f32 - 1.0);
+    (bin as usize, position - bin)
+}
+
+/// Cumulative distribution of the escape values, the colors are spread by the rank of the values in it
+#[derive(Copy, Clone)]
+pub struct Histogram
+{
+    min_key: f32,
+    max_key: f32,
+    /// Fraction of the values in the bins up to each one included
+    ranks: [f32; HISTOGRAM_BINS],
+}
+
+impl Default for Histogram
+{
+    /// Every value has a rank of 0
+    fn default() -> Self
+    {
+        Self
+        {
+            min_key: 0.0,
+            max_key: 0.0,
+            ranks: [0.0; HISTOGRAM_BINS],
+        }
+    }
+}
+
+impl Histogram
+{
+    /// Merges the histograms of the cells, the bins of the cells are added to the bin containing their center
+    pub fn merge(cells: &[&CellHistogram]) -> Self
+    {
+        let cells = cells.iter().filter(|cell| !cell.is_empty());
+
+        let min_key = cells.clone().map(|cell| cell.min_key).fold(f32::INFINITY, f32::min);
+        let max_key = cells.clone().map(|cell| cell.max_key).fold(f32::NEG_INFINITY, f32::max);
+        if min_key > max_key
+        {
+            return Self::default();
+        }
+
+        let mut res = Self
+        {
+            min_key,
+            max_key,
+            ..Self::default()
+        };
+
+        let mut total = 0.0;
+        for (key, count) in cells.flat_map(|cell| cell.bins())
+        {
+            // A single value is put in the first bin, like in `histogram_bin`
+            let bin = if max_key > min_key { ((key - min_key) / (max_key - min_key) * HISTOGRAM_BINS as f32) as usize } else { 0 };
+            let bin = bin.min(HISTOGRAM_BINS - 1);
+            res.ranks[bin] += count as f32;
+            total += count as f32;
+        }
+
+        let mut cumulative = 0.0;
+        for rank in &mut res.ranks
+        {
+            cumulative += *rank;
+            *rank = cumulative / total;
+        }
+
+        res
+    }
+
+    /// Range of the keys covered by the bins
+    pub fn key_range(&self) -> Vec2
+    {
+        vec2(self.min_key, self.max_key)
+    }
+
+    /// Fraction of the values in the bins up to each one included
+    pub fn ranks(&self) -> &[f32; HISTOGRAM_BINS]
+    {
+        &self.ranks
+    }
+
+    /// Fraction of the values below `v`, interpolated inside its bin
+    pub fn rank(&self, v: f32) -> f32
+    {
+        let (bin, t) = histogram_bin(self.key_range(), v);
+        let start = if bin == 0 { 0.0 } else { self.ranks[bin - 1] };
+        start + (self.ranks[bin] - start) * t
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn escaped(value: f32) -> EscapeData
+    {
+        EscapeData { value, coverage: 1.0, interior_value: 0.0, interior_coverage: 0.0 }
+    }
+
+    fn cell(values: &[f32]) -> CellHistogram
+    {
+        CellHistogram::new(values.iter().map(|&value| escaped(value)))
+    }
+
+    #[test]
+    fn ranks_increase_with_the_values()
+    {
+        let first: std::vec::Vec<f32> = (0..500).map(|i| i as f32 * 0.1).collect();
+        let second: std::vec::Vec<f32> = (0..300).map(|i| 20.0 + (i * i) as f32).collect();
+        let histogram = Histogram::merge(&[&cell(&first), &cell(&second)]);
+
+        let mut previous = 0.0;
+        for i in 0..2000
+        {
+            let rank = histogram.rank(i as f32 * 50.0);
+            assert!(rank >= previous, "{i}");
+            previous = rank;
+        }
+        assert_eq!(histogram.rank(0.0), 0.0);
+        assert_eq!(histogram.rank(1.0e6), 1.0);
+        assert_eq!(histogram.ranks()[HISTOGRAM_BINS - 1], 1.0);
+
+        // The first cell has more values and they are smaller, they take most of the ranks
+        let median = histogram.rank(49.9);
+        assert!((median - 500.0 / 800.0).abs() < 0.02, "{median}");
+    }
+
+    #[test]
+    fn single_value()
+    {
+        let cell = cell(&[5.0; 20]);
+        assert!(!cell.is_empty());
+        let histogram = Histogram::merge(&[&cell]);
+        assert_eq!(histogram.key_range().x, histogram.key_range().y);
+        assert_eq!(histogram.rank(5.0), 1.0);
+        assert_eq!(histogram.rank(100.0), 1.0);
+    }
+
+    #[test]
+    fn empty_cells_are_ignored()
+    {
+        let inside = EscapeData { value: 0.0, coverage: 0.0, interior_value: 1.0, interior_coverage: 1.0 };
+        let empty = CellHistogram::new([inside; 10].into_iter());
+        assert!(empty.is_empty());
+        assert_eq!(Histogram::merge(&[&empty]).rank(3.0), 0.0);
+
+        let values = [1.0, 2.0, 3.0, 4.0];
+        let (with_empty, without) = (Histogram::merge(&[&empty, &cell(&values), &empty]), Histogram::merge(&[&cell(&values)]));
+        assert_eq!(with_empty.key_range(), without.key_range());
+        assert_eq!(with_empty.ranks(), without.ranks());
+    }
+
+    #[test]
+    fn bins_over_the_range()
+    {
+        let range = glam::vec2(histogram_key(1.0), histogram_key(9.0));
+        assert_eq!(histogram_bin(range, 1.0), (0, 0.0));
+        assert_eq!(histogram_bin(range, 0.0).0, 0);
+        assert_eq!(histogram_bin(range, 9.0), (HISTOGRAM_BINS - 1, 1.0));
+        assert_eq!(histogram_bin(range, 1.0e3), (HISTOGRAM_BINS - 1, 1.0));
+        let (bin, t) = histogram_bin(range, 3.0);
+        assert!(bin > 0 && bin < HISTOGRAM_BINS - 1 && (0.0..1.0).contains(&t));
+    }
+}
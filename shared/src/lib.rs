@@ -3,8 +3,14 @@
 
 #![cfg_attr(feature = "libm", feature(repr_simd))]
 
+#[cfg(feature = "std")]
+extern crate std;
+
 pub mod math;
 pub mod fractal;
 pub mod render;
 pub mod compute;
 pub mod color;
+pub mod formula;
+pub mod gradient;
+pub mod histogram;
@@ -2,6 +2,8 @@ use core::ops::*;
 use core::iter::{Product, Sum};
 #[cfg(feature = "bytemuck")]
 use bytemuck::NoUninit;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 use num_traits::{Zero, One, Inv};
 
 use super::function::{IntoFunc, Constant};
@@ -71,9 +73,44 @@ pub trait ComplexNumber:
     fn to_complex64(self) -> Complex64;
 }
 
+/// Shared implementation of [`Exp::pow`] for the complex numbers.
+///
+/// Non-integer powers use the principal branch of the logarithm, whose cut is along the negative real axis.
+/// Real integer powers don't have a branch cut and are computed with multiplications, which is faster and more precise.
+pub(crate) fn complex_pow<C: ComplexNumber>(base: C, exp: C) -> C
+{
+    let zero = C::Scalar::zero();
+    let n: f32 = num_traits::AsPrimitive::as_(exp.re());
+    if exp.im() == zero && num_traits::Float::fract(exp.re()) == zero && super::abs(n) <= 64.0
+    {
+        let mut res = C::ONE;
+        let mut square = base;
+        let mut exponent = super::abs(n) as u32;
+        while exponent > 0
+        {
+            if exponent & 1 == 1
+            {
+                res *= square;
+            }
+            square = square.squared();
+            exponent >>= 1;
+        }
+        return if n < 0.0 { res.inv() } else { res };
+    }
+
+    // The logarithm of 0 is not defined, but the limit is 0 for exponents with a positive real part
+    if base == C::ZERO
+    {
+        return if exp.re() > zero { C::ZERO } else { C::ONE / C::ZERO };
+    }
+
+    (exp * base.ln()).exp()
+}
+
 #[cfg_attr(not(feature = "libm"), repr(C))]
 #[cfg_attr(feature = "libm", repr(simd))]
 #[cfg_attr(feature = "bytemuck", derive(NoUninit))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[derive(Clone, Copy, PartialEq)]
 pub struct Complex64(f64, f64);
 
@@ -447,7 +484,7 @@ impl Exp for Complex64
 
         let modulus = self.modulus();
         let sgn = if self.im() < 0.0 { -1.0 } else { 1.0 };
-        Self::new((modulus + self.re()) / 2.0, sgn * (modulus - self.re()) / 2.0)
+        Self::new(Exp::sqrt((modulus + self.re()) / 2.0), sgn * Exp::sqrt((modulus - self.re()) / 2.0))
     }
 
     fn exp(self) -> Self
@@ -464,7 +501,7 @@ impl Exp for Complex64
 
     fn pow(self, exp: Self) -> Self
     {
-        (exp * self.ln()).exp()
+        complex_pow(self, exp)
     }
 
     fn ln(self) -> Self
@@ -872,7 +909,7 @@ impl super::Exp for Complex32
 
         let modulus = self.modulus();
         let sgn = if self.im() < 0.0 { -1.0 } else { 1.0 };
-        Self::new((modulus + self.re()) / 2.0, sgn * (modulus - self.re()) / 2.0)
+        Self::new(Exp::sqrt((modulus + self.re()) / 2.0), sgn * Exp::sqrt((modulus - self.re()) / 2.0))
     }
 
     fn exp(self) -> Self
@@ -882,7 +919,7 @@ impl super::Exp for Complex32
 
     fn pow(self, exp: Self) -> Self
     {
-        (exp * self.ln()).exp()
+        complex_pow(self, exp)
     }
 
     fn ln(self) -> Self
@@ -910,3 +947,64 @@ impl Trigo for Complex32
         Self::new(cos * self.im().cosh(), -sin * self.im().sinh())
     }
 }
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn c(re: f64, im: f64) -> Complex64
+    {
+        Complex64::new(re, im)
+    }
+
+    #[test]
+    fn integer_powers_are_exact()
+    {
+        let base = c(1.0, 1.0);
+        assert!(base.pow(c(0.0, 0.0)) == Complex64::ONE);
+        assert!(base.pow(c(2.0, 0.0)) == c(0.0, 2.0));
+        assert!(base.pow(c(8.0, 0.0)) == c(16.0, 0.0));
+        assert!(base.pow(c(-2.0, 0.0)) == c(0.0, -0.5));
+        assert!(base.pow(c(64.0, 0.0)) == c(2.0_f64.powi(32), 0.0));
+        assert!(Complex32::new(1.0, 1.0).pow(Complex32::new(8.0, 0.0)) == Complex32::new(16.0, 0.0));
+
+        // Above the fast path the logarithm is used
+        let expected = c(2.0_f64.powi(32), 2.0_f64.powi(32));
+        let res = base.pow(c(65.0, 0.0));
+        assert!(res.fuzzy_eq(expected, 1.0e-12 * expected.modulus()));
+        assert!(res != expected);
+    }
+
+    #[test]
+    fn integer_powers_have_no_branch_cut()
+    {
+        assert!(c(-2.0, 0.0).pow(c(3.0, 0.0)) == c(-8.0, 0.0));
+        assert!(c(-2.0, 0.0).pow(c(-1.0, 0.0)) == c(-0.5, 0.0));
+        assert!(c(-2.0, 1.0e-12).pow(c(2.0, 0.0)).fuzzy_eq(c(-2.0, -1.0e-12).pow(c(2.0, 0.0)).conjugate(), 1.0e-15));
+    }
+
+    #[test]
+    fn other_powers_use_the_principal_branch()
+    {
+        let i = c(0.0, 1.0);
+        assert!(i.pow(i).fuzzy_eq(c((-core::f64::consts::FRAC_PI_2).exp(), 0.0), 1.0e-15));
+        assert!(c(3.0, 4.0).pow(c(0.5, 0.0)).fuzzy_eq(c(2.0, 1.0), 1.0e-15));
+        assert!(c(-4.0, 0.0).pow(c(0.5, 0.0)).fuzzy_eq(c(0.0, 2.0), 1.0e-15));
+
+        // The square root jumps across the negative real axis
+        assert!(c(-1.0, 1.0e-12).pow(c(0.5, 0.0)).fuzzy_eq(i, 1.0e-9));
+        assert!(c(-1.0, -1.0e-12).pow(c(0.5, 0.0)).fuzzy_eq(-i, 1.0e-9));
+    }
+
+    #[test]
+    fn powers_of_zero()
+    {
+        let zero = Complex64::ZERO;
+        assert!(zero.pow(c(0.0, 0.0)) == Complex64::ONE);
+        assert!(zero.pow(c(3.0, 0.0)) == zero);
+        assert!(zero.pow(c(0.5, 0.0)) == zero);
+        assert!(zero.pow(c(1.0, 2.0)) == zero);
+        assert!(!zero.pow(c(-0.5, 0.0)).re().is_finite());
+    }
+}
@@ -0,0 +1,1684 @@
+use core::cmp::Ordering;
+use core::iter::{Product, Sum};
+use core::num::FpCategory;
+use core::ops::*;
+use core::str::FromStr;
+use glam::DVec2;
+use num_traits::{AsPrimitive, Float, Inv, Num, NumCast, One, Pow, ToPrimitive, Zero};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use super::{Complex32, Complex64, ComplexNumber, Scalar, Vector};
+
+/// Software floating point number made of two `f64`, with about 106 bits of mantissa
+///
+/// The value is the unevaluated sum `hi + lo`, where `lo` is smaller than half an ulp of `hi`.
+/// It is much slower than `f64`, and only meant for the CPU compute path,
+/// to render the generic fractals at zooms where an `f64` runs out of precision.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct DoubleDouble
+{
+    hi: f64,
+    lo: f64,
+}
+
+// Splits an f64 in two halves of 26 bits, so that their products are exact
+const SPLITTER: f64 = 134217729.0; // 2^27 + 1
+// Relative precision at which the series stop
+const SERIES_EPSILON: f64 = 1.0e-33;
+const MAX_SERIES_TERMS: u32 = 40;
+
+fn two_sum(a: f64, b: f64) -> (f64, f64)
+{
+    let s = a + b;
+    let bb = s - a;
+    (s, (a - (s - bb)) + (b - bb))
+}
+
+// Requires |a| >= |b|
+fn quick_two_sum(a: f64, b: f64) -> (f64, f64)
+{
+    let s = a + b;
+    (s, b - (s - a))
+}
+
+fn split(a: f64) -> (f64, f64)
+{
+    let t = SPLITTER * a;
+    let hi = t - (t - a);
+    (hi, a - hi)
+}
+
+fn two_prod(a: f64, b: f64) -> (f64, f64)
+{
+    let p = a * b;
+    let (a_hi, a_lo) = split(a);
+    let (b_hi, b_lo) = split(b);
+    (p, ((a_hi * b_hi - p) + a_hi * b_lo + a_lo * b_hi) + a_lo * b_lo)
+}
+
+// 2^exponent, for exponents in the normal range
+fn pow2(exponent: i32) -> f64
+{
+    f64::from_bits(((exponent + 1023) as u64) << 52)
+}
+
+impl DoubleDouble
+{
+    pub const ZERO: Self = Self::from_f64(0.0);
+    pub const ONE: Self = Self::from_f64(1.0);
+    pub const LN_2: Self = Self { hi: core::f64::consts::LN_2, lo: 2.3190468138462996e-17 };
+    pub const LN_10: Self = Self { hi: core::f64::consts::LN_10, lo: -2.1707562233822494e-16 };
+    pub const PI: Self = Self { hi: core::f64::consts::PI, lo: 1.2246467991473532e-16 };
+    pub const FRAC_PI_2: Self = Self { hi: core::f64::consts::FRAC_PI_2, lo: 6.123233995736766e-17 };
+
+    pub const fn from_f64(value: f64) -> Self
+    {
+        Self { hi: value, lo: 0.0 }
+    }
+
+    /// Creates the number `hi + lo`
+    pub fn new(hi: f64, lo: f64) -> Self
+    {
+        let (hi, lo) = two_sum(hi, lo);
+        Self::normalized(hi, lo)
+    }
+
+    /// The closest `f64` to the number
+    pub fn hi(self) -> f64
+    {
+        self.hi
+    }
+
+    /// The remainder of the number that doesn't fit in [`DoubleDouble::hi`]
+    pub fn lo(self) -> f64
+    {
+        self.lo
+    }
+
+    fn normalized(hi: f64, lo: f64) -> Self
+    {
+        if !hi.is_finite()
+        {
+            return Self::from_f64(hi);
+        }
+
+        let (hi, lo) = quick_two_sum(hi, lo);
+        Self { hi, lo }
+    }
+
+    fn from_i64(value: i64) -> Self
+    {
+        let hi = value as f64;
+        // The rounding error of hi is small enough to be exact in an f64
+        Self::new(hi, (value as i128 - hi as i128) as f64)
+    }
+
+    fn from_u64(value: u64) -> Self
+    {
+        let hi = value as f64;
+        Self::new(hi, (value as i128 - hi as i128) as f64)
+    }
+
+    fn mul_f64(self, rhs: f64) -> Self
+    {
+        let (p, e) = two_prod(self.hi, rhs);
+        Self::normalized(p, e + self.lo * rhs)
+    }
+
+    fn div_f64(self, rhs: f64) -> Self
+    {
+        let q1 = self.hi / rhs;
+        let (p, e) = two_prod(q1, rhs);
+        let (s, f) = two_sum(self.hi, -p);
+        let q2 = (s + (f - e + self.lo)) / rhs;
+        Self::normalized(q1, q2)
+    }
+
+    /// Multiplies by `2^exponent`, which is exact
+    fn mul_pow2(self, mut exponent: i32) -> Self
+    {
+        let mut value = self;
+        while exponent > 1000
+        {
+            value = Self { hi: value.hi * pow2(1000), lo: value.lo * pow2(1000) };
+            exponent -= 1000;
+        }
+        while exponent < -1000
+        {
+            value = Self { hi: value.hi * pow2(-1000), lo: value.lo * pow2(-1000) };
+            exponent += 1000;
+        }
+
+        Self { hi: value.hi * pow2(exponent), lo: value.lo * pow2(exponent) }
+    }
+
+    // exp(x) - 1 with a taylor series, for small x
+    fn exp_m1_series(self) -> Self
+    {
+        let mut term = self;
+        let mut sum = self;
+        for n in 2..MAX_SERIES_TERMS
+        {
+            term = (term * self).div_f64(n as f64);
+            sum += term;
+            if term.hi.abs() <= sum.hi.abs() * SERIES_EPSILON
+            {
+                break;
+            }
+        }
+
+        sum
+    }
+
+    // sin(x) and cos(x) with taylor series, for |x| <= pi/4
+    fn sin_cos_series(self) -> (Self, Self)
+    {
+        let x2 = self * self;
+
+        let mut term = self;
+        let mut sin = self;
+        let mut n = 1.0;
+        for _ in 0..MAX_SERIES_TERMS
+        {
+            term = -(term * x2).div_f64((n + 1.0) * (n + 2.0));
+            sin += term;
+            n += 2.0;
+            if term.hi.abs() <= sin.hi.abs() * SERIES_EPSILON
+            {
+                break;
+            }
+        }
+
+        let mut term = Self::ONE;
+        let mut cos = Self::ONE;
+        let mut n = 0.0;
+        for _ in 0..MAX_SERIES_TERMS
+        {
+            term = -(term * x2).div_f64((n + 1.0) * (n + 2.0));
+            cos += term;
+            n += 2.0;
+            if term.hi.abs() <= SERIES_EPSILON
+            {
+                break;
+            }
+        }
+
+        (sin, cos)
+    }
+}
+
+impl From<f32> for DoubleDouble
+{
+    fn from(value: f32) -> Self
+    {
+        Self::from_f64(value as f64)
+    }
+}
+
+impl From<f64> for DoubleDouble
+{
+    fn from(value: f64) -> Self
+    {
+        Self::from_f64(value)
+    }
+}
+
+impl From<u32> for DoubleDouble
+{
+    fn from(value: u32) -> Self
+    {
+        Self::from_f64(value as f64)
+    }
+}
+
+impl From<i32> for DoubleDouble
+{
+    fn from(value: i32) -> Self
+    {
+        Self::from_f64(value as f64)
+    }
+}
+
+impl From<u64> for DoubleDouble
+{
+    fn from(value: u64) -> Self
+    {
+        Self::from_u64(value)
+    }
+}
+
+impl From<i64> for DoubleDouble
+{
+    fn from(value: i64) -> Self
+    {
+        Self::from_i64(value)
+    }
+}
+
+impl PartialOrd for DoubleDouble
+{
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering>
+    {
+        match self.hi.partial_cmp(&other.hi)?
+        {
+            Ordering::Equal => self.lo.partial_cmp(&other.lo),
+            ordering => Some(ordering),
+        }
+    }
+}
+
+impl Neg for DoubleDouble
+{
+    type Output = Self;
+
+    fn neg(self) -> Self
+    {
+        Self { hi: -self.hi, lo: -self.lo }
+    }
+}
+
+impl Add for DoubleDouble
+{
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self
+    {
+        let (s1, s2) = two_sum(self.hi, rhs.hi);
+        let (t1, t2) = two_sum(self.lo, rhs.lo);
+        if !s1.is_finite()
+        {
+            return Self::from_f64(s1);
+        }
+
+        let (s1, s2) = quick_two_sum(s1, s2 + t1);
+        Self::normalized(s1, s2 + t2)
+    }
+}
+
+impl Sub for DoubleDouble
+{
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self
+    {
+        self + -rhs
+    }
+}
+
+impl Mul for DoubleDouble
+{
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self
+    {
+        let (p, e) = two_prod(self.hi, rhs.hi);
+        Self::normalized(p, e + (self.hi * rhs.lo + self.lo * rhs.hi))
+    }
+}
+
+impl Div for DoubleDouble
+{
+    type Output = Self;
+
+    fn div(self, rhs: Self) -> Self
+    {
+        let q1 = self.hi / rhs.hi;
+        if !q1.is_finite()
+        {
+            return Self::from_f64(q1);
+        }
+
+        let r = self - rhs.mul_f64(q1);
+        let q2 = r.hi / rhs.hi;
+        let r = r - rhs.mul_f64(q2);
+        let q3 = r.hi / rhs.hi;
+
+        Self::normalized(q1, q2) + Self::from_f64(q3)
+    }
+}
+
+impl Rem for DoubleDouble
+{
+    type Output = Self;
+
+    fn rem(self, rhs: Self) -> Self
+    {
+        self - rhs * (self / rhs).trunc()
+    }
+}
+
+macro_rules! double_double_assign_op
+{
+    ($assign_trait:ident, $assign_fn:ident, $op:tt) =>
+    {
+        impl $assign_trait for DoubleDouble
+        {
+            fn $assign_fn(&mut self, rhs: Self)
+            {
+                *self = *self $op rhs;
+            }
+        }
+    };
+}
+
+double_double_assign_op!(AddAssign, add_assign, +);
+double_double_assign_op!(SubAssign, sub_assign, -);
+double_double_assign_op!(MulAssign, mul_assign, *);
+double_double_assign_op!(DivAssign, div_assign, /);
+double_double_assign_op!(RemAssign, rem_assign, %);
+
+impl Inv for DoubleDouble
+{
+    type Output = Self;
+
+    fn inv(self) -> Self
+    {
+        self.recip()
+    }
+}
+
+impl Pow<DoubleDouble> for DoubleDouble
+{
+    type Output = Self;
+
+    fn pow(self, rhs: Self) -> Self
+    {
+        self.powf(rhs)
+    }
+}
+
+impl Zero for DoubleDouble
+{
+    fn zero() -> Self
+    {
+        Self::ZERO
+    }
+
+    fn is_zero(&self) -> bool
+    {
+        self.hi == 0.0
+    }
+}
+
+impl One for DoubleDouble
+{
+    fn one() -> Self
+    {
+        Self::ONE
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ParseDoubleDoubleError;
+
+impl core::fmt::Display for ParseDoubleDoubleError
+{
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result
+    {
+        f.write_str("invalid double-double literal")
+    }
+}
+
+impl FromStr for DoubleDouble
+{
+    type Err = ParseDoubleDoubleError;
+
+    /// Parses a decimal number such as `-1.25e-3`, keeping all the precision of the double-double
+    fn from_str(s: &str) -> Result<Self, Self::Err>
+    {
+        let (negative, s) = match s.as_bytes().first()
+        {
+            Some(b'-') => (true, &s[1..]),
+            Some(b'+') => (false, &s[1..]),
+            _ => (false, s),
+        };
+
+        let (mantissa, exponent) = match s.find(['e', 'E'])
+        {
+            Some(index) => (&s[..index], s[index + 1..].parse::<i32>().map_err(|_| ParseDoubleDoubleError)?),
+            None => (s, 0),
+        };
+
+        let mut value = Self::ZERO;
+        let mut digit_count = 0;
+        let mut fractional_digits = 0;
+        let mut seen_point = false;
+        for c in mantissa.chars()
+        {
+            match c
+            {
+                '0'..='9' =>
+                {
+                    value = value.mul_f64(10.0) + Self::from_f64((c as u8 - b'0') as f64);
+                    digit_count += 1;
+                    if seen_point
+                    {
+                        fractional_digits += 1;
+                    }
+                },
+                '.' if !seen_point => seen_point = true,
+                _ => return Err(ParseDoubleDoubleError),
+            }
+        }
+
+        if digit_count == 0
+        {
+            return Err(ParseDoubleDoubleError);
+        }
+
+        let exponent = exponent.saturating_sub(fractional_digits);
+        let scale = Self::from_f64(10.0).powi(exponent.saturating_abs());
+        let value = if exponent < 0 { value / scale } else { value * scale };
+
+        Ok(if negative { -value } else { value })
+    }
+}
+
+// Most significant digits written by the Display implementation, enough to read back every bit
+const MAX_DISPLAY_DIGITS: usize = 34;
+
+impl core::fmt::Display for DoubleDouble
+{
+    /// Writes the number in scientific notation such as `-1.25e-3`, which [`DoubleDouble::from_str`] reads back
+    ///
+    /// The precision of the formatter is the number of significant digits, 32 by default.
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result
+    {
+        // The low part is meaningless outside of the normal range of an f64
+        if !self.hi.is_normal()
+        {
+            return core::fmt::Display::fmt(&self.hi, f);
+        }
+
+        let digit_count = f.precision().unwrap_or(32).clamp(1, MAX_DISPLAY_DIGITS);
+
+        // Scales the number to [1, 10)
+        let mut exponent = Float::floor(Float::log10(self.hi.abs())) as i32;
+        let scale = Self::from_f64(10.0).powi(exponent.saturating_abs());
+        let mut value = if exponent < 0 { self.abs() * scale } else { self.abs() / scale };
+        if value.hi >= 10.0
+        {
+            value = value.div_f64(10.0);
+            exponent += 1;
+        }
+        else if value.hi < 1.0
+        {
+            value = value.mul_f64(10.0);
+            exponent -= 1;
+        }
+
+        // One more digit than written, to round the last one
+        let mut digits = [0u8; MAX_DISPLAY_DIGITS + 1];
+        for digit in digits.iter_mut().take(digit_count + 1)
+        {
+            let integer = Float::floor(value).hi.clamp(0.0, 9.0);
+            *digit = integer as u8;
+            value = (value - Self::from_f64(integer)).mul_f64(10.0);
+        }
+
+        if digits[digit_count] >= 5
+        {
+            let mut i = digit_count;
+            loop
+            {
+                if i == 0
+                {
+                    // All the digits were 9
+                    digits[0] = 1;
+                    exponent += 1;
+                    break;
+                }
+
+                i -= 1;
+                if digits[i] == 9
+                {
+                    digits[i] = 0;
+                }
+                else
+                {
+                    digits[i] += 1;
+                    break;
+                }
+            }
+        }
+
+        let fractional_count = digits[1..digit_count].iter().rposition(|&digit| digit != 0).map_or(0, |i| i + 1);
+
+        if self.hi < 0.0
+        {
+            f.write_str("-")?;
+        }
+        write!(f, "{}", digits[0])?;
+        if fractional_count > 0
+        {
+            f.write_str(".")?;
+            for &digit in &digits[1..=fractional_count]
+            {
+                write!(f, "{}", digit)?;
+            }
+        }
+        if exponent != 0
+        {
+            write!(f, "e{}", exponent)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Num for DoubleDouble
+{
+    type FromStrRadixErr = ParseDoubleDoubleError;
+
+    fn from_str_radix(str: &str, radix: u32) -> Result<Self, Self::FromStrRadixErr>
+    {
+        if radix == 10
+        {
+            str.parse()
+        }
+        else
+        {
+            f64::from_str_radix(str, radix).map(Self::from_f64).map_err(|_| ParseDoubleDoubleError)
+        }
+    }
+}
+
+impl ToPrimitive for DoubleDouble
+{
+    fn to_i64(&self) -> Option<i64>
+    {
+        let value = self.trunc();
+        value.hi.to_i64()?.checked_add(value.lo as i64)
+    }
+
+    fn to_u64(&self) -> Option<u64>
+    {
+        let value = self.trunc();
+        value.hi.to_u64()?.checked_add_signed(value.lo as i64)
+    }
+
+    fn to_f32(&self) -> Option<f32>
+    {
+        Some(self.hi as f32)
+    }
+
+    fn to_f64(&self) -> Option<f64>
+    {
+        Some(self.hi)
+    }
+}
+
+impl NumCast for DoubleDouble
+{
+    fn from<T: ToPrimitive>(n: T) -> Option<Self>
+    {
+        let value = n.to_f64()?;
+        // Large integers don't fit in an f64
+        if value.abs() >= 9007199254740992.0
+        {
+            if let Some(value) = n.to_i64()
+            {
+                return Some(Self::from_i64(value));
+            }
+            if let Some(value) = n.to_u64()
+            {
+                return Some(Self::from_u64(value));
+            }
+        }
+
+        Some(Self::from_f64(value))
+    }
+}
+
+impl AsPrimitive<f32> for DoubleDouble
+{
+    fn as_(self) -> f32
+    {
+        self.hi as f32
+    }
+}
+
+impl AsPrimitive<f64> for DoubleDouble
+{
+    fn as_(self) -> f64
+    {
+        self.hi
+    }
+}
+
+impl AsPrimitive<DoubleDouble> for u32
+{
+    fn as_(self) -> DoubleDouble
+    {
+        self.into()
+    }
+}
+
+impl Float for DoubleDouble
+{
+    fn nan() -> Self
+    {
+        Self::from_f64(f64::NAN)
+    }
+
+    fn infinity() -> Self
+    {
+        Self::from_f64(f64::INFINITY)
+    }
+
+    fn neg_infinity() -> Self
+    {
+        Self::from_f64(f64::NEG_INFINITY)
+    }
+
+    fn neg_zero() -> Self
+    {
+        Self::from_f64(-0.0)
+    }
+
+    fn min_value() -> Self
+    {
+        Self::from_f64(f64::MIN)
+    }
+
+    fn min_positive_value() -> Self
+    {
+        Self::from_f64(f64::MIN_POSITIVE)
+    }
+
+    fn epsilon() -> Self
+    {
+        Self::from_f64(4.93038065763132e-32) // 2^-104
+    }
+
+    fn max_value() -> Self
+    {
+        Self::from_f64(f64::MAX)
+    }
+
+    fn is_nan(self) -> bool
+    {
+        self.hi.is_nan()
+    }
+
+    fn is_infinite(self) -> bool
+    {
+        self.hi.is_infinite()
+    }
+
+    fn is_finite(self) -> bool
+    {
+        self.hi.is_finite()
+    }
+
+    fn is_normal(self) -> bool
+    {
+        self.hi.is_normal()
+    }
+
+    fn classify(self) -> FpCategory
+    {
+        self.hi.classify()
+    }
+
+    fn floor(self) -> Self
+    {
+        let hi = Float::floor(self.hi);
+        if hi == self.hi
+        {
+            Self::normalized(hi, Float::floor(self.lo))
+        }
+        else
+        {
+            Self::from_f64(hi)
+        }
+    }
+
+    fn ceil(self) -> Self
+    {
+        let hi = Float::ceil(self.hi);
+        if hi == self.hi
+        {
+            Self::normalized(hi, Float::ceil(self.lo))
+        }
+        else
+        {
+            Self::from_f64(hi)
+        }
+    }
+
+    fn round(self) -> Self
+    {
+        // Rounds half away from zero, like f64
+        if self.hi >= 0.0
+        {
+            (self + Self::from_f64(0.5)).floor()
+        }
+        else
+        {
+            -(-self + Self::from_f64(0.5)).floor()
+        }
+    }
+
+    fn trunc(self) -> Self
+    {
+        if self.hi >= 0.0 { self.floor() } else { self.ceil() }
+    }
+
+    fn fract(self) -> Self
+    {
+        self - self.trunc()
+    }
+
+    fn abs(self) -> Self
+    {
+        if self.hi < 0.0 { -self } else { self }
+    }
+
+    fn signum(self) -> Self
+    {
+        Self::from_f64(Float::signum(self.hi))
+    }
+
+    fn is_sign_positive(self) -> bool
+    {
+        self.hi.is_sign_positive()
+    }
+
+    fn is_sign_negative(self) -> bool
+    {
+        self.hi.is_sign_negative()
+    }
+
+    fn mul_add(self, a: Self, b: Self) -> Self
+    {
+        self * a + b
+    }
+
+    fn recip(self) -> Self
+    {
+        Self::ONE / self
+    }
+
+    fn powi(self, n: i32) -> Self
+    {
+        let mut base = self;
+        let mut exponent = n.unsigned_abs();
+        let mut result = Self::ONE;
+        while exponent > 0
+        {
+            if exponent & 1 != 0
+            {
+                result *= base;
+            }
+            base *= base;
+            exponent >>= 1;
+        }
+
+        if n < 0 { result.recip() } else { result }
+    }
+
+    fn powf(self, n: Self) -> Self
+    {
+        if n.fract().is_zero() && n.abs().hi < i32::MAX as f64
+        {
+            return self.powi(n.hi as i32);
+        }
+
+        (n * self.ln()).exp()
+    }
+
+    fn sqrt(self) -> Self
+    {
+        if self.hi <= 0.0
+        {
+            return if self.hi == 0.0 { Self::ZERO } else { Self::nan() };
+        }
+        if self.hi.is_infinite()
+        {
+            return self;
+        }
+
+        // One Newton iteration from the f64 approximation doubles the precision
+        let root = Float::sqrt(self.hi);
+        let (p, e) = two_prod(root, root);
+        let residual = self - Self { hi: p, lo: e };
+        let (hi, lo) = two_sum(root, residual.hi / (2.0 * root));
+        Self::normalized(hi, lo)
+    }
+
+    fn exp(self) -> Self
+    {
+        if self.hi > 709.79
+        {
+            return Self::infinity();
+        }
+        if self.hi < -745.2
+        {
+            return Self::ZERO;
+        }
+        if self.is_nan()
+        {
+            return self;
+        }
+
+        // exp(x) = 2^k * exp(r)^1024 with x = k ln(2) + 1024 r
+        let k = Float::round(self.hi / Self::LN_2.hi);
+        let r = (self - Self::LN_2.mul_f64(k)).mul_pow2(-10);
+
+        let mut s = r.exp_m1_series();
+        for _ in 0..10
+        {
+            // (1 + s)^2 - 1
+            s = s * s + s.mul_pow2(1);
+        }
+
+        (s + Self::ONE).mul_pow2(k as i32)
+    }
+
+    fn exp2(self) -> Self
+    {
+        (self * Self::LN_2).exp()
+    }
+
+    fn ln(self) -> Self
+    {
+        if self.hi <= 0.0
+        {
+            return if self.hi == 0.0 { Self::neg_infinity() } else { Self::nan() };
+        }
+        if !self.is_finite()
+        {
+            return self;
+        }
+
+        // One Newton iteration on exp(y) = x from the f64 approximation
+        let y = Self::from_f64(Float::ln(self.hi));
+        y + self * (-y).exp() - Self::ONE
+    }
+
+    fn log(self, base: Self) -> Self
+    {
+        self.ln() / base.ln()
+    }
+
+    fn log2(self) -> Self
+    {
+        self.ln() / Self::LN_2
+    }
+
+    fn log10(self) -> Self
+    {
+        self.ln() / Self::LN_10
+    }
+
+    fn max(self, other: Self) -> Self
+    {
+        if self.is_nan() || other > self { other } else { self }
+    }
+
+    fn min(self, other: Self) -> Self
+    {
+        if self.is_nan() || other < self { other } else { self }
+    }
+
+    fn abs_sub(self, other: Self) -> Self
+    {
+        (self - other).max(Self::ZERO)
+    }
+
+    fn cbrt(self) -> Self
+    {
+        if self.is_zero() || !self.is_finite()
+        {
+            return self;
+        }
+
+        let y = Self::from_f64(Float::cbrt(self.hi));
+        y - (y * y * y - self) / (y * y).mul_f64(3.0)
+    }
+
+    fn hypot(self, other: Self) -> Self
+    {
+        (self * self + other * other).sqrt()
+    }
+
+    fn sin(self) -> Self
+    {
+        self.sin_cos().0
+    }
+
+    fn cos(self) -> Self
+    {
+        self.sin_cos().1
+    }
+
+    fn tan(self) -> Self
+    {
+        let (sin, cos) = self.sin_cos();
+        sin / cos
+    }
+
+    fn asin(self) -> Self
+    {
+        self.atan2((Self::ONE - self * self).sqrt())
+    }
+
+    fn acos(self) -> Self
+    {
+        (Self::ONE - self * self).sqrt().atan2(self)
+    }
+
+    fn atan(self) -> Self
+    {
+        self.atan2(Self::ONE)
+    }
+
+    fn atan2(self, other: Self) -> Self
+    {
+        if self.is_zero() && other.is_zero()
+        {
+            return Self::ZERO;
+        }
+
+        // One Newton iteration from the f64 approximation, the angle of (other, self) rotated by -angle is the correction
+        let angle = Self::from_f64(Float::atan2(self.hi, other.hi));
+        let (sin, cos) = angle.sin_cos();
+        angle + (self * cos - other * sin) / (other * cos + self * sin)
+    }
+
+    fn sin_cos(self) -> (Self, Self)
+    {
+        if !self.is_finite()
+        {
+            return (Self::nan(), Self::nan());
+        }
+
+        // Reduce to [-pi/4, pi/4] and use the symmetries for each quadrant
+        let quadrant = Float::round(self.hi / Self::FRAC_PI_2.hi);
+        let r = self - Self::FRAC_PI_2.mul_f64(quadrant);
+        let (sin, cos) = r.sin_cos_series();
+
+        match (quadrant as i64).rem_euclid(4)
+        {
+            0 => (sin, cos),
+            1 => (cos, -sin),
+            2 => (-sin, -cos),
+            _ => (-cos, sin),
+        }
+    }
+
+    fn exp_m1(self) -> Self
+    {
+        if self.hi.abs() < 0.5
+        {
+            self.exp_m1_series()
+        }
+        else
+        {
+            self.exp() - Self::ONE
+        }
+    }
+
+    fn ln_1p(self) -> Self
+    {
+        if self.hi <= -1.0
+        {
+            return (self + Self::ONE).ln();
+        }
+
+        // Newton iteration on exp(y) - 1 = x, which doesn't lose precision around zero
+        let y = Self::from_f64(Float::ln_1p(self.hi));
+        let exp_m1 = y.exp_m1();
+        y + (self - exp_m1) / (exp_m1 + Self::ONE)
+    }
+
+    fn sinh(self) -> Self
+    {
+        if self.hi.abs() < 0.5
+        {
+            // Avoids the cancellation of (exp(x) - exp(-x)) / 2
+            let exp_m1 = self.exp_m1_series();
+            return (exp_m1 + exp_m1 / (exp_m1 + Self::ONE)).mul_pow2(-1);
+        }
+
+        let exp = self.exp();
+        (exp - exp.recip()).mul_pow2(-1)
+    }
+
+    fn cosh(self) -> Self
+    {
+        let exp = self.exp();
+        (exp + exp.recip()).mul_pow2(-1)
+    }
+
+    fn tanh(self) -> Self
+    {
+        if self.hi.abs() > 40.0
+        {
+            return self.signum();
+        }
+
+        self.sinh() / self.cosh()
+    }
+
+    fn asinh(self) -> Self
+    {
+        if self.hi < 0.0
+        {
+            return -(-self).asinh();
+        }
+
+        (self + (self * self + Self::ONE).sqrt()).ln()
+    }
+
+    fn acosh(self) -> Self
+    {
+        (self + (self * self - Self::ONE).sqrt()).ln()
+    }
+
+    fn atanh(self) -> Self
+    {
+        ((Self::ONE + self) / (Self::ONE - self)).ln().mul_pow2(-1)
+    }
+
+    fn integer_decode(self) -> (u64, i16, i8)
+    {
+        self.hi.integer_decode()
+    }
+}
+
+impl Scalar for DoubleDouble
+{
+    type Vector2 = DDVec2;
+    type Complex = ComplexDD;
+}
+
+
+/// 2D vector of [`DoubleDouble`]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct DDVec2
+{
+    pub x: DoubleDouble,
+    pub y: DoubleDouble,
+}
+
+impl DDVec2
+{
+    pub const fn new(x: DoubleDouble, y: DoubleDouble) -> Self
+    {
+        Self { x, y }
+    }
+}
+
+impl From<DVec2> for DDVec2
+{
+    fn from(value: DVec2) -> Self
+    {
+        Self::new(value.x.into(), value.y.into())
+    }
+}
+
+macro_rules! dd_vec2_op
+{
+    ($op_trait:ident, $op_fn:ident, $assign_trait:ident, $assign_fn:ident, $op:tt) =>
+    {
+        impl $op_trait for DDVec2
+        {
+            type Output = Self;
+
+            fn $op_fn(self, rhs: Self) -> Self
+            {
+                Self::new(self.x $op rhs.x, self.y $op rhs.y)
+            }
+        }
+
+        impl $assign_trait for DDVec2
+        {
+            fn $assign_fn(&mut self, rhs: Self)
+            {
+                *self = *self $op rhs;
+            }
+        }
+    };
+}
+
+dd_vec2_op!(Add, add, AddAssign, add_assign, +);
+dd_vec2_op!(Sub, sub, SubAssign, sub_assign, -);
+dd_vec2_op!(Mul, mul, MulAssign, mul_assign, *);
+dd_vec2_op!(Div, div, DivAssign, div_assign, /);
+
+impl Vector for DDVec2
+{
+    type Scalar = DoubleDouble;
+
+    fn new(x: Self::Scalar, y: Self::Scalar) -> Self
+    {
+        DDVec2::new(x, y)
+    }
+
+    fn x(self) -> Self::Scalar
+    {
+        self.x
+    }
+
+    fn y(self) -> Self::Scalar
+    {
+        self.y
+    }
+
+    fn abs(self) -> Self
+    {
+        Self::new(self.x.abs(), self.y.abs())
+    }
+
+    fn dot(self, rhs: Self) -> Self::Scalar
+    {
+        self.x * rhs.x + self.y * rhs.y
+    }
+}
+
+
+/// Complex number made of two [`DoubleDouble`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ComplexDD(DoubleDouble, DoubleDouble);
+
+impl Default for ComplexDD
+{
+    fn default() -> Self
+    {
+        Self::ZERO
+    }
+}
+
+impl Zero for ComplexDD
+{
+    fn zero() -> Self
+    {
+        Self::ZERO
+    }
+
+    fn is_zero(&self) -> bool
+    {
+        *self == Self::ZERO
+    }
+}
+
+impl One for ComplexDD
+{
+    fn one() -> Self
+    {
+        Self::ONE
+    }
+}
+
+impl From<DoubleDouble> for ComplexDD
+{
+    fn from(value: DoubleDouble) -> Self
+    {
+        Self::new(value, DoubleDouble::ZERO)
+    }
+}
+
+macro_rules! complex_dd_from_primitive
+{
+    ($t:ty) =>
+    {
+        impl From<$t> for ComplexDD
+        {
+            fn from(value: $t) -> Self
+            {
+                <DoubleDouble as From<$t>>::from(value).into()
+            }
+        }
+    };
+}
+
+complex_dd_from_primitive!(f32);
+complex_dd_from_primitive!(f64);
+complex_dd_from_primitive!(u32);
+complex_dd_from_primitive!(u64);
+complex_dd_from_primitive!(i32);
+complex_dd_from_primitive!(i64);
+
+impl ComplexDD
+{
+    pub const fn new(real: DoubleDouble, imaginary: DoubleDouble) -> Self
+    {
+        Self(real, imaginary)
+    }
+}
+
+impl ComplexNumber for ComplexDD
+{
+    type Scalar = DoubleDouble;
+
+    const ZERO: Self = Self::new(DoubleDouble::ZERO, DoubleDouble::ZERO);
+    const ONE: Self = Self::new(DoubleDouble::ONE, DoubleDouble::ZERO);
+    const I: Self = Self::new(DoubleDouble::ZERO, DoubleDouble::ONE);
+
+    fn from_cartesian(real: DoubleDouble, imaginary: DoubleDouble) -> Self
+    {
+        Self::new(real, imaginary)
+    }
+
+    fn from_polar(modulus: DoubleDouble, argument: DoubleDouble) -> Self
+    {
+        let (sin, cos) = argument.sin_cos();
+        Self::new(cos * modulus, sin * modulus)
+    }
+
+    fn from_vector(vec: DDVec2) -> Self
+    {
+        Self::new(vec.x, vec.y)
+    }
+
+    fn to_vector(self) -> DDVec2
+    {
+        DDVec2::new(self.0, self.1)
+    }
+
+    /// The real part of the complex number
+    fn re(self) -> DoubleDouble
+    {
+        self.0
+    }
+
+    fn re_mut(&mut self) -> &mut DoubleDouble
+    {
+        &mut self.0
+    }
+
+    /// The imaginary part of the complex number
+    fn im(self) -> DoubleDouble
+    {
+        self.1
+    }
+
+    fn im_mut(&mut self) -> &mut DoubleDouble
+    {
+        &mut self.1
+    }
+
+    #[doc(alias = "magnitude")]
+    fn modulus(self) -> DoubleDouble
+    {
+        self.modulus_squared().sqrt()
+    }
+
+    fn modulus_squared(self) -> DoubleDouble
+    {
+        self.to_vector().dot(self.to_vector())
+    }
+
+    fn argument(self) -> DoubleDouble
+    {
+        self.im().atan2(self.re())
+    }
+
+    fn conjugate(self) -> Self
+    {
+        Self::new(self.re(), -self.im())
+    }
+
+    fn fuzzy_eq(self, rhs: Self, max_abs_diff: DoubleDouble) -> bool
+    {
+        let diff = (self - rhs).to_vector().abs();
+        diff.x <= max_abs_diff && diff.y <= max_abs_diff
+    }
+
+    fn from_complex32(value: Complex32) -> Self
+    {
+        Self::new(value.re().into(), value.im().into())
+    }
+
+    fn to_complex32(self) -> Complex32
+    {
+        Complex32::new(self.re().as_(), self.im().as_())
+    }
+
+    fn to_complex64(self) -> Complex64
+    {
+        Complex64::new(self.re().as_(), self.im().as_())
+    }
+}
+
+impl From<Complex64> for ComplexDD
+{
+    fn from(value: Complex64) -> Self
+    {
+        Self::new(value.re().into(), value.im().into())
+    }
+}
+
+impl Add for ComplexDD
+{
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self
+    {
+        Self::new(self.0 + rhs.0, self.1 + rhs.1)
+    }
+}
+
+impl Sub for ComplexDD
+{
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self
+    {
+        Self::new(self.0 - rhs.0, self.1 - rhs.1)
+    }
+}
+
+impl Neg for ComplexDD
+{
+    type Output = Self;
+
+    fn neg(self) -> Self
+    {
+        Self::new(-self.0, -self.1)
+    }
+}
+
+impl Mul for ComplexDD
+{
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self
+    {
+        Self::new(self.re() * rhs.re() - self.im() * rhs.im(), self.re() * rhs.im() + self.im() * rhs.re())
+    }
+}
+
+impl Div for ComplexDD
+{
+    type Output = Self;
+
+    fn div(self, rhs: Self) -> Self
+    {
+        self * rhs.conjugate() / rhs.modulus_squared()
+    }
+}
+
+impl Mul<DoubleDouble> for ComplexDD
+{
+    type Output = Self;
+
+    fn mul(self, rhs: DoubleDouble) -> Self
+    {
+        Self::new(self.0 * rhs, self.1 * rhs)
+    }
+}
+
+impl Mul<ComplexDD> for DoubleDouble
+{
+    type Output = ComplexDD;
+
+    fn mul(self, rhs: ComplexDD) -> ComplexDD
+    {
+        rhs * self
+    }
+}
+
+impl Div<DoubleDouble> for ComplexDD
+{
+    type Output = Self;
+
+    fn div(self, rhs: DoubleDouble) -> Self
+    {
+        Self::new(self.0 / rhs, self.1 / rhs)
+    }
+}
+
+macro_rules! complex_dd_assign_op
+{
+    ($assign_trait:ident, $assign_fn:ident, $rhs:ty, $op:tt) =>
+    {
+        impl $assign_trait<$rhs> for ComplexDD
+        {
+            fn $assign_fn(&mut self, rhs: $rhs)
+            {
+                *self = *self $op rhs;
+            }
+        }
+    };
+}
+
+complex_dd_assign_op!(AddAssign, add_assign, ComplexDD, +);
+complex_dd_assign_op!(SubAssign, sub_assign, ComplexDD, -);
+complex_dd_assign_op!(MulAssign, mul_assign, ComplexDD, *);
+complex_dd_assign_op!(DivAssign, div_assign, ComplexDD, /);
+complex_dd_assign_op!(MulAssign, mul_assign, DoubleDouble, *);
+complex_dd_assign_op!(DivAssign, div_assign, DoubleDouble, /);
+
+impl Inv for ComplexDD
+{
+    type Output = Self;
+
+    fn inv(self) -> Self::Output
+    {
+        self.conjugate() / self.modulus_squared()
+    }
+}
+
+impl Sum for ComplexDD
+{
+    fn sum<I>(iter: I) -> Self
+    where
+        I: Iterator<Item = Self>,
+    {
+        iter.fold(Self::ZERO, Self::add)
+    }
+}
+
+impl Product for ComplexDD
+{
+    fn product<I>(iter: I) -> Self
+    where
+        I: Iterator<Item = Self>,
+    {
+        iter.fold(Self::ONE, Self::mul)
+    }
+}
+
+impl super::Exp for ComplexDD
+{
+    fn squared(self) -> Self
+    {
+        let re_im = self.re() * self.im();
+        Self::new(self.re() * self.re() - self.im() * self.im(), re_im + re_im)
+    }
+
+    fn sqrt(self) -> Self
+    {
+        let modulus = self.modulus();
+        let half: DoubleDouble = 0.5_f32.into();
+        let re = Float::sqrt((modulus + self.re()) * half);
+        let im = Float::sqrt((modulus - self.re()) * half);
+        Self::new(re, if self.im() < DoubleDouble::ZERO { -im } else { im })
+    }
+
+    fn exp(self) -> Self
+    {
+        Self::from_polar(Float::exp(self.re()), self.im())
+    }
+
+    fn pow(self, exp: Self) -> Self
+    {
+        super::complex::complex_pow(self, exp)
+    }
+
+    fn ln(self) -> Self
+    {
+        Self::new(Float::ln(self.modulus()), self.argument())
+    }
+
+    fn log(self, base: Self) -> Self
+    {
+        self.ln() / base.ln()
+    }
+}
+
+impl super::Trigo for ComplexDD
+{
+    fn sin(self) -> Self
+    {
+        let (sin, cos) = self.re().sin_cos();
+        Self::new(sin * self.im().cosh(), cos * self.im().sinh())
+    }
+
+    fn cos(self) -> Self
+    {
+        let (sin, cos) = self.re().sin_cos();
+        Self::new(cos * self.im().cosh(), -sin * self.im().sinh())
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use std::format;
+    use std::string::ToString;
+
+    fn assert_close(value: DoubleDouble, expected: DoubleDouble, relative_error: f64)
+    {
+        let error = (value - expected).abs();
+        assert!(error.hi() <= expected.abs().hi() * relative_error, "{value} is not close to {expected}");
+    }
+
+    #[test]
+    fn two_sum_is_error_free()
+    {
+        let pairs = [(1.0, 1.0e-20), (0.1, 0.2), (2.0f64.powi(60), 3.0), (-1.5, 2.0f64.powi(-70))];
+        for (a, b) in pairs
+        {
+            let (s, e) = two_sum(a, b);
+            assert_eq!(s, a + b);
+            // The error is exactly what the rounded sum lost
+            assert_eq!(two_sum(s, e), (s, e));
+            assert_eq!(s - a - (b - e), 0.0);
+        }
+
+        assert_eq!(two_sum(1.0, 1.0e-20), (1.0, 1.0e-20));
+        assert_eq!(two_sum(2.0f64.powi(60), 3.0), (2.0f64.powi(60), 3.0));
+    }
+
+    #[test]
+    fn two_prod_is_error_free()
+    {
+        let pairs = [(0.1, 0.3), (1.0 / 3.0, 3.0), (2.0f64.powi(30) + 1.0, 2.0f64.powi(30) + 3.0), (-7.25e100, 1.0e-90)];
+        for (a, b) in pairs
+        {
+            let (p, e) = two_prod(a, b);
+            assert_eq!(p, a * b);
+            // A fused multiply-add rounds only once, so it computes the exact error
+            assert_eq!(e, a.mul_add(b, -p));
+        }
+    }
+
+    #[test]
+    fn add_and_mul_keep_the_low_part()
+    {
+        let one = DoubleDouble::ONE;
+        let tiny = DoubleDouble::from_f64(1.0e-20);
+        assert_eq!(1.0 + 1.0e-20 - 1.0, 0.0);
+        assert_eq!((one + tiny - one).hi(), 1.0e-20);
+
+        let sum = one + tiny;
+        assert_eq!((sum.hi(), sum.lo()), (1.0, 1.0e-20));
+
+        // (1 + 2^-60)^2 = 1 + 2^-59 + 2^-120, where 2^-120 is beyond the precision
+        let x = DoubleDouble::new(1.0, 2.0f64.powi(-60));
+        let square = x * x;
+        assert_eq!((square.hi(), square.lo()), (1.0, 2.0f64.powi(-59)));
+
+        assert_eq!(DoubleDouble::from_u64(u64::MAX) - DoubleDouble::from_u64(u64::MAX - 1), one);
+    }
+
+    #[test]
+    fn division()
+    {
+        assert_eq!(DoubleDouble::ONE / DoubleDouble::from_f64(4.0), DoubleDouble::from_f64(0.25));
+
+        let third = DoubleDouble::ONE / DoubleDouble::from_f64(3.0);
+        assert_eq!(third.hi(), 1.0 / 3.0);
+        assert_close(third * DoubleDouble::from_f64(3.0), DoubleDouble::ONE, 1.0e-31);
+
+        let quotient = DoubleDouble::PI / DoubleDouble::LN_10;
+        assert_close(quotient * DoubleDouble::LN_10, DoubleDouble::PI, 1.0e-31);
+
+        assert!((DoubleDouble::ONE / DoubleDouble::ZERO).is_infinite());
+    }
+
+    #[test]
+    fn sqrt()
+    {
+        assert_eq!(DoubleDouble::from_f64(4.0).sqrt(), DoubleDouble::from_f64(2.0));
+        assert_eq!(DoubleDouble::ZERO.sqrt(), DoubleDouble::ZERO);
+        assert!(DoubleDouble::from_f64(-1.0).sqrt().is_nan());
+
+        let two = DoubleDouble::from_f64(2.0);
+        let root = two.sqrt();
+        assert_close(root * root, two, 1.0e-31);
+        assert_close(root, "1.41421356237309504880168872420969807857".parse().unwrap(), 1.0e-31);
+    }
+
+    #[test]
+    fn parse()
+    {
+        let tenth: DoubleDouble = "0.1".parse().unwrap();
+        assert_close(tenth, DoubleDouble::ONE / DoubleDouble::from_f64(10.0), 1.0e-32);
+        assert_ne!(tenth.lo(), 0.0);
+
+        let thousandth = DoubleDouble::from_f64(1.25) / DoubleDouble::from_f64(1000.0);
+        assert_close("-1.25e-3".parse().unwrap(), -thousandth, 1.0e-32);
+        assert_eq!("-1.25e2".parse(), Ok(DoubleDouble::from_f64(-125.0)));
+        assert_eq!("+250".parse(), Ok(DoubleDouble::from_f64(250.0)));
+        assert_eq!("2.5E2".parse(), Ok(DoubleDouble::from_f64(250.0)));
+        assert_eq!(".5".parse(), Ok(DoubleDouble::from_f64(0.5)));
+
+        for invalid in ["", "-", ".", "1.2.3", "1e", "1e2.5", "abc", "1,5"]
+        {
+            assert_eq!(invalid.parse::<DoubleDouble>(), Err(ParseDoubleDoubleError), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn display()
+    {
+        assert_eq!(DoubleDouble::ZERO.to_string(), "0");
+        assert_eq!(DoubleDouble::from_f64(1.5).to_string(), "1.5");
+        assert_eq!(DoubleDouble::from_f64(-250.0).to_string(), "-2.5e2");
+        assert_eq!("1e-3".parse::<DoubleDouble>().unwrap().to_string(), "1e-3");
+        // The f64 closest to 0.001 is not exactly 0.001
+        assert_eq!(DoubleDouble::from_f64(0.001).to_string(), "1.0000000000000000208166817117217e-3");
+        assert_eq!(DoubleDouble::from_f64(9.75).to_string(), "9.75");
+        assert_eq!(format!("{:.2}", DoubleDouble::from_f64(9.75)), "9.8");
+        assert_eq!(format!("{:.2}", DoubleDouble::from_f64(9.96)), "1e1");
+        assert_eq!(format!("{:.20}", DoubleDouble::PI), "3.1415926535897932385");
+        assert_eq!(DoubleDouble::from_f64(f64::INFINITY).to_string(), "inf");
+    }
+
+    #[test]
+    fn display_round_trips()
+    {
+        let values = [
+            DoubleDouble::PI,
+            DoubleDouble::ONE / DoubleDouble::from_f64(3.0),
+            -DoubleDouble::from_f64(2.0).sqrt() * DoubleDouble::from_f64(1.0e-100),
+            DoubleDouble::from_f64(1.0e300) / DoubleDouble::from_f64(7.0),
+            DoubleDouble::new(12345.678, 1.0e-13),
+            DoubleDouble::from_f64(1.0e-20),
+        ];
+        for value in values
+        {
+            let parsed: DoubleDouble = value.to_string().parse().unwrap();
+            assert_close(parsed, value, 1.0e-30);
+        }
+    }
+}
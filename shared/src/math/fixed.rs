@@ -0,0 +1,384 @@
+use core::cmp::Ordering;
+use core::ops::*;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Signed fixed-point number made of `N` 64 bits limbs
+///
+/// The most significant limb holds the integer part and the `N - 1` other limbs hold the fractional part.
+/// This is only used on the CPU, to compute values that need more precision than an `f64` can offer.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Fixed<const N: usize>
+{
+    negative: bool,
+    // Least significant limb first
+    magnitude: [u64; N],
+}
+
+const LIMB_SCALE: f64 = 18446744073709551616.0; // 2^64
+
+impl<const N: usize> Default for Fixed<N>
+{
+    fn default() -> Self
+    {
+        Self::ZERO
+    }
+}
+
+impl<const N: usize> Fixed<N>
+{
+    pub const ZERO: Self = Self { negative: false, magnitude: [0; N] };
+
+    /// Number of bits after the binary point
+    pub const FRACTIONAL_BITS: u32 = (N as u32 - 1) * 64;
+
+    pub fn from_f64(value: f64) -> Self
+    {
+        let bits = value.to_bits();
+        let negative = (bits >> 63) != 0;
+        let biased_exponent = ((bits >> 52) & 0x7ff) as i32;
+        let fraction = bits & ((1 << 52) - 1);
+
+        if biased_exponent == 0x7ff
+        {
+            // Infinity or NaN are not representable
+            return Self::ZERO;
+        }
+
+        let (mantissa, exponent) = if biased_exponent == 0
+        {
+            (fraction, -1074)
+        }
+        else
+        {
+            (fraction | (1 << 52), biased_exponent - 1075)
+        };
+
+        // value = mantissa * 2^exponent, place each bit of the mantissa in the limbs
+        let mut magnitude = [0; N];
+        let first_bit = exponent + Self::FRACTIONAL_BITS as i32;
+        for i in 0..53
+        {
+            let bit = first_bit + i;
+            if (mantissa >> i) & 1 == 0 || bit < 0 || bit >= (N as i32) * 64
+            {
+                continue;
+            }
+
+            magnitude[(bit / 64) as usize] |= 1 << (bit % 64);
+        }
+
+        Self::from_parts(negative, magnitude)
+    }
+
+    pub fn to_f64(self) -> f64
+    {
+        // From the least significant limb, a scale for the lowest limbs would underflow
+        let mut value = 0.0;
+        for limb in self.magnitude
+        {
+            value = value / LIMB_SCALE + limb as f64;
+        }
+
+        if self.negative { -value } else { value }
+    }
+
+    /// Converts to a fixed-point number with a different number of limbs, truncating the extra fractional bits
+    pub fn resize<const M: usize>(self) -> Fixed<M>
+    {
+        let mut magnitude = [0; M];
+        for i in 0..M.min(N)
+        {
+            magnitude[M - 1 - i] = self.magnitude[N - 1 - i];
+        }
+
+        Fixed::from_parts(self.negative, magnitude)
+    }
+
+    pub fn abs(self) -> Self
+    {
+        Self { negative: false, magnitude: self.magnitude }
+    }
+
+    pub fn is_negative(self) -> bool
+    {
+        self.negative
+    }
+
+    pub fn squared(self) -> Self
+    {
+        self * self
+    }
+
+    /// Splits the value into `f64` terms of decreasing magnitude whose exact sum is the value, zero is a single term
+    pub fn f64_terms(self) -> impl Iterator<Item = f64>
+    {
+        let mut rest = self;
+        let mut first = true;
+        core::iter::from_fn(move ||
+        {
+            // The bits below the smallest subnormal are dropped
+            let term = rest.to_f64();
+            if term == 0.0 && !first
+            {
+                return None;
+            }
+
+            first = false;
+            rest -= Self::from_f64(term);
+            Some(term)
+        })
+    }
+
+    fn from_parts(negative: bool, magnitude: [u64; N]) -> Self
+    {
+        // Zero is never negative so that it has a single representation
+        Self { negative: negative && magnitude.iter().any(|&limb| limb != 0), magnitude }
+    }
+
+    fn cmp_magnitude(a: &[u64; N], b: &[u64; N]) -> Ordering
+    {
+        for i in (0..N).rev()
+        {
+            match a[i].cmp(&b[i])
+            {
+                Ordering::Equal => continue,
+                ordering => return ordering,
+            }
+        }
+
+        Ordering::Equal
+    }
+
+    fn add_magnitude(a: &[u64; N], b: &[u64; N]) -> [u64; N]
+    {
+        let mut result = [0; N];
+        let mut carry = false;
+        for i in 0..N
+        {
+            let (sum, carry1) = a[i].overflowing_add(b[i]);
+            let (sum, carry2) = sum.overflowing_add(carry as u64);
+            result[i] = sum;
+            carry = carry1 || carry2;
+        }
+
+        result
+    }
+
+    // Requires a >= b
+    fn sub_magnitude(a: &[u64; N], b: &[u64; N]) -> [u64; N]
+    {
+        let mut result = [0; N];
+        let mut borrow = false;
+        for i in 0..N
+        {
+            let (difference, borrow1) = a[i].overflowing_sub(b[i]);
+            let (difference, borrow2) = difference.overflowing_sub(borrow as u64);
+            result[i] = difference;
+            borrow = borrow1 || borrow2;
+        }
+
+        result
+    }
+
+    fn mul_magnitude(a: &[u64; N], b: &[u64; N]) -> [u64; N]
+    {
+        // Schoolbook multiplication, column by column.
+        // Only the columns from N - 1 are kept, the lower ones only contribute through their carry.
+        let mut result = [0; N];
+        let mut accumulator: u128 = 0;
+        let mut accumulator_high: u64 = 0;
+
+        for column in 0..(2 * N - 1)
+        {
+            let first = column.saturating_sub(N - 1);
+            let last = column.min(N - 1);
+            for k in first..=last
+            {
+                let product = a[k] as u128 * b[column - k] as u128;
+                let (sum, overflow) = accumulator.overflowing_add(product);
+                accumulator = sum;
+                accumulator_high += overflow as u64;
+            }
+
+            if column >= N - 1
+            {
+                result[column - (N - 1)] = accumulator as u64;
+            }
+
+            accumulator = (accumulator >> 64) | ((accumulator_high as u128) << 64);
+            accumulator_high = 0;
+        }
+
+        result
+    }
+}
+
+impl<const N: usize> PartialOrd for Fixed<N>
+{
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering>
+    {
+        Some(self.cmp(other))
+    }
+}
+
+impl<const N: usize> Ord for Fixed<N>
+{
+    fn cmp(&self, other: &Self) -> Ordering
+    {
+        match (self.negative, other.negative)
+        {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (false, false) => Self::cmp_magnitude(&self.magnitude, &other.magnitude),
+            (true, true) => Self::cmp_magnitude(&other.magnitude, &self.magnitude),
+        }
+    }
+}
+
+impl<const N: usize> Neg for Fixed<N>
+{
+    type Output = Self;
+
+    fn neg(self) -> Self
+    {
+        Self::from_parts(!self.negative, self.magnitude)
+    }
+}
+
+impl<const N: usize> Add for Fixed<N>
+{
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self
+    {
+        if self.negative == rhs.negative
+        {
+            return Self::from_parts(self.negative, Self::add_magnitude(&self.magnitude, &rhs.magnitude));
+        }
+
+        match Self::cmp_magnitude(&self.magnitude, &rhs.magnitude)
+        {
+            Ordering::Less => Self::from_parts(rhs.negative, Self::sub_magnitude(&rhs.magnitude, &self.magnitude)),
+            _ => Self::from_parts(self.negative, Self::sub_magnitude(&self.magnitude, &rhs.magnitude)),
+        }
+    }
+}
+
+impl<const N: usize> AddAssign for Fixed<N>
+{
+    fn add_assign(&mut self, rhs: Self)
+    {
+        *self = *self + rhs;
+    }
+}
+
+impl<const N: usize> Sub for Fixed<N>
+{
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self
+    {
+        self + -rhs
+    }
+}
+
+impl<const N: usize> SubAssign for Fixed<N>
+{
+    fn sub_assign(&mut self, rhs: Self)
+    {
+        *self = *self - rhs;
+    }
+}
+
+impl<const N: usize> Mul for Fixed<N>
+{
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self
+    {
+        Self::from_parts(self.negative != rhs.negative, Self::mul_magnitude(&self.magnitude, &rhs.magnitude))
+    }
+}
+
+impl<const N: usize> MulAssign for Fixed<N>
+{
+    fn mul_assign(&mut self, rhs: Self)
+    {
+        *self = *self * rhs;
+    }
+}
+
+impl<const N: usize> From<Fixed<N>> for super::DoubleDouble
+{
+    fn from(value: Fixed<N>) -> Self
+    {
+        let hi = value.to_f64();
+        let lo = (value - Fixed::from_f64(hi)).to_f64();
+        Self::new(hi, lo)
+    }
+}
+
+// Written as the list of its f64 terms, which keeps every bit without depending on the number of limbs
+#[cfg(feature = "serde")]
+impl<const N: usize> Serialize for Fixed<N>
+{
+    fn serialize<Ser: serde::Serializer>(&self, serializer: Ser) -> Result<Ser::Ok, Ser::Error>
+    {
+        serializer.collect_seq(self.f64_terms())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, const N: usize> Deserialize<'de> for Fixed<N>
+{
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error>
+    {
+        struct Visitor<const N: usize>;
+
+        impl<'de, const N: usize> serde::de::Visitor<'de> for Visitor<N>
+        {
+            type Value = Fixed<N>;
+
+            fn expecting(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result
+            {
+                write!(f, "a list of finite numbers")
+            }
+
+            fn visit_seq<A: serde::de::SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error>
+            {
+                let mut value = Fixed::ZERO;
+                while let Some(term) = seq.next_element::<f64>()?
+                {
+                    if !term.is_finite()
+                    {
+                        return Err(serde::de::Error::invalid_value(serde::de::Unexpected::Float(term), &self));
+                    }
+                    value += Fixed::from_f64(term);
+                }
+                Ok(value)
+            }
+        }
+
+        deserializer.deserialize_seq(Visitor)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn f64_terms_sum_to_the_value()
+    {
+        let value = -(Fixed::<18>::from_f64(1.75) + Fixed::from_f64(3.0e-20) + Fixed::from_f64(5.0e-45) + Fixed::from_f64(7.0e-300));
+        let terms: std::vec::Vec<f64> = value.f64_terms().collect();
+        assert!(terms.len() >= 4);
+        assert!(terms.windows(2).all(|pair| pair[1].abs() < pair[0].abs()));
+        assert_eq!(terms.iter().fold(Fixed::ZERO, |sum, &term| sum + Fixed::from_f64(term)), value);
+
+        assert_eq!(Fixed::<18>::ZERO.f64_terms().collect::<std::vec::Vec<_>>(), [0.0]);
+        assert_eq!(Fixed::<3>::from_f64(-0.5).f64_terms().collect::<std::vec::Vec<_>>(), [-0.5]);
+    }
+}
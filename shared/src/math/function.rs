@@ -1,7 +1,7 @@
 use num_traits::{Float, Zero, One, Inv, Pow};
 use core::ops::*;
 
-use super::{Complex64, Complex32, Exp as ExpTrait, Trigo};
+use super::{Complex64, Complex32, ComplexDD, DoubleDouble, Exp as ExpTrait, Trigo};
 
 
 pub trait Function<I>
@@ -79,6 +79,11 @@ impl<F> Func<F>
 		Func(PowConst(exponent)).compose(self)
 	}
 
+	pub fn complex_pow_const<E>(self, exponent: E) -> Func<Composition<ComplexPowConst<E>, F>>
+	{
+		Func(ComplexPowConst(exponent)).compose(self)
+	}
+
 	pub fn sin(self) -> Func<Composition<Sin, F>>
 	{
 		Func::SIN.compose(self)
@@ -595,6 +600,34 @@ impl<I, E> Differentiable<I> for PowConst<E>
 }
 
 
+/// Raises to a constant power with [`Exp::pow`](ExpTrait::pow), which takes the principal branch for non-integer powers
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct ComplexPowConst<E>(pub E);
+
+impl<I: ExpTrait + Clone> Function<I> for ComplexPowConst<I>
+{
+	type Output = I;
+	
+	fn get(&self, x: I) -> Self::Output
+	{
+		x.pow(self.0.clone())
+	}
+}
+
+impl<I, E> Differentiable<I> for ComplexPowConst<E>
+	where
+		E: One + Sub<Output = E> + Clone
+{
+	type Derivative = Product<Constant<E>, Self>;
+
+	fn derivative(&self) -> Func<Self::Derivative>
+	{
+		Func::constant(self.0.clone()) * Func(ComplexPowConst(self.0.clone() - One::one()))
+	}
+}
+
+
 #[derive(Debug, Copy, Clone)]
 pub struct Power<B, E>(pub B, pub E);
 
@@ -776,3 +809,23 @@ impl IntoFunc for Complex32
 		Func::constant(self)
 	}
 }
+
+impl IntoFunc for DoubleDouble
+{
+	type Type = Constant<DoubleDouble>;
+	
+	fn into_func(self) -> Func<Self::Type>
+	{
+		Func::constant(self)
+	}
+}
+
+impl IntoFunc for ComplexDD
+{
+	type Type = Constant<ComplexDD>;
+	
+	fn into_func(self) -> Func<Self::Type>
+	{
+		Func::constant(self)
+	}
+}
@@ -1,9 +1,13 @@
 mod complex;
+mod fixed;
+mod double_double;
 pub mod function;
 
 pub use function::{Func, Function, Differentiable};
 
 pub use complex::*;
+pub use fixed::Fixed;
+pub use double_double::{DoubleDouble, DDVec2, ComplexDD, ParseDoubleDoubleError};
 
 use glam::{Vec2 as FVec2, DVec2};
 use num_traits::{Float, Pow, Inv, AsPrimitive, NumAssign, Euclid};
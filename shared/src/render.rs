@@ -1,8 +1,9 @@
-use glam::{Vec2 as FVec2, DVec2};
+use glam::{Vec2 as FVec2, DVec2, UVec2};
 #[cfg(feature = "bytemuck")]
 use bytemuck::NoUninit;
 
 use crate::math::{Scalar, Vec2};
+use crate::fractal::ColorParams;
 
 #[repr(C)]
 #[cfg_attr(feature = "bytemuck", derive(NoUninit))]
@@ -32,6 +33,9 @@ pub struct Instance32
 {
     pub pos: FVec2,
     pub size: FVec2,
+    /// Region of the texture shown on the instance, in texture coordinates
+    pub uv_min: FVec2,
+    pub uv_size: FVec2,
 }
 
 impl Default for Instance32
@@ -42,6 +46,8 @@ impl Default for Instance32
         {
             pos: FVec2::ZERO,
             size: FVec2::ONE,
+            uv_min: FVec2::ZERO,
+            uv_size: FVec2::ONE,
         }
     }
 }
@@ -86,6 +92,9 @@ pub struct Instance64
 {
     pub pos: DVec2,
     pub size: DVec2,
+    /// Region of the texture shown on the instance, in texture coordinates
+    pub uv_min: FVec2,
+    pub uv_size: FVec2,
 }
 
 impl Default for Instance64
@@ -96,6 +105,8 @@ impl Default for Instance64
         {
             pos: DVec2::ZERO,
             size: DVec2::ONE,
+            uv_min: FVec2::ZERO,
+            uv_size: FVec2::ONE,
         }
     }
 }
@@ -107,11 +118,38 @@ impl From<Instance64> for Instance32
         Self
         {
             pos: value.pos.as_vec2(),
-            size: value.size.as_vec2()
+            size: value.size.as_vec2(),
+            uv_min: value.uv_min,
+            uv_size: value.uv_size,
         }    
     }
 }
 
+/// Uniforms of the fragment shader that colors the cells containing [`crate::compute::EscapeData`]
+#[repr(C)]
+#[cfg_attr(feature = "bytemuck", derive(NoUninit))]
+#[derive(Copy, Clone)]
+pub struct ColoringUniforms
+{
+    pub color: ColorParams,
+    /// Size of the cell textures in texels
+    pub texture_size: UVec2,
+    /// Range of the keys of the [`crate::histogram::Histogram`], whose ranks are in a texture
+    pub histogram_key_range: FVec2,
+}
+
+impl ColoringUniforms
+{
+    pub fn new(color: ColorParams, texture_size: UVec2, histogram_key_range: FVec2) -> Self
+    {
+        Self
+        {
+            color,
+            texture_size,
+            histogram_key_range,
+        }
+    }
+}
 
 #[repr(C)]
 #[derive(Copy, Clone)]
@@ -127,6 +165,8 @@ pub struct Instance<S: Scalar>
 {
     pub pos: Vec2<S>,
     pub size: Vec2<S>,
+    pub uv_min: FVec2,
+    pub uv_size: FVec2,
 }
 
 impl From<Uniforms32> for Uniforms<f32>
@@ -161,6 +201,8 @@ impl From<Instance32> for Instance<f32>
         {
             pos: value.pos,
             size: value.size,
+            uv_min: value.uv_min,
+            uv_size: value.uv_size,
         }
     }
 }
@@ -173,6 +215,8 @@ impl From<Instance64> for Instance<f64>
         {
             pos: value.pos,
             size: value.size,
+            uv_min: value.uv_min,
+            uv_size: value.uv_size,
         }
     }
 }